@@ -377,7 +377,7 @@ mod game {
     use bevy::prelude::*;
     use bevy::utils::HashMap;
     use bevy_pigeon::app::{client_tick, server_tick};
-    use bevy_pigeon::sync::{CNetDir, NetComp, NetEntity, SNetDir};
+    use bevy_pigeon::sync::{CNetDir, NetComp, NetEntity, NetId, SNetDir};
     use bevy_pigeon::types::NetTransform;
     use bevy_pigeon::{NetLabel, SyncC};
     use carrier_pigeon::net::CIdSpec;
@@ -457,7 +457,7 @@ mod game {
             for msg in client.recv::<DelPlayer>() {
                 if let Some((entity, _net_e)) = q_player
                     .iter()
-                    .filter(|(_e, net_e)| net_e.id == msg.0 as u64)
+                    .filter(|(_e, net_e)| net_e.id == msg.0 as NetId)
                     .next()
                 {
                     commands.entity(entity).despawn_recursive();
@@ -582,7 +582,7 @@ mod game {
                 transform: Transform::from_xyz(0.0, 0.5, 0.0),
                 ..default()
             })
-            .insert(NetEntity::new(cid as u64))
+            .insert(NetEntity::new(cid as NetId))
             .insert(net_comp)
             .insert(GameItem)
             .insert(Player)
@@ -0,0 +1,287 @@
+//! Delivery information for each connection.
+//!
+//! `carrier-pigeon`'s UDP transport doesn't report which packets arrived. With acks enabled using
+//! [`enable_acks`](crate::AppExt::enable_acks), both ends send a small, sequenced UDP message every
+//! frame that also acknowledges the last 32 such messages from the other end. From this, the
+//! [`NetAcks`] resource knows the newest sequence number the peer received, and estimates of the
+//! packet loss and round trip time of every connection.
+//!
+//! ```ignore
+//! fn show_loss(acks: Res<NetAcks>) {
+//!     if let Some(info) = acks.server() {
+//!         info!("Packet loss: {:.1}%", info.loss() * 100.0);
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::{CId, Client, Server};
+use serde::{Deserialize, Serialize};
+
+/// The number of sequence numbers that are acknowledged in every message.
+const ACK_BITS: u32 = 32;
+
+/// The message that acks are sent as.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub(crate) struct AckMsg {
+    /// The sequence number of this message.
+    seq: u32,
+    /// The newest sequence number received from the peer.
+    ack: Option<u32>,
+    /// Whether each of the 32 sequence numbers before `ack` were received, newest in the lowest
+    /// bit.
+    ack_bits: u32,
+}
+
+/// The delivery information of a single connection.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct AckInfo {
+    /// The sequence number of the next message sent.
+    next_seq: u32,
+    /// The newest of our sequence numbers that the peer acknowledged.
+    latest_acked: Option<u32>,
+    /// Which of the 32 sequence numbers before `latest_acked` were acknowledged.
+    acked_bits: u32,
+    /// The newest sequence number received from the peer.
+    remote_latest: Option<u32>,
+    /// Which of the 32 sequence numbers before `remote_latest` were received.
+    remote_bits: u32,
+    /// The smoothed packet loss.
+    loss: f32,
+    /// The local time of the last successful send, in seconds.
+    last_send: Option<f64>,
+    /// The local time of the last message received, in seconds.
+    last_recv: Option<f64>,
+    /// The sequence numbers and send times of the last 32 messages sent, by sequence number
+    /// modulo 32.
+    send_times: [(u32, f64); ACK_BITS as usize],
+    /// The smoothed round trip time, in seconds.
+    rtt: Option<f32>,
+}
+
+impl AckInfo {
+    /// The newest sequence number that the peer acknowledged receiving.
+    pub fn latest_acked(&self) -> Option<u32> {
+        self.latest_acked
+    }
+
+    /// Whether the peer acknowledged receiving sequence number `seq`.
+    ///
+    /// Only the last 33 sequence numbers are known; older ones return `None`.
+    pub fn is_acked(&self, seq: u32) -> Option<bool> {
+        let latest = self.latest_acked?;
+        match latest.checked_sub(seq)? {
+            0 => Some(true),
+            back if back <= ACK_BITS => Some(self.acked_bits & (1 << (back - 1)) != 0),
+            _ => None,
+        }
+    }
+
+    /// The sequence number of the last message sent to the peer.
+    pub fn sent_seq(&self) -> Option<u32> {
+        self.next_seq.checked_sub(1)
+    }
+
+    /// The estimated fraction of packets that are lost, between 0 and 1.
+    pub fn loss(&self) -> f32 {
+        self.loss
+    }
+
+    /// The estimated round trip time in seconds, or `None` if no message was acknowledged yet.
+    ///
+    /// Since acks are sent once per frame, this includes up to a frame of delay on the peer.
+    pub fn rtt(&self) -> Option<f32> {
+        self.rtt
+    }
+
+    /// The local time that a message was last sent successfully, in seconds.
+    pub fn last_send(&self) -> Option<f64> {
+        self.last_send
+    }
+
+    /// The local time that a message was last received, in seconds.
+    pub fn last_recv(&self) -> Option<f64> {
+        self.last_recv
+    }
+
+    /// Creates the next message to send at the local time `now`.
+    fn next_msg(&mut self, now: f64) -> AckMsg {
+        let msg = AckMsg {
+            seq: self.next_seq,
+            ack: self.remote_latest,
+            ack_bits: self.remote_bits,
+        };
+        self.send_times[(self.next_seq % ACK_BITS) as usize] = (self.next_seq, now);
+        self.next_seq += 1;
+        msg
+    }
+
+    /// Updates the round trip time with the acknowledgement of `seq` at the local time `now`.
+    fn sample_rtt(&mut self, seq: u32, now: f64) {
+        let (sent_seq, sent) = self.send_times[(seq % ACK_BITS) as usize];
+        if sent_seq != seq {
+            return;
+        }
+        let sample = (now - sent) as f32;
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt * 0.9 + sample * 0.1,
+            None => sample,
+        });
+    }
+
+    /// Records a message received from the peer at the local time `now`.
+    fn receive(&mut self, msg: &AckMsg, now: f64) {
+        self.remote_latest = Some(shift_in(
+            self.remote_latest,
+            &mut self.remote_bits,
+            msg.seq,
+            1,
+        ));
+
+        let ack = match msg.ack {
+            Some(ack) => ack,
+            None => return,
+        };
+        let old = match self.latest_acked {
+            Some(old) => old,
+            None => {
+                self.latest_acked = Some(ack);
+                self.acked_bits = msg.ack_bits;
+                self.sample_rtt(ack, now);
+                return;
+            }
+        };
+        if ack < old {
+            // An older ack arrived late; it can still fill in the bits.
+            let back = old - ack;
+            if back <= ACK_BITS {
+                self.acked_bits |= ((msg.ack_bits << 1) | 1).wrapping_shl(back - 1);
+            }
+            return;
+        }
+
+        // The sequence numbers that fall out of the window are final; count the lost ones.
+        let shift = ack - old;
+        for back in (ACK_BITS + 1).saturating_sub(shift).max(1)..=ACK_BITS.min(old) {
+            let lost = self.acked_bits & (1 << (back - 1)) == 0;
+            self.loss = self.loss * 0.95 + if lost { 0.05 } else { 0.0 };
+        }
+        self.latest_acked = Some(shift_in(Some(old), &mut self.acked_bits, ack, 0));
+        self.acked_bits |= msg.ack_bits;
+        if ack > old {
+            self.sample_rtt(ack, now);
+        }
+    }
+}
+
+/// Moves the window of `bits` after `latest` forward to `seq`, marking the previous latest as
+/// received, and returns the new latest.
+///
+/// If `seq` is older than `latest`, it is marked in `bits` if `mark` is 1.
+fn shift_in(latest: Option<u32>, bits: &mut u32, seq: u32, mark: u32) -> u32 {
+    match latest {
+        None => seq,
+        Some(latest) if seq > latest => {
+            let shift = seq - latest;
+            *bits = if shift > ACK_BITS {
+                0
+            } else {
+                ((*bits << 1) | 1).wrapping_shl(shift - 1)
+            };
+            seq
+        }
+        Some(latest) => {
+            let back = latest - seq;
+            if (1..=ACK_BITS).contains(&back) {
+                *bits |= mark << (back - 1);
+            }
+            latest
+        }
+    }
+}
+
+/// The delivery information of every connection.
+///
+/// On the client, this has the connection to the server. On the server, this has the connection
+/// to every client.
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub struct NetAcks {
+    server: Option<AckInfo>,
+    clients: HashMap<CId, AckInfo>,
+}
+
+impl NetAcks {
+    /// The delivery information of the connection to the server, on a client.
+    pub fn server(&self) -> Option<&AckInfo> {
+        self.server.as_ref()
+    }
+
+    /// The delivery information of the connection to client `cid`, on the server.
+    pub fn client(&self, cid: CId) -> Option<&AckInfo> {
+        self.clients.get(&cid)
+    }
+
+    /// The delivery information of the connections to all clients, on the server.
+    pub fn clients(&self) -> impl Iterator<Item = (CId, &AckInfo)> + '_ {
+        self.clients.iter().map(|(cid, info)| (*cid, info))
+    }
+
+    /// Removes the delivery information of client `cid`.
+    pub fn remove_client(&mut self, cid: CId) {
+        self.clients.remove(&cid);
+    }
+
+    /// Removes the delivery information of the server.
+    pub(crate) fn remove_server(&mut self) {
+        self.server = None;
+    }
+}
+
+/// Sends an [`AckMsg`] on every connection.
+pub fn send_acks(
+    time: Res<Time>,
+    mut acks: ResMut<NetAcks>,
+    server: Option<Res<Server>>,
+    client: Option<Res<Client>>,
+) {
+    let now = time.elapsed_seconds_f64();
+    if let Some(server) = server {
+        for cid in server.cids() {
+            let info = acks.clients.entry(cid).or_default();
+            match server.send_to(cid, &info.next_msg(now)) {
+                Ok(()) => info.last_send = Some(now),
+                Err(e) => error!("{}", e),
+            }
+        }
+    } else if let Some(client) = client {
+        let info = acks.server.get_or_insert_with(default);
+        match client.send(&info.next_msg(now)) {
+            Ok(()) => info.last_send = Some(now),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Receives the [`AckMsg`]s into the [`NetAcks`].
+pub fn recv_acks(
+    time: Res<Time>,
+    mut acks: ResMut<NetAcks>,
+    server: Option<Res<Server>>,
+    client: Option<Res<Client>>,
+) {
+    let now = time.elapsed_seconds_f64();
+    if let Some(server) = server {
+        for msg in server.recv::<AckMsg>() {
+            let info = acks.clients.entry(msg.cid).or_default();
+            info.receive(&msg, now);
+            info.last_recv = Some(now);
+        }
+    } else if let Some(client) = client {
+        for msg in client.recv::<AckMsg>() {
+            let info = acks.server.get_or_insert_with(default);
+            info.receive(&msg, now);
+            info.last_recv = Some(now);
+        }
+    }
+}
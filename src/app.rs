@@ -1,33 +1,259 @@
 //! Contains the plugins, systems, and components for the bevy app.
 
-use crate::sync::{CNetDir, NetCompMsg, SNetDir};
-use crate::sync::{NetComp, NetEntity};
+use crate::ack::{recv_acks, send_acks, AckMsg, NetAcks};
+use crate::atomic::GroupBuffer;
+use crate::authority::{is_authority, AuthorityGained, AuthorityLost};
+use crate::background::Received;
+use crate::budget::{RecvBudget, RecvOverflow};
+use crate::bundle::SyncBundle;
+use crate::cadence::{advance_cadence, SendCadence};
+use crate::channel::{
+    flush_channels, ChannelConfig, NetChannels, ReliableChannelMsg, UnreliableChannelMsg,
+};
+use crate::command::{
+    recv_command_acks, recv_commands, send_command_acks, send_commands, ClientCommands,
+    CommandAckMsg, CommandAcked, CommandMsg, CommandQueue,
+};
+use crate::config::start_server;
+use crate::congestion::{update_send_rates, AdaptiveSendRate, SendRates, SendThrottle};
+use crate::connect::{
+    handle_connections, handle_versioned_connections, poll_connecting, recv_connection_response,
+    ConnectFailed, ConnectSucceeded, ConnectionHook, ConnectionResponse, NetConnected,
+};
+use crate::deferred::{apply_deferred_updates, DeferredApply};
+use crate::disconnect::{remove_on_disconnect, server_disconnects};
+use crate::error::{ErrorLog, NetErrorEvent, NetErrorKind, SendErrors};
+use crate::extrapolate::{extrapolate, Extrapolatable};
+use crate::filter::{evaluate_send_filters, NetFilters};
+use crate::fragment::{FragmentConfig, Fragments, NetCompFragment};
+use crate::group::NetGroups;
+use crate::host::host;
+use crate::ids::{require_net_entity, NetCompTypes};
+use crate::input::{
+    advance_tick, recv_inputs, send_input, InputConfig, InputHistory, InputMsg, LocalInput,
+    NetTick, PlayerInputs, ServerTick,
+};
+use crate::interest::ClientInterest;
+use crate::interpolate::{advance_timeline, interpolate, InterpolationDelayChanged, TickTimeline};
+use crate::jitter::JitterBuffer;
+use crate::key::{assign_key_ids, KeyCollision};
+use crate::limits::{report_malformed, MalformedMsg, NetLimits};
+use crate::lod::{
+    lod_recv, lod_send, update_lod, LodTier, LodVariants, ReducedNetCompMsg, SyncLod,
+};
+use crate::mapping::{
+    resolve_net_entities, update_net_entity_map, DuplicateNetEntity, MapNetEntities, NetEntityMap,
+};
+use crate::middleware::{Middleware, NetPipeline, PipedMsg};
+use crate::movement::{client_move, server_move, MoveInput, MoveState, MovementConfig};
+use crate::ordering::OrderedUpdates;
+use crate::persist::{load_world_at_startup, WorldFile, WorldLoaded};
+use crate::player::{spawn_players, update_connected_players, ConnectedPlayers, PlayerFactory};
+use crate::priority::{update_priorities, DistancePriority};
+use crate::profiler::{NetProfiler, ProfileEntry, ProfiledSystem};
+use crate::resend::{ack_resends, resend, AckedNetCompMsg, NetCompAck, ResendConfig, Resends};
+#[cfg(feature = "async")]
+use crate::runtime::{drain_bridge, AsyncBridge};
+use crate::schema::{register, MsgSchema, MsgTableLike};
+use crate::session::{
+    start_sessions, suspend_sessions, SessionExpired, SessionIdentity, SessionResumed, Sessions,
+};
+use crate::snapshot::{
+    recv_snapshots, send_snapshots, send_spawns, SendDelta, SendSnapshot, SnapshotApplied,
+    SnapshotRegistry, WorldSnapshot,
+};
+use crate::spawn::{
+    recv_spawn_requests, recv_spawn_responses, send_spawn_requests, send_spawn_responses,
+    ProvisionalIds, RequestSpawn, RespondSpawn, SpawnRequestMsg, SpawnRequested, SpawnResolved,
+    SpawnResponseMsg,
+};
+use crate::spec::{NetSendTo, NetSpec};
+use crate::stage::{add_net_stages, init_net_stages, NetStage};
+use crate::staging::{apply_staged_updates, StagedUpdates};
+use crate::stale::{detect_stale, expire_stale, NetResumed, NetStale, NetStaleness, StaleTimeout};
+use crate::stats::{MsgStats, NetStats};
+use crate::sync::{alt_transport, AltNetCompMsg, CNetDir, NetCompMsg, NetId, RecvNetComp, SNetDir};
+use crate::sync::{
+    ApplyMode, Channel, NetComp, NetEntity, NetWriteAccess, SendTick, SyncConfig, SyncSchedule,
+    Threshold,
+};
+use crate::validate::{RecvSanitizers, SyncValidators, SyncViolation, Update, Validation};
+use crate::version::{recv_handshake, ConnectionRejected, ProtocolVersion};
+use crate::visibility::NetHidden;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use carrier_pigeon::net::{CIdSpec, NetMsg};
-use carrier_pigeon::{Client, MsgRegError, MsgTable, Server, SortedMsgTable, Transport};
+use bevy::transform::TransformSystem;
+use bevy::utils::tracing::field;
+use bevy::utils::Instant;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::net::{CIdSpec, Config, NetMsg};
+#[cfg(doc)]
+use carrier_pigeon::MsgTable;
+use carrier_pigeon::{CId, Client, MsgRegError, MsgTableParts, Server, SortedMsgTable, Transport};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::any::Any;
+use std::borrow::Cow;
+use std::io;
 use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// An event that forces a sync of component `T`.
 ///
 /// This can be used if you need to force a sync of component `T` with message type `M`. This is
 /// most useful if you are using the change detection; you may want to force a sync of components
 /// when a new client joins.
+///
+/// The [`Default`] event syncs the component of all entities; [`SyncC::entity`] syncs a single
+/// one.
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
 pub struct SyncC<T> {
+    entity: Option<Entity>,
+    _pd: PhantomData<T>,
+}
+
+impl<T> SyncC<T> {
+    /// Creates a [`SyncC`] that forces a sync of component `T` of only `entity`.
+    pub fn entity(entity: Entity) -> Self {
+        SyncC {
+            entity: Some(entity),
+            _pd: PhantomData,
+        }
+    }
+}
+
+/// An event that is sent when a received update of component `T` is applied to an entity.
+///
+/// Unlike [`Changed<T>`], this is only sent for changes that came from the network, so it can be
+/// used to react to remote changes of a single entity, like playing an effect when the health of
+/// another player changes:
+///
+/// ```ignore
+/// fn on_hit(mut updates: EventReader<NetUpdated<Health>>, q: Query<&Health>) {
+///     for update in updates.iter() {
+///         if let Ok(health) = q.get(update.entity) {
+///             // play the hit effect.
+///         }
+///     }
+/// }
+/// ```
+///
+/// To react on a single entity instead, give it an [`OnNetUpdate<T>`] hook.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct NetUpdated<T> {
+    /// The entity whose component was updated.
+    pub entity: Entity,
+    /// The client that sent the update, or 0 if it was sent by the server.
+    pub cid: CId,
+    /// The [`NetTick`] of the sender when it sent the update, if it has one.
+    pub tick: Option<u32>,
+    _pd: PhantomData<T>,
+}
+
+impl<T> NetUpdated<T> {
+    fn new(entity: Entity, sender: UpdateSender) -> Self {
+        NetUpdated {
+            entity,
+            cid: sender.cid,
+            tick: sender.tick,
+            _pd: PhantomData,
+        }
+    }
+}
+
+/// The sender of a received update, which is kept with the update until it is applied.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct UpdateSender {
+    /// The client that sent the update, or 0 if it was sent by the server.
+    pub(crate) cid: CId,
+    /// The [`NetTick`] of the sender when it sent the update, if it has one.
+    pub(crate) tick: Option<u32>,
+    /// When the update was received.
+    pub(crate) at: Instant,
+}
+
+impl UpdateSender {
+    /// The sender of `msg`, which was just received.
+    fn of<M: Any + Send + Sync>(msg: &RecvNetComp<M>) -> Self {
+        UpdateSender {
+            cid: msg.cid,
+            tick: msg.tick.map(|t| t.tick),
+            at: Instant::now(),
+        }
+    }
+}
+
+/// The sender of the newest received update of component `T` that was applied to this entity.
+///
+/// This is inserted and kept up to date whenever a received update is applied, so debugging tools
+/// and gameplay, like attributing a kill to the client that last moved a client-authoritative
+/// prop, can see who last drove the component, and when.
+#[derive(Component, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct NetSource<T> {
+    /// The client that sent the update, or 0 if it was sent by the server.
+    pub cid: CId,
+    /// The [`NetTick`] of the sender when it sent the update, if it has one.
+    pub tick: Option<u32>,
+    /// When the update was received.
+    pub at: Instant,
     _pd: PhantomData<T>,
 }
 
+/// A hook that runs every time a received update of component `T` is applied to this entity.
+///
+/// This is a per-entity [`NetUpdated`]: the hook is called right when the update is applied, with
+/// the same event, so an entity can react to its own remote changes without a system that filters
+/// every event for it:
+///
+/// ```ignore
+/// commands.spawn((
+///     NetEntity::new(id),
+///     NetComp::<Health>::default(),
+///     Health(100),
+///     OnNetUpdate::<Health>::new(|update, commands| {
+///         commands.entity(update.entity).insert(HitFlash::default());
+///     }),
+/// ));
+/// ```
+///
+/// The hook gets [`Commands`], so its changes are applied at the end of the receive stage.
+#[derive(Component)]
+pub struct OnNetUpdate<T> {
+    hook: UpdateHook<T>,
+}
+
+/// A function that reacts to an applied update of component `T`.
+type UpdateHook<T> = Box<dyn Fn(&NetUpdated<T>, &mut Commands) + Send + Sync>;
+
+impl<T> std::fmt::Debug for OnNetUpdate<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnNetUpdate").finish_non_exhaustive()
+    }
+}
+
+impl<T> OnNetUpdate<T> {
+    /// Creates an [`OnNetUpdate`] that runs `hook` for every applied update.
+    pub fn new(hook: impl Fn(&NetUpdated<T>, &mut Commands) + Send + Sync + 'static) -> Self {
+        OnNetUpdate {
+            hook: Box::new(hook),
+        }
+    }
+}
+
 /// A label that is applied to all networking systems.
 #[derive(SystemLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
 pub struct NetLabel;
 
+/// A label for the systems that accept new connections on the server.
+#[derive(SystemLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+struct AcceptLabel;
+
 /// The client plugin.
 ///
 /// Automatically clears client's message buffer and receive new messages at the start of every
-/// frame.
+/// frame. Also polls the [`Connecting`](crate::Connecting) resource if a connection is being made in the background.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
 pub struct ClientPlugin;
 
@@ -40,29 +266,108 @@ pub struct ServerPlugin;
 
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_to_stage(CoreStage::First, client_tick.label(NetLabel));
+        app.add_event::<ConnectSucceeded>()
+            .add_event::<ConnectFailed>()
+            .add_system_to_stage(
+                CoreStage::First,
+                poll_connecting.label(NetLabel).before(client_tick),
+            )
+            .add_system_to_stage(CoreStage::First, client_tick.label(NetLabel))
+            .init_resource::<ServerTick>()
+            .init_resource::<NetLimits>()
+            .add_event::<MalformedMsg>()
+            .add_system_to_stage(
+                CoreStage::First,
+                report_malformed.label(NetLabel).after(client_tick),
+            )
+            .init_resource::<NetEntityMap>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_net_entity_map.label(NetLabel))
+            .add_event::<DuplicateNetEntity>();
+        init_net_stages(app);
+        add_tick_systems(app);
+        #[cfg(debug_assertions)]
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            crate::mapping::detect_duplicate_ids
+                .label(NetLabel)
+                .before(update_net_entity_map),
+        );
     }
 }
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_to_stage(CoreStage::First, server_tick.label(NetLabel));
+        app.init_resource::<NetGroups>()
+            .init_resource::<ClientInterest>()
+            .add_startup_system(start_server.label(NetLabel))
+            .add_system_to_stage(CoreStage::First, server_tick.label(NetLabel))
+            .init_resource::<NetLimits>()
+            .add_event::<MalformedMsg>()
+            .add_system_to_stage(
+                CoreStage::First,
+                report_malformed.label(NetLabel).after(server_tick),
+            )
+            .init_resource::<NetEntityMap>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_net_entity_map.label(NetLabel))
+            .add_event::<DuplicateNetEntity>()
+            .init_resource::<ConnectedPlayers>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_connected_players.label(NetLabel),
+            );
+        init_net_stages(app);
+        add_tick_systems(app);
+        #[cfg(debug_assertions)]
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            crate::mapping::detect_duplicate_ids
+                .label(NetLabel)
+                .before(update_net_entity_map),
+        );
     }
 }
 
 /// Clears client's message buffer and receive new messages.
-pub fn client_tick(client: Option<ResMut<Client>>) {
+///
+/// If the messages were already received by the
+/// [`BackgroundRecvPlugin`](crate::background::BackgroundRecvPlugin), this only records them.
+pub fn client_tick(client: Option<ResMut<Client>>, received: Option<Res<Received<Client>>>) {
     if let Some(mut client) = client {
-        client.clear_msgs();
-        client.recv_msgs();
+        let span = info_span!("client_tick", msgs = field::Empty).entered();
+        let msgs = match received {
+            Some(received) => received.msgs,
+            None => {
+                client.clear_msgs();
+                client.recv_msgs()
+            }
+        };
+        span.record("msgs", msgs);
     }
 }
 
 /// Clears server's message buffer and receive new messages.
-pub fn server_tick(server: Option<ResMut<Server>>) {
+///
+/// If the messages were already received by the
+/// [`BackgroundRecvPlugin`](crate::background::BackgroundRecvPlugin), this only records them.
+/// Also advances the [`NetTick`].
+pub fn server_tick(
+    server: Option<ResMut<Server>>,
+    received: Option<Res<Received<Server>>>,
+    tick: Option<ResMut<NetTick>>,
+) {
     if let Some(mut server) = server {
-        server.clear_msgs();
-        server.recv_msgs();
+        if let Some(mut tick) = tick {
+            tick.0 = tick.0.wrapping_add(1);
+        }
+        let span = info_span!("server_tick", msgs = field::Empty).entered();
+        let msgs = match received {
+            Some(received) => received.msgs,
+            None => {
+                server.clear_msgs();
+                server.recv_msgs()
+            }
+        };
+        span.record("msgs", msgs);
     }
 }
 
@@ -76,10 +381,13 @@ pub trait AppExt {
     /// Types `T` and `M` ***can*** be the same type; if the component `T` implements all the
     /// required traits, you may use it as `M`.
     ///
+    /// `table` can be a [`MsgTable`] or a [`SortedMsgTable`], which identifies the messages by
+    /// the type name of `M`. See [`MsgTableLike`] for more info.
+    ///
     /// ### Panics
     /// panics if `NetCompMsg<M>` is already registered in the table
     /// (If you call this method twice with the same `M`).
-    fn sync_comp<T, M>(&mut self, table: &mut MsgTable, transport: Transport) -> &mut Self
+    fn sync_comp<T, M>(&mut self, table: &mut impl MsgTableLike, transport: Transport) -> &mut Self
     where
         T: Clone + Into<M> + Component,
         M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
@@ -89,24 +397,18 @@ pub trait AppExt {
     /// Same as [`sync_comp()`](App::sync_comp), but doesn't panic in the event of a [`MsgRegError`].
     fn try_sync_comp<T, M>(
         &mut self,
-        table: &mut MsgTable,
+        table: &mut impl MsgTableLike,
         transport: Transport,
     ) -> Result<&mut Self, MsgRegError>
     where
         T: Clone + Into<M> + Component,
         M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
 
-    /// Adds everything needed to sync component `T` using message type `M`.
-    ///
-    /// Registers the type `NetCompMsg<M>` into `table` and adds the system required to sync
-    /// components of type `T`, using type `M` to send.
+    /// Adds everything needed to sync component `T` using message type `M`, into a
+    /// [`SortedMsgTable`].
     ///
-    /// Types `T` and `M` ***can*** be the same type; if the component `T` implements all the
-    /// required traits, you may use it as `M`.
-    ///
-    /// ### Panics
-    /// panics if `NetCompMsg<M>` is already registered in the table
-    /// (If you call this method twice with the same `M`).
+    /// Same as [`sync_comp()`](App::sync_comp), which takes a [`SortedMsgTable`] as well.
+    #[deprecated(note = "`sync_comp` takes a `SortedMsgTable` as well")]
     fn sync_comp_sorted<T, M>(
         &mut self,
         table: &mut SortedMsgTable,
@@ -116,9 +418,11 @@ pub trait AppExt {
         T: Clone + Into<M> + Component,
         M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
 
-    /// Adds everything needed to sync component `T` using message type `M`.
+    /// Adds everything needed to sync component `T` using message type `M`, into a
+    /// [`SortedMsgTable`].
     ///
-    /// Same as [`sync_comp()`](App::sync_comp), but doesn't panic in the event of a [`MsgRegError`].
+    /// Same as [`try_sync_comp()`](App::try_sync_comp), which takes a [`SortedMsgTable`] as well.
+    #[deprecated(note = "`try_sync_comp` takes a `SortedMsgTable` as well")]
     fn try_sync_comp_sorted<T, M>(
         &mut self,
         table: &mut SortedMsgTable,
@@ -127,249 +431,3344 @@ pub trait AppExt {
     where
         T: Clone + Into<M> + Component,
         M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
-}
 
-impl AppExt for App {
-    /// Adds everything needed to sync component `T` using message type `M`.
-    ///
-    /// Registers the type `NetCompMsg<M>` into `table` and adds the system required to sync
-    /// components of type `T`, using type `M` to send.
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`.
     ///
-    /// Types `T` and `M` ***can*** be the same type; if the component `T` implements all the
-    /// required traits, you may use it as `M`.
+    /// Same as [`sync_comp()`](App::sync_comp), but with the options of the [`SyncConfig`], like
+    /// the send rate and threshold, instead of only the transport.
     ///
     /// ### Panics
     /// panics if `NetCompMsg<M>` is already registered in the table
     /// (If you call this method twice with the same `M`).
-    fn sync_comp<T, M>(&mut self, table: &mut MsgTable, transport: Transport) -> &mut Self
+    fn sync_comp_cfg<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+    ) -> &mut Self
     where
         T: Clone + Into<M> + Component,
-        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
-    {
-        table.register::<NetCompMsg<M>>(transport).unwrap();
-
-        self.add_event::<SyncC<T>>();
-        self.add_system_to_stage(CoreStage::Last, send_on_event::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::Last, comp_send::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::First, comp_recv::<T, M>.label(NetLabel));
-        self
-    }
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
 
-    /// Adds everything needed to sync component `T` using message type `M`.
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`.
     ///
-    /// Same as [`sync_comp()`](App::sync_comp), but doesn't panic in the event of a [`MsgRegError`].
-    fn try_sync_comp<T, M>(
+    /// Same as [`sync_comp_cfg()`](App::sync_comp_cfg), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_sync_comp_cfg<T, M>(
         &mut self,
-        table: &mut MsgTable,
-        transport: Transport,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
     ) -> Result<&mut Self, MsgRegError>
     where
         T: Clone + Into<M> + Component,
-        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
-    {
-        table.register::<NetCompMsg<M>>(transport)?;
-
-        self.add_event::<SyncC<T>>();
-        self.add_system_to_stage(CoreStage::Last, send_on_event::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::Last, comp_send::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::First, comp_recv::<T, M>.label(NetLabel));
-        Ok(self)
-    }
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
 
-    /// Adds everything needed to sync component `T` using message type `M`.
-    ///
-    /// Registers the type `NetCompMsg<M>` into `table` and adds the system required to sync
-    /// components of type `T`, using type `M` to send.
+    /// Adds everything needed to sync component `T` using message type `M`, with the stable
+    /// `id` on the wire.
     ///
-    /// Types `T` and `M` ***can*** be the same type; if the component `T` implements all the
-    /// required traits, you may use it as `M`.
+    /// Same as [`sync_comp()`](App::sync_comp), but the messages are registered under ids made
+    /// from `id`, such as `"game.transform"`, instead of from the type name of `M`. The type name
+    /// changes when `M` is renamed or moved to another module, so ids that are defined in one
+    /// shared place keep the client and server in agreement across differently organized
+    /// codebases and versions. A [`MsgTable`] has no ids, so it ignores `id`.
     ///
     /// ### Panics
-    /// panics if `NetCompMsg<M>` is already registered in the table
-    /// (If you call this method twice with the same `M`).
-    fn sync_comp_sorted<T, M>(
+    /// panics if `NetCompMsg<M>` or `id` is already registered in the table
+    /// (If you call this method twice with the same `M` or `id`).
+    fn sync_comp_as<T, M>(
         &mut self,
-        table: &mut SortedMsgTable,
+        table: &mut impl MsgTableLike,
         transport: Transport,
+        id: &str,
     ) -> &mut Self
     where
         T: Clone + Into<M> + Component,
-        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
-    {
-        let id = "bevy-pigeon::".to_owned() + std::any::type_name::<M>();
-        table.register::<NetCompMsg<M>>(transport, &id).unwrap();
-
-        self.add_event::<SyncC<T>>();
-        self.add_system_to_stage(CoreStage::Last, send_on_event::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::Last, comp_send::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::First, comp_recv::<T, M>.label(NetLabel));
-        self
-    }
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
 
-    /// Adds everything needed to sync component `T` using message type `M`.
+    /// Adds everything needed to sync component `T` using message type `M`, with the stable
+    /// `id` on the wire.
     ///
-    /// Same as [`sync_comp()`](App::sync_comp), but doesn't panic in the event of a [`MsgRegError`].
-    fn try_sync_comp_sorted<T, M>(
+    /// Same as [`sync_comp_as()`](App::sync_comp_as), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_sync_comp_as<T, M>(
         &mut self,
-        table: &mut SortedMsgTable,
+        table: &mut impl MsgTableLike,
         transport: Transport,
+        id: &str,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`, with the stable `id` on the wire.
+    ///
+    /// Same as [`sync_comp_as()`](App::sync_comp_as), but with the options of the
+    /// [`SyncConfig`].
+    ///
+    /// ### Panics
+    /// panics if `NetCompMsg<M>` or `id` is already registered in the table
+    /// (If you call this method twice with the same `M` or `id`).
+    fn sync_comp_cfg_as<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+        id: &str,
+    ) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`, with the stable `id` on the wire.
+    ///
+    /// Same as [`sync_comp_cfg_as()`](App::sync_comp_cfg_as), but doesn't panic in the event of
+    /// a [`MsgRegError`].
+    fn try_sync_comp_cfg_as<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+        id: &str,
     ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to sync all components of bundle `B`, with the shared `config`.
+    ///
+    /// Same as calling [`sync_comp_cfg()`](App::sync_comp_cfg) for every component of the
+    /// bundle. See the [`bundle`](crate::bundle) module for more info.
+    ///
+    /// ### Panics
+    /// panics if the message type of any of the components is already registered in the table.
+    fn sync_bundle<B: SyncBundle>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<()>,
+    ) -> &mut Self;
+
+    /// Adds everything needed to sync all components of bundle `B`, with the shared `config`.
+    ///
+    /// Same as [`sync_bundle()`](App::sync_bundle), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_sync_bundle<B: SyncBundle>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<()>,
+    ) -> Result<&mut Self, MsgRegError>;
+
+    /// Sets the function used to apply a received message of type `M` to component `T`.
+    ///
+    /// By default, the message is cloned and converted into `T`. For large message types, this
+    /// can be used to update the component in place instead, avoiding a clone per entity per frame.
+    ///
+    /// ### Panics
+    /// panics if `T` is not synced using `M`
+    /// (If [`sync_comp()`](App::sync_comp) wasn't called before this).
+    fn set_sync_apply<T, M>(&mut self, apply: fn(&M, &mut T)) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds a validator for updates to component `T` sent by clients.
+    ///
+    /// The validator runs on the server before a received message is applied, and can accept,
+    /// clamp, or reject it. Clamped and rejected updates send a [`SyncViolation`] event. See the
+    /// [`validate`](crate::validate) module for more info.
+    fn add_sync_validator<T, M, F>(&mut self, validator: F) -> &mut Self
     where
         T: Clone + Into<M> + Component,
         M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
-    {
-        let id = "bevy-pigeon::".to_owned() + std::any::type_name::<M>();
-        table.register::<NetCompMsg<M>>(transport, &id)?;
+        F: Fn(&Update, &M, &T) -> Validation<M> + Send + Sync + 'static;
 
-        self.add_event::<SyncC<T>>();
-        self.add_system_to_stage(CoreStage::Last, send_on_event::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::Last, comp_send::<T, M>.label(NetLabel));
-        self.add_system_to_stage(CoreStage::First, comp_recv::<T, M>.label(NetLabel));
-        Ok(self)
-    }
-}
+    /// Detects entities whose component `T` expects updates, but hasn't received one in
+    /// `window` seconds, sending [`NetStale`](crate::stale::NetStale) and
+    /// [`NetResumed`](crate::stale::NetResumed) events.
+    ///
+    /// See the [`stale`](crate::stale) module for more info.
+    fn detect_stale<T, M>(&mut self, window: f32) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync;
 
-/// A system that forces a sync of a certain component.
-fn send_on_event<T, M>(
-    mut er: EventReader<SyncC<T>>,
-    server: Option<ResMut<Server>>,
-    client: Option<ResMut<Client>>,
-    q: Query<(&NetEntity, &NetComp<T, M>, &T)>,
-) where
-    T: Clone + Into<M> + Component,
-    M: Clone + Into<T> + Any + Send + Sync,
-{
-    if er.iter().count() == 0 {
-        return;
-    }
-    trace!("Force Syncing {}", std::any::type_name::<T>());
+    /// Despawns entities that stay stale for longer than `timeout` seconds, so ghost entities
+    /// don't pile up when their despawn messages are lost.
+    ///
+    /// This needs [`detect_stale`](AppExt::detect_stale) to find the stale entities. See the
+    /// [`stale`](crate::stale) module for more info.
+    fn despawn_stale_after(&mut self, timeout: f32) -> &mut Self;
 
-    // Almost copy-paste from [`comp_send`] ignoring change detection
-    if let Some(server) = server {
-        for (net_e, net_c, comp) in q.iter() {
-            if let Some(to_spec) = net_c.s_dir.to() {
-                if let Err(e) = server.send_spec(
-                    *to_spec,
-                    &NetCompMsg::<M>::new(net_e.id, comp.clone().into()),
-                ) {
-                    error!("{}", e);
-                }
-            }
-        }
-    } else if let Some(client) = client {
-        for (net_e, net_c, comp) in q.iter() {
-            if let CNetDir::To = net_c.c_dir {
-                if let Err(e) = client.send(&NetCompMsg::<M>::new(net_e.id, comp.clone().into())) {
-                    error!("{}", e);
-                }
-            }
-        }
-    }
-}
+    /// Calls `callback` on entities that stay stale for longer than `timeout` seconds, instead
+    /// of despawning them.
+    ///
+    /// This needs [`detect_stale`](AppExt::detect_stale) to find the stale entities. See the
+    /// [`stale`](crate::stale) module for more info.
+    fn on_stale_timeout(
+        &mut self,
+        timeout: f32,
+        callback: impl Fn(Entity, &mut Commands) + Send + Sync + 'static,
+    ) -> &mut Self;
 
-/// A system that sends component `T` using messages of type `M`.
-///
-/// Most of the time, you will call [`sync_comp`](AppExt::sync_comp) which will add this system.
-/// Only add it manually if you know what you are doing and want custom control over when it runs.
-#[allow(clippy::type_complexity)]
-pub fn comp_send<T, M>(
-    server: Option<ResMut<Server>>,
-    client: Option<ResMut<Client>>,
-    q: Query<(&NetEntity, &NetComp<T, M>, &T, ChangeTrackers<T>)>,
-) where
-    T: Clone + Into<M> + Component,
-    M: Clone + Into<T> + Any + Send + Sync,
-{
-    if let Some(server) = server {
-        for (net_e, net_c, comp, ct) in q.iter() {
-            // If we are using change detection, and the component hasn't been changed, skip.
-            if net_c.cd && !ct.is_changed() {
-                continue;
-            }
+    /// Sends synced components only `rate` times per second, instead of every frame.
+    ///
+    /// The rate can be changed later in the [`SendCadence`] resource. See the
+    /// [`cadence`](crate::cadence) module for more info.
+    fn set_send_cadence(&mut self, rate: f32) -> &mut Self;
 
-            if let Some(to_spec) = net_c.s_dir.to() {
-                if let Err(e) = server.send_spec(
-                    *to_spec,
-                    &NetCompMsg::<M>::new(net_e.id, comp.clone().into()),
-                ) {
-                    error!("{}", e);
-                }
-            }
-        }
-    } else if let Some(client) = client {
-        for (net_e, net_c, comp, ct) in q.iter() {
-            // If we are using change detection, and the component hasn't been changed, skip.
-            if net_c.cd && !ct.is_changed() {
-                continue;
-            }
+    /// Adds the [`NetStage`]s after stage `recv_after` and stage `send_after`, instead of after
+    /// [`CoreStage::First`] and [`CoreStage::Last`].
+    ///
+    /// This has to be called before the [`ClientPlugin`] or [`ServerPlugin`] is added, which add
+    /// the stages at their default places otherwise. See the [`stage`](crate::stage) module for
+    /// more info.
+    fn add_net_stages(
+        &mut self,
+        recv_after: impl StageLabel,
+        send_after: impl StageLabel,
+    ) -> &mut Self;
 
-            if let CNetDir::To = net_c.c_dir {
-                if let Err(e) = client.send(&NetCompMsg::<M>::new(net_e.id, comp.clone().into())) {
-                    error!("{}", e);
-                }
-            }
-        }
-    }
-}
+    /// Runs [`NetStage`] `stage` once, without the rest of the schedule.
+    ///
+    /// This steps the network on its own, such as in tests. Nothing happens if the stage hasn't
+    /// been added.
+    fn run_net_stage(&mut self, stage: NetStage) -> &mut Self;
 
-/// A system that receives messages of type `M` and applies it to component `T`.
-///
-/// Most of the time, you will call [`sync_comp`](AppExt::sync_comp) which will add this system.
-/// Only add it manually if you know what you are doing and want custom control over when it runs.
-pub fn comp_recv<T, M>(
-    server: Option<ResMut<Server>>,
-    client: Option<ResMut<Client>>,
-    mut q: Query<(&NetEntity, &mut NetComp<T, M>, &mut T)>,
-) where
-    T: Clone + Into<M> + Component,
-    M: Clone + Into<T> + Any + Send + Sync,
-{
-    if let Some(server) = server {
-        // Cache messages
-        let msgs: Vec<NetMsg<NetCompMsg<M>>> = server.recv::<NetCompMsg<M>>().collect();
-        for (net_e, mut net_c, mut comp) in q.iter_mut() {
-            if let Some(&spec) = net_c.s_dir.from() {
-                if let Some(valid_msg) = get_latest_msg(&msgs, net_c.last, spec, net_e.id) {
-                    net_c.last = valid_msg.time;
-                    *comp = valid_msg.msg.clone().into();
-                }
-            }
-            // Warn on overlap
-            if let SNetDir::ToFrom(to_spec, from_spec) = net_c.s_dir {
-                if to_spec.overlaps(from_spec) {
-                    warn!("NetEntity {{ id: {} }} has overlapping `CIdSpec`s in NetDirection::ToFrom. Applying anyway.", net_e.id);
-                }
-            }
-        }
-    } else if let Some(client) = client {
-        // Cache messages
-        let msgs: Vec<NetMsg<NetCompMsg<M>>> = client.recv::<NetCompMsg<M>>().collect();
-        for (net_e, mut net_c, mut comp) in q.iter_mut() {
-            if net_c.c_dir == CNetDir::From {
-                if let Some(valid_msg) = get_latest_msg(&msgs, net_c.last, CIdSpec::All, net_e.id) {
-                    net_c.last = valid_msg.time;
-                    *comp = valid_msg.msg.clone().into();
-                }
+    /// Adds a sanitizer for updates to component `T` received from the server.
+    ///
+    /// The sanitizer runs on the client before a received message is applied, and can accept,
+    /// transform, or reject it, the same way a validator does with
+    /// [`add_sync_validator`](AppExt::add_sync_validator). See the
+    /// [`validate`](crate::validate) module for more info.
+    fn add_recv_sanitizer<T, M, F>(&mut self, sanitizer: F) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        F: Fn(&Update, &M, &T) -> Validation<M> + Send + Sync + 'static;
 
-                if let Some(valid_msg) = msgs.iter().filter(|msg| msg.id == net_e.id).last() {
-                    *comp = valid_msg.msg.clone().into();
-                }
-            }
-        }
-    }
-}
+    /// Enables sending snapshots of the networked world to clients that join late.
+    ///
+    /// Registers the snapshot message type into `table`. See the [`snapshot`](crate::snapshot)
+    /// module for more info.
+    ///
+    /// ### Panics
+    /// panics if snapshots are already enabled in the table.
+    fn enable_snapshots(&mut self, table: &mut impl MsgTableLike) -> &mut Self;
 
-/// Helper function that gets the most recent message that matches `from_spec` for entity with `id`
-/// if it is sent later that current.
-fn get_latest_msg<'a, M: Any + Send + Sync>(
-    msgs: &'a [NetMsg<NetCompMsg<M>>],
+    /// Enables sending snapshots of the networked world to clients that join late.
+    ///
+    /// Same as [`enable_snapshots()`](App::enable_snapshots), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_enable_snapshots(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>;
+
+    /// Loads the networked world from the file at `path` at startup, if it exists.
+    ///
+    /// The file is saved with [`save_world_file`](crate::persist::save_world_file). See the
+    /// [`persist`](crate::persist) module for more info.
+    fn load_world_on_startup(&mut self, path: impl Into<PathBuf>) -> &mut Self;
+
+    /// Adds an [`AsyncBridge<T>`](crate::runtime::AsyncBridge) with room for `capacity`
+    /// messages, which sends the messages from async tasks as `T` events. Requires the `async`
+    /// feature.
+    ///
+    /// See the [`runtime`](crate::runtime) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `capacity` is 0.
+    #[cfg(feature = "async")]
+    fn add_async_bridge<T: Send + Sync + 'static>(&mut self, capacity: usize) -> &mut Self;
+
+    /// Resolves the [`NetEntityRef`](crate::mapping::NetEntityRef)s in component `T` whenever it
+    /// changes.
+    ///
+    /// See the [`mapping`](crate::mapping) module for more info.
+    fn map_net_entities<T: MapNetEntities + Component>(&mut self) -> &mut Self;
+
+    /// Gives the entities with a [`NetKey`](crate::key::NetKey) the [`NetEntity`] derived from
+    /// their key, so entities that exist on every instance, like the ones placed in a level, are
+    /// matched up without hand-assigned ids.
+    ///
+    /// See the [`key`](crate::key) module for more info.
+    fn add_net_keys(&mut self) -> &mut Self;
+
+    /// Extrapolates component `T`, synced using message type `M`, on the entities with an
+    /// [`Extrapolate<T>`](crate::extrapolate::Extrapolate).
+    ///
+    /// See the [`extrapolate`](crate::extrapolate) module for more info.
+    fn extrapolate_comp<T, M>(&mut self) -> &mut Self
+    where
+        T: Extrapolatable + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync;
+
+    /// Interpolates component `T`, synced using message type `M`, on the entities with an
+    /// [`Interpolate<T>`](crate::interpolate::Interpolate).
+    ///
+    /// See the [`interpolate`](crate::interpolate) module for more info.
+    fn interpolate_comp<T, M>(&mut self) -> &mut Self
+    where
+        T: Extrapolatable + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync;
+
+    /// Adds a [`TickTimeline`](crate::interpolate::TickTimeline) for a server that ticks
+    /// `tick_rate` times per second, which the [`Interpolate`](crate::interpolate::Interpolate)s
+    /// that use ticks follow.
+    ///
+    /// See the [`interpolate`](crate::interpolate) module for more info.
+    fn add_tick_timeline(&mut self, tick_rate: f32) -> &mut Self;
+
+    /// Adds a [`JitterBuffer<M>`](crate::jitter::JitterBuffer) for the synced components sent as
+    /// message type `M`.
+    ///
+    /// See the [`jitter`](crate::jitter) module for more info.
+    fn add_jitter_buffer<M: Clone + Any + Send + Sync>(&mut self) -> &mut Self;
+
+    /// Applies at most `max_msgs` received updates of message type `M` per frame, carrying the
+    /// rest over to the next frames.
+    ///
+    /// See the [`budget`](crate::budget) module for more info.
+    fn add_recv_budget<M: Clone + Any + Send + Sync>(&mut self, max_msgs: usize) -> &mut Self;
+
+    /// Applies the received updates of component `T` over several frames, spending at most
+    /// `max_time` per frame, instead of all at once.
+    ///
+    /// See the [`deferred`](crate::deferred) module for more info.
+    fn defer_apply<T, M>(&mut self, max_time: Duration) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Stages the received updates of all components, and applies them in one exclusive system
+    /// at the end of [`NetStage::Receive`], so other systems never see half of them applied.
+    ///
+    /// See the [`staging`](crate::staging) module for more info.
+    fn stage_net_updates(&mut self) -> &mut Self;
+
+    /// Lowers the rate that the server sends updates to clients with a congested connection,
+    /// and raises it again once the connection recovers.
+    ///
+    /// This needs acks, enabled with [`enable_acks`](App::enable_acks). See the
+    /// [`congestion`](crate::congestion) module for more info.
+    fn adaptive_send_rate(&mut self, config: AdaptiveSendRate) -> &mut Self;
+
+    /// Sends the entities that are far from a client's viewpoint to that client less often.
+    ///
+    /// See the [`priority`](crate::priority) module for more info.
+    fn prioritize_by_distance(&mut self, priority: DistancePriority) -> &mut Self;
+
+    /// Sends the entities in a lower [`LodTier`](crate::lod::LodTier) to some clients, as
+    /// decided by `lod`.
+    ///
+    /// See the [`lod`](crate::lod) module for more info.
+    fn sync_lod(&mut self, lod: SyncLod) -> &mut Self;
+
+    /// Sends component `T` as the reduced message type `R` to the clients that have the entity
+    /// in the [`Reduced`](crate::lod::LodTier::Reduced) tier.
+    ///
+    /// Registers the reduced message type into `table`. `T` needs to be synced using `M` first.
+    /// See the [`lod`](crate::lod) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `R` is already registered as a reduced message type in the table.
+    fn add_lod_variant<T, M, R>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        T: Clone + Into<M> + Into<R> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        R: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Sends component `T` as the reduced message type `R` to the clients that have the entity
+    /// in the [`Reduced`](crate::lod::LodTier::Reduced) tier.
+    ///
+    /// Same as [`add_lod_variant()`](App::add_lod_variant), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_lod_variant<T, M, R>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Into<R> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        R: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Sets the connection message type `C` and response message type `R` that the `MsgTable`
+    /// is built with.
+    ///
+    /// On the server, new connections are then handled using the
+    /// [`ConnectionHook<C, R>`](crate::connect::ConnectionHook), which accepts every connection
+    /// if it isn't inserted, so don't call `Server::handle_new_cons` yourself. See the
+    /// [`connect`](crate::connect) module for more info.
+    fn set_connection_msgs<C, R>(&mut self) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync,
+        R: Clone + Default + Any + Send + Sync;
+
+    /// Sets the connection message type to [`Versioned<C>`](crate::version::Versioned) and the
+    /// response message type to [`Handshake<R>`](crate::version::Handshake), which the `MsgTable`
+    /// is built with, with the given game version.
+    ///
+    /// This is the same as [`set_connection_msgs`](App::set_connection_msgs), but the server
+    /// first rejects the clients with an incompatible protocol version. See the
+    /// [`version`](crate::version) module for more info.
+    fn set_versioned_connection_msgs<C, R>(&mut self, game_version: u32) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync,
+        R: Clone + Default + Any + Send + Sync;
+
+    /// Spawns the bundle returned by `factory` as the player entity of every client that connects
+    /// to the server, and despawns it when the client disconnects.
+    ///
+    /// See the [`player`](crate::player) module for more info.
+    fn spawn_players<B, F>(&mut self, factory: F) -> &mut Self
+    where
+        B: Bundle,
+        F: Fn(CId) -> B + Send + Sync + 'static;
+
+    /// Keeps the entities owned by disconnected clients for `grace_period` seconds, so that they
+    /// can resume their session by reconnecting with the same session id.
+    ///
+    /// `identity` gets the session id from the connection message of type `C`. Needs to be called
+    /// after [`set_connection_msgs`](App::set_connection_msgs). See the
+    /// [`session`](crate::session) module for more info.
+    fn enable_sessions<C, F>(&mut self, grace_period: f64, identity: F) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync,
+        F: Fn(&C) -> Option<u64> + Send + Sync + 'static;
+
+    /// Runs `server` as a sub app, with a [`Server`] on the loopback interface, and connects to it
+    /// with the connection message `con_msg`.
+    ///
+    /// `C` is the connection message type and `R` is the response message type, which need to
+    /// match the types that `parts` were built with. See the [`host`](crate::host) module for more
+    /// info.
+    /// ### Panics
+    /// panics if the server can't be started.
+    fn host_server<C, R>(
+        &mut self,
+        server: App,
+        parts: MsgTableParts,
+        config: Config,
+        con_msg: C,
+    ) -> &mut Self
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync;
+
+    /// Runs `server` as a sub app, with a [`Server`] on the loopback interface, and connects to it
+    /// with the connection message `con_msg`.
+    ///
+    /// Same as [`host_server()`](App::host_server), but doesn't panic if the server can't be
+    /// started.
+    fn try_host_server<C, R>(
+        &mut self,
+        server: App,
+        parts: MsgTableParts,
+        config: Config,
+        con_msg: C,
+    ) -> io::Result<&mut Self>
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync;
+
+    /// Adds everything needed for clients to request spawns of type `R` for predicted entities.
+    ///
+    /// Registers the request type into `table`, along with the response type the first time this
+    /// is called. See the [`spawn`](crate::spawn) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `R` is already registered as a spawn request in the table.
+    fn add_spawn_request<R>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        R: Clone + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed for clients to request spawns of type `R` for predicted entities.
+    ///
+    /// Same as [`add_spawn_request()`](App::add_spawn_request), but doesn't panic in the event of
+    /// a [`MsgRegError`].
+    fn try_add_spawn_request<R>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        R: Clone + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to send inputs of type `I` from the clients to the server.
+    ///
+    /// Registers the input message type into `table`, adds the [`LocalInput<I>`] and
+    /// [`PlayerInputs<I>`] resources, and adds the systems that send and receive them. See the
+    /// [`input`](crate::input) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `I` is already registered as an input in the table.
+    fn add_input<I>(&mut self, table: &mut impl MsgTableLike, transport: Transport) -> &mut Self
+    where
+        I: Clone + Default + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to send inputs of type `I` from the clients to the server.
+    ///
+    /// Same as [`add_input()`](App::add_input), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_input<I>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        I: Clone + Default + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to send ordered commands of type `C` from the clients to the server.
+    ///
+    /// Registers the command and acknowledgment messages into `table` over TCP, adds the
+    /// [`CommandQueue<C>`] and [`ClientCommands<C>`] resources and the [`CommandAcked<C>`] event,
+    /// and adds the systems that send and receive them. See the [`command`](crate::command)
+    /// module for more info.
+    ///
+    /// ### Panics
+    /// panics if `C` is already registered as a command in the table.
+    fn add_commands<C>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds everything needed to send ordered commands of type `C` from the clients to the server.
+    ///
+    /// Same as [`add_commands()`](App::add_commands), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_commands<C>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        C: Clone + Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds the server-authoritative [`KinematicController`](crate::movement::KinematicController) movement.
+    ///
+    /// Registers [`MoveInput`] as an input (see [`add_input()`](App::add_input)), along with the
+    /// message the server sends back, and adds the systems that move the characters. See the
+    /// [`movement`](crate::movement) module for more info.
+    ///
+    /// ### Panics
+    /// panics if the movement is already added.
+    fn add_movement(&mut self, table: &mut impl MsgTableLike) -> &mut Self;
+
+    /// Adds the server-authoritative [`KinematicController`](crate::movement::KinematicController) movement.
+    ///
+    /// Same as [`add_movement()`](App::add_movement), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_movement(&mut self, table: &mut impl MsgTableLike)
+        -> Result<&mut Self, MsgRegError>;
+
+    /// Adds the acks that keep the [`NetAcks`](crate::ack::NetAcks) resource up to date.
+    ///
+    /// Registers the ack message type into `table`. See the [`ack`](crate::ack) module for more
+    /// info.
+    ///
+    /// ### Panics
+    /// panics if acks are already enabled.
+    fn enable_acks(&mut self, table: &mut impl MsgTableLike) -> &mut Self;
+
+    /// Adds the acks that keep the [`NetAcks`](crate::ack::NetAcks) resource up to date.
+    ///
+    /// Same as [`enable_acks()`](App::enable_acks), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_enable_acks(&mut self, table: &mut impl MsgTableLike) -> Result<&mut Self, MsgRegError>;
+
+    /// Adds a filter that decides whether client `cid` may receive the updates of `entity`,
+    /// with read access to the whole world.
+    ///
+    /// See the [`filter`](crate::filter) module for more info.
+    fn add_send_filter(
+        &mut self,
+        filter: impl Fn(&World, Entity, CId) -> bool + Send + Sync + 'static,
+    ) -> &mut Self;
+
+    /// Adds a user-defined channel named `name`.
+    ///
+    /// Messages can then be sent on it using the [`NetChannels`] resource.
+    fn add_net_channel(&mut self, name: &'static str, config: ChannelConfig) -> &mut Self;
+
+    /// Registers message type `T` into `table` so that it can be sent on user-defined channels.
+    ///
+    /// ### Panics
+    /// panics if `T` is already registered as a channel message in the table.
+    fn register_channel_msg<T>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Registers message type `T` into `table` so that it can be sent on user-defined channels.
+    ///
+    /// Same as [`register_channel_msg()`](App::register_channel_msg), but doesn't panic in the
+    /// event of a [`MsgRegError`].
+    fn try_register_channel_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Adds `middleware` to the end of the [`NetPipeline`] that piped messages go through.
+    ///
+    /// See the [`middleware`](crate::middleware) module for more info.
+    fn add_middleware(&mut self, middleware: impl Middleware) -> &mut Self;
+
+    /// Registers message type `T` into `table` so that it can be sent through the
+    /// [`NetPipeline`] with [`PipedNet`](crate::middleware::PipedNet).
+    ///
+    /// ### Panics
+    /// panics if `T` is already registered as a piped message in the table.
+    fn register_piped_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Registers message type `T` into `table` so that it can be sent through the
+    /// [`NetPipeline`] with [`PipedNet`](crate::middleware::PipedNet).
+    ///
+    /// Same as [`register_piped_msg()`](App::register_piped_msg), but doesn't panic in the
+    /// event of a [`MsgRegError`].
+    fn try_register_piped_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Registers user message type `T` into `table`, and records it in the
+    /// [`MsgSchema`](crate::schema::MsgSchema) so that it is verified when clients connect.
+    ///
+    /// A [`SortedMsgTable`] identifies `T` by its type name; use
+    /// [`register_msg_as()`](App::register_msg_as) to choose the id. See the
+    /// [`schema`](crate::schema) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `T` is already registered in the table.
+    fn register_msg<T>(&mut self, table: &mut impl MsgTableLike, transport: Transport) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Registers user message type `T` into `table`, and records it in the
+    /// [`MsgSchema`](crate::schema::MsgSchema).
+    ///
+    /// Same as [`register_msg()`](App::register_msg), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_register_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Registers user message type `T` into `table` with the identifier `id`, and records it in
+    /// the [`MsgSchema`](crate::schema::MsgSchema) so that it is verified when clients connect.
+    ///
+    /// A [`MsgTable`] has no ids, so it ignores `id`. See the [`schema`](crate::schema) module for
+    /// more info.
+    ///
+    /// ### Panics
+    /// panics if `T` or `id` is already registered in the table.
+    fn register_msg_as<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+        id: &str,
+    ) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+
+    /// Registers user message type `T` into `table` with the identifier `id`, and records it in
+    /// the [`MsgSchema`](crate::schema::MsgSchema).
+    ///
+    /// Same as [`register_msg_as()`](App::register_msg_as), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_register_msg_as<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+        id: &str,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+}
+
+impl AppExt for App {
+    /// Adds everything needed to sync component `T` using message type `M`.
+    ///
+    /// Registers the type `NetCompMsg<M>` into `table` and adds the system required to sync
+    /// components of type `T`, using type `M` to send.
+    ///
+    /// Types `T` and `M` ***can*** be the same type; if the component `T` implements all the
+    /// required traits, you may use it as `M`.
+    ///
+    /// `table` can be a [`MsgTable`] or a [`SortedMsgTable`], which identifies the messages by
+    /// the type name of `M`. See [`MsgTableLike`] for more info.
+    ///
+    /// ### Panics
+    /// panics if `NetCompMsg<M>` is already registered in the table
+    /// (If you call this method twice with the same `M`).
+    fn sync_comp<T, M>(&mut self, table: &mut impl MsgTableLike, transport: Transport) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp::<T, M>(table, transport).unwrap()
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`.
+    ///
+    /// Same as [`sync_comp()`](App::sync_comp), but doesn't panic in the event of a [`MsgRegError`].
+    fn try_sync_comp<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp_cfg(table, SyncConfig::<T, M>::new(transport))
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, into a
+    /// [`SortedMsgTable`].
+    ///
+    /// Same as [`sync_comp()`](App::sync_comp), which takes a [`SortedMsgTable`] as well.
+    fn sync_comp_sorted<T, M>(
+        &mut self,
+        table: &mut SortedMsgTable,
+        transport: Transport,
+    ) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.sync_comp::<T, M>(table, transport)
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, into a
+    /// [`SortedMsgTable`].
+    ///
+    /// Same as [`try_sync_comp()`](App::try_sync_comp), which takes a [`SortedMsgTable`] as well.
+    fn try_sync_comp_sorted<T, M>(
+        &mut self,
+        table: &mut SortedMsgTable,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp::<T, M>(table, transport)
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`.
+    ///
+    /// Same as [`sync_comp()`](App::sync_comp), but with the options of the [`SyncConfig`], like
+    /// the send rate and threshold, instead of only the transport.
+    ///
+    /// ### Panics
+    /// panics if `NetCompMsg<M>` is already registered in the table
+    /// (If you call this method twice with the same `M`).
+    fn sync_comp_cfg<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+    ) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp_cfg::<T, M>(table, config).unwrap()
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`.
+    ///
+    /// Same as [`sync_comp_cfg()`](App::sync_comp_cfg), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_sync_comp_cfg<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp_cfg_as(table, config, std::any::type_name::<M>())
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, with the stable
+    /// `id` on the wire.
+    ///
+    /// Same as [`sync_comp()`](App::sync_comp), but the messages are registered under ids made
+    /// from `id`, such as `"game.transform"`, instead of from the type name of `M`. The type name
+    /// changes when `M` is renamed or moved to another module, so ids that are defined in one
+    /// shared place keep the client and server in agreement across differently organized
+    /// codebases and versions. A [`MsgTable`] has no ids, so it ignores `id`.
+    ///
+    /// ### Panics
+    /// panics if `NetCompMsg<M>` or `id` is already registered in the table
+    /// (If you call this method twice with the same `M` or `id`).
+    fn sync_comp_as<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+        id: &str,
+    ) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp_as::<T, M>(table, transport, id).unwrap()
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, with the stable
+    /// `id` on the wire.
+    ///
+    /// Same as [`sync_comp_as()`](App::sync_comp_as), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_sync_comp_as<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+        id: &str,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp_cfg_as(table, SyncConfig::<T, M>::new(transport), id)
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`, with the stable `id` on the wire.
+    ///
+    /// Same as [`sync_comp_as()`](App::sync_comp_as), but with the options of the
+    /// [`SyncConfig`].
+    ///
+    /// ### Panics
+    /// panics if `NetCompMsg<M>` or `id` is already registered in the table
+    /// (If you call this method twice with the same `M` or `id`).
+    fn sync_comp_cfg_as<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+        id: &str,
+    ) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_sync_comp_cfg_as::<T, M>(table, config, id)
+            .unwrap()
+    }
+
+    /// Adds everything needed to sync component `T` using message type `M`, as configured by
+    /// `config`, with the stable `id` on the wire.
+    ///
+    /// Same as [`sync_comp_cfg_as()`](App::sync_comp_cfg_as), but doesn't panic in the event of
+    /// a [`MsgRegError`].
+    fn try_sync_comp_cfg_as<T, M>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<T, M>,
+        id: &str,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        {
+            let mut schema = self.world.get_resource_or_insert_with(MsgSchema::default);
+            register_sync_msgs::<M>(&mut schema, table, config.transport, id)?;
+        }
+        Ok(add_sync_systems::<T, M>(self, &config))
+    }
+
+    /// Adds everything needed to sync all components of bundle `B`, with the shared `config`.
+    ///
+    /// Same as calling [`sync_comp_cfg()`](App::sync_comp_cfg) for every component of the
+    /// bundle. See the [`bundle`](crate::bundle) module for more info.
+    ///
+    /// ### Panics
+    /// panics if the message type of any of the components is already registered in the table.
+    fn sync_bundle<B: SyncBundle>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<()>,
+    ) -> &mut Self {
+        self.try_sync_bundle::<B>(table, config).unwrap()
+    }
+
+    /// Adds everything needed to sync all components of bundle `B`, with the shared `config`.
+    ///
+    /// Same as [`sync_bundle()`](App::sync_bundle), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_sync_bundle<B: SyncBundle>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        config: SyncConfig<()>,
+    ) -> Result<&mut Self, MsgRegError> {
+        B::sync(self, table, &config)?;
+        Ok(self)
+    }
+
+    /// Sets the function used to apply a received message of type `M` to component `T`.
+    ///
+    /// By default, the message is cloned and converted into `T`. For large message types, this
+    /// can be used to update the component in place instead, avoiding a clone per entity per frame.
+    ///
+    /// ### Panics
+    /// panics if `T` is not synced using `M`
+    /// (If [`sync_comp()`](App::sync_comp) wasn't called before this).
+    fn set_sync_apply<T, M>(&mut self, apply: fn(&M, &mut T)) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.world.resource_mut::<SyncInfo<T, M>>().apply = apply;
+        self
+    }
+
+    /// Adds a validator for updates to component `T` sent by clients.
+    ///
+    /// The validator runs on the server before a received message is applied, and can accept,
+    /// clamp, or reject it. Clamped and rejected updates send a [`SyncViolation`] event. See the
+    /// [`validate`](crate::validate) module for more info.
+    fn add_sync_validator<T, M, F>(&mut self, validator: F) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        F: Fn(&Update, &M, &T) -> Validation<M> + Send + Sync + 'static,
+    {
+        self.init_resource::<SyncValidators<T, M>>();
+        self.add_event::<SyncViolation>();
+        self.world
+            .resource_mut::<SyncValidators<T, M>>()
+            .add(validator);
+        self
+    }
+
+    /// Detects entities whose component `T` expects updates, but hasn't received one in
+    /// `window` seconds, sending [`NetStale`](crate::stale::NetStale) and
+    /// [`NetResumed`](crate::stale::NetResumed) events.
+    ///
+    /// See the [`stale`](crate::stale) module for more info.
+    fn detect_stale<T, M>(&mut self, window: f32) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        self.init_resource::<NetStaleness>();
+        self.add_event::<NetStale>();
+        self.add_event::<NetResumed>();
+        self.world
+            .resource_mut::<NetStaleness>()
+            .set_window::<T>(window);
+        self.add_system_to_stage(CoreStage::PreUpdate, detect_stale::<T, M>.label(NetLabel))
+    }
+
+    /// Despawns entities that stay stale for longer than `timeout` seconds, so ghost entities
+    /// don't pile up when their despawn messages are lost.
+    ///
+    /// This needs [`detect_stale`](AppExt::detect_stale) to find the stale entities. See the
+    /// [`stale`](crate::stale) module for more info.
+    fn despawn_stale_after(&mut self, timeout: f32) -> &mut Self {
+        set_stale_timeout(self, timeout, StaleTimeout::Despawn)
+    }
+
+    /// Calls `callback` on entities that stay stale for longer than `timeout` seconds, instead
+    /// of despawning them.
+    ///
+    /// This needs [`detect_stale`](AppExt::detect_stale) to find the stale entities. See the
+    /// [`stale`](crate::stale) module for more info.
+    fn on_stale_timeout(
+        &mut self,
+        timeout: f32,
+        callback: impl Fn(Entity, &mut Commands) + Send + Sync + 'static,
+    ) -> &mut Self {
+        set_stale_timeout(self, timeout, StaleTimeout::Call(Box::new(callback)))
+    }
+
+    /// Sends synced components only `rate` times per second, instead of every frame.
+    ///
+    /// The rate can be changed later in the [`SendCadence`] resource. See the
+    /// [`cadence`](crate::cadence) module for more info.
+    fn set_send_cadence(&mut self, rate: f32) -> &mut Self {
+        if !self.world.contains_resource::<SendCadence>() {
+            self.add_system_to_stage(CoreStage::First, advance_cadence.label(NetLabel));
+        }
+        self.insert_resource(SendCadence::new(rate))
+    }
+
+    /// Adds the [`NetStage`]s after stage `recv_after` and stage `send_after`, instead of after
+    /// [`CoreStage::First`] and [`CoreStage::Last`].
+    ///
+    /// This has to be called before the [`ClientPlugin`] or [`ServerPlugin`] is added, which add
+    /// the stages at their default places otherwise. See the [`stage`](crate::stage) module for
+    /// more info.
+    fn add_net_stages(
+        &mut self,
+        recv_after: impl StageLabel,
+        send_after: impl StageLabel,
+    ) -> &mut Self {
+        if !add_net_stages(self, recv_after, send_after) {
+            warn!("The network stages were already added; add them before the plugins.");
+        }
+        self
+    }
+
+    /// Runs [`NetStage`] `stage` once, without the rest of the schedule.
+    ///
+    /// This steps the network on its own, such as in tests. Nothing happens if the stage hasn't
+    /// been added.
+    fn run_net_stage(&mut self, stage: NetStage) -> &mut Self {
+        if let Some(stage) = self.schedule.get_stage_mut::<SystemStage>(stage) {
+            stage.run(&mut self.world);
+        }
+        self
+    }
+
+    /// Adds a sanitizer for updates to component `T` received from the server.
+    ///
+    /// The sanitizer runs on the client before a received message is applied, and can accept,
+    /// transform, or reject it, the same way a validator does with
+    /// [`add_sync_validator`](AppExt::add_sync_validator). See the
+    /// [`validate`](crate::validate) module for more info.
+    fn add_recv_sanitizer<T, M, F>(&mut self, sanitizer: F) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        F: Fn(&Update, &M, &T) -> Validation<M> + Send + Sync + 'static,
+    {
+        self.init_resource::<RecvSanitizers<T, M>>();
+        self.world
+            .resource_mut::<RecvSanitizers<T, M>>()
+            .add(sanitizer);
+        self
+    }
+
+    /// Enables sending snapshots of the networked world to clients that join late.
+    ///
+    /// Registers the snapshot message type into `table`. See the [`snapshot`](crate::snapshot)
+    /// module for more info.
+    ///
+    /// ### Panics
+    /// panics if snapshots are already enabled in the table.
+    fn enable_snapshots(&mut self, table: &mut impl MsgTableLike) -> &mut Self {
+        self.try_enable_snapshots(table).unwrap()
+    }
+
+    /// Enables sending snapshots of the networked world to clients that join late.
+    ///
+    /// Same as [`enable_snapshots()`](App::enable_snapshots), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_enable_snapshots(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError> {
+        register::<WorldSnapshot>(self, table, Transport::TCP, "bevy-pigeon::snapshot")?;
+        Ok(add_snapshot_systems(self))
+    }
+
+    /// Loads the networked world from the file at `path` at startup, if it exists.
+    ///
+    /// The file is saved with [`save_world_file`](crate::persist::save_world_file). See the
+    /// [`persist`](crate::persist) module for more info.
+    fn load_world_on_startup(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.insert_resource(WorldFile { path: path.into() })
+            .add_event::<WorldLoaded>()
+            .add_startup_system(load_world_at_startup.label(NetLabel))
+    }
+
+    /// Adds an [`AsyncBridge<T>`](crate::runtime::AsyncBridge) with room for `capacity`
+    /// messages, which sends the messages from async tasks as `T` events. Requires the `async`
+    /// feature.
+    ///
+    /// See the [`runtime`](crate::runtime) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `capacity` is 0.
+    #[cfg(feature = "async")]
+    fn add_async_bridge<T: Send + Sync + 'static>(&mut self, capacity: usize) -> &mut Self {
+        self.insert_resource(AsyncBridge::<T>::new(capacity))
+            .add_event::<T>()
+            .add_system_to_stage(CoreStage::First, drain_bridge::<T>.label(NetLabel))
+    }
+
+    /// Resolves the [`NetEntityRef`](crate::mapping::NetEntityRef)s in component `T` whenever it
+    /// changes.
+    ///
+    /// See the [`mapping`](crate::mapping) module for more info.
+    fn map_net_entities<T: MapNetEntities + Component>(&mut self) -> &mut Self {
+        self.init_resource::<NetEntityMap>();
+        self.add_system_to_stage(
+            CoreStage::PreUpdate,
+            resolve_net_entities::<T>
+                .label(NetLabel)
+                .after(update_net_entity_map),
+        )
+    }
+
+    /// Gives the entities with a [`NetKey`](crate::key::NetKey) the [`NetEntity`] derived from
+    /// their key, so entities that exist on every instance, like the ones placed in a level, are
+    /// matched up without hand-assigned ids.
+    ///
+    /// See the [`key`](crate::key) module for more info.
+    fn add_net_keys(&mut self) -> &mut Self {
+        self.add_event::<KeyCollision>()
+            .add_system_to_stage(CoreStage::First, assign_key_ids.label(NetLabel))
+    }
+
+    /// Extrapolates component `T`, synced using message type `M`, on the entities with an
+    /// [`Extrapolate<T>`](crate::extrapolate::Extrapolate).
+    ///
+    /// See the [`extrapolate`](crate::extrapolate) module for more info.
+    fn extrapolate_comp<T, M>(&mut self) -> &mut Self
+    where
+        T: Extrapolatable + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        self.add_system_to_stage(CoreStage::PreUpdate, extrapolate::<T, M>.label(NetLabel))
+    }
+
+    /// Interpolates component `T`, synced using message type `M`, on the entities with an
+    /// [`Interpolate<T>`](crate::interpolate::Interpolate).
+    ///
+    /// See the [`interpolate`](crate::interpolate) module for more info.
+    fn interpolate_comp<T, M>(&mut self) -> &mut Self
+    where
+        T: Extrapolatable + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        self.add_event::<InterpolationDelayChanged>()
+            .add_system_to_stage(CoreStage::PreUpdate, interpolate::<T, M>.label(NetLabel))
+    }
+
+    /// Adds a [`TickTimeline`](crate::interpolate::TickTimeline) for a server that ticks
+    /// `tick_rate` times per second, which the [`Interpolate`](crate::interpolate::Interpolate)s
+    /// that use ticks follow.
+    ///
+    /// See the [`interpolate`](crate::interpolate) module for more info.
+    fn add_tick_timeline(&mut self, tick_rate: f32) -> &mut Self {
+        init_net_stages(self);
+        self.insert_resource(TickTimeline::new(tick_rate))
+            .add_system_to_stage(NetStage::Receive, advance_timeline.after(NetLabel))
+    }
+
+    /// Adds a [`JitterBuffer<M>`](crate::jitter::JitterBuffer) for the synced components sent as
+    /// message type `M`.
+    ///
+    /// See the [`jitter`](crate::jitter) module for more info.
+    fn add_jitter_buffer<M: Clone + Any + Send + Sync>(&mut self) -> &mut Self {
+        self.init_resource::<JitterBuffer<M>>();
+        remove_on_disconnect::<JitterBuffer<M>>(self)
+    }
+
+    /// Applies at most `max_msgs` received updates of message type `M` per frame, carrying the
+    /// rest over to the next frames.
+    ///
+    /// See the [`budget`](crate::budget) module for more info.
+    fn add_recv_budget<M: Clone + Any + Send + Sync>(&mut self, max_msgs: usize) -> &mut Self {
+        self.insert_resource(RecvBudget::<M>::new(max_msgs))
+            .add_event::<RecvOverflow>()
+    }
+
+    /// Applies the received updates of component `T` over several frames, spending at most
+    /// `max_time` per frame, instead of all at once.
+    ///
+    /// See the [`deferred`](crate::deferred) module for more info.
+    fn defer_apply<T, M>(&mut self, max_time: Duration) -> &mut Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.insert_resource(DeferredApply::<T, M>::new(max_time))
+            .add_event::<NetUpdated<T>>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                apply_deferred_updates::<T, M>.label(NetLabel),
+            )
+    }
+
+    /// Stages the received updates of all components, and applies them in one exclusive system
+    /// at the end of [`NetStage::Receive`], so other systems never see half of them applied.
+    ///
+    /// See the [`staging`](crate::staging) module for more info.
+    fn stage_net_updates(&mut self) -> &mut Self {
+        init_net_stages(self);
+        self.init_resource::<StagedUpdates>()
+            .add_system_to_stage(NetStage::Receive, apply_staged_updates.after(NetLabel))
+    }
+
+    /// Lowers the rate that the server sends updates to clients with a congested connection,
+    /// and raises it again once the connection recovers.
+    ///
+    /// This needs acks, enabled with [`enable_acks`](App::enable_acks). See the
+    /// [`congestion`](crate::congestion) module for more info.
+    fn adaptive_send_rate(&mut self, config: AdaptiveSendRate) -> &mut Self {
+        self.insert_resource(config)
+            .init_resource::<SendRates>()
+            .add_system_to_stage(CoreStage::PostUpdate, update_send_rates.label(NetLabel))
+    }
+
+    /// Sends the entities that are far from a client's viewpoint to that client less often.
+    ///
+    /// See the [`priority`](crate::priority) module for more info.
+    fn prioritize_by_distance(&mut self, priority: DistancePriority) -> &mut Self {
+        self.insert_resource(priority).add_system_to_stage(
+            CoreStage::PostUpdate,
+            update_priorities
+                .label(NetLabel)
+                .after(TransformSystem::TransformPropagate),
+        )
+    }
+
+    /// Sends the entities in a lower [`LodTier`](crate::lod::LodTier) to some clients, as
+    /// decided by `lod`.
+    ///
+    /// See the [`lod`](crate::lod) module for more info.
+    fn sync_lod(&mut self, lod: SyncLod) -> &mut Self {
+        self.insert_resource(lod)
+            .add_system_to_stage(CoreStage::PostUpdate, update_lod.label(NetLabel))
+    }
+
+    /// Sends component `T` as the reduced message type `R` to the clients that have the entity
+    /// in the [`Reduced`](crate::lod::LodTier::Reduced) tier.
+    ///
+    /// Registers the reduced message type into `table`. `T` needs to be synced using `M` first.
+    /// See the [`lod`](crate::lod) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `R` is already registered as a reduced message type in the table.
+    fn add_lod_variant<T, M, R>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        T: Clone + Into<M> + Into<R> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        R: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_add_lod_variant::<T, M, R>(table).unwrap()
+    }
+
+    /// Sends component `T` as the reduced message type `R` to the clients that have the entity
+    /// in the [`Reduced`](crate::lod::LodTier::Reduced) tier.
+    ///
+    /// Same as [`add_lod_variant()`](App::add_lod_variant), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_lod_variant<T, M, R>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Clone + Into<M> + Into<R> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+        R: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        let id = "bevy-pigeon::reduced::".to_owned() + std::any::type_name::<R>();
+        register::<ReducedNetCompMsg<R>>(self, table, Transport::UDP, &id)?;
+        Ok(add_lod_systems::<T, M, R>(self))
+    }
+
+    /// Sets the connection message type `C` and response message type `R` that the `MsgTable`
+    /// is built with.
+    ///
+    /// On the server, new connections are then handled using the
+    /// [`ConnectionHook<C, R>`](crate::connect::ConnectionHook), which accepts every connection
+    /// if it isn't inserted, so don't call `Server::handle_new_cons` yourself. See the
+    /// [`connect`](crate::connect) module for more info.
+    fn set_connection_msgs<C, R>(&mut self) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync,
+        R: Clone + Default + Any + Send + Sync,
+    {
+        self.init_resource::<ConnectionHook<C, R>>()
+            .add_event::<NetConnected<C>>()
+            .add_event::<ConnectionResponse<R>>()
+            .add_system_to_stage(
+                CoreStage::First,
+                handle_connections::<C, R>
+                    .label(NetLabel)
+                    .label(AcceptLabel)
+                    .after(server_tick)
+                    .before(spawn_players),
+            )
+            .add_system_to_stage(
+                CoreStage::First,
+                recv_connection_response::<R>
+                    .label(NetLabel)
+                    .after(poll_connecting),
+            )
+    }
+
+    /// Sets the connection message type to [`Versioned<C>`](crate::version::Versioned) and the
+    /// response message type to [`Handshake<R>`](crate::version::Handshake), which the `MsgTable`
+    /// is built with, with the given game version.
+    ///
+    /// This is the same as [`set_connection_msgs`](App::set_connection_msgs), but the server
+    /// first rejects the clients with an incompatible protocol version. See the
+    /// [`version`](crate::version) module for more info.
+    fn set_versioned_connection_msgs<C, R>(&mut self, game_version: u32) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync,
+        R: Clone + Default + Any + Send + Sync,
+    {
+        self.insert_resource(ProtocolVersion::current(game_version))
+            .init_resource::<ConnectionHook<C, R>>()
+            .add_event::<NetConnected<C>>()
+            .add_event::<ConnectionResponse<R>>()
+            .add_event::<ConnectionRejected>()
+            .add_system_to_stage(
+                CoreStage::First,
+                handle_versioned_connections::<C, R>
+                    .label(NetLabel)
+                    .label(AcceptLabel)
+                    .after(server_tick)
+                    .before(spawn_players),
+            )
+            .add_system_to_stage(
+                CoreStage::First,
+                recv_handshake::<R>.label(NetLabel).after(poll_connecting),
+            )
+    }
+
+    /// Spawns the bundle returned by `factory` as the player entity of every client that connects
+    /// to the server, and despawns it when the client disconnects.
+    ///
+    /// See the [`player`](crate::player) module for more info.
+    fn spawn_players<B, F>(&mut self, factory: F) -> &mut Self
+    where
+        B: Bundle,
+        F: Fn(CId) -> B + Send + Sync + 'static,
+    {
+        self.insert_resource(PlayerFactory::new(factory))
+            .add_system_to_stage(
+                CoreStage::First,
+                spawn_players
+                    .label(NetLabel)
+                    .after(server_tick)
+                    .after(server_disconnects),
+            )
+    }
+
+    /// Keeps the entities owned by disconnected clients for `grace_period` seconds, so that they
+    /// can resume their session by reconnecting with the same session id.
+    ///
+    /// `identity` gets the session id from the connection message of type `C`. Needs to be called
+    /// after [`set_connection_msgs`](App::set_connection_msgs). See the
+    /// [`session`](crate::session) module for more info.
+    fn enable_sessions<C, F>(&mut self, grace_period: f64, identity: F) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync,
+        F: Fn(&C) -> Option<u64> + Send + Sync + 'static,
+    {
+        self.insert_resource(Sessions::new(grace_period))
+            .insert_resource(SessionIdentity::<C>::new(identity))
+            .add_event::<SessionResumed>()
+            .add_event::<SessionExpired>()
+            .add_system_to_stage(
+                CoreStage::First,
+                suspend_sessions
+                    .label(NetLabel)
+                    .after(server_disconnects)
+                    .before(spawn_players),
+            )
+            .add_system_to_stage(
+                CoreStage::First,
+                start_sessions::<C>
+                    .label(NetLabel)
+                    .after(AcceptLabel)
+                    .after(suspend_sessions)
+                    .before(spawn_players),
+            )
+    }
+
+    /// Runs `server` as a sub app, with a [`Server`] on the loopback interface, and connects to it
+    /// with the connection message `con_msg`.
+    ///
+    /// `C` is the connection message type and `R` is the response message type, which need to
+    /// match the types that `parts` were built with. See the [`host`](crate::host) module for more
+    /// info.
+    /// ### Panics
+    /// panics if the server can't be started.
+    fn host_server<C, R>(
+        &mut self,
+        server: App,
+        parts: MsgTableParts,
+        config: Config,
+        con_msg: C,
+    ) -> &mut Self
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        self.try_host_server::<C, R>(server, parts, config, con_msg)
+            .unwrap()
+    }
+
+    /// Runs `server` as a sub app, with a [`Server`] on the loopback interface, and connects to it
+    /// with the connection message `con_msg`.
+    ///
+    /// Same as [`host_server()`](App::host_server), but doesn't panic if the server can't be
+    /// started.
+    fn try_host_server<C, R>(
+        &mut self,
+        server: App,
+        parts: MsgTableParts,
+        config: Config,
+        con_msg: C,
+    ) -> io::Result<&mut Self>
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        host::<C, R>(self, server, parts, config, con_msg)?;
+        Ok(self)
+    }
+
+    /// Adds everything needed for clients to request spawns of type `R` for predicted entities.
+    ///
+    /// Registers the request type into `table`, along with the response type the first time this
+    /// is called. See the [`spawn`](crate::spawn) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `R` is already registered as a spawn request in the table.
+    fn add_spawn_request<R>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        R: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_add_spawn_request::<R>(table).unwrap()
+    }
+
+    /// Adds everything needed for clients to request spawns of type `R` for predicted entities.
+    ///
+    /// Same as [`add_spawn_request()`](App::add_spawn_request), but doesn't panic in the event of
+    /// a [`MsgRegError`].
+    fn try_add_spawn_request<R>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        R: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        if !self.world.contains_resource::<ProvisionalIds>() {
+            register::<SpawnResponseMsg>(
+                self,
+                table,
+                Transport::TCP,
+                "bevy-pigeon::spawn::response",
+            )?;
+        }
+        let id = "bevy-pigeon::spawn::request::".to_owned() + std::any::type_name::<R>();
+        register::<SpawnRequestMsg<R>>(self, table, Transport::TCP, &id)?;
+        Ok(add_spawn_systems::<R>(self))
+    }
+
+    /// Adds everything needed to send inputs of type `I` from the clients to the server.
+    ///
+    /// Registers the input message type into `table`, adds the [`LocalInput<I>`] and
+    /// [`PlayerInputs<I>`] resources, and adds the systems that send and receive them. See the
+    /// [`input`](crate::input) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `I` is already registered as an input in the table.
+    fn add_input<I>(&mut self, table: &mut impl MsgTableLike, transport: Transport) -> &mut Self
+    where
+        I: Clone + Default + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_add_input::<I>(table, transport).unwrap()
+    }
+
+    /// Adds everything needed to send inputs of type `I` from the clients to the server.
+    ///
+    /// Same as [`add_input()`](App::add_input), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_input<I>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        I: Clone + Default + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        let id = "bevy-pigeon::input::".to_owned() + std::any::type_name::<I>();
+        register::<InputMsg<I>>(self, table, transport, &id)?;
+        Ok(add_input_systems::<I>(self))
+    }
+
+    /// Adds everything needed to send ordered commands of type `C` from the clients to the server.
+    ///
+    /// Registers the command and acknowledgment messages into `table` over TCP, adds the
+    /// [`CommandQueue<C>`] and [`ClientCommands<C>`] resources and the [`CommandAcked<C>`] event,
+    /// and adds the systems that send and receive them. See the [`command`](crate::command)
+    /// module for more info.
+    ///
+    /// ### Panics
+    /// panics if `C` is already registered as a command in the table.
+    fn add_commands<C>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        C: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_add_commands::<C>(table).unwrap()
+    }
+
+    /// Adds everything needed to send ordered commands of type `C` from the clients to the server.
+    ///
+    /// Same as [`add_commands()`](App::add_commands), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_commands<C>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        C: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        let id = "bevy-pigeon::command::".to_owned() + std::any::type_name::<C>();
+        register::<CommandMsg<C>>(self, table, Transport::TCP, &id)?;
+        let id = "bevy-pigeon::command_ack::".to_owned() + std::any::type_name::<C>();
+        register::<CommandAckMsg<C>>(self, table, Transport::TCP, &id)?;
+        Ok(add_command_systems::<C>(self))
+    }
+
+    /// Adds the server-authoritative [`KinematicController`](crate::movement::KinematicController) movement.
+    ///
+    /// Registers [`MoveInput`] as an input (see [`add_input()`](App::add_input)), along with the
+    /// message the server sends back, and adds the systems that move the characters. See the
+    /// [`movement`](crate::movement) module for more info.
+    ///
+    /// ### Panics
+    /// panics if the movement is already added.
+    fn add_movement(&mut self, table: &mut impl MsgTableLike) -> &mut Self {
+        self.try_add_movement(table).unwrap()
+    }
+
+    /// Adds the server-authoritative [`KinematicController`](crate::movement::KinematicController) movement.
+    ///
+    /// Same as [`add_movement()`](App::add_movement), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_add_movement(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError> {
+        register::<MoveState>(self, table, Transport::UDP, "bevy-pigeon::movement::state")?;
+        self.try_add_input::<MoveInput>(table, Transport::UDP)?;
+        Ok(add_movement_systems(self))
+    }
+
+    /// Adds the acks that keep the [`NetAcks`](crate::ack::NetAcks) resource up to date.
+    ///
+    /// Registers the ack message type into `table`. See the [`ack`](crate::ack) module for more
+    /// info.
+    ///
+    /// ### Panics
+    /// panics if acks are already enabled.
+    fn enable_acks(&mut self, table: &mut impl MsgTableLike) -> &mut Self {
+        self.try_enable_acks(table).unwrap()
+    }
+
+    /// Adds the acks that keep the [`NetAcks`](crate::ack::NetAcks) resource up to date.
+    ///
+    /// Same as [`enable_acks()`](App::enable_acks), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_enable_acks(&mut self, table: &mut impl MsgTableLike) -> Result<&mut Self, MsgRegError> {
+        register::<AckMsg>(self, table, Transport::UDP, "bevy-pigeon::ack")?;
+        Ok(add_ack_systems(self))
+    }
+
+    /// Adds a filter that decides whether client `cid` may receive the updates of `entity`,
+    /// with read access to the whole world.
+    ///
+    /// See the [`filter`](crate::filter) module for more info.
+    fn add_send_filter(
+        &mut self,
+        filter: impl Fn(&World, Entity, CId) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        if !self.world.contains_resource::<NetFilters>() {
+            self.init_resource::<NetFilters>();
+            self.add_system_to_stage(CoreStage::Last, evaluate_send_filters.before(NetLabel));
+        }
+        self.world.resource_mut::<NetFilters>().add(filter);
+        self
+    }
+
+    /// Adds a user-defined channel named `name`.
+    ///
+    /// Messages can then be sent on it using the [`NetChannels`] resource.
+    fn add_net_channel(&mut self, name: &'static str, config: ChannelConfig) -> &mut Self {
+        if !self.world.contains_resource::<NetChannels>() {
+            self.init_resource::<NetChannels>();
+            self.add_system_to_stage(CoreStage::Last, flush_channels.label(NetLabel));
+        }
+        self.world.resource_mut::<NetChannels>().add(name, config);
+        self
+    }
+
+    /// Registers message type `T` into `table` so that it can be sent on user-defined channels.
+    ///
+    /// ### Panics
+    /// panics if `T` is already registered as a channel message in the table.
+    fn register_channel_msg<T>(&mut self, table: &mut impl MsgTableLike) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_register_channel_msg::<T>(table).unwrap()
+    }
+
+    /// Registers message type `T` into `table` so that it can be sent on user-defined channels.
+    ///
+    /// Same as [`register_channel_msg()`](App::register_channel_msg), but doesn't panic in the
+    /// event of a [`MsgRegError`].
+    fn try_register_channel_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        let id = "bevy-pigeon::channel::reliable::".to_owned() + std::any::type_name::<T>();
+        register::<ReliableChannelMsg<T>>(self, table, Transport::TCP, &id)?;
+        let id = "bevy-pigeon::channel::unreliable::".to_owned() + std::any::type_name::<T>();
+        register::<UnreliableChannelMsg<T>>(self, table, Transport::UDP, &id)?;
+        Ok(self)
+    }
+
+    /// Adds `middleware` to the end of the [`NetPipeline`] that piped messages go through.
+    ///
+    /// See the [`middleware`](crate::middleware) module for more info.
+    fn add_middleware(&mut self, middleware: impl Middleware) -> &mut Self {
+        self.init_resource::<NetPipeline>();
+        self.world.resource_mut::<NetPipeline>().push(middleware);
+        self
+    }
+
+    /// Registers message type `T` into `table` so that it can be sent through the
+    /// [`NetPipeline`] with [`PipedNet`](crate::middleware::PipedNet).
+    ///
+    /// ### Panics
+    /// panics if `T` is already registered as a piped message in the table.
+    fn register_piped_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_register_piped_msg::<T>(table, transport).unwrap()
+    }
+
+    /// Registers message type `T` into `table` so that it can be sent through the
+    /// [`NetPipeline`] with [`PipedNet`](crate::middleware::PipedNet).
+    ///
+    /// Same as [`register_piped_msg()`](App::register_piped_msg), but doesn't panic in the
+    /// event of a [`MsgRegError`].
+    fn try_register_piped_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        let id = "bevy-pigeon::piped::".to_owned() + std::any::type_name::<T>();
+        register::<PipedMsg<T>>(self, table, transport, &id)?;
+        self.init_resource::<NetPipeline>();
+        Ok(self)
+    }
+
+    /// Registers user message type `T` into `table`, and records it in the
+    /// [`MsgSchema`](crate::schema::MsgSchema) so that it is verified when clients connect.
+    ///
+    /// A [`SortedMsgTable`] identifies `T` by its type name; use
+    /// [`register_msg_as()`](App::register_msg_as) to choose the id. See the
+    /// [`schema`](crate::schema) module for more info.
+    ///
+    /// ### Panics
+    /// panics if `T` is already registered in the table.
+    fn register_msg<T>(&mut self, table: &mut impl MsgTableLike, transport: Transport) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_register_msg::<T>(table, transport).unwrap()
+    }
+
+    /// Registers user message type `T` into `table`, and records it in the
+    /// [`MsgSchema`](crate::schema::MsgSchema).
+    ///
+    /// Same as [`register_msg()`](App::register_msg), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_register_msg<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_register_msg_as::<T>(table, transport, std::any::type_name::<T>())
+    }
+
+    /// Registers user message type `T` into `table` with the identifier `id`, and records it in
+    /// the [`MsgSchema`](crate::schema::MsgSchema) so that it is verified when clients connect.
+    ///
+    /// A [`MsgTable`] has no ids, so it ignores `id`. See the [`schema`](crate::schema) module for
+    /// more info.
+    ///
+    /// ### Panics
+    /// panics if `T` or `id` is already registered in the table.
+    fn register_msg_as<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+        id: &str,
+    ) -> &mut Self
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.try_register_msg_as::<T>(table, transport, id).unwrap()
+    }
+
+    /// Registers user message type `T` into `table` with the identifier `id`, and records it in
+    /// the [`MsgSchema`](crate::schema::MsgSchema).
+    ///
+    /// Same as [`register_msg_as()`](App::register_msg_as), but doesn't panic in the event of a
+    /// [`MsgRegError`].
+    fn try_register_msg_as<T>(
+        &mut self,
+        table: &mut impl MsgTableLike,
+        transport: Transport,
+        id: &str,
+    ) -> Result<&mut Self, MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        register::<T>(self, table, transport, id)?;
+        Ok(self)
+    }
+}
+
+/// Information about how component `T` was registered to be synced using message type `M`.
+///
+/// This is inserted as a resource by [`sync_comp`](AppExt::sync_comp) and its variants.
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct SyncInfo<T, M> {
+    transport: Transport,
+    apply: fn(&M, &mut T),
+    apply_mode: ApplyMode,
+    rate: Option<f32>,
+    priority: bool,
+    threshold: Option<Threshold<T>>,
+    group: Option<usize>,
+}
+
+impl<T, M> SyncInfo<T, M> {
+    fn new(config: &SyncConfig<T, M>) -> Self
+    where
+        M: Clone + Into<T>,
+    {
+        SyncInfo {
+            transport: config.transport,
+            apply: apply_clone::<T, M>,
+            apply_mode: config.apply_mode,
+            rate: config.rate,
+            priority: config.priority,
+            threshold: config.threshold(),
+            group: None,
+        }
+    }
+
+    /// The transport that the component was registered with.
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// The function used to apply a received message to the component.
+    pub fn apply(&self) -> fn(&M, &mut T) {
+        self.apply
+    }
+
+    /// How received updates are applied to the component.
+    pub fn apply_mode(&self) -> ApplyMode {
+        self.apply_mode
+    }
+
+    /// The most times per second that the component is sent, if limited.
+    pub fn rate(&self) -> Option<f32> {
+        self.rate
+    }
+
+    /// Whether the component is sent less often for far away entities.
+    pub fn priority(&self) -> bool {
+        self.priority
+    }
+
+    /// The index of the bundle in the [`SyncGroups`](crate::atomic::SyncGroups) that the
+    /// component is part of, if any.
+    pub fn group(&self) -> Option<usize> {
+        self.group
+    }
+
+    /// Makes the component part of bundle `group`.
+    pub(crate) fn set_group(&mut self, group: usize) {
+        self.group = Some(group);
+    }
+
+    /// Gets how a [`NetComp`] with the transport override `transport` should be sent.
+    fn route(&self, transport: Option<Transport>, config: Option<&FragmentConfig>) -> Route {
+        let transport = transport.unwrap_or(self.transport);
+        Route {
+            alt: transport != self.transport,
+            fragment_budget: match transport {
+                Transport::UDP => config.map(FragmentConfig::payload_budget),
+                Transport::TCP => None,
+            },
+        }
+    }
+}
+
+/// Applies `msg` to `comp` by cloning it and converting it into `T`.
+///
+/// This is the default way received messages are applied.
+pub(crate) fn apply_clone<T, M: Clone + Into<T>>(msg: &M, comp: &mut T) {
+    *comp = msg.clone().into();
+}
+
+/// Applies `msg` to `comp` of `entity` with `apply`, in place or by inserting the changed copy,
+/// as `mode` says.
+pub(crate) fn apply_as<T: Clone + Component, M>(
+    mode: ApplyMode,
+    entity: Entity,
+    msg: &M,
+    comp: &mut Mut<T>,
+    apply: fn(&M, &mut T),
+    commands: &mut Commands,
+) {
+    match mode {
+        ApplyMode::Mutate => apply(msg, comp),
+        ApplyMode::Insert => {
+            let mut new = T::clone(comp);
+            apply(msg, &mut new);
+            commands.entity(entity).insert(new);
+        }
+    }
+}
+
+/// How a [`NetCompMsg`] should be sent.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+struct Route {
+    /// Whether to send it as an [`AltNetCompMsg`].
+    alt: bool,
+    /// The size above which the message should be fragmented, if it should be fragmented.
+    fragment_budget: Option<usize>,
+}
+
+/// Registers the message types needed to sync components using message type `M` into `table`,
+/// and records them in `schema`.
+///
+/// A [`SortedMsgTable`] identifies them by ids made from `name`.
+pub(crate) fn register_sync_msgs<M>(
+    schema: &mut MsgSchema,
+    table: &mut impl MsgTableLike,
+    transport: Transport,
+    name: &str,
+) -> Result<(), MsgRegError>
+where
+    M: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let id = "bevy-pigeon::".to_owned() + name;
+    table.register_recorded::<NetCompMsg<M>>(schema, transport, &id)?;
+    let alt_id = "bevy-pigeon::alt::".to_owned() + name;
+    table.register_recorded::<AltNetCompMsg<M>>(schema, alt_transport(transport), &alt_id)?;
+    let frag_id = "bevy-pigeon::fragment::".to_owned() + name;
+    table.register_recorded::<NetCompFragment<M>>(schema, Transport::UDP, &frag_id)?;
+    let acked_id = "bevy-pigeon::acked::".to_owned() + name;
+    table.register_recorded::<AckedNetCompMsg<M>>(schema, Transport::UDP, &acked_id)?;
+    let ack_id = "bevy-pigeon::ack::".to_owned() + name;
+    table.register_recorded::<NetCompAck<M>>(schema, Transport::UDP, &ack_id)
+}
+
+/// Adds the resources, events and systems needed to sync component `T` using message type `M`.
+pub(crate) fn add_sync_systems<'a, T, M>(app: &'a mut App, config: &SyncConfig<T, M>) -> &'a mut App
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let schedule = config.schedule;
+    let SyncSchedule { recv, send, .. } = schedule;
+    init_net_stages(app);
+    app.insert_resource(SyncInfo::<T, M>::new(config));
+    app.init_resource::<FragmentConfig>();
+    app.init_resource::<NetStats>();
+    app.init_resource::<Fragments<M>>();
+    app.add_event::<SyncC<T>>();
+    app.add_event::<NetUpdated<T>>();
+    app.add_event::<AuthorityGained>();
+    app.add_event::<AuthorityLost>();
+    app.add_event::<SyncViolation>();
+    app.add_event::<RecvOverflow>();
+    app.add_event::<NetErrorEvent>();
+    app.init_resource::<SnapshotRegistry>();
+    app.world
+        .resource_mut::<SnapshotRegistry>()
+        .register::<T, M>();
+    app.add_system_to_stage(
+        send,
+        schedule
+            .join(resync_on_dir_change::<T, M>)
+            .before(send_on_event::<T, M>),
+    );
+    app.add_system_to_stage(send, schedule.join(send_on_event::<T, M>));
+    app.add_system_to_stage(
+        send,
+        schedule
+            .join(comp_send::<T, M>)
+            .with_run_criteria(send_rate_due::<T, M>),
+    );
+    app.add_system_to_stage(recv, schedule.join(comp_recv::<T, M>));
+    if !app.world.contains_resource::<NetCompTypes>() {
+        app.init_resource::<NetCompTypes>();
+        app.add_system_to_stage(CoreStage::Last, require_net_entity.at_end().label(NetLabel));
+    }
+    app.world.resource_mut::<NetCompTypes>().register::<T, M>();
+    app.init_resource::<ResendConfig>();
+    app.init_resource::<Resends<M>>();
+    remove_on_disconnect::<Resends<M>>(app);
+    app.add_system_to_stage(
+        send,
+        schedule
+            .join(resend::<M>)
+            .after(comp_send::<T, M>)
+            .after(send_on_event::<T, M>),
+    );
+    app.add_system_to_stage(recv, schedule.join(ack_resends::<M>));
+    config.add_smoothing(app);
+    app
+}
+
+/// Runs [`comp_send`] only as often as the [`SendCadence`] and the [`rate`](SyncInfo::rate) of
+/// component `T` allow.
+///
+/// Skipping the system, instead of returning early from it, keeps the changes made in between
+/// for the next send.
+fn send_rate_due<T, M>(
+    time: Res<Time>,
+    cadence: Option<Res<SendCadence>>,
+    info: Option<Res<SyncInfo<T, M>>>,
+    mut next: Local<f64>,
+) -> ShouldRun
+where
+    T: Send + Sync + 'static,
+    M: Send + Sync + 'static,
+{
+    if cadence.is_some_and(|c| !c.is_due()) {
+        return ShouldRun::No;
+    }
+    let rate = match info.and_then(|i| i.rate) {
+        Some(rate) if rate > 0.0 => rate as f64,
+        _ => return ShouldRun::Yes,
+    };
+    let now = time.elapsed_seconds_f64();
+    if now < *next {
+        return ShouldRun::No;
+    }
+    // Catch up without sending a burst after a long frame.
+    *next = (*next + 1.0 / rate).max(now);
+    ShouldRun::Yes
+}
+
+/// Adds the resources, events and systems needed to send and receive snapshots.
+fn add_snapshot_systems(app: &mut App) -> &mut App {
+    app.init_resource::<SnapshotRegistry>();
+    app.add_event::<SendSnapshot>();
+    app.add_event::<SendDelta>();
+    app.add_event::<SnapshotApplied>();
+    app.add_system_to_stage(CoreStage::Last, send_snapshots.label(NetLabel));
+    app.add_system_to_stage(
+        CoreStage::Last,
+        send_spawns.label(NetLabel).before(send_snapshots),
+    );
+    app.add_system_to_stage(
+        CoreStage::First,
+        recv_snapshots.label(NetLabel).after(client_tick),
+    );
+    app
+}
+
+/// Adds the resources, events and systems needed for spawn requests of type `R`.
+fn add_spawn_systems<R>(app: &mut App) -> &mut App
+where
+    R: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    if !app.world.contains_resource::<ProvisionalIds>() {
+        app.init_resource::<ProvisionalIds>();
+        app.add_event::<RespondSpawn>();
+        app.add_event::<SpawnResolved>();
+        app.add_system_to_stage(CoreStage::Last, send_spawn_responses.label(NetLabel));
+        app.add_system_to_stage(
+            CoreStage::First,
+            recv_spawn_responses.label(NetLabel).after(client_tick),
+        );
+    }
+    app.add_event::<RequestSpawn<R>>();
+    app.add_event::<SpawnRequested<R>>();
+    app.add_system_to_stage(CoreStage::Last, send_spawn_requests::<R>.label(NetLabel));
+    app.add_system_to_stage(
+        CoreStage::First,
+        recv_spawn_requests::<R>.label(NetLabel).after(server_tick),
+    );
+    app
+}
+
+/// Adds the resources and systems needed for inputs of type `I`.
+fn add_input_systems<I>(app: &mut App) -> &mut App
+where
+    I: Clone + Default + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    add_tick_systems(app);
+    if !app.world.contains_resource::<InputConfig>() {
+        app.init_resource::<InputConfig>();
+    }
+    app.init_resource::<LocalInput<I>>();
+    app.init_resource::<InputHistory<I>>();
+    app.init_resource::<PlayerInputs<I>>();
+    remove_on_disconnect::<PlayerInputs<I>>(app);
+    app.add_system_to_stage(CoreStage::Last, send_input::<I>.label(NetLabel));
+    app.add_system_to_stage(
+        CoreStage::First,
+        recv_inputs::<I>.label(NetLabel).after(server_tick),
+    );
+    app
+}
+
+/// Adds the resources and systems needed to send commands of type `C`.
+fn add_command_systems<C>(app: &mut App) -> &mut App
+where
+    C: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    app.init_resource::<CommandQueue<C>>();
+    app.init_resource::<ClientCommands<C>>();
+    remove_on_disconnect::<ClientCommands<C>>(app);
+    app.add_event::<CommandAcked<C>>();
+    app.add_event::<NetErrorEvent>();
+    app.add_system_to_stage(CoreStage::Last, send_commands::<C>.label(NetLabel));
+    app.add_system_to_stage(CoreStage::Last, send_command_acks::<C>.label(NetLabel));
+    app.add_system_to_stage(
+        CoreStage::First,
+        recv_commands::<C>.label(NetLabel).after(server_tick),
+    );
+    app.add_system_to_stage(
+        CoreStage::First,
+        recv_command_acks::<C>.label(NetLabel).after(client_tick),
+    );
+    app
+}
+
+/// Adds the [`NetTick`] and the system that advances it on a client, once.
+///
+/// The server advances it in [`server_tick`].
+fn add_tick_systems(app: &mut App) -> &mut App {
+    if !app.world.contains_resource::<NetTick>() {
+        app.init_resource::<NetTick>();
+        app.add_system_to_stage(CoreStage::First, advance_tick.label(NetLabel));
+    }
+    app
+}
+
+/// Adds the resources and systems needed for the acks.
+fn add_ack_systems(app: &mut App) -> &mut App {
+    app.init_resource::<NetAcks>();
+    app.add_system_to_stage(CoreStage::Last, send_acks.label(NetLabel));
+    app.add_system_to_stage(
+        CoreStage::First,
+        recv_acks
+            .label(NetLabel)
+            .after(client_tick)
+            .after(server_tick),
+    );
+    app
+}
+
+/// Adds the systems that send and receive component `T` as the reduced message type `R`.
+fn add_lod_systems<T, M, R>(app: &mut App) -> &mut App
+where
+    T: Clone + Into<M> + Into<R> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    R: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    app.world
+        .get_resource_or_insert_with(LodVariants::default)
+        .add::<T>();
+    init_net_stages(app);
+    app.add_system_to_stage(NetStage::Send, lod_send::<T, M, R>.label(NetLabel));
+    app.add_system_to_stage(
+        NetStage::Receive,
+        lod_recv::<T, M, R>.label(NetLabel).after(comp_recv::<T, M>),
+    );
+    app
+}
+
+/// Adds the resources and systems needed for the [`KinematicController`](crate::movement::KinematicController) movement.
+fn add_movement_systems(app: &mut App) -> &mut App {
+    app.init_resource::<MovementConfig>();
+    app.add_system_to_stage(CoreStage::PreUpdate, server_move.label(NetLabel));
+    app.add_system_to_stage(
+        CoreStage::PostUpdate,
+        client_move
+            .label(NetLabel)
+            .before(TransformSystem::TransformPropagate),
+    );
+    app
+}
+
+/// Sets the timeout of stale entities, and adds the system that expires them.
+fn set_stale_timeout(app: &mut App, timeout: f32, action: StaleTimeout) -> &mut App {
+    app.init_resource::<NetStaleness>();
+    let mut staleness = app.world.resource_mut::<NetStaleness>();
+    let first = staleness.timeout().is_none();
+    staleness.set_timeout(Some((timeout, action)));
+    if first {
+        app.add_system_to_stage(CoreStage::PreUpdate, expire_stale.after(NetLabel));
+    }
+    app
+}
+
+/// The components of an entity that restrict who its synced components are sent to.
+pub(crate) type SendFilters<'a> = (Option<&'a NetSendTo>, Option<&'a NetHidden>);
+
+/// The resources that restrict which clients receive which entities.
+pub(crate) type Interests<'a> = (Option<&'a ClientInterest>, Option<&'a NetFilters>);
+
+/// The clients that a message is sent to from the server.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) enum Recipients {
+    /// The clients matching a [`CIdSpec`].
+    Spec(CIdSpec),
+    /// An explicit list of clients.
+    Cids(Vec<CId>),
+}
+
+impl Recipients {
+    /// Gets the recipients of `net_c` on the entity with `id`, given that it is sent to `spec`.
+    pub(crate) fn of<T, M>(
+        server: &Server,
+        id: NetId,
+        net_c: &NetComp<T, M>,
+        spec: CIdSpec,
+        (send_to, hidden): SendFilters,
+        groups: Option<&NetGroups>,
+        (interest, filters): Interests,
+    ) -> Self
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        if matches!(hidden, Some(hidden) if hidden.from_all()) {
+            return Recipients::Cids(vec![]);
+        }
+        let send_to = send_to.map(|s| &s.0);
+        let hidden = hidden.map(|h| &h.0);
+        let interest = interest.filter(|interest| !interest.is_empty());
+        let filters = filters.filter(|filters| filters.denies_any(id));
+        // Avoid listing the clients if the specs can be combined.
+        if net_c.group.is_none() && hidden.is_none() && interest.is_none() && filters.is_none() {
+            match (spec, send_to.map(NetSpec::as_cid_spec)) {
+                (spec, None) | (spec, Some(Some(CIdSpec::All))) => return Recipients::Spec(spec),
+                (CIdSpec::All, Some(Some(send_to))) => return Recipients::Spec(send_to),
+                _ => {}
+            }
+        }
+
+        let candidates: Vec<CId> = match net_c.group {
+            Some(group) => groups
+                .into_iter()
+                .flat_map(|groups| groups.members(group))
+                .collect(),
+            None => server.cids().collect(),
+        };
+        Recipients::Cids(
+            candidates
+                .into_iter()
+                .filter(|cid| spec.matches(*cid))
+                .filter(|cid| send_to.iter().all(|s| s.matches(*cid)))
+                .filter(|cid| !hidden.iter().any(|h| h.matches(*cid)))
+                .filter(|cid| interest.iter().all(|i| i.is_interested(*cid, id)))
+                .filter(|cid| filters.iter().all(|f| f.allows(id, *cid)))
+                .collect(),
+        )
+    }
+
+    /// Whether `cid` is one of the recipients.
+    pub(crate) fn includes(&self, cid: CId) -> bool {
+        match self {
+            Recipients::Spec(spec) => spec.matches(cid),
+            Recipients::Cids(cids) => cids.contains(&cid),
+        }
+    }
+
+    /// Lists the recipients.
+    pub(crate) fn cids(&self, server: &Server) -> Vec<CId> {
+        match self {
+            Recipients::Spec(spec) => server.cids().filter(|cid| spec.matches(*cid)).collect(),
+            Recipients::Cids(cids) => cids.clone(),
+        }
+    }
+
+    /// Sends `msg` to the recipients, adding any errors to `errors`.
+    pub(crate) fn send<T: Any + Send + Sync>(
+        &self,
+        server: &Server,
+        msg: &T,
+        errors: &mut SendErrors,
+    ) {
+        match self {
+            Recipients::Spec(spec) => {
+                if let Err(e) = server.send_spec(*spec, msg) {
+                    let cid = match spec {
+                        CIdSpec::Only(cid) => Some(*cid),
+                        _ => None,
+                    };
+                    errors.push((cid, e.to_string()));
+                }
+            }
+            Recipients::Cids(cids) => {
+                for cid in cids.iter() {
+                    if let Err(e) = server.send_to(*cid, msg) {
+                        errors.push((Some(*cid), e.to_string()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sends `msg` to `recipients` from the server, using `route`.
+fn server_send<M>(
+    server: &Server,
+    recipients: &Recipients,
+    route: Route,
+    msg: NetCompMsg<M>,
+    frags: Option<&mut Fragments<M>>,
+    errors: &mut SendErrors,
+) where
+    M: Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    if matches!(recipients, Recipients::Cids(cids) if cids.is_empty()) {
+        return;
+    }
+
+    if let (Some(budget), Some(frags)) = (route.fragment_budget, frags) {
+        if let Some(fragments) = frags.split(&msg, budget) {
+            for fragment in fragments {
+                recipients.send(server, &fragment, errors);
+            }
+            return;
+        }
+    }
+
+    if route.alt {
+        recipients.send(server, &AltNetCompMsg(msg), errors);
+    } else {
+        recipients.send(server, &msg, errors);
+    }
+}
+
+/// Sends `msg` to the server from the client, using `route`.
+fn client_send<M>(
+    client: &Client,
+    route: Route,
+    msg: NetCompMsg<M>,
+    frags: Option<&mut Fragments<M>>,
+    errors: &mut SendErrors,
+) where
+    M: Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    if let (Some(budget), Some(frags)) = (route.fragment_budget, frags) {
+        if let Some(fragments) = frags.split(&msg, budget) {
+            for fragment in fragments {
+                if let Err(e) = client.send(&fragment) {
+                    errors.push((None, e.to_string()));
+                }
+            }
+            return;
+        }
+    }
+
+    let result = if route.alt {
+        client.send(&AltNetCompMsg(msg))
+    } else {
+        client.send(&msg)
+    };
+    if let Err(e) = result {
+        errors.push((None, e.to_string()));
+    }
+}
+
+/// A system that forces a sync of a certain component.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn send_on_event<T, M>(
+    mut er: EventReader<SyncC<T>>,
+    time: Res<Time>,
+    net_tick: Option<Res<NetTick>>,
+    server: Option<ResMut<Server>>,
+    client: Option<ResMut<Client>>,
+    info: Option<Res<SyncInfo<T, M>>>,
+    groups: Option<Res<NetGroups>>,
+    (interest, net_filters): (Option<Res<ClientInterest>>, Option<Res<NetFilters>>),
+    config: Option<Res<FragmentConfig>>,
+    mut frags: Option<ResMut<Fragments<M>>>,
+    mut resends: Option<ResMut<Resends<M>>>,
+    mut ew: EventWriter<NetErrorEvent>,
+    mut error_log: Local<ErrorLog>,
+    q: Query<(Entity, &NetEntity, &NetComp<T, M>, &T, SendFilters)>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    // `None` syncs all entities.
+    let mut entities = Some(HashSet::default());
+    for event in er.iter() {
+        match (event.entity, entities.as_mut()) {
+            (Some(entity), Some(entities)) => {
+                entities.insert(entity);
+            }
+            (None, _) => entities = None,
+            _ => {}
+        }
+    }
+    if entities.as_ref().is_some_and(HashSet::is_empty) {
+        return;
+    }
+    let included = |entity: &Entity| entities.as_ref().is_none_or(|e| e.contains(entity));
+    trace!("Force Syncing {}", std::any::type_name::<T>());
+    let now = time.elapsed_seconds_f64();
+    let tick = net_tick.as_deref().map(SendTick::now);
+    let mut errors = vec![];
+    let route = |net_c: &NetComp<T, M>| {
+        info.as_ref()
+            .map(|i| i.route(net_c.send_transport(), config.as_deref()))
+            .unwrap_or_default()
+    };
+
+    // Almost copy-paste from [`comp_send`] ignoring change detection
+    if let Some(server) = server {
+        for (entity, net_e, net_c, comp, filters) in q.iter().filter(|i| included(&i.0)) {
+            if let Some(to_spec) = net_c.s_dir.to() {
+                let recipients = Recipients::of(
+                    &server,
+                    net_e.id,
+                    net_c,
+                    *to_spec,
+                    filters,
+                    groups.as_deref(),
+                    (interest.as_deref(), net_filters.as_deref()),
+                );
+                if let (Some(Channel::UnreliableAcked), Some(resends)) =
+                    (net_c.channel, resends.as_deref_mut())
+                {
+                    let cids = recipients.cids(&server);
+                    let msg = (*net_e, comp.clone().into());
+                    resends.server_send(&server, &cids, entity, msg, now, &mut errors);
+                    report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+                    continue;
+                }
+                let msg = NetCompMsg::<M>::new(*net_e, comp.clone().into()).with_tick(tick);
+                server_send(
+                    &server,
+                    &recipients,
+                    route(net_c),
+                    msg,
+                    frags.as_deref_mut(),
+                    &mut errors,
+                );
+                report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+            }
+        }
+    } else if let Some(client) = client {
+        for (entity, net_e, net_c, comp, _) in q.iter().filter(|i| included(&i.0)) {
+            if let CNetDir::To = net_c.c_dir {
+                if let (Some(Channel::UnreliableAcked), Some(resends)) =
+                    (net_c.channel, resends.as_deref_mut())
+                {
+                    let msg = (*net_e, comp.clone().into());
+                    resends.client_send(&client, entity, msg, now, &mut errors);
+                    report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+                    continue;
+                }
+                let msg = NetCompMsg::<M>::new(*net_e, comp.clone().into()).with_tick(tick);
+                client_send(
+                    &client,
+                    route(net_c),
+                    msg,
+                    frags.as_deref_mut(),
+                    &mut errors,
+                );
+                report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+            }
+        }
+    }
+}
+
+/// A system that forces a sync of component `T` of the entities whose [`NetComp`] changed
+/// direction, such as an entity becoming server controlled.
+///
+/// This also resets the [`last`](NetComp::last) timestamp, so the updates of the new sender aren't
+/// dropped as stale, and both sides converge right away instead of on the next change. If this
+/// peer gained or lost authority over the entity, an [`AuthorityGained`] or [`AuthorityLost`]
+/// event is sent.
+#[allow(clippy::type_complexity)]
+fn resync_on_dir_change<T, M>(
+    server: Option<Res<Server>>,
+    mut dirs: Local<HashMap<Entity, (CNetDir, SNetDir)>>,
+    removed: RemovedComponents<NetComp<T, M>>,
+    mut q: Query<(Entity, &mut NetComp<T, M>), Changed<NetComp<T, M>>>,
+    mut ew: EventWriter<SyncC<T>>,
+    mut ew_gained: EventWriter<AuthorityGained>,
+    mut ew_lost: EventWriter<AuthorityLost>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    for entity in removed.iter() {
+        dirs.remove(&entity);
+    }
+    let is_server = server.is_some();
+    for (entity, mut net_c) in q.iter_mut() {
+        let dir = (net_c.c_dir, net_c.s_dir);
+        let prev = match dirs.insert(entity, dir) {
+            Some(prev) if prev != dir => prev,
+            _ => continue,
+        };
+        // Don't trigger change detection, as the direction is all that is tracked here.
+        net_c.bypass_change_detection().last = None;
+        ew.send(SyncC::entity(entity));
+        match (
+            is_authority(prev.0, prev.1, is_server),
+            is_authority(dir.0, dir.1, is_server),
+        ) {
+            (false, true) => ew_gained.send(AuthorityGained(entity)),
+            (true, false) => ew_lost.send(AuthorityLost(entity)),
+            _ => {}
+        }
+    }
+}
+
+/// A system that sends component `T` using messages of type `M`.
+///
+/// Most of the time, you will call [`sync_comp`](AppExt::sync_comp) which will add this system.
+/// Only add it manually if you know what you are doing and want custom control over when it runs.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn comp_send<T, M>(
+    (time, net_tick): (Res<Time>, Option<Res<NetTick>>),
+    server: Option<ResMut<Server>>,
+    client: Option<ResMut<Client>>,
+    info: Option<Res<SyncInfo<T, M>>>,
+    groups: Option<Res<NetGroups>>,
+    (interest, net_filters): (Option<Res<ClientInterest>>, Option<Res<NetFilters>>),
+    config: Option<Res<FragmentConfig>>,
+    mut frags: Option<ResMut<Fragments<M>>>,
+    mut resends: Option<ResMut<Resends<M>>>,
+    mut stats: Option<ResMut<NetStats>>,
+    mut profiler: Option<ResMut<NetProfiler>>,
+    mut throttle: SendThrottle,
+    mut ew: EventWriter<NetErrorEvent>,
+    mut error_log: Local<ErrorLog>,
+    mut sends: Local<HashMap<Entity, SendState<T>>>,
+    q: Query<(
+        Entity,
+        &NetEntity,
+        &NetComp<T, M>,
+        &T,
+        ChangeTrackers<T>,
+        SendFilters,
+    )>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let span = info_span!(
+        "comp_send",
+        component = std::any::type_name::<T>(),
+        msgs = field::Empty
+    )
+    .entered();
+    let start = Instant::now();
+    let mut sent = 0u32;
+    let mut bytes = 0u64;
+    let measure = stats.is_some() || profiler.is_some();
+    let now = time.elapsed_seconds_f64();
+    let mut errors = vec![];
+    let route = |net_c: &NetComp<T, M>| {
+        info.as_ref()
+            .map(|i| i.route(net_c.send_transport(), config.as_deref()))
+            .unwrap_or_default()
+    };
+    let priority = info.as_ref().is_none_or(|i| i.priority);
+    let threshold = info.as_ref().and_then(|i| i.threshold);
+    let tick = net_tick.as_deref().map(SendTick::now);
+    if !sends.is_empty() {
+        sends.retain(|entity, _| q.contains(*entity));
+    }
+
+    if let Some(server) = server {
+        let reduced = throttle.has_reduced::<T>();
+        for (entity, net_e, net_c, comp, ct, filters) in q.iter() {
+            // If we are using change detection, and the component hasn't been changed (by at
+            // least the threshold), or isn't due, skip, unless the update was held for a client
+            // that was skipped before.
+            let changed = SendState::due(&mut sends, entity, net_c, comp, &ct, threshold, now);
+            let held = throttle.is_held(net_e.id);
+            if !changed && !held {
+                continue;
+            }
+
+            if let Some(to_spec) = net_c.s_dir.to() {
+                sent += 1;
+                let mut recipients = Recipients::of(
+                    &server,
+                    net_e.id,
+                    net_c,
+                    *to_spec,
+                    filters,
+                    groups.as_deref(),
+                    (interest.as_deref(), net_filters.as_deref()),
+                );
+                if held || throttle.is_throttling() {
+                    let cids = recipients.cids(&server);
+                    // Reduced clients get the reduced message type instead, if there is one.
+                    let serves = |tier| match tier {
+                        LodTier::Full => true,
+                        LodTier::Reduced => !reduced,
+                        LodTier::Presence => false,
+                    };
+                    let cids = throttle.filter(net_e.id, changed, net_c.cd, cids, serves, priority);
+                    recipients = Recipients::Cids(cids);
+                }
+                SendState::sent(&mut sends, entity, net_c, comp, threshold, now);
+                if let (Some(Channel::UnreliableAcked), Some(resends)) =
+                    (net_c.channel, resends.as_deref_mut())
+                {
+                    let cids = recipients.cids(&server);
+                    let msg = (*net_e, comp.clone().into());
+                    if measure {
+                        bytes += count_sent::<T, _>(stats.as_deref_mut(), &msg.1, cids.len());
+                    }
+                    resends.server_send(&server, &cids, entity, msg, now, &mut errors);
+                    report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+                    continue;
+                }
+                let msg = NetCompMsg::<M>::new(*net_e, comp.clone().into()).with_tick(tick);
+                if measure {
+                    let count = recipients.cids(&server).len();
+                    bytes += count_sent::<T, _>(stats.as_deref_mut(), &msg, count);
+                }
+                server_send(
+                    &server,
+                    &recipients,
+                    route(net_c),
+                    msg,
+                    frags.as_deref_mut(),
+                    &mut errors,
+                );
+                report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+            }
+        }
+    } else if let Some(client) = client {
+        for (entity, net_e, net_c, comp, ct, _) in q.iter() {
+            // If we are using change detection, and the component hasn't been changed (by at
+            // least the threshold), or isn't due, skip.
+            if !SendState::due(&mut sends, entity, net_c, comp, &ct, threshold, now) {
+                continue;
+            }
+
+            if let CNetDir::To = net_c.c_dir {
+                sent += 1;
+                SendState::sent(&mut sends, entity, net_c, comp, threshold, now);
+                if let (Some(Channel::UnreliableAcked), Some(resends)) =
+                    (net_c.channel, resends.as_deref_mut())
+                {
+                    let msg = (*net_e, comp.clone().into());
+                    if measure {
+                        bytes += count_sent::<T, _>(stats.as_deref_mut(), &msg.1, 1);
+                    }
+                    resends.client_send(&client, entity, msg, now, &mut errors);
+                    report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+                    continue;
+                }
+                let msg = NetCompMsg::<M>::new(*net_e, comp.clone().into()).with_tick(tick);
+                if measure {
+                    bytes += count_sent::<T, _>(stats.as_deref_mut(), &msg, 1);
+                }
+                client_send(
+                    &client,
+                    route(net_c),
+                    msg,
+                    frags.as_deref_mut(),
+                    &mut errors,
+                );
+                report_errors::<T>(&mut errors, entity, now, &mut error_log, &mut ew);
+            }
+        }
+    }
+    span.record("msgs", sent);
+    if let Some(profiler) = profiler.as_deref_mut() {
+        profiler.record(ProfileEntry {
+            system: ProfiledSystem::Send,
+            type_name: std::any::type_name::<T>(),
+            msgs: sent,
+            bytes,
+            duration: start.elapsed(),
+        });
+    }
+}
+
+/// What [`comp_send`] last sent of an entity's component, for the send rates and thresholds of
+/// its [`NetComp`].
+#[derive(Clone, Debug)]
+pub struct SendState<T> {
+    /// The time that the component was last sent.
+    time: f64,
+    /// The value that was last sent, if there is a threshold.
+    value: Option<T>,
+    /// Whether a change is waiting for the next send, because of the send rate.
+    pending: bool,
+}
+
+impl<T> SendState<T> {
+    /// Whether the component `comp` of `entity` should be sent now.
+    ///
+    /// The threshold of `net_c` overrides `threshold`, the one of the [`SyncInfo`].
+    fn due<M>(
+        sends: &mut HashMap<Entity, SendState<T>>,
+        entity: Entity,
+        net_c: &NetComp<T, M>,
+        comp: &T,
+        ct: &ChangeTrackers<T>,
+        threshold: Option<Threshold<T>>,
+        now: f64,
+    ) -> bool
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        let state = sends.get_mut(&entity);
+        let pending = state.as_ref().is_some_and(|s| s.pending);
+        let changed = !net_c.cd
+            || pending
+            || (ct.is_changed()
+                && match (net_c.threshold_fn().or(threshold), &state) {
+                    (
+                        Some((threshold, delta)),
+                        Some(SendState {
+                            value: Some(prev), ..
+                        }),
+                    ) => delta(prev, comp) >= threshold,
+                    _ => true,
+                });
+        if !changed {
+            return false;
+        }
+        let due = match (net_c.rate, &state) {
+            (Some(rate), Some(state)) if rate > 0.0 => now - state.time >= 1.0 / rate as f64,
+            _ => true,
+        };
+        if !due && net_c.cd {
+            // Send the change once the entity is due again.
+            if let Some(state) = state {
+                state.pending = true;
+            }
+        }
+        due
+    }
+
+    /// Records that the component `comp` of `entity` was sent, if `net_c` needs it.
+    fn sent<M>(
+        sends: &mut HashMap<Entity, SendState<T>>,
+        entity: Entity,
+        net_c: &NetComp<T, M>,
+        comp: &T,
+        threshold: Option<Threshold<T>>,
+        now: f64,
+    ) where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        let threshold = net_c.threshold_fn().or(threshold);
+        if threshold.is_none() && net_c.rate.is_none() {
+            return;
+        }
+        let state = SendState {
+            time: now,
+            value: threshold.map(|_| comp.clone()),
+            pending: false,
+        };
+        sends.insert(entity, state);
+    }
+}
+
+/// Counts the update `msg` of component `T` that was sent to `recipients` peers into `stats`.
+///
+/// Returns the estimated size in bytes of the update, for all recipients.
+fn count_sent<T, S: Serialize>(stats: Option<&mut NetStats>, msg: &S, recipients: usize) -> u64 {
+    let bytes = bincode::serialized_size(msg).unwrap_or(0) * recipients as u64;
+    if let Some(stats) = stats {
+        let stats = stats.comp_mut::<T>();
+        stats.sent += recipients as u64;
+        stats.sent_bytes += bytes;
+    }
+    bytes
+}
+
+/// Sends a [`NetErrorEvent`] for every error in `errors`, and logs them.
+fn report_errors<T>(
+    errors: &mut SendErrors,
+    entity: Entity,
+    now: f64,
+    log: &mut ErrorLog,
+    ew: &mut EventWriter<NetErrorEvent>,
+) {
+    for (cid, error) in errors.drain(..) {
+        let event = NetErrorEvent {
+            kind: NetErrorKind::Send,
+            entity: Some(entity),
+            type_name: std::any::type_name::<T>(),
+            cid,
+            error,
+        };
+        log.log(&event, now);
+        ew.send(event);
+    }
+}
+
+/// Reassembles the received [`NetCompFragment`]s into the messages that are complete.
+///
+/// Sends a [`MalformedMsg`] for every fragment that is dropped.
+fn reassemble<M>(
+    frag_msgs: &[NetMsg<NetCompFragment<M>>],
+    frags: Option<&mut Fragments<M>>,
+    limits: &NetLimits,
+    malformed: &mut EventWriter<MalformedMsg>,
+    from_server: bool,
+) -> Vec<(CId, Option<u32>, NetCompMsg<M>)>
+where
+    M: Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let frags = match frags {
+        Some(frags) => frags,
+        None => return vec![],
+    };
+    frags.age();
+    let mut reassembled = vec![];
+    for m in frag_msgs {
+        match frags.push(m.cid, m.time, m, limits) {
+            Ok(Some((time, msg))) => reassembled.push((m.cid, time, msg)),
+            Ok(None) => {}
+            Err(error) => {
+                let type_name = std::any::type_name::<NetCompFragment<M>>();
+                warn!("Dropped a malformed {}: {}", type_name, error);
+                malformed.send(MalformedMsg {
+                    cid: (!from_server).then_some(m.cid),
+                    type_name,
+                    error,
+                });
+            }
+        }
+    }
+    reassembled
+}
+
+/// Merges the received [`NetCompMsg`]s, [`AltNetCompMsg`]s, [`AckedNetCompMsg`]s and reassembled
+/// messages into one list.
+fn merge_msgs<'a, M: Any + Send + Sync>(
+    msgs: &'a [NetMsg<NetCompMsg<M>>],
+    alt_msgs: &'a [NetMsg<AltNetCompMsg<M>>],
+    acked_msgs: &'a [NetMsg<AckedNetCompMsg<M>>],
+    reassembled: &'a [(CId, Option<u32>, NetCompMsg<M>)],
+) -> Vec<RecvNetComp<'a, M>> {
+    let msgs = msgs.iter().map(|m| RecvNetComp {
+        cid: m.cid,
+        time: m.time,
+        id: m.id,
+        generation: m.generation,
+        tick: m.tick,
+        msg: &m.msg,
+    });
+    let alt_msgs = alt_msgs.iter().map(|m| RecvNetComp {
+        cid: m.cid,
+        time: m.time,
+        id: m.0.id,
+        generation: m.0.generation,
+        tick: m.0.tick,
+        msg: &m.0.msg,
+    });
+    let reassembled = reassembled.iter().map(|(cid, time, m)| RecvNetComp {
+        cid: *cid,
+        time: *time,
+        id: m.id,
+        generation: m.generation,
+        tick: m.tick,
+        msg: &m.msg,
+    });
+    let acked_msgs = acked_msgs.iter().map(|m| RecvNetComp {
+        cid: m.cid,
+        time: m.time,
+        id: m.id,
+        generation: m.generation,
+        tick: None,
+        msg: &m.msg,
+    });
+    msgs.chain(alt_msgs)
+        .chain(acked_msgs)
+        .chain(reassembled)
+        .collect()
+}
+
+/// A system that receives messages of type `M` and applies it to component `T`.
+///
+/// Most of the time, you will call [`sync_comp`](AppExt::sync_comp) which will add this system.
+/// Only add it manually if you know what you are doing and want custom control over when it runs.
+#[allow(clippy::too_many_arguments)]
+pub fn comp_recv<T, M>(
+    (time, server_tick): (Res<Time>, Option<Res<ServerTick>>),
+    server: Option<ResMut<Server>>,
+    client: Option<ResMut<Client>>,
+    info: Option<Res<SyncInfo<T, M>>>,
+    validators: Option<Res<SyncValidators<T, M>>>,
+    sanitizers: Option<Res<RecvSanitizers<T, M>>>,
+    mut violations: EventWriter<SyncViolation>,
+    mut frags: Option<ResMut<Fragments<M>>>,
+    limits: Option<Res<NetLimits>>,
+    mut malformed: EventWriter<MalformedMsg>,
+    mut buffers: RecvBuffers<T, M>,
+    mut overflow: EventWriter<RecvOverflow>,
+    mut stats: Option<ResMut<NetStats>>,
+    mut profiler: Option<ResMut<NetProfiler>>,
+    (mut updated, mut commands): (EventWriter<NetUpdated<T>>, Commands),
+    mut q: Query<RecvItem<'_, T, M>>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let span = info_span!(
+        "comp_recv",
+        component = std::any::type_name::<T>(),
+        msgs = field::Empty
+    )
+    .entered();
+    let start = Instant::now();
+    let (apply, mode) = info
+        .map(|i| (i.apply, i.apply_mode))
+        .unwrap_or((apply_clone::<T, M>, ApplyMode::Mutate));
+    let limits = limits.map(|l| *l).unwrap_or_default();
+    let now = time.elapsed_seconds_f64();
+    if let Some(server) = server {
+        // Cache messages
+        let msgs: Vec<NetMsg<NetCompMsg<M>>> = server.recv::<NetCompMsg<M>>().collect();
+        let alt_msgs: Vec<NetMsg<AltNetCompMsg<M>>> = server.recv::<AltNetCompMsg<M>>().collect();
+        let acked_msgs: Vec<NetMsg<AckedNetCompMsg<M>>> =
+            server.recv::<AckedNetCompMsg<M>>().collect();
+        let frag_msgs: Vec<NetMsg<NetCompFragment<M>>> =
+            server.recv::<NetCompFragment<M>>().collect();
+        let reassembled = reassemble(
+            &frag_msgs,
+            frags.as_deref_mut(),
+            &limits,
+            &mut malformed,
+            false,
+        );
+        let msgs = merge_msgs(&msgs, &alt_msgs, &acked_msgs, &reassembled);
+        let released;
+        let msgs = match buffers.jitter.as_deref_mut() {
+            Some(jitter) => {
+                released = jitter.buffer(&msgs, now);
+                merge_msgs(&[], &[], &[], &released)
+            }
+            None => msgs,
+        };
+        let budgeted;
+        let msgs = match buffers.budget.as_deref_mut() {
+            Some(budget) => {
+                let (taken, overflowed) = budget.take(&msgs);
+                budgeted = taken;
+                overflow.send_batch(overflowed);
+                merge_msgs(&[], &[], &[], &budgeted)
+            }
+            None => msgs,
+        };
+        if let Some(server_tick) = server_tick.as_deref() {
+            for tick in msgs.iter().filter_map(|m| m.tick) {
+                server_tick.observe(tick.tick);
+            }
+        }
+        span.record("msgs", msgs.len() as u64);
+        if let Some(profiler) = profiler.as_deref_mut() {
+            profile_recv::<T, M>(profiler, &msgs, start);
+        }
+        for (entity, net_e, mut net_c, mut comp, access, ordered, (mut source, hook)) in
+            q.iter_mut()
+        {
+            if let Some(&spec) = net_c.s_dir.from() {
+                let allowed = |cid| spec.matches(cid) && access.iter().all(|a| a.allows(cid));
+                if let Some(access) = access {
+                    for m in msgs.iter().filter(|m| m.is_for(net_e)) {
+                        if spec.matches(m.cid) && !access.allows(m.cid) {
+                            warn!(
+                                "Client {} doesn't have write access to NetEntity {{ id: {} }}. Dropping update.",
+                                m.cid, net_e.id
+                            );
+                        }
+                    }
+                }
+                if let Some(stats) = stats.as_deref_mut() {
+                    count_msgs(&msgs, &net_c, allowed, net_e, stats.comp_mut::<T>());
+                }
+                // Drop the updates if a newer one was applied to another component.
+                let valid_msgs = select_msgs(&msgs, &net_c, allowed, net_e)
+                    .into_iter()
+                    .filter(|m| ordered.is_none_or(|o| o.check(m.tick)));
+                for valid_msg in valid_msgs {
+                    let validation = match validators {
+                        Some(ref validators) => {
+                            let update = Update {
+                                cid: valid_msg.cid,
+                                id: net_e.id,
+                                dt: match (valid_msg.time, net_c.last) {
+                                    (Some(time), Some(last)) => {
+                                        Some(time.wrapping_sub(last) as f32 / 1000.0)
+                                    }
+                                    _ => None,
+                                },
+                            };
+                            validators.validate(&update, valid_msg.msg, &comp)
+                        }
+                        None => Validation::Accept,
+                    };
+                    match validation {
+                        Validation::Accept => {
+                            net_c.last = valid_msg.time;
+                            net_c.tick = valid_msg.tick.map(|t| t.tick);
+                            if let Some(ordered) = ordered {
+                                ordered.commit(valid_msg.tick);
+                            }
+                            let applied = buffers.dispatch(
+                                (entity, &mut comp),
+                                valid_msg,
+                                Cow::Borrowed(valid_msg.msg),
+                                (apply, mode, now),
+                                &mut commands,
+                            );
+                            if applied {
+                                let sender = UpdateSender::of(valid_msg);
+                                report_applied(
+                                    entity,
+                                    sender,
+                                    (source.as_deref_mut(), hook),
+                                    &mut updated,
+                                    &mut commands,
+                                );
+                            }
+                            if let Some(stats) = stats.as_deref_mut() {
+                                stats.comp_mut::<T>().applied += 1;
+                            }
+                        }
+                        Validation::Clamp(msg, reason) => {
+                            violations.send(SyncViolation {
+                                cid: valid_msg.cid,
+                                id: net_e.id,
+                                component: std::any::type_name::<T>(),
+                                reason,
+                                rejected: false,
+                            });
+                            net_c.last = valid_msg.time;
+                            net_c.tick = valid_msg.tick.map(|t| t.tick);
+                            if let Some(ordered) = ordered {
+                                ordered.commit(valid_msg.tick);
+                            }
+                            let applied = buffers.dispatch(
+                                (entity, &mut comp),
+                                valid_msg,
+                                Cow::Owned(msg),
+                                (apply, mode, now),
+                                &mut commands,
+                            );
+                            if applied {
+                                let sender = UpdateSender::of(valid_msg);
+                                report_applied(
+                                    entity,
+                                    sender,
+                                    (source.as_deref_mut(), hook),
+                                    &mut updated,
+                                    &mut commands,
+                                );
+                            }
+                            if let Some(stats) = stats.as_deref_mut() {
+                                stats.comp_mut::<T>().applied += 1;
+                            }
+                        }
+                        Validation::Reject(reason) => {
+                            debug!(
+                                "Rejected an update to NetEntity {{ id: {} }} from client {}: {}.",
+                                net_e.id, valid_msg.cid, reason
+                            );
+                            violations.send(SyncViolation {
+                                cid: valid_msg.cid,
+                                id: net_e.id,
+                                component: std::any::type_name::<T>(),
+                                reason,
+                                rejected: true,
+                            });
+                        }
+                    }
+                }
+            }
+            // Warn on overlap
+            if let SNetDir::ToFrom(to_spec, from_spec) = net_c.s_dir {
+                if to_spec.overlaps(from_spec) {
+                    warn!("NetEntity {{ id: {} }} has overlapping `CIdSpec`s in NetDirection::ToFrom. Applying anyway.", net_e.id);
+                }
+            }
+        }
+    } else if let Some(client) = client {
+        // Cache messages
+        let msgs: Vec<NetMsg<NetCompMsg<M>>> = client.recv::<NetCompMsg<M>>().collect();
+        let alt_msgs: Vec<NetMsg<AltNetCompMsg<M>>> = client.recv::<AltNetCompMsg<M>>().collect();
+        let acked_msgs: Vec<NetMsg<AckedNetCompMsg<M>>> =
+            client.recv::<AckedNetCompMsg<M>>().collect();
+        let frag_msgs: Vec<NetMsg<NetCompFragment<M>>> =
+            client.recv::<NetCompFragment<M>>().collect();
+        let reassembled = reassemble(
+            &frag_msgs,
+            frags.as_deref_mut(),
+            &limits,
+            &mut malformed,
+            true,
+        );
+        let msgs = merge_msgs(&msgs, &alt_msgs, &acked_msgs, &reassembled);
+        let released;
+        let msgs = match buffers.jitter.as_deref_mut() {
+            Some(jitter) => {
+                released = jitter.buffer(&msgs, now);
+                merge_msgs(&[], &[], &[], &released)
+            }
+            None => msgs,
+        };
+        let budgeted;
+        let msgs = match buffers.budget.as_deref_mut() {
+            Some(budget) => {
+                let (taken, overflowed) = budget.take(&msgs);
+                budgeted = taken;
+                overflow.send_batch(overflowed);
+                merge_msgs(&[], &[], &[], &budgeted)
+            }
+            None => msgs,
+        };
+        span.record("msgs", msgs.len() as u64);
+        if let Some(profiler) = profiler.as_deref_mut() {
+            profile_recv::<T, M>(profiler, &msgs, start);
+        }
+        for (entity, net_e, mut net_c, mut comp, _, ordered, (mut source, hook)) in q.iter_mut() {
+            if net_c.c_dir == CNetDir::From {
+                if let Some(stats) = stats.as_deref_mut() {
+                    count_msgs(&msgs, &net_c, |_| true, net_e, stats.comp_mut::<T>());
+                }
+                let valid_msgs = select_msgs(&msgs, &net_c, |_| true, net_e)
+                    .into_iter()
+                    .filter(|m| ordered.is_none_or(|o| o.check(m.tick)));
+                for valid_msg in valid_msgs {
+                    let sanitized = match sanitizers {
+                        Some(ref sanitizers) => {
+                            let update = Update {
+                                cid: valid_msg.cid,
+                                id: net_e.id,
+                                dt: match (valid_msg.time, net_c.last) {
+                                    (Some(time), Some(last)) => {
+                                        Some(time.wrapping_sub(last) as f32 / 1000.0)
+                                    }
+                                    _ => None,
+                                },
+                            };
+                            sanitizers.sanitize(&update, valid_msg.msg, &comp)
+                        }
+                        None => Validation::Accept,
+                    };
+                    let msg = match sanitized {
+                        Validation::Accept => Cow::Borrowed(valid_msg.msg),
+                        Validation::Clamp(msg, _) => Cow::Owned(msg),
+                        Validation::Reject(reason) => {
+                            debug!(
+                                "Rejected an update to NetEntity {{ id: {} }} from the server: {}.",
+                                net_e.id, reason
+                            );
+                            continue;
+                        }
+                    };
+                    net_c.last = valid_msg.time;
+                    net_c.tick = valid_msg.tick.map(|t| t.tick);
+                    if let Some(ordered) = ordered {
+                        ordered.commit(valid_msg.tick);
+                    }
+                    let applied = buffers.dispatch(
+                        (entity, &mut comp),
+                        valid_msg,
+                        msg,
+                        (apply, mode, now),
+                        &mut commands,
+                    );
+                    if applied {
+                        let sender = UpdateSender::of(valid_msg);
+                        report_applied(
+                            entity,
+                            sender,
+                            (source.as_deref_mut(), hook),
+                            &mut updated,
+                            &mut commands,
+                        );
+                    }
+                    if let Some(stats) = stats.as_deref_mut() {
+                        stats.comp_mut::<T>().applied += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Records the received `msgs` of component `T` in `profiler`, with the time since `start`.
+///
+/// The time is measured before the messages are applied.
+fn profile_recv<T, M: Any + Send + Sync + Serialize>(
+    profiler: &mut NetProfiler,
+    msgs: &[RecvNetComp<M>],
+    start: Instant,
+) {
+    profiler.record(ProfileEntry {
+        system: ProfiledSystem::Recv,
+        type_name: std::any::type_name::<T>(),
+        msgs: msgs.len() as u32,
+        bytes: msgs
+            .iter()
+            .map(|m| bincode::serialized_size(m.msg).unwrap_or(0))
+            .sum(),
+        duration: start.elapsed(),
+    });
+}
+
+/// The optional buffers that the updates received by [`comp_recv`] go through.
+#[derive(SystemParam)]
+pub struct RecvBuffers<'w, 's, T: Component, M: Any + Send + Sync> {
+    jitter: Option<ResMut<'w, JitterBuffer<M>>>,
+    budget: Option<ResMut<'w, RecvBudget<M>>>,
+    deferred: Option<ResMut<'w, DeferredApply<T, M>>>,
+    group: Option<ResMut<'w, GroupBuffer<T, M>>>,
+    staged: Option<ResMut<'w, StagedUpdates>>,
+    #[system_param(ignore)]
+    _pd: PhantomData<&'s ()>,
+}
+
+impl<'w, 's, T: Component, M: Any + Send + Sync> std::fmt::Debug for RecvBuffers<'w, 's, T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecvBuffers")
+            .field("jitter", &self.jitter.is_some())
+            .field("budget", &self.budget.is_some())
+            .field("deferred", &self.deferred.is_some())
+            .field("group", &self.group.is_some())
+            .field("staged", &self.staged.is_some())
+            .finish()
+    }
+}
+
+impl<'w, 's, T: Component, M: Any + Send + Sync> RecvBuffers<'w, 's, T, M> {
+    /// Applies `msg` to `comp` of `entity` with `apply` as `mode` says, or stages it if the
+    /// updates are staged.
+    fn apply(
+        &mut self,
+        (entity, comp): (Entity, &mut Mut<T>),
+        msg: &M,
+        (apply, mode): (fn(&M, &mut T), ApplyMode),
+        commands: &mut Commands,
+    ) where
+        T: Clone,
+        M: Clone,
+    {
+        match self.staged.as_deref_mut() {
+            Some(staged) => staged.stage(entity, msg.clone(), apply, mode),
+            None => apply_as(mode, entity, msg, comp, apply, commands),
+        }
+    }
+
+    /// Holds `msg`, the possibly sanitized message of `received`, in the [`GroupBuffer`] until
+    /// the rest of its bundle arrives, if `T` is part of a bundle and the update has a tick, or
+    /// queues it in the [`DeferredApply`], if there is one. Otherwise applies it right away, like
+    /// [`apply`](Self::apply).
+    ///
+    /// Returns whether `msg` was applied right away.
+    fn dispatch(
+        &mut self,
+        (entity, comp): (Entity, &mut Mut<T>),
+        received: &RecvNetComp<M>,
+        msg: Cow<M>,
+        (apply, mode, now): (fn(&M, &mut T), ApplyMode, f64),
+        commands: &mut Commands,
+    ) -> bool
+    where
+        T: Clone,
+        M: Clone,
+    {
+        let net_e = received.net_e();
+        let sender = UpdateSender::of(received);
+        match (
+            received.tick,
+            self.group.as_deref_mut(),
+            self.deferred.as_deref_mut(),
+        ) {
+            (Some(tick), Some(group), _) => {
+                group.push(net_e, tick, sender, msg.into_owned(), now);
+                false
+            }
+            (_, _, Some(deferred)) => {
+                deferred.push(net_e, sender, msg.into_owned());
+                false
+            }
+            _ => {
+                self.apply((entity, comp), &msg, (apply, mode), commands);
+                true
+            }
+        }
+    }
+}
+
+/// The components that [`comp_recv`] queries for.
+type RecvItem<'a, T, M> = (
+    Entity,
+    &'a NetEntity,
+    &'a mut NetComp<T, M>,
+    &'a mut T,
+    Option<&'a NetWriteAccess>,
+    Option<&'a OrderedUpdates>,
+    Reports<'a, T>,
+);
+
+/// The components that are updated when a received update of component `T` is applied.
+pub(crate) type Reports<'a, T> = (Option<&'a mut NetSource<T>>, Option<&'a OnNetUpdate<T>>);
+
+/// Reports that an update from `sender` was applied to component `T` of `entity`, with a
+/// [`NetUpdated`] event, its [`NetSource`], which is inserted if the entity doesn't have one yet,
+/// and its [`OnNetUpdate`] hook.
+pub(crate) fn report_applied<T: Component>(
+    entity: Entity,
+    sender: UpdateSender,
+    (source, hook): (Option<&mut NetSource<T>>, Option<&OnNetUpdate<T>>),
+    updated: &mut EventWriter<NetUpdated<T>>,
+    commands: &mut Commands,
+) {
+    let event = NetUpdated::new(entity, sender);
+    if let Some(hook) = hook {
+        (hook.hook)(&event, commands);
+    }
+    updated.send(event);
+    let new_source = NetSource {
+        cid: sender.cid,
+        tick: sender.tick,
+        at: sender.at,
+        _pd: PhantomData,
+    };
+    match source {
+        Some(source) => *source = new_source,
+        None => {
+            commands.entity(entity).insert(new_source);
+        }
+    }
+}
+
+/// Helper function that counts the messages for `net_e`, sent by a client that passes `filter`,
+/// into `stats`.
+fn count_msgs<T, M>(
+    msgs: &[RecvNetComp<M>],
+    net_c: &NetComp<T, M>,
+    filter: impl Fn(CId) -> bool,
+    net_e: &NetEntity,
+    stats: &mut MsgStats,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let times = msgs
+        .iter()
+        .filter(|m| filter(m.cid) && m.is_for(net_e))
+        .map(|m| m.time);
+    if net_c.sequenced() {
+        stats.count(times, net_c.last, net_c.ordered());
+    } else {
+        stats.received += times.count() as u64;
+    }
+}
+
+/// Helper function that gets the messages to apply to `net_c` for `net_e`, in the order to apply
+/// them, sent by a client that passes `filter`, honoring the sequencing and ordering of its
+/// [`Channel`](crate::sync::Channel).
+fn select_msgs<'a, 'm, T, M>(
+    msgs: &'a [RecvNetComp<'m, M>],
+    net_c: &NetComp<T, M>,
+    filter: impl Fn(CId) -> bool,
+    net_e: &NetEntity,
+) -> Vec<&'a RecvNetComp<'m, M>>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    if net_c.ordered() {
+        get_newer_msgs(msgs, net_c.last, filter, net_e)
+    } else if net_c.sequenced() {
+        get_latest_msg(msgs, net_c.last, filter, net_e)
+            .into_iter()
+            .collect()
+    } else {
+        msgs.iter()
+            .rev()
+            .find(|m| filter(m.cid) && m.is_for(net_e))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Helper function that gets every message that passes `filter` for `net_e` and is sent later than
+/// current, in the order they were sent.
+///
+/// Messages without a send time are all kept, in the order they were received.
+fn get_newer_msgs<'a, 'm, M: Any + Send + Sync>(
+    msgs: &'a [RecvNetComp<'m, M>],
+    current: Option<u32>,
+    filter: impl Fn(CId) -> bool,
+    net_e: &NetEntity,
+) -> Vec<&'a RecvNetComp<'m, M>> {
+    let current = current.unwrap_or(0);
+    let mut newer: Vec<_> = msgs
+        .iter()
+        .filter(|m| filter(m.cid) && m.is_for(net_e))
+        .filter(|m| m.time.is_none_or(|time| time > current))
+        .collect();
+    if newer.iter().all(|m| m.time.is_some()) {
+        // The messages are merged from several message types, so they may be out of order.
+        newer.sort_by_key(|m| m.time);
+    }
+    newer
+}
+
+/// Helper function that gets the most recent message that passes `filter` for `net_e` if it is
+/// sent later that current.
+fn get_latest_msg<'a, 'm, M: Any + Send + Sync>(
+    msgs: &'a [RecvNetComp<'m, M>],
     current: Option<u32>,
-    spec: CIdSpec,
-    id: u64,
-) -> Option<&'a NetMsg<'a, NetCompMsg<M>>> {
+    filter: impl Fn(CId) -> bool,
+    net_e: &NetEntity,
+) -> Option<&'a RecvNetComp<'m, M>> {
     let mut latest_time = current.unwrap_or(0);
     let mut latest = None;
-    for m in msgs.iter().filter(|m| spec.matches(m.cid) && m.id == id) {
+    for m in msgs.iter().filter(|m| filter(m.cid) && m.is_for(net_e)) {
         if let Some(time) = m.time {
             // If this packet has a send time, get the last.
             if time > latest_time {
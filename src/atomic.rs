@@ -0,0 +1,362 @@
+//! Applying the updates of the components in a bundle together.
+//!
+//! The components of a [bundle](crate::bundle) are sent as separate messages, which can arrive in
+//! different frames. A client could then see a new position with an old rotation, or a health
+//! value from a different tick. The components of a bundle are kept consistent instead:
+//!
+//! - When any component of the bundle changes on an entity, the sender sends all of them, tagged
+//!   with the same [`SendTick`].
+//! - The receiver, a client or the server, holds the received updates until it has one for
+//!   every component of the bundle that it receives on the entity, and then applies them in the
+//!   same frame.
+//!
+//! A newer complete tick replaces the older held updates. If an update is lost, the rest are
+//! applied anyway once they have waited for [`max_wait`](SyncGroups::max_wait).
+//!
+//! Updates sent over [`UnreliableAcked`](crate::Channel::UnreliableAcked) are applied right away.
+//! Components that skip sends of their own, because of a threshold or send rate on their
+//! [`NetComp`], leave the group incomplete until it times out.
+
+use crate::app::{apply_as, report_applied, Reports, SyncC, UpdateSender};
+use crate::staging::StagedUpdates;
+use crate::sync::{CNetDir, NetComp, NetEntity, SendTick, SyncSchedule};
+use crate::{NetUpdated, SyncInfo};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::{Client, Server};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+/// A label for the systems that record which bundles changed.
+#[derive(SystemLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+struct CollectLabel;
+
+/// A label for the systems that count the held updates.
+#[derive(SystemLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+struct CountLabel;
+
+/// A label for the systems that apply the held updates.
+#[derive(SystemLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+struct ApplyLabel;
+
+/// The bundles whose updates are applied together.
+#[derive(Resource, Clone, Debug)]
+pub struct SyncGroups {
+    /// The time in seconds that held updates wait for the rest of their bundle, before they are
+    /// applied anyway. Defaults to 0.25.
+    pub max_wait: f32,
+    /// The number of bundles.
+    len: usize,
+    /// The schedules of the bundles.
+    schedules: HashSet<SyncSchedule>,
+    /// The entities with a changed component, for every bundle.
+    changed: HashMap<usize, HashSet<Entity>>,
+    /// The number of components of the bundle that every entity receives.
+    expected: HashMap<(usize, NetEntity), usize>,
+    /// The number of held updates of every tick, and when the first of them was received.
+    held: HashMap<(usize, NetEntity), BTreeMap<u32, (usize, f64)>>,
+}
+
+impl Default for SyncGroups {
+    fn default() -> Self {
+        SyncGroups {
+            max_wait: 0.25,
+            len: 0,
+            schedules: HashSet::default(),
+            changed: HashMap::default(),
+            expected: HashMap::default(),
+            held: HashMap::default(),
+        }
+    }
+}
+
+impl SyncGroups {
+    /// The number of bundles.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no bundles.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The newest tick of the held updates of bundle `group` for `net_e` that can be applied:
+    /// complete, or waited long enough.
+    fn ready(&self, group: usize, net_e: &NetEntity, now: f64) -> Option<u32> {
+        let expected = self.expected.get(&(group, *net_e)).copied().unwrap_or(0);
+        self.held
+            .get(&(group, *net_e))?
+            .iter()
+            .rev()
+            .find(|(_, (count, since))| *count >= expected || now - since >= self.max_wait as f64)
+            .map(|(tick, _)| *tick)
+    }
+}
+
+/// The received updates of message type `M` for component `T` of a bundle, held until the rest
+/// of the bundle arrives.
+#[derive(Resource)]
+pub struct GroupBuffer<T, M> {
+    /// The held updates of every entity by tick, with their sender and the time they were
+    /// received.
+    held: HashMap<NetEntity, BTreeMap<u32, (M, UpdateSender, f64)>>,
+    _pd: PhantomData<T>,
+}
+
+impl<T, M> Default for GroupBuffer<T, M> {
+    fn default() -> Self {
+        GroupBuffer {
+            held: HashMap::default(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T, M> std::fmt::Debug for GroupBuffer<T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GroupBuffer")
+            .field("held", &self.held.len())
+            .finish()
+    }
+}
+
+impl<T, M> GroupBuffer<T, M> {
+    /// The number of entities with a held update.
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Whether there are no held updates.
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// Holds `msg` from `sender` for `net_e`, received at `now`.
+    pub(crate) fn push(
+        &mut self,
+        net_e: NetEntity,
+        tag: SendTick,
+        sender: UpdateSender,
+        msg: M,
+        now: f64,
+    ) {
+        self.held
+            .entry(net_e)
+            .or_default()
+            .insert(tag.tick, (msg, sender, now));
+    }
+}
+
+/// Adds a new bundle to the [`SyncGroups`], returning its index.
+pub(crate) fn add_group(app: &mut App, schedule: &SyncSchedule) -> usize {
+    if !app.world.contains_resource::<SyncGroups>() {
+        app.init_resource::<SyncGroups>();
+    }
+    let mut groups = app.world.resource_mut::<SyncGroups>();
+    let group = groups.len;
+    groups.len += 1;
+    // Bundles can run in different stages, which each need to clear the state once.
+    if groups.schedules.insert(*schedule) {
+        let SyncSchedule { recv, send, .. } = *schedule;
+        app.add_system_to_stage(
+            send,
+            schedule.join(clear_group_changes).before(CollectLabel),
+        );
+        app.add_system_to_stage(recv, schedule.join(clear_group_counts).after(ApplyLabel));
+    }
+    group
+}
+
+/// Adds the resources and systems that keep the components of bundle `group` consistent, for
+/// component `T` with message type `M`.
+pub(crate) fn add_group_systems<T, M>(app: &mut App, group: usize, schedule: &SyncSchedule)
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let SyncSchedule { recv, send, .. } = *schedule;
+    app.world.resource_mut::<SyncInfo<T, M>>().set_group(group);
+    app.init_resource::<GroupBuffer<T, M>>();
+    app.add_system_to_stage(
+        send,
+        schedule
+            .join(collect_group_changes::<T, M>)
+            .label(CollectLabel),
+    );
+    app.add_system_to_stage(
+        send,
+        schedule
+            .join(send_group_changes::<T, M>)
+            .after(CollectLabel)
+            .before(crate::app::send_on_event::<T, M>),
+    );
+    app.add_system_to_stage(
+        recv,
+        schedule
+            .join(count_group_updates::<T, M>)
+            .label(CountLabel)
+            .after(crate::app::comp_recv::<T, M>),
+    );
+    app.add_system_to_stage(
+        recv,
+        schedule
+            .join(apply_group_updates::<T, M>)
+            .label(ApplyLabel)
+            .after(CountLabel),
+    );
+}
+
+/// Clears the changed entities of the last frame.
+fn clear_group_changes(mut groups: ResMut<SyncGroups>) {
+    groups.changed.clear();
+}
+
+/// Clears the counts of the held updates, once they are applied.
+fn clear_group_counts(mut groups: ResMut<SyncGroups>) {
+    groups.expected.clear();
+    groups.held.clear();
+}
+
+/// Records the entities whose component `T`, which is sent by this peer, changed.
+fn collect_group_changes<T, M>(
+    server: Option<Res<Server>>,
+    info: Res<SyncInfo<T, M>>,
+    mut groups: ResMut<SyncGroups>,
+    q: Query<(Entity, &NetComp<T, M>, ChangeTrackers<T>)>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let group = match info.group() {
+        Some(group) => group,
+        None => return,
+    };
+    let is_server = server.is_some();
+    for (entity, net_c, ct) in q.iter() {
+        let sends = match is_server {
+            true => net_c.s_dir.to().is_some(),
+            false => net_c.c_dir == CNetDir::To,
+        };
+        if sends && net_c.cd && ct.is_changed() {
+            groups.changed.entry(group).or_default().insert(entity);
+        }
+    }
+}
+
+/// Forces a sync of component `T` on the entities where another component of its bundle
+/// changed, so the whole bundle is sent on the same tick.
+fn send_group_changes<T, M>(
+    info: Res<SyncInfo<T, M>>,
+    groups: Res<SyncGroups>,
+    q: Query<(&NetComp<T, M>, ChangeTrackers<T>)>,
+    mut ew: EventWriter<SyncC<T>>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let changed = match info.group().and_then(|group| groups.changed.get(&group)) {
+        Some(changed) => changed,
+        None => return,
+    };
+    for entity in changed.iter() {
+        // Changed components, and ones without change detection, are sent anyway.
+        if let Ok((net_c, ct)) = q.get(*entity) {
+            if net_c.cd && !ct.is_changed() {
+                ew.send(SyncC::entity(*entity));
+            }
+        }
+    }
+}
+
+/// Counts the held updates of component `T` and the components of the bundle that each entity
+/// receives.
+fn count_group_updates<T, M>(
+    client: Option<Res<Client>>,
+    server: Option<Res<Server>>,
+    info: Res<SyncInfo<T, M>>,
+    buffer: Res<GroupBuffer<T, M>>,
+    mut groups: ResMut<SyncGroups>,
+    q: Query<(&NetEntity, &NetComp<T, M>)>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let group = match info.group() {
+        Some(group) if client.is_some() || server.is_some() => group,
+        _ => return,
+    };
+    let is_server = server.is_some();
+    for (net_e, net_c) in q.iter() {
+        let receives = match is_server {
+            true => net_c.s_dir.from().is_some(),
+            false => net_c.c_dir == CNetDir::From,
+        };
+        if receives {
+            *groups.expected.entry((group, *net_e)).or_default() += 1;
+        }
+    }
+    for (net_e, ticks) in buffer.held.iter() {
+        let held = groups.held.entry((group, *net_e)).or_default();
+        for (tick, (_, _, received)) in ticks.iter() {
+            let (count, since) = held.entry(*tick).or_insert((0, *received));
+            *count += 1;
+            *since = since.min(*received);
+        }
+    }
+}
+
+/// Applies the held updates of component `T` whose bundle is complete, or waited long enough,
+/// and reports them with a [`NetUpdated`] event, the [`NetSource`](crate::NetSource) and the
+/// [`OnNetUpdate`](crate::OnNetUpdate) hook.
+fn apply_group_updates<T, M>(
+    time: Res<Time>,
+    info: Res<SyncInfo<T, M>>,
+    groups: Res<SyncGroups>,
+    mut buffer: ResMut<GroupBuffer<T, M>>,
+    mut staged: Option<ResMut<StagedUpdates>>,
+    (mut updated, mut commands): (EventWriter<NetUpdated<T>>, Commands),
+    mut q: Query<(Entity, &NetEntity, &mut T, Reports<T>)>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let group = match info.group() {
+        Some(group) if !buffer.is_empty() => group,
+        _ => return,
+    };
+    let apply = info.apply();
+    let mode = info.apply_mode();
+    let now = time.elapsed_seconds_f64();
+    let mut seen = HashSet::default();
+    for (entity, net_e, mut comp, (mut source, hook)) in q.iter_mut() {
+        let held = match buffer.held.get_mut(net_e) {
+            Some(held) => held,
+            None => continue,
+        };
+        seen.insert(*net_e);
+        let tick = match groups.ready(group, net_e, now) {
+            Some(tick) => tick,
+            None => continue,
+        };
+        // Apply the newest update up to the ready tick, in case this one was lost, and drop the
+        // older ones.
+        let newer = held.split_off(&tick.wrapping_add(1));
+        if let Some((msg, sender, _)) = held.values().next_back() {
+            match staged.as_deref_mut() {
+                Some(staged) => staged.stage(entity, msg.clone(), apply, mode),
+                None => apply_as(mode, entity, msg, &mut comp, apply, &mut commands),
+            }
+            let reports = (source.as_deref_mut(), hook);
+            report_applied(entity, *sender, reports, &mut updated, &mut commands);
+        }
+        *held = newer;
+    }
+    // Drop the updates of despawned entities.
+    buffer
+        .held
+        .retain(|net_e, held| !held.is_empty() && seen.contains(net_e));
+}
@@ -0,0 +1,53 @@
+//! Events for when authority over an entity is handed over.
+//!
+//! The peer with authority over an entity is the one that simulates it and sends its components:
+//! a client with a [`CNetDir::To`] direction, or the server when it sends the component without
+//! receiving it from any client. When the direction of a [`NetComp`](crate::sync::NetComp) is
+//! changed at runtime, such as an entity becoming server controlled, the affected peer gets an
+//! [`AuthorityGained`] or [`AuthorityLost`] event, so gameplay code can turn the local simulation
+//! on or off:
+//!
+//! ```ignore
+//! fn toggle_physics(
+//!     mut gained: EventReader<AuthorityGained>,
+//!     mut lost: EventReader<AuthorityLost>,
+//!     mut q: Query<&mut RigidBody>,
+//! ) {
+//!     for AuthorityGained(entity) in gained.iter() {
+//!         if let Ok(mut body) = q.get_mut(*entity) {
+//!             *body = RigidBody::Dynamic;
+//!         }
+//!     }
+//!     for AuthorityLost(entity) in lost.iter() {
+//!         if let Ok(mut body) = q.get_mut(*entity) {
+//!             *body = RigidBody::KinematicPositionBased;
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! The events are sent for every synced component type whose direction changed, so an entity
+//! with several [`NetComp`](crate::sync::NetComp)s that are handed over together gets one event
+//! per type. They are not sent when an entity is spawned.
+
+use crate::sync::{CNetDir, SNetDir};
+use bevy::prelude::*;
+
+/// An event that is sent when this peer gains authority over the entity.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct AuthorityGained(pub Entity);
+
+/// An event that is sent when this peer loses authority over the entity.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct AuthorityLost(pub Entity);
+
+/// Whether this peer has authority over a component with the directions `c_dir` and `s_dir`.
+///
+/// `server` is whether this peer is the server.
+pub(crate) fn is_authority(c_dir: CNetDir, s_dir: SNetDir, server: bool) -> bool {
+    if server {
+        s_dir.to().is_some() && s_dir.from().is_none()
+    } else {
+        c_dir == CNetDir::To
+    }
+}
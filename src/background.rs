@@ -0,0 +1,249 @@
+//! Receiving messages on a dedicated networking thread.
+//!
+//! Normally, [`client_tick`] and [`server_tick`] read the sockets and deserialize the received
+//! messages at the start of the frame, so a large burst of messages eats into the frame time.
+//! With the [`BackgroundRecvPlugin`], a dedicated thread does this instead, for the whole time
+//! between the end of one frame and the start of the next, while rendering or the frame limiter
+//! runs. The tick systems then only pick up the messages that were already received.
+//!
+//! `carrier-pigeon` owns the sockets in the [`Client`] or [`Server`], and keeps the deserialized
+//! messages in it, so the connection itself is handed over a channel: it is sent to the thread in
+//! the [`BackgroundRecvStage`] after the [`NetStage::Send`], and the thread polls it for new
+//! messages until [`CoreStage::First`] asks for it back. The thread then polls it once more and
+//! sends it back, so the first stage only waits for the messages of that last poll. Systems that
+//! run outside of the schedule, or in sub apps, can't use the connection in between.
+//!
+//! ```ignore
+//! app.add_plugin(ClientPlugin).add_plugin(BackgroundRecvPlugin);
+//! ```
+
+use crate::app::{client_tick, server_tick};
+use crate::stage::{init_net_stages, NetStage};
+use bevy::prelude::*;
+use carrier_pigeon::{Client, Server};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::marker::PhantomData;
+use std::thread;
+use std::time::Duration;
+
+/// The time that the receive thread waits between polls of the connection.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The label of the stage that hands the connection to the background task.
+#[derive(StageLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct BackgroundRecvStage;
+
+/// A plugin that receives the messages of the [`Client`] and [`Server`] on a dedicated thread.
+///
+/// This should be added after the [`ClientPlugin`](crate::ClientPlugin) or
+/// [`ServerPlugin`](crate::ServerPlugin).
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct BackgroundRecvPlugin;
+
+impl Plugin for BackgroundRecvPlugin {
+    fn build(&self, app: &mut App) {
+        init_net_stages(app);
+        app.add_stage_after(
+            NetStage::Send,
+            BackgroundRecvStage,
+            SystemStage::single_threaded(),
+        )
+        .add_system_to_stage(BackgroundRecvStage, start_recv::<Client>)
+        .add_system_to_stage(BackgroundRecvStage, start_recv::<Server>)
+        .add_system_to_stage(CoreStage::First, finish_recv::<Client>.before(client_tick))
+        .add_system_to_stage(CoreStage::First, finish_recv::<Server>.before(server_tick));
+    }
+}
+
+/// A connection that can receive messages.
+pub(crate) trait Peer: Resource {
+    /// Clears the message buffer.
+    fn clear(&mut self);
+
+    /// Receives new messages into the message buffer, returning the number received.
+    fn recv_more(&mut self) -> u32;
+}
+
+impl Peer for Client {
+    fn clear(&mut self) {
+        self.clear_msgs();
+    }
+
+    fn recv_more(&mut self) -> u32 {
+        self.recv_msgs()
+    }
+}
+
+impl Peer for Server {
+    fn clear(&mut self) {
+        self.clear_msgs();
+    }
+
+    fn recv_more(&mut self) -> u32 {
+        self.recv_msgs()
+    }
+}
+
+/// The channels to the dedicated thread that receives the messages of connection `P`.
+#[derive(Resource)]
+struct RecvThread<P> {
+    /// Hands the connection to the thread.
+    conns: Sender<P>,
+    /// Asks the thread to hand the connection back.
+    stop: Sender<()>,
+    /// The connection, and the number of messages it received, from the thread.
+    back: Receiver<(P, u32)>,
+    /// Whether the connection is on the thread.
+    away: bool,
+}
+
+impl<P: Peer> RecvThread<P> {
+    /// Spawns the receive thread.
+    ///
+    /// ### Panics
+    /// panics if the thread can't be spawned.
+    fn spawn() -> Self {
+        let (conns, conns_rx) = unbounded();
+        let (stop, stop_rx) = unbounded();
+        let (back_tx, back) = unbounded();
+        thread::Builder::new()
+            .name("bevy-pigeon-recv".into())
+            .spawn(move || recv_loop(conns_rx, stop_rx, back_tx))
+            .expect("failed to spawn the receive thread");
+        RecvThread {
+            conns,
+            stop,
+            back,
+            away: false,
+        }
+    }
+}
+
+/// Polls every connection that is handed to the thread for new messages, until it is asked to
+/// hand it back.
+///
+/// Returns once the app, and with it the other end of the channels, is dropped.
+fn recv_loop<P: Peer>(conns: Receiver<P>, stop: Receiver<()>, back: Sender<(P, u32)>) {
+    while let Ok(mut peer) = conns.recv() {
+        peer.clear();
+        let mut msgs = 0;
+        loop {
+            msgs += peer.recv_more();
+            match stop.recv_timeout(POLL_INTERVAL) {
+                Ok(()) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        // Pick up the messages that arrived while the app was waiting.
+        msgs += peer.recv_more();
+        if back.send((peer, msgs)).is_err() {
+            return;
+        }
+    }
+}
+
+/// A marker that the messages of connection `P` were already received in the background this
+/// frame.
+#[derive(Resource, Debug)]
+pub struct Received<P> {
+    /// The number of messages that were received.
+    pub msgs: u32,
+    _pd: PhantomData<P>,
+}
+
+impl<P> Received<P> {
+    /// Marks that `msgs` messages were already received.
+    pub(crate) fn new(msgs: u32) -> Self {
+        Received {
+            msgs,
+            _pd: PhantomData,
+        }
+    }
+}
+
+/// Hands connection `P` to its receive thread, spawning the thread the first time.
+///
+/// If the thread has stopped, the connection stays in the world, and the tick systems receive
+/// its messages as usual.
+pub(crate) fn start_recv<P: Peer>(world: &mut World) {
+    let peer = match world.remove_resource::<P>() {
+        Some(peer) => peer,
+        None => return,
+    };
+    let mut thread = world
+        .remove_resource::<RecvThread<P>>()
+        .unwrap_or_else(RecvThread::spawn);
+    match thread.conns.send(peer) {
+        Ok(()) => thread.away = true,
+        Err(e) => world.insert_resource(e.into_inner()),
+    }
+    world.insert_resource(thread);
+}
+
+/// Takes connection `P` back from its receive thread, and inserts it back into the world with
+/// the messages it received.
+pub(crate) fn finish_recv<P: Peer>(world: &mut World) {
+    world.remove_resource::<Received<P>>();
+    let back = match world.get_resource_mut::<RecvThread<P>>() {
+        Some(mut thread) if thread.away => {
+            thread.away = false;
+            // The thread only polls once more after this.
+            let _ = thread.stop.send(());
+            thread.back.recv()
+        }
+        _ => return,
+    };
+    match back {
+        Ok((peer, msgs)) => {
+            world.insert_resource(peer);
+            world.insert_resource(Received::<P>::new(msgs));
+        }
+        Err(_) => error!(
+            "The receive thread of the {} stopped, and took the connection with it",
+            std::any::type_name::<P>()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A connection that receives one message per poll.
+    #[derive(Resource, Default)]
+    struct FakePeer {
+        buffered: u32,
+    }
+
+    impl Peer for FakePeer {
+        fn clear(&mut self) {
+            self.buffered = 0;
+        }
+
+        fn recv_more(&mut self) -> u32 {
+            self.buffered += 1;
+            1
+        }
+    }
+
+    #[test]
+    fn connection_is_received_on_the_thread_and_handed_back() {
+        let mut world = World::new();
+        world.insert_resource(FakePeer { buffered: 10 });
+
+        start_recv::<FakePeer>(&mut world);
+        assert!(!world.contains_resource::<FakePeer>());
+
+        finish_recv::<FakePeer>(&mut world);
+        let msgs = world.resource::<Received<FakePeer>>().msgs;
+        // At least the first poll and the one after it was asked back.
+        assert!(msgs >= 2);
+        assert_eq!(world.resource::<FakePeer>().buffered, msgs);
+
+        // The same thread is used again.
+        start_recv::<FakePeer>(&mut world);
+        finish_recv::<FakePeer>(&mut world);
+        assert!(world.contains_resource::<FakePeer>());
+    }
+}
@@ -0,0 +1,483 @@
+//! Packing small fields into bits.
+//!
+//! Most formats spend at least a byte on every `bool` and small enum. A type that implements
+//! [`BitPack`] describes itself in a fixed number of bits instead, and can be sent as:
+//!
+//! - [`Packed<T>`], which sends a single value in `ceil(T::BITS / 8)` bytes, so a struct of eight
+//!   flags takes one byte instead of eight.
+//! - [`PackedVec<T>`], which sends a batch of values back to back, so a batch of 64
+//!   [`NetVisibility`](crate::types::NetVisibility)s takes 8 bytes, plus 4 for the length.
+//!
+//! ```ignore
+//! #[derive(Clone, Component)]
+//! struct Flags {
+//!     grounded: bool,
+//!     crouching: bool,
+//!     team: u8,
+//! }
+//!
+//! impl BitPack for Flags {
+//!     const BITS: u32 = 4;
+//!
+//!     fn pack(&self, w: &mut BitWriter) {
+//!         self.grounded.pack(w);
+//!         self.crouching.pack(w);
+//!         w.write_bits(self.team as u64, 2);
+//!     }
+//!
+//!     fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+//!         Ok(Flags {
+//!             grounded: bool::unpack(r)?,
+//!             crouching: bool::unpack(r)?,
+//!             team: r.read_bits(2)? as u8,
+//!         })
+//!     }
+//! }
+//!
+//! app.sync_comp::<Flags, Packed<Flags>>(&mut table, Transport::UDP);
+//! ```
+//!
+//! The `Flags -> Packed<Flags>` conversion is implemented for you. Like with
+//! [`Encoded`](crate::format::Encoded), the orphan rules don't allow implementing the other
+//! direction here, so it has to be implemented in your crate. With the `types` feature, the small
+//! types in [`types`](crate::types) implement [`BitPack`], and convert both ways.
+
+use serde::de::{Error as _, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// The error when a [`BitReader`] runs out of bits.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct OutOfBits;
+
+impl Display for OutOfBits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ran out of bits")
+    }
+}
+
+impl std::error::Error for OutOfBits {}
+
+/// Writes values bit by bit, least significant bit first.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    /// The number of bits written.
+    len: usize,
+}
+
+impl BitWriter {
+    /// Creates an empty [`BitWriter`].
+    pub fn new() -> Self {
+        BitWriter::default()
+    }
+
+    /// Writes a single bit.
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Writes the lowest `count` bits of `value`.
+    ///
+    /// ### Panics
+    /// panics if `count` is more than 64.
+    pub fn write_bits(&mut self, value: u64, count: u32) {
+        assert!(count <= 64, "can't write more than 64 bits at once");
+        for i in 0..count {
+            self.write_bit(value >> i & 1 != 0);
+        }
+    }
+
+    /// The number of bits written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no bits were written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the written bytes. The unused bits of the last byte are 0.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads values bit by bit, least significant bit first.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    /// The number of bits read.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Creates a [`BitReader`] that reads `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, pos: 0 }
+    }
+
+    /// Reads a single bit.
+    pub fn read_bit(&mut self) -> Result<bool, OutOfBits> {
+        let byte = self.bytes.get(self.pos / 8).ok_or(OutOfBits)?;
+        let bit = byte >> (self.pos % 8) & 1 != 0;
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    /// Reads `count` bits into the lowest bits of a `u64`.
+    ///
+    /// ### Panics
+    /// panics if `count` is more than 64.
+    pub fn read_bits(&mut self, count: u32) -> Result<u64, OutOfBits> {
+        assert!(count <= 64, "can't read more than 64 bits at once");
+        let mut value = 0;
+        for i in 0..count {
+            value |= (self.read_bit()? as u64) << i;
+        }
+        Ok(value)
+    }
+
+    /// The number of bits left to read.
+    pub fn remaining(&self) -> usize {
+        (self.bytes.len() * 8).saturating_sub(self.pos)
+    }
+}
+
+/// A type that can be written in a fixed number of bits.
+pub trait BitPack: Sized {
+    /// The number of bits that every value is written in.
+    const BITS: u32;
+
+    /// Writes `self` in exactly [`BITS`](Self::BITS) bits.
+    fn pack(&self, w: &mut BitWriter);
+
+    /// Reads a value that was written with [`pack`](Self::pack).
+    fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits>;
+}
+
+impl BitPack for bool {
+    const BITS: u32 = 1;
+
+    fn pack(&self, w: &mut BitWriter) {
+        w.write_bit(*self);
+    }
+
+    fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+        r.read_bit()
+    }
+}
+
+macro_rules! impl_bit_pack_int {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl BitPack for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn pack(&self, w: &mut BitWriter) {
+                    w.write_bits(*self as $u as u64, Self::BITS);
+                }
+
+                fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+                    Ok(r.read_bits(Self::BITS)? as $u as $t)
+                }
+            }
+        )*
+    };
+}
+
+impl_bit_pack_int!(u8 => u8, u16 => u16, u32 => u32, u64 => u64, i8 => u8, i16 => u16, i32 => u32, i64 => u64);
+
+impl BitPack for f32 {
+    const BITS: u32 = 32;
+
+    fn pack(&self, w: &mut BitWriter) {
+        self.to_bits().pack(w);
+    }
+
+    fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+        u32::unpack(r).map(f32::from_bits)
+    }
+}
+
+/// The number of bytes that `count` values of `T` take.
+fn byte_len<T: BitPack>(count: usize) -> usize {
+    (count * T::BITS as usize).div_ceil(8)
+}
+
+/// A message `T` that is sent packed into bits.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Packed<T: BitPack>(pub T);
+
+impl<T: BitPack> Packed<T> {
+    /// Gets the inner message.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: BitPack> From<T> for Packed<T> {
+    fn from(msg: T) -> Self {
+        Packed(msg)
+    }
+}
+
+impl<T: BitPack> Deref for Packed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: BitPack> DerefMut for Packed<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: BitPack> Serialize for Packed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut w = BitWriter::new();
+        self.0.pack(&mut w);
+        // A tuple has no length prefix; the length follows from `T::BITS`.
+        let bytes = w.into_bytes();
+        let mut tuple = serializer.serialize_tuple(bytes.len())?;
+        for byte in bytes.iter() {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de, T: BitPack> Deserialize<'de> for Packed<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let len = byte_len::<T>(1);
+        let bytes = deserializer.deserialize_tuple(len, BytesVisitor { len })?;
+        let msg = T::unpack(&mut BitReader::new(&bytes)).map_err(D::Error::custom)?;
+        Ok(Packed(msg))
+    }
+}
+
+/// A batch of messages `T` that are sent packed into bits, back to back.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct PackedVec<T: BitPack>(pub Vec<T>);
+
+impl<T: BitPack> From<Vec<T>> for PackedVec<T> {
+    fn from(msgs: Vec<T>) -> Self {
+        PackedVec(msgs)
+    }
+}
+
+impl<T: BitPack> Deref for PackedVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T: BitPack> DerefMut for PackedVec<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<T: BitPack> Serialize for PackedVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let count = u32::try_from(self.0.len()).map_err(serde::ser::Error::custom)?;
+        let mut w = BitWriter::new();
+        for msg in self.0.iter() {
+            msg.pack(&mut w);
+        }
+        let bytes = w.into_bytes();
+        let mut tuple = serializer.serialize_tuple(1 + bytes.len())?;
+        tuple.serialize_element(&count)?;
+        for byte in bytes.iter() {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de, T: BitPack> Deserialize<'de> for PackedVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (count, bytes) =
+            deserializer.deserialize_tuple(usize::MAX, CountedVisitor::<T> { _pd: PhantomData })?;
+        let mut r = BitReader::new(&bytes);
+        let msgs = (0..count)
+            .map(|_| T::unpack(&mut r))
+            .collect::<Result<_, _>>()
+            .map_err(D::Error::custom)?;
+        Ok(PackedVec(msgs))
+    }
+}
+
+/// Reads `len` bytes from a tuple.
+struct BytesVisitor {
+    len: usize,
+}
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "packed bytes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(self.len);
+        for i in 0..self.len {
+            let byte = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Reads the count of a [`PackedVec<T>`], and then the bytes of that many values, from a tuple.
+struct CountedVisitor<T> {
+    _pd: PhantomData<T>,
+}
+
+impl<'de, T: BitPack> Visitor<'de> for CountedVisitor<T> {
+    type Value = (u32, Vec<u8>);
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "a count followed by packed bytes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let count: u32 = seq
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        // Don't allocate up front, so a bogus count can't allocate more than the message holds.
+        let mut bytes = vec![];
+        for i in 0..byte_len::<T>(count as usize) {
+            let byte = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i + 1, &self))?;
+            bytes.push(byte);
+        }
+        Ok((count, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A value that takes 3 bits, so the values after it aren't byte-aligned.
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    struct Three(u8);
+
+    impl BitPack for Three {
+        const BITS: u32 = 3;
+
+        fn pack(&self, w: &mut BitWriter) {
+            w.write_bits(self.0 as u64, 3);
+        }
+
+        fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+            Ok(Three(r.read_bits(3)? as u8))
+        }
+    }
+
+    #[test]
+    fn unaligned_values_round_trip() {
+        let mut w = BitWriter::new();
+        w.write_bits(0b101, 3);
+        w.write_bit(true);
+        w.write_bits(0x1abc, 13);
+        w.write_bits(0, 0);
+        w.write_bits(0b10, 2);
+        assert_eq!(w.len(), 19);
+        let bytes = w.into_bytes();
+        assert_eq!(bytes.len(), 3);
+
+        let mut r = BitReader::new(&bytes);
+        assert_eq!(r.read_bits(3), Ok(0b101));
+        assert_eq!(r.read_bit(), Ok(true));
+        assert_eq!(r.read_bits(13), Ok(0x1abc));
+        assert_eq!(r.read_bits(0), Ok(0));
+        assert_eq!(r.read_bits(2), Ok(0b10));
+        // The unused bits of the last byte are 0.
+        assert_eq!(r.remaining(), 5);
+        assert_eq!(r.read_bits(5), Ok(0));
+    }
+
+    #[test]
+    fn full_width_values_round_trip() {
+        let mut w = BitWriter::new();
+        // Offset everything by a bit, so no value starts on a byte.
+        true.pack(&mut w);
+        u64::MAX.pack(&mut w);
+        i64::MIN.pack(&mut w);
+        u32::MAX.pack(&mut w);
+        (-1i8).pack(&mut w);
+        f32::MIN_POSITIVE.pack(&mut w);
+        (-0.0f32).pack(&mut w);
+        let bytes = w.into_bytes();
+
+        let mut r = BitReader::new(&bytes);
+        assert_eq!(bool::unpack(&mut r), Ok(true));
+        assert_eq!(u64::unpack(&mut r), Ok(u64::MAX));
+        assert_eq!(i64::unpack(&mut r), Ok(i64::MIN));
+        assert_eq!(u32::unpack(&mut r), Ok(u32::MAX));
+        assert_eq!(i8::unpack(&mut r), Ok(-1));
+        assert_eq!(f32::unpack(&mut r), Ok(f32::MIN_POSITIVE));
+        assert_eq!(
+            f32::unpack(&mut r).map(f32::to_bits),
+            Ok((-0.0f32).to_bits())
+        );
+    }
+
+    #[test]
+    fn reading_past_the_end_is_an_error() {
+        let mut r = BitReader::new(&[0xff]);
+        assert_eq!(r.read_bits(9), Err(OutOfBits));
+
+        let mut r = BitReader::new(&[0xff]);
+        assert_eq!(r.read_bits(8), Ok(0xff));
+        assert_eq!(r.remaining(), 0);
+        assert_eq!(r.read_bit(), Err(OutOfBits));
+        assert_eq!(BitReader::new(&[]).read_bit(), Err(OutOfBits));
+    }
+
+    #[test]
+    fn packed_values_round_trip() {
+        let packed = Packed(u16::MAX);
+        let bytes = bincode::serialize(&packed).unwrap();
+        assert_eq!(bytes.len(), 2);
+        assert_eq!(bincode::deserialize::<Packed<u16>>(&bytes).unwrap(), packed);
+        assert!(bincode::deserialize::<Packed<u16>>(&bytes[..1]).is_err());
+
+        let vec = PackedVec((0..8).map(Three).collect());
+        let bytes = bincode::serialize(&vec).unwrap();
+        // 4 bytes of count, and 24 bits.
+        assert_eq!(bytes.len(), 4 + 3);
+        assert_eq!(
+            bincode::deserialize::<PackedVec<Three>>(&bytes).unwrap(),
+            vec
+        );
+    }
+
+    #[test]
+    fn packed_vec_with_a_bogus_count_is_an_error() {
+        let mut bytes = bincode::serialize(&PackedVec(vec![Three(1)])).unwrap();
+        bytes[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(bincode::deserialize::<PackedVec<Three>>(&bytes).is_err());
+    }
+}
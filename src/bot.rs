@@ -0,0 +1,237 @@
+//! Headless bot clients for automated tests and load tests. Requires the `testing` feature.
+//!
+//! A [`BotClient`] is a client [`App`] without rendering or input, connected to a server over the
+//! normal transport. It answers the connection handshake on its own, and runs scripted behaviors
+//! instead of a player, so the server logic, relevancy and bandwidth can be exercised with many
+//! clients:
+//!
+//! ```ignore
+//! let mut bots = (0..50)
+//!     .map(|_| {
+//!         let mut bot = BotClient::connect::<Join, Welcome>(addr, parts.clone(), Join::bot(), |app, table| {
+//!             app.add_movement(table);
+//!             app.sync_comp::<Transform, NetTransform>(table, Transport::UDP);
+//!         })?;
+//!         bot.wander(1.0).echo_received::<Transform>();
+//!         Ok(bot)
+//!     })
+//!     .collect::<io::Result<Vec<_>>>()?;
+//!
+//! loop {
+//!     for bot in bots.iter_mut() {
+//!         bot.update();
+//!     }
+//! }
+//! ```
+//!
+//! The built-in behaviors are [`wander`](BotClient::wander), which walks in a random direction
+//! using the [`MoveInput`] of the [`movement`](crate::movement) module, and
+//! [`echo_received`](BotClient::echo_received), which logs and counts the received updates of a
+//! component. Any other behavior can be added as a system with
+//! [`add_behavior`](BotClient::add_behavior).
+
+use crate::input::LocalInput;
+use crate::movement::MoveInput;
+use crate::schema::MsgSchema;
+use crate::sync::NetEntity;
+use crate::version::{Handshake, Versioned};
+use crate::ClientPlugin;
+use bevy::ecs::schedule::IntoSystemDescriptor;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::net::Config;
+use carrier_pigeon::{Client, MsgTable, MsgTableParts};
+use std::any::Any;
+use std::fmt::Debug;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The seed of the next [`wander`](BotClient::wander) behavior.
+static NEXT_SEED: AtomicU64 = AtomicU64::new(1);
+
+/// A headless client app that is driven by scripted behaviors.
+#[derive(Debug)]
+pub struct BotClient {
+    /// The client app.
+    pub app: App,
+}
+
+impl BotClient {
+    /// Connects a new bot to the server at `addr`, blocking until the server responds.
+    ///
+    /// `setup` is called with a message table, and should register the synced components and
+    /// messages the same way the server does; the table itself isn't used, since `parts` is.
+    /// `C` and `R` are the connection and response message types that `parts` was built with.
+    pub fn connect<C, R>(
+        addr: SocketAddr,
+        parts: MsgTableParts,
+        con_msg: C,
+        setup: impl FnOnce(&mut App, &mut MsgTable),
+    ) -> io::Result<Self>
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        let app = bot_app(setup);
+        let (client, _) = Client::new(addr, parts, Config::default(), con_msg).block::<R>()?;
+        Ok(BotClient::new(app, client))
+    }
+
+    /// Connects a new bot to a server that checks the
+    /// [`ProtocolVersion`](crate::version::ProtocolVersion), blocking until the server responds.
+    ///
+    /// Same as [`connect`](Self::connect), but `parts` was built with [`Versioned<C>`] and
+    /// [`Handshake<R>`]. The bot sends game version `game` and the hash of its
+    /// [`MsgSchema`], and fails if the server rejects it.
+    pub fn connect_versioned<C, R>(
+        addr: SocketAddr,
+        parts: MsgTableParts,
+        game: u32,
+        con_msg: C,
+        setup: impl FnOnce(&mut App, &mut MsgTable),
+    ) -> io::Result<Self>
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        let app = bot_app(setup);
+        let mut con_msg = Versioned::new(game, con_msg);
+        if let Some(schema) = app.world.get_resource::<MsgSchema>() {
+            con_msg = con_msg.with_schema(schema);
+        }
+        let (client, response) =
+            Client::new(addr, parts, Config::default(), con_msg).block::<Handshake<R>>()?;
+        if let Handshake::Rejected(reason) = response {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                reason.to_string(),
+            ));
+        }
+        Ok(BotClient::new(app, client))
+    }
+
+    /// Creates a bot from its app and connected client, and runs the startup systems.
+    fn new(mut app: App, client: Client) -> Self {
+        app.insert_resource(client);
+        app.update();
+        BotClient { app }
+    }
+
+    /// Updates the app once.
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    /// Whether the bot is still connected.
+    pub fn is_connected(&self) -> bool {
+        self.app.world.contains_resource::<Client>()
+    }
+
+    /// Walks in a random direction, picking a new one every `interval` seconds.
+    ///
+    /// This writes the [`LocalInput<MoveInput>`], so it needs the movement, added with
+    /// [`add_movement`](crate::AppExt::add_movement) in the setup.
+    pub fn wander(&mut self, interval: f32) -> &mut Self {
+        // Every bot walks its own way, but the same way on every run.
+        let seed = NEXT_SEED.fetch_add(1, Ordering::Relaxed);
+        self.app
+            .insert_resource(Wander::new(interval, seed))
+            .add_system(wander);
+        self
+    }
+
+    /// Logs every received change of component `T`, and counts them in the [`BotReceived`].
+    pub fn echo_received<T: Component + Debug>(&mut self) -> &mut Self {
+        self.app
+            .init_resource::<BotReceived>()
+            .add_system(echo_received::<T>);
+        self
+    }
+
+    /// Adds a behavior, which is a system that runs every update.
+    pub fn add_behavior<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self {
+        self.app.add_system(system);
+        self
+    }
+}
+
+/// Creates the app of a bot, and calls `setup` on it.
+fn bot_app(setup: impl FnOnce(&mut App, &mut MsgTable)) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).add_plugin(ClientPlugin);
+    setup(&mut app, &mut MsgTable::new());
+    app
+}
+
+/// The number of received changes of every component type, by type name, counted by
+/// [`echo_received`](BotClient::echo_received).
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct BotReceived(pub HashMap<&'static str, u64>);
+
+impl BotReceived {
+    /// The number of received changes of component `T`.
+    pub fn count<T: Component>(&self) -> u64 {
+        self.0.get(std::any::type_name::<T>()).copied().unwrap_or(0)
+    }
+}
+
+/// The state of the [`wander`](BotClient::wander) behavior.
+#[derive(Resource, Copy, Clone, PartialEq, Debug)]
+struct Wander {
+    /// The seconds between changes of direction.
+    interval: f32,
+    /// The time until the next change of direction.
+    left: f32,
+    /// The state of the xorshift generator.
+    rng: u64,
+}
+
+impl Wander {
+    fn new(interval: f32, seed: u64) -> Self {
+        Wander {
+            interval,
+            left: 0.0,
+            // Xorshift gets stuck on 0.
+            rng: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    /// A random number in `-1.0..1.0`.
+    fn next(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+/// Picks a new random [`MoveInput`] every interval.
+fn wander(
+    time: Res<Time>,
+    mut wander: ResMut<Wander>,
+    input: Option<ResMut<LocalInput<MoveInput>>>,
+) {
+    let mut input = match input {
+        Some(input) => input,
+        None => return,
+    };
+    wander.left -= time.delta_seconds();
+    if wander.left > 0.0 {
+        return;
+    }
+    wander.left = wander.interval;
+    let dir = Vec3::new(wander.next(), 0.0, wander.next());
+    input.0.dir = dir.normalize_or_zero();
+}
+
+/// Logs and counts the received changes of component `T`.
+fn echo_received<T: Component + Debug>(
+    mut received: ResMut<BotReceived>,
+    q: Query<(&NetEntity, &T), Changed<T>>,
+) {
+    for (net_e, comp) in q.iter() {
+        info!("Received {:?} on NetEntity {{ id: {} }}", comp, net_e.id);
+        *received.0.entry(std::any::type_name::<T>()).or_default() += 1;
+    }
+}
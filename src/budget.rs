@@ -0,0 +1,99 @@
+//! Limiting how many received updates are applied per frame.
+//!
+//! After a hitch, a frame can receive many frames' worth of updates at once, and applying them
+//! all makes that frame slow too, which can cascade. With a [`RecvBudget<M>`], at most
+//! [`max_msgs`](RecvBudget::max_msgs) updates of message type `M` are applied per frame; the rest
+//! are kept, oldest first, and applied in the following frames. A [`RecvOverflow`] event is sent
+//! every frame that updates are carried over.
+//!
+//! Enable it for a message type with [`add_recv_budget`](crate::AppExt::add_recv_budget).
+//! The messages are still read from the socket and deserialized by `carrier-pigeon` in
+//! [`client_tick`](crate::app::client_tick) and [`server_tick`](crate::app::server_tick); the
+//! budget only spreads out applying them.
+
+use crate::sync::{NetCompMsg, RecvNetComp};
+use bevy::prelude::*;
+use carrier_pigeon::CId;
+use std::any::Any;
+use std::collections::VecDeque;
+
+/// A received update that is waiting to be applied, with its sender and send time.
+type Pending<M> = (CId, Option<u32>, NetCompMsg<M>);
+
+/// An event that is sent when more updates were received than the [`RecvBudget`] allows.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct RecvOverflow {
+    /// The type name of the message type.
+    pub type_name: &'static str,
+    /// The number of updates that were carried over to the next frame.
+    pub deferred: usize,
+    /// The number of updates that were dropped because the backlog was full.
+    pub dropped: usize,
+}
+
+/// A limit on how many received updates of message type `M` are applied per frame.
+#[derive(Resource)]
+pub struct RecvBudget<M: Any + Send + Sync> {
+    /// The most updates that are applied per frame.
+    pub max_msgs: usize,
+    /// The most updates that are carried over. Once the backlog is full, the oldest updates are
+    /// dropped. Defaults to 16 times `max_msgs`.
+    pub max_backlog: usize,
+    backlog: VecDeque<Pending<M>>,
+}
+
+impl<M: Any + Send + Sync> std::fmt::Debug for RecvBudget<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecvBudget")
+            .field("max_msgs", &self.max_msgs)
+            .field("max_backlog", &self.max_backlog)
+            .field("backlog", &self.backlog.len())
+            .finish()
+    }
+}
+
+impl<M: Clone + Any + Send + Sync> RecvBudget<M> {
+    /// Creates a budget that applies at most `max_msgs` updates per frame.
+    pub fn new(max_msgs: usize) -> Self {
+        RecvBudget {
+            max_msgs,
+            max_backlog: max_msgs.saturating_mul(16),
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// The number of updates that are waiting to be applied.
+    pub fn backlog(&self) -> usize {
+        self.backlog.len()
+    }
+
+    /// Queues the received `msgs` after the backlog, and returns the oldest ones that fit in the
+    /// budget, along with a [`RecvOverflow`] if any are left.
+    pub(crate) fn take(
+        &mut self,
+        msgs: &[RecvNetComp<M>],
+    ) -> (Vec<Pending<M>>, Option<RecvOverflow>) {
+        self.backlog
+            .extend(msgs.iter().map(|m| (m.cid, m.time, m.to_msg())));
+        let taken: Vec<_> = self
+            .backlog
+            .drain(..self.max_msgs.min(self.backlog.len()))
+            .collect();
+
+        let dropped = self.backlog.len().saturating_sub(self.max_backlog);
+        self.backlog.drain(..dropped);
+        if dropped > 0 {
+            warn!(
+                "Dropped {} updates of {} that didn't fit in the receive backlog",
+                dropped,
+                std::any::type_name::<M>()
+            );
+        }
+        let overflow = (!self.backlog.is_empty() || dropped > 0).then(|| RecvOverflow {
+            type_name: std::any::type_name::<M>(),
+            deferred: self.backlog.len(),
+            dropped,
+        });
+        (taken, overflow)
+    }
+}
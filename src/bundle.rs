@@ -0,0 +1,115 @@
+//! Syncing several components with the same settings.
+//!
+//! Entities of a common archetype usually sync a few components together, with the same
+//! transport and send rate. Instead of calling [`sync_comp_cfg`](crate::AppExt::sync_comp_cfg)
+//! for each of them, list them in a tuple of [`Synced`]s and register them all at once with
+//! [`sync_bundle`](crate::AppExt::sync_bundle):
+//!
+//! ```ignore
+//! type ReplicatedActor = (
+//!     Synced<Transform, NetTransform>,
+//!     Synced<Velocity>,
+//!     Synced<Health>,
+//! );
+//!
+//! app.sync_bundle::<ReplicatedActor>(
+//!     &mut table,
+//!     SyncConfig::new(Transport::UDP).with_rate(20.0),
+//! );
+//! ```
+//!
+//! The updates of the components in a bundle are applied together; see the
+//! [`atomic`](crate::atomic) module.
+//!
+//! The shared config is a `SyncConfig<()>`, so only the settings that don't depend on the
+//! component type can be set on it. Components that need a threshold or smoothing can be synced
+//! with [`sync_comp_cfg`](crate::AppExt::sync_comp_cfg) next to the bundle.
+//!
+//! Bundles can be registered into a `MsgTable` or a `SortedMsgTable`; see
+//! [`MsgTableLike`].
+
+use crate::atomic::{add_group, add_group_systems};
+use crate::schema::MsgTableLike;
+use crate::sync::SyncConfig;
+use crate::AppExt;
+use bevy::prelude::*;
+use carrier_pigeon::MsgRegError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::Any;
+use std::marker::PhantomData;
+
+/// Component `T` synced using message type `M`, as part of a [`SyncBundle`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Synced<T, M = T>(PhantomData<(T, M)>);
+
+/// A single component in a [`SyncBundle`].
+pub trait SyncBundleItem {
+    /// Adds everything needed to sync the component with `config`.
+    fn sync(
+        app: &mut App,
+        table: &mut impl MsgTableLike,
+        config: &SyncConfig<()>,
+    ) -> Result<(), MsgRegError>;
+
+    /// Makes the component part of the bundle with index `group` in the
+    /// [`SyncGroups`](crate::atomic::SyncGroups), so its updates are applied together with the
+    /// rest of the bundle.
+    fn group(app: &mut App, group: usize, config: &SyncConfig<()>);
+}
+
+impl<T, M> SyncBundleItem for Synced<T, M>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    fn sync(
+        app: &mut App,
+        table: &mut impl MsgTableLike,
+        config: &SyncConfig<()>,
+    ) -> Result<(), MsgRegError> {
+        app.try_sync_comp_cfg::<T, M>(table, config.cast())?;
+        Ok(())
+    }
+
+    fn group(app: &mut App, group: usize, config: &SyncConfig<()>) {
+        add_group_systems::<T, M>(app, group, &config.schedule);
+    }
+}
+
+/// A set of components that are synced with the same config, written as a tuple of
+/// [`Synced`]s.
+pub trait SyncBundle {
+    /// Adds everything needed to sync all components with `config`.
+    fn sync(
+        app: &mut App,
+        table: &mut impl MsgTableLike,
+        config: &SyncConfig<()>,
+    ) -> Result<(), MsgRegError>;
+}
+
+macro_rules! impl_sync_bundle {
+    ($($item:ident),*) => {
+        impl<$($item: SyncBundleItem),*> SyncBundle for ($($item,)*) {
+            fn sync(
+                app: &mut App,
+                table: &mut impl MsgTableLike,
+                config: &SyncConfig<()>,
+            ) -> Result<(), MsgRegError> {
+                $($item::sync(app, table, config)?;)*
+                let group = add_group(app, &config.schedule);
+                $($item::group(app, group, config);)*
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_sync_bundle!(A);
+impl_sync_bundle!(A, B);
+impl_sync_bundle!(A, B, C);
+impl_sync_bundle!(A, B, C, D);
+impl_sync_bundle!(A, B, C, D, E);
+impl_sync_bundle!(A, B, C, D, E, F);
+impl_sync_bundle!(A, B, C, D, E, F, G);
+impl_sync_bundle!(A, B, C, D, E, F, G, H);
@@ -0,0 +1,66 @@
+//! Sending updates at a fixed cadence, independent of the frame rate.
+//!
+//! By default, [`comp_send`](crate::app::comp_send) runs every frame, so a server running at an
+//! uncapped frame rate checks for changes and sends them hundreds of times per second. With a
+//! [`SendCadence`], added with [`set_send_cadence`](crate::AppExt::set_send_cadence), every
+//! synced component is only sent on the frames where the cadence is due. Changes made in the
+//! frames in between are not lost; the latest state is sent on the next due frame.
+//!
+//! ```ignore
+//! app.set_send_cadence(30.0);
+//!
+//! fn overloaded(mut cadence: ResMut<SendCadence>) {
+//!     cadence.rate = Some(20.0);
+//! }
+//! ```
+//!
+//! All components are sent on the same frames, so a client receives the state of a frame at once.
+//! The [`rate`](crate::SyncConfig::rate) of a component can lower its rate further. Forced syncs
+//! with [`SyncC`](crate::SyncC) are still sent right away.
+
+use bevy::prelude::*;
+
+/// The rate at which synced components are sent.
+///
+/// See the [module docs](self).
+#[derive(Resource, Copy, Clone, PartialEq, Debug, Default)]
+pub struct SendCadence {
+    /// The sends per second, or `None` to send every frame. This can be changed at any time.
+    pub rate: Option<f32>,
+    /// The time of the next send.
+    next: f64,
+    /// Whether this frame sends.
+    due: bool,
+}
+
+impl SendCadence {
+    /// Creates a cadence of `rate` sends per second.
+    pub fn new(rate: f32) -> Self {
+        SendCadence {
+            rate: Some(rate),
+            ..default()
+        }
+    }
+
+    /// Whether the synced components are sent this frame.
+    pub fn is_due(&self) -> bool {
+        self.due
+    }
+}
+
+/// Decides whether the [`SendCadence`] is due this frame.
+pub fn advance_cadence(time: Res<Time>, mut cadence: ResMut<SendCadence>) {
+    let rate = match cadence.rate {
+        Some(rate) if rate > 0.0 => rate as f64,
+        _ => {
+            cadence.due = true;
+            return;
+        }
+    };
+    let now = time.elapsed_seconds_f64();
+    cadence.due = now >= cadence.next;
+    if cadence.due {
+        // Catch up without sending a burst after a long frame.
+        cadence.next = (cadence.next + 1.0 / rate).max(now);
+    }
+}
@@ -0,0 +1,325 @@
+//! User-defined channels for sending messages.
+//!
+//! A channel is a named queue with its own delivery guarantees ([`Channel`]) and priority.
+//! Messages sent on a channel are queued in the [`NetChannels`] resource and flushed at the end of
+//! the frame, highest priority first, so that they don't compete with each other in the same queue.
+//!
+//! When a send budget is set with [`NetChannels::set_budget`], only that many messages are sent
+//! per frame. The rest stay queued and gain priority every frame they wait (see
+//! [`NetChannels::set_aging`]), so low priority messages are never starved forever.
+//!
+//! Message types sent on channels need to be registered with
+//! [`register_channel_msg`](crate::AppExt::register_channel_msg). Every message carries the name
+//! of its channel, so a type that is sent on several channels can be received from one of them
+//! with [`ChannelRecvExt::recv_on`], or from all of them with [`ChannelRecvExt::recv_channel`].
+
+use crate::sync::Channel;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::net::CIdSpec;
+use carrier_pigeon::{CId, Client, Server, Transport};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+
+/// The configuration of a user-defined channel.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct ChannelConfig {
+    /// The delivery guarantees of the channel.
+    pub channel: Channel,
+    /// The priority of the channel. Channels with a higher priority are sent first.
+    pub priority: u8,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            channel: Channel::ReliableOrdered,
+            priority: 0,
+        }
+    }
+}
+
+impl ChannelConfig {
+    /// Creates a new [`ChannelConfig`] with the given delivery guarantees and a priority of `0`.
+    pub fn new(channel: Channel) -> Self {
+        ChannelConfig {
+            channel,
+            priority: 0,
+        }
+    }
+
+    /// Sets the priority of the channel.
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// The wrapper that messages sent on a reliable channel are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct ReliableChannelMsg<T> {
+    channel: String,
+    msg: T,
+}
+
+/// The wrapper that messages sent on an unreliable channel are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct UnreliableChannelMsg<T> {
+    channel: String,
+    msg: T,
+}
+
+/// A message received on a user-defined channel.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ChannelMsg<T> {
+    /// The [`CId`] of the sender.
+    pub cid: CId,
+    /// The name of the channel that the message was sent on.
+    pub channel: String,
+    /// The message.
+    pub msg: T,
+}
+
+/// A function that sends a queued message using the client or server.
+type SendFn = Box<dyn FnOnce(Option<&Client>, Option<&Server>) + Send + Sync>;
+
+/// A message queued on a channel.
+struct Queued {
+    channel: &'static str,
+    priority: u8,
+    /// The number of frames this message has been waiting to be sent.
+    age: u32,
+    send: SendFn,
+}
+
+impl Queued {
+    /// The priority of this message, taking into account how long it has been waiting.
+    fn effective_priority(&self, aging: u32) -> u32 {
+        (self.priority as u32).saturating_add(self.age.saturating_mul(aging))
+    }
+}
+
+/// The user-defined channels and the messages queued on them.
+///
+/// Channels are added with [`add_net_channel`](crate::AppExt::add_net_channel).
+#[derive(Resource)]
+pub struct NetChannels {
+    configs: HashMap<&'static str, ChannelConfig>,
+    queue: Vec<Queued>,
+    budget: Option<usize>,
+    aging: u32,
+}
+
+impl Default for NetChannels {
+    fn default() -> Self {
+        NetChannels {
+            configs: HashMap::default(),
+            queue: vec![],
+            budget: None,
+            aging: 1,
+        }
+    }
+}
+
+impl Debug for NetChannels {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetChannels")
+            .field("configs", &self.configs)
+            .field("queued", &self.queue.len())
+            .field("budget", &self.budget)
+            .field("aging", &self.aging)
+            .finish()
+    }
+}
+
+impl NetChannels {
+    /// Adds a channel named `name`, replacing any channel with the same name.
+    pub fn add(&mut self, name: &'static str, config: ChannelConfig) {
+        self.configs.insert(name, config);
+    }
+
+    /// Gets the configuration of the channel named `name`.
+    pub fn config(&self, name: &str) -> Option<&ChannelConfig> {
+        self.configs.get(name)
+    }
+
+    /// Sets the maximum number of messages sent per frame. `None` means no limit.
+    ///
+    /// Messages that don't fit in the budget are sent in later frames.
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    /// Sets how much priority a queued message gains for every frame it waits to be sent.
+    ///
+    /// Defaults to `1`. Setting this to `0` disables aging, which can starve low priority
+    /// channels when the budget is always used up.
+    pub fn set_aging(&mut self, aging: u32) {
+        self.aging = aging;
+    }
+
+    /// The number of messages that are waiting to be sent.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Queues `msg` to be sent to the server on the channel named `channel`.
+    ///
+    /// This does nothing if this instance is not a client.
+    pub fn send_on<T>(&mut self, channel: &'static str, msg: T)
+    where
+        T: Clone + Any + Send + Sync,
+    {
+        self.queue(channel, move |reliable, client, _server| {
+            let client = match client {
+                Some(client) => client,
+                None => return,
+            };
+            let channel = channel.to_owned();
+            let result = if reliable {
+                client.send(&ReliableChannelMsg { channel, msg })
+            } else {
+                client.send(&UnreliableChannelMsg { channel, msg })
+            };
+            if let Err(e) = result {
+                error!("{}", e);
+            }
+        });
+    }
+
+    /// Queues `msg` to be sent to the clients matching `spec` on the channel named `channel`.
+    ///
+    /// This does nothing if this instance is not a server.
+    pub fn send_spec_on<T>(&mut self, channel: &'static str, spec: CIdSpec, msg: T)
+    where
+        T: Clone + Any + Send + Sync,
+    {
+        self.queue(channel, move |reliable, _client, server| {
+            let server = match server {
+                Some(server) => server,
+                None => return,
+            };
+            let channel = channel.to_owned();
+            let result = if reliable {
+                server.send_spec(spec, &ReliableChannelMsg { channel, msg })
+            } else {
+                server.send_spec(spec, &UnreliableChannelMsg { channel, msg })
+            };
+            if let Err(e) = result {
+                error!("{}", e);
+            }
+        });
+    }
+
+    /// Queues `msg` to be sent to the client `cid` on the channel named `channel`.
+    ///
+    /// This does nothing if this instance is not a server.
+    pub fn send_to_on<T>(&mut self, channel: &'static str, cid: CId, msg: T)
+    where
+        T: Clone + Any + Send + Sync,
+    {
+        self.send_spec_on(channel, CIdSpec::Only(cid), msg);
+    }
+
+    /// Queues `send` on the channel named `channel`.
+    fn queue(
+        &mut self,
+        channel: &'static str,
+        send: impl FnOnce(bool, Option<&Client>, Option<&Server>) + Send + Sync + 'static,
+    ) {
+        let config = match self.configs.get(channel) {
+            Some(config) => *config,
+            None => {
+                warn!("No channel named \"{}\". Dropping message.", channel);
+                return;
+            }
+        };
+        let reliable = config.channel.transport() == Transport::TCP;
+        self.queue.push(Queued {
+            channel,
+            priority: config.priority,
+            age: 0,
+            send: Box::new(move |client, server| send(reliable, client, server)),
+        });
+    }
+}
+
+/// Sends the messages queued in [`NetChannels`], highest priority first, up to the budget.
+pub fn flush_channels(
+    mut channels: ResMut<NetChannels>,
+    client: Option<Res<Client>>,
+    server: Option<Res<Server>>,
+) {
+    let aging = channels.aging;
+    let mut queue = std::mem::take(&mut channels.queue);
+    // Stable sort, so messages on the same priority keep their order.
+    queue.sort_by_key(|q| std::cmp::Reverse(q.effective_priority(aging)));
+
+    let budget = channels.budget.unwrap_or(usize::MAX).min(queue.len());
+    let mut remaining = queue.split_off(budget);
+    for queued in queue {
+        trace!("Sending message on channel \"{}\"", queued.channel);
+        (queued.send)(client.as_deref(), server.as_deref());
+    }
+
+    if !remaining.is_empty() {
+        debug!(
+            "{} channel messages delayed by the send budget",
+            remaining.len()
+        );
+    }
+    for queued in remaining.iter_mut() {
+        queued.age = queued.age.saturating_add(1);
+    }
+    channels.queue = remaining;
+}
+
+/// An extension trait for receiving messages sent on a user-defined channel.
+pub trait ChannelRecvExt {
+    /// Gets the messages of type `T` received on any channel, along with the channel they were
+    /// sent on and the [`CId`] of the sender.
+    fn recv_channel<T: Clone + Any + Send + Sync>(&self) -> Vec<ChannelMsg<T>>;
+
+    /// Gets the messages of type `T` received on the channel named `channel`, along with the
+    /// [`CId`] of the sender.
+    fn recv_on<T: Clone + Any + Send + Sync>(&self, channel: &str) -> Vec<(CId, T)> {
+        self.recv_channel::<T>()
+            .into_iter()
+            .filter(|m| m.channel == channel)
+            .map(|m| (m.cid, m.msg))
+            .collect()
+    }
+}
+
+impl ChannelRecvExt for Client {
+    fn recv_channel<T: Clone + Any + Send + Sync>(&self) -> Vec<ChannelMsg<T>> {
+        let reliable = self.recv::<ReliableChannelMsg<T>>().map(|m| ChannelMsg {
+            cid: m.cid,
+            channel: m.channel.clone(),
+            msg: m.msg.clone(),
+        });
+        let unreliable = self.recv::<UnreliableChannelMsg<T>>().map(|m| ChannelMsg {
+            cid: m.cid,
+            channel: m.channel.clone(),
+            msg: m.msg.clone(),
+        });
+        reliable.chain(unreliable).collect()
+    }
+}
+
+impl ChannelRecvExt for Server {
+    fn recv_channel<T: Clone + Any + Send + Sync>(&self) -> Vec<ChannelMsg<T>> {
+        let reliable = self.recv::<ReliableChannelMsg<T>>().map(|m| ChannelMsg {
+            cid: m.cid,
+            channel: m.channel.clone(),
+            msg: m.msg.clone(),
+        });
+        let unreliable = self.recv::<UnreliableChannelMsg<T>>().map(|m| ChannelMsg {
+            cid: m.cid,
+            channel: m.channel.clone(),
+            msg: m.msg.clone(),
+        });
+        reliable.chain(unreliable).collect()
+    }
+}
@@ -0,0 +1,432 @@
+//! Sending ordered commands from the clients to the server.
+//!
+//! Strategy and simulation games drive the game with discrete orders, like building, moving or
+//! cancelling, instead of a continuous [input](crate::input). Every order must arrive, be executed
+//! once, and be executed in the order it was given. Register a command type with
+//! [`add_commands`](crate::AppExt::add_commands), then submit commands to the
+//! [`CommandQueue<C>`] resource on the client. They are numbered in submission order and sent
+//! over TCP, which is reliable and ordered.
+//!
+//! On the server, the commands of every client are queued in the [`ClientCommands<C>`] resource,
+//! and only come out of it in submission order. A command that arrives before an earlier one is
+//! held back until the earlier one arrives, so none is skipped. Once a command is executed, or
+//! rejected, the server acknowledges it with [`ack`](ClientCommands::ack). The client then removes
+//! it from its pending commands and gets a [`CommandAcked<C>`] event.
+//!
+//! On the client:
+//!
+//! ```ignore
+//! fn order_build(mut commands: ResMut<CommandQueue<Order>>) {
+//!     let seq = commands.submit(Order::Build { kind: Barracks, at: IVec2::new(4, 7) });
+//! }
+//!
+//! fn on_acked(mut acked: EventReader<CommandAcked<Order>>) {
+//!     for ack in acked.iter() {
+//!         if ack.outcome == CommandOutcome::Rejected {
+//!             // show that `ack.command` failed.
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! On the server:
+//!
+//! ```ignore
+//! fn execute_orders(mut commands: ResMut<ClientCommands<Order>>) {
+//!     while let Some(cmd) = commands.pop_any() {
+//!         let outcome = match execute(cmd.cid, &cmd.command) {
+//!             Ok(()) => CommandOutcome::Executed,
+//!             Err(_) => CommandOutcome::Rejected,
+//!         };
+//!         commands.ack(cmd.cid, cmd.seq, outcome);
+//!     }
+//! }
+//! ```
+
+use crate::disconnect::ClientState;
+use crate::error::{ErrorLog, NetErrorEvent, NetErrorKind};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::{CId, Client, Server};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+
+/// What the server did with a command.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum CommandOutcome {
+    /// The command was executed.
+    Executed,
+    /// The command was rejected, and had no effect.
+    Rejected,
+}
+
+/// The message that commands are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct CommandMsg<C> {
+    #[serde(with = "crate::varint")]
+    seq: u32,
+    command: C,
+}
+
+/// The message that the acknowledgments of commands of type `C` are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct CommandAckMsg<C> {
+    #[serde(with = "crate::varint")]
+    seq: u32,
+    outcome: CommandOutcome,
+    #[serde(skip)]
+    _pd: PhantomData<C>,
+}
+
+/// The commands that this client submitted, and that the server hasn't acknowledged yet.
+#[derive(Resource, Clone, Debug)]
+pub struct CommandQueue<C> {
+    /// The sequence number of the next submitted command.
+    next_seq: u32,
+    /// The submitted commands that weren't sent yet.
+    outbox: Vec<u32>,
+    /// The submitted commands that weren't acknowledged yet.
+    pending: BTreeMap<u32, C>,
+}
+
+impl<C> Default for CommandQueue<C> {
+    fn default() -> Self {
+        CommandQueue {
+            next_seq: 0,
+            outbox: vec![],
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C> CommandQueue<C> {
+    /// Submits `command`, to be sent to the server at the end of the frame.
+    ///
+    /// Returns its sequence number, which its [`CommandAcked<C>`] event will have.
+    pub fn submit(&mut self, command: C) -> u32 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.outbox.push(seq);
+        self.pending.insert(seq, command);
+        seq
+    }
+
+    /// Whether the command with sequence number `seq` wasn't acknowledged yet.
+    pub fn is_pending(&self, seq: u32) -> bool {
+        self.pending.contains_key(&seq)
+    }
+
+    /// Gets the commands that weren't acknowledged yet, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = (u32, &C)> + '_ {
+        self.pending.iter().map(|(seq, command)| (*seq, command))
+    }
+
+    /// Forgets the commands that weren't acknowledged yet.
+    ///
+    /// You should call this when disconnecting, as the server forgets them too.
+    pub fn clear(&mut self) {
+        self.outbox.clear();
+        self.pending.clear();
+    }
+}
+
+/// An event that is sent on the client when the server acknowledges a command.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CommandAcked<C> {
+    /// The sequence number that [`submit`](CommandQueue::submit) returned.
+    pub seq: u32,
+    /// What the server did with the command.
+    pub outcome: CommandOutcome,
+    /// The command.
+    pub command: C,
+}
+
+/// A command that the server received.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ReceivedCommand<C> {
+    /// The client that sent the command.
+    pub cid: CId,
+    /// The sequence number of the command, to [`ack`](ClientCommands::ack) it with.
+    pub seq: u32,
+    /// The command.
+    pub command: C,
+}
+
+/// The maximum number of commands of a single client that are held back, waiting for an earlier
+/// command.
+const MAX_HELD: usize = 1024;
+
+/// The received commands of a single client.
+#[derive(Clone, Debug)]
+struct ClientQueue<C> {
+    /// The sequence number of the next command, used to drop duplicates.
+    next_seq: Option<u32>,
+    queue: VecDeque<(u32, C)>,
+    /// The commands that arrived before an earlier one, by sequence number.
+    held: HashMap<u32, C>,
+}
+
+impl<C> Default for ClientQueue<C> {
+    fn default() -> Self {
+        ClientQueue {
+            next_seq: None,
+            queue: VecDeque::new(),
+            held: HashMap::default(),
+        }
+    }
+}
+
+/// What [`ClientCommands::push`] did with a command.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Pushed {
+    /// The command was queued, along with the held back commands that followed it.
+    Queued,
+    /// The command was held back until the earlier commands arrive.
+    Held,
+    /// The command was dropped for being a duplicate.
+    Duplicate,
+    /// The command was dropped, since too many commands are held back already.
+    Overflow,
+}
+
+/// The received commands of every client, in submission order.
+///
+/// A command is acknowledged to its client with [`ack`](Self::ack), once it is executed or
+/// rejected. Commands should be acknowledged in the order they are taken out.
+#[derive(Resource, Clone, Debug)]
+pub struct ClientCommands<C> {
+    clients: HashMap<CId, ClientQueue<C>>,
+    /// The client order of the queued commands, so they can be taken in the order they arrived.
+    order: VecDeque<CId>,
+    /// The acknowledgments that weren't sent yet.
+    acks: Vec<(CId, u32, CommandOutcome)>,
+}
+
+impl<C> Default for ClientCommands<C> {
+    fn default() -> Self {
+        ClientCommands {
+            clients: HashMap::default(),
+            order: VecDeque::new(),
+            acks: vec![],
+        }
+    }
+}
+
+impl<C> ClientCommands<C> {
+    /// Queues `command` with sequence number `seq` from client `cid`.
+    ///
+    /// A command that is ahead of the next expected one is held back until the commands before it
+    /// arrive.
+    fn push(&mut self, cid: CId, seq: u32, command: C) -> Pushed {
+        let client = self.clients.entry(cid).or_default();
+        if let Some(next) = client.next_seq {
+            // Compare wrapping, so the sequence numbers can overflow.
+            let ahead = seq.wrapping_sub(next);
+            if ahead > u32::MAX / 2 || client.held.contains_key(&seq) {
+                return Pushed::Duplicate;
+            }
+            if ahead > 0 {
+                if client.held.len() >= MAX_HELD {
+                    return Pushed::Overflow;
+                }
+                client.held.insert(seq, command);
+                return Pushed::Held;
+            }
+        }
+        client.queue.push_back((seq, command));
+        self.order.push_back(cid);
+        let mut next = seq.wrapping_add(1);
+        while let Some(command) = client.held.remove(&next) {
+            client.queue.push_back((next, command));
+            self.order.push_back(cid);
+            next = next.wrapping_add(1);
+        }
+        client.next_seq = Some(next);
+        Pushed::Queued
+    }
+
+    /// Takes the oldest queued command of client `cid`.
+    pub fn pop(&mut self, cid: CId) -> Option<ReceivedCommand<C>> {
+        let (seq, command) = self.clients.get_mut(&cid)?.queue.pop_front()?;
+        if let Some(i) = self.order.iter().position(|c| *c == cid) {
+            self.order.remove(i);
+        }
+        Some(ReceivedCommand { cid, seq, command })
+    }
+
+    /// Takes the oldest queued command of any client.
+    ///
+    /// The commands of a single client come out in submission order, and the commands of
+    /// different clients in the order they arrived.
+    pub fn pop_any(&mut self) -> Option<ReceivedCommand<C>> {
+        let cid = *self.order.front()?;
+        self.pop(cid)
+    }
+
+    /// Gets the queued commands of client `cid`, oldest first.
+    pub fn get(&self, cid: CId) -> impl Iterator<Item = (u32, &C)> + '_ {
+        self.clients
+            .get(&cid)
+            .into_iter()
+            .flat_map(|client| client.queue.iter().map(|(seq, command)| (*seq, command)))
+    }
+
+    /// The number of queued commands of all clients.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Whether no client has a queued command.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Acknowledges the command with sequence number `seq` to client `cid`, with its `outcome`.
+    ///
+    /// The acknowledgments are sent at the end of the frame.
+    pub fn ack(&mut self, cid: CId, seq: u32, outcome: CommandOutcome) {
+        self.acks.push((cid, seq, outcome));
+    }
+
+    /// Removes all commands of client `cid`.
+    pub fn remove_client(&mut self, cid: CId) {
+        self.clients.remove(&cid);
+        self.order.retain(|c| *c != cid);
+        self.acks.retain(|(c, _, _)| *c != cid);
+    }
+}
+
+impl<C: Send + Sync + 'static> ClientState for ClientCommands<C> {
+    fn remove_client(&mut self, cid: CId) {
+        ClientCommands::remove_client(self, cid);
+    }
+}
+
+/// Sends the newly submitted commands of the [`CommandQueue<C>`] to the server.
+pub fn send_commands<C: Clone + Any + Send + Sync>(
+    time: Res<Time>,
+    client: Option<Res<Client>>,
+    mut queue: ResMut<CommandQueue<C>>,
+    mut ew: EventWriter<NetErrorEvent>,
+    mut error_log: Local<ErrorLog>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    if queue.outbox.is_empty() {
+        return;
+    }
+    let queue = &mut *queue;
+    for seq in queue.outbox.drain(..) {
+        let command = match queue.pending.get(&seq) {
+            Some(command) => command.clone(),
+            None => continue,
+        };
+        if let Err(e) = client.send(&CommandMsg { seq, command }) {
+            report_error::<CommandMsg<C>>(None, e.to_string(), &time, &mut ew, &mut error_log);
+        }
+    }
+}
+
+/// Receives the commands of the clients into the [`ClientCommands<C>`].
+pub fn recv_commands<C: Clone + Any + Send + Sync>(
+    server: Option<Res<Server>>,
+    mut commands: ResMut<ClientCommands<C>>,
+) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    for msg in server.recv::<CommandMsg<C>>() {
+        match commands.push(msg.cid, msg.seq, msg.command.clone()) {
+            Pushed::Queued => {}
+            Pushed::Held => debug!(
+                "Holding back command {} from client {} until the earlier ones arrive.",
+                msg.seq, msg.cid
+            ),
+            Pushed::Duplicate => debug!(
+                "Dropped duplicate command {} from client {}.",
+                msg.seq, msg.cid
+            ),
+            Pushed::Overflow => error!(
+                "Dropped command {} from client {}, since too many of its commands are waiting \
+                 for an earlier one.",
+                msg.seq, msg.cid
+            ),
+        }
+    }
+}
+
+/// Sends the acknowledgments of the [`ClientCommands<C>`] to their clients.
+pub fn send_command_acks<C: Any + Send + Sync>(
+    time: Res<Time>,
+    server: Option<Res<Server>>,
+    mut commands: ResMut<ClientCommands<C>>,
+    mut ew: EventWriter<NetErrorEvent>,
+    mut error_log: Local<ErrorLog>,
+) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    for (cid, seq, outcome) in commands.acks.drain(..) {
+        let msg = CommandAckMsg::<C> {
+            seq,
+            outcome,
+            _pd: PhantomData,
+        };
+        if let Err(e) = server.send_to(cid, &msg) {
+            report_error::<CommandAckMsg<C>>(
+                Some(cid),
+                e.to_string(),
+                &time,
+                &mut ew,
+                &mut error_log,
+            );
+        }
+    }
+}
+
+/// Receives the acknowledgments of the commands of the [`CommandQueue<C>`], sending a
+/// [`CommandAcked<C>`] for each.
+pub fn recv_command_acks<C: Any + Send + Sync>(
+    client: Option<Res<Client>>,
+    mut queue: ResMut<CommandQueue<C>>,
+    mut ew: EventWriter<CommandAcked<C>>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    for msg in client.recv::<CommandAckMsg<C>>() {
+        if let Some(command) = queue.pending.remove(&msg.seq) {
+            ew.send(CommandAcked {
+                seq: msg.seq,
+                outcome: msg.outcome,
+                command,
+            });
+        }
+    }
+}
+
+/// Sends a [`NetErrorEvent`] for an `error` while sending message type `T` to `cid`, and logs it.
+fn report_error<T>(
+    cid: Option<CId>,
+    error: String,
+    time: &Time,
+    ew: &mut EventWriter<NetErrorEvent>,
+    error_log: &mut ErrorLog,
+) {
+    let event = NetErrorEvent {
+        kind: NetErrorKind::Send,
+        entity: None,
+        type_name: std::any::type_name::<T>(),
+        cid,
+        error,
+    };
+    error_log.log(&event, time.elapsed_seconds_f64());
+    ew.send(event);
+}
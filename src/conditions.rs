@@ -0,0 +1,61 @@
+//! Run criteria for gating systems on the networking status.
+//!
+//! Instead of taking `Option<Res<Client>>` in every system and checking it by hand, you can use
+//! these with `.with_run_criteria(...)`:
+//!
+//! ```ignore
+//! app.add_system(spin.with_run_criteria(server_running));
+//! ```
+
+use crate::sync::{CNetDir, NetComp};
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use carrier_pigeon::{Client, Server};
+use std::any::Any;
+
+/// Runs if there is a [`Client`] resource.
+pub fn client_connected(client: Option<Res<Client>>) -> ShouldRun {
+    client.is_some().into()
+}
+
+/// Runs if there is a [`Server`] resource.
+pub fn server_running(server: Option<Res<Server>>) -> ShouldRun {
+    server.is_some().into()
+}
+
+/// Runs if there is a [`Client`] resource but no [`Server`] resource.
+pub fn is_client(client: Option<Res<Client>>, server: Option<Res<Server>>) -> ShouldRun {
+    (client.is_some() && server.is_none()).into()
+}
+
+/// Runs if there is both a [`Client`] and a [`Server`] resource.
+pub fn is_host(client: Option<Res<Client>>, server: Option<Res<Server>>) -> ShouldRun {
+    (client.is_some() && server.is_some()).into()
+}
+
+/// Runs if there is neither a [`Client`] nor a [`Server`] resource.
+pub fn is_offline(client: Option<Res<Client>>, server: Option<Res<Server>>) -> ShouldRun {
+    (client.is_none() && server.is_none()).into()
+}
+
+/// Runs if this instance is the one sending component `T` for any entity.
+///
+/// On the server, this is true if any [`NetComp<T, M>`] sends to the clients. On the client, this
+/// is true if any [`NetComp<T, M>`] has a [`CNetDir::To`] direction.
+pub fn has_authority<T, M>(
+    client: Option<Res<Client>>,
+    server: Option<Res<Server>>,
+    q: Query<&NetComp<T, M>>,
+) -> ShouldRun
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    if server.is_some() {
+        q.iter().any(|net_c| net_c.s_dir.to().is_some()).into()
+    } else if client.is_some() {
+        q.iter().any(|net_c| net_c.c_dir == CNetDir::To).into()
+    } else {
+        ShouldRun::No
+    }
+}
@@ -0,0 +1,303 @@
+//! Server configuration that is loaded at runtime.
+//!
+//! A [`PigeonServerConfig`] can be loaded from a TOML file (with the `config` feature) and/or
+//! environment variables, so a dedicated server can be configured per deployment without
+//! recompiling.
+//!
+//! ```toml
+//! bind_address = "0.0.0.0"
+//! address_family = "dual_stack"
+//! port = 7777
+//! max_players = 16
+//! tick_rate = 30.0
+//! timeout = 10.0
+//! ban_list = "bans.txt"
+//! ```
+//!
+//! ```ignore
+//! let config = PigeonServerConfig::from_file("server.toml")?.with_env()?;
+//! App::new()
+//!     .insert_resource(config)
+//!     .insert_resource(parts)
+//!     .add_plugins(DedicatedServerPlugins::default())
+//!     .run();
+//! ```
+//!
+//! If the config is inserted before the [`ServerPlugin`](crate::ServerPlugin) is added, the plugin
+//! uses it:
+//! - At startup, if there is a `MsgTableParts` resource and no [`Server`] yet, the server is
+//!   started with the configured address and timeout.
+//! - Connections are rejected while [`max_players`](PigeonServerConfig::max_players) clients are
+//!   connected, when connections are handled by
+//!   [`set_connection_msgs`](crate::AppExt::set_connection_msgs).
+//! - The [`DedicatedServerPlugin`](crate::dedicated::DedicatedServerPlugin) uses its tick rate.
+
+use bevy::prelude::*;
+use carrier_pigeon::net::Config;
+use carrier_pigeon::{MsgTableParts, Server};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The prefix of the environment variables read by [`PigeonServerConfig::with_env`].
+const ENV_PREFIX: &str = "PIGEON_";
+
+/// Which IP versions a server listens on when its bind address is the default `0.0.0.0`.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    /// Only IPv4, by binding `0.0.0.0`.
+    #[default]
+    V4,
+    /// Both IPv4 and IPv6, by binding `::`.
+    ///
+    /// IPv4 clients are accepted as IPv4-mapped IPv6 addresses. `carrier-pigeon` binds the
+    /// sockets itself, so this relies on IPv6 sockets being dual-stack by default, which they are
+    /// on Linux and macOS, but not on Windows. Where they aren't,
+    /// [`listen`](PigeonServerConfig::listen) fails instead of only listening on IPv6.
+    DualStack,
+}
+
+impl FromStr for AddressFamily {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "v4" | "ipv4" => Ok(AddressFamily::V4),
+            "dual_stack" | "dual-stack" | "dualstack" => Ok(AddressFamily::DualStack),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The configuration of a server.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct PigeonServerConfig {
+    /// The address to listen on. Defaults to `0.0.0.0`.
+    ///
+    /// This can be an IPv4 or IPv6 address. Any address other than `0.0.0.0` is listened on as
+    /// is; for `0.0.0.0`, the [`address_family`](Self::address_family) decides which IP versions
+    /// are listened on.
+    pub bind_address: IpAddr,
+    /// Which IP versions to listen on if the bind address is `0.0.0.0`. Defaults to
+    /// [`AddressFamily::V4`].
+    pub address_family: AddressFamily,
+    /// The port to listen on. Defaults to 7777.
+    pub port: u16,
+    /// The maximum number of connected clients, or `None` for no limit. Defaults to `None`.
+    pub max_players: Option<u32>,
+    /// The number of ticks per second. Defaults to 60.
+    pub tick_rate: f64,
+    /// The time in seconds without a message from a client before it is disconnected. Defaults
+    /// to 10.
+    pub timeout: f64,
+    /// The path of a file with the banned players, one per line. Defaults to `None`.
+    pub ban_list: Option<PathBuf>,
+}
+
+impl Default for PigeonServerConfig {
+    fn default() -> Self {
+        PigeonServerConfig {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            address_family: AddressFamily::V4,
+            port: 7777,
+            max_players: None,
+            tick_rate: 60.0,
+            timeout: 10.0,
+            ban_list: None,
+        }
+    }
+}
+
+/// An error loading a [`PigeonServerConfig`].
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read.
+    Io(io::Error),
+    /// The config file isn't valid.
+    Parse(String),
+    /// An environment variable has an invalid value.
+    Env {
+        /// The name of the variable.
+        var: String,
+        /// The invalid value.
+        value: String,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read the config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "invalid config file: {}", e),
+            ConfigError::Env { var, value } => write!(f, "invalid value {:?} for {}", value, var),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl PigeonServerConfig {
+    /// Loads the config from the TOML file at `path`.
+    ///
+    /// Missing fields are set to their defaults.
+    #[cfg(feature = "config")]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    /// Loads the config from the environment variables, using the defaults for the ones that
+    /// aren't set.
+    ///
+    /// See [`with_env`](Self::with_env) for the variables that are read.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        PigeonServerConfig::default().with_env()
+    }
+
+    /// Overrides the fields of this config with the environment variables that are set.
+    ///
+    /// The variables are the field names in upper case with a `PIGEON_` prefix, such as
+    /// `PIGEON_PORT` or `PIGEON_MAX_PLAYERS`.
+    pub fn with_env(mut self) -> Result<Self, ConfigError> {
+        if let Some(bind_address) = env_var("BIND_ADDRESS")? {
+            self.bind_address = bind_address;
+        }
+        if let Some(address_family) = env_var("ADDRESS_FAMILY")? {
+            self.address_family = address_family;
+        }
+        if let Some(port) = env_var("PORT")? {
+            self.port = port;
+        }
+        if let Some(max_players) = env_var("MAX_PLAYERS")? {
+            self.max_players = Some(max_players);
+        }
+        if let Some(tick_rate) = env_var("TICK_RATE")? {
+            self.tick_rate = tick_rate;
+        }
+        if let Some(timeout) = env_var("TIMEOUT")? {
+            self.timeout = timeout;
+        }
+        if let Some(ban_list) = env_var("BAN_LIST")? {
+            self.ban_list = Some(ban_list);
+        }
+        Ok(self)
+    }
+
+    /// The address to listen on.
+    ///
+    /// This is the bind address, unless it is `0.0.0.0` and the address family is
+    /// [`DualStack`](AddressFamily::DualStack), which listens on `::`.
+    pub fn addr(&self) -> SocketAddr {
+        let ip = match (self.bind_address, self.address_family) {
+            (IpAddr::V4(ip), AddressFamily::DualStack) if ip.is_unspecified() => {
+                IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+            }
+            (ip, _) => ip,
+        };
+        SocketAddr::new(ip, self.port)
+    }
+
+    /// The `carrier-pigeon` config with the configured timeout.
+    pub fn net_config(&self) -> Config {
+        Config {
+            timeout: Duration::from_secs_f64(self.timeout),
+            ..Config::default()
+        }
+    }
+
+    /// Starts a server with this config.
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the address family is
+    /// [`DualStack`](AddressFamily::DualStack), but IPv6 sockets don't accept IPv4 on this system.
+    pub fn listen(&self, parts: MsgTableParts) -> io::Result<Server> {
+        let addr = self.addr();
+        let dual_stack = self.address_family == AddressFamily::DualStack;
+        if dual_stack && addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) && !ipv6_accepts_ipv4()? {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "IPv6 sockets are IPv6 only on this system, so the server can't listen on both \
+                 IPv4 and IPv6; set the bind address to 0.0.0.0 or :: instead",
+            ));
+        }
+        Server::new(addr, parts, self.net_config())
+    }
+
+    /// Reads the ban list, skipping empty lines.
+    ///
+    /// Returns an empty list if no ban list is configured.
+    pub fn load_ban_list(&self) -> io::Result<Vec<String>> {
+        let path = match &self.ban_list {
+            Some(path) => path,
+            None => return Ok(vec![]),
+        };
+        Ok(std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    /// Whether another client can connect while `connected` clients are connected.
+    pub(crate) fn has_room(&self, connected: usize) -> bool {
+        self.max_players
+            .map(|max| connected < max as usize)
+            .unwrap_or(true)
+    }
+}
+
+/// Whether IPv6 sockets accept IPv4 connections by default on this system.
+///
+/// `carrier-pigeon` binds its sockets without changing `IPV6_V6ONLY`, so they get this default.
+#[allow(deprecated)]
+fn ipv6_accepts_ipv4() -> io::Result<bool> {
+    // Only the getter of the deprecated option is used, which still works on bound sockets.
+    let probe = TcpListener::bind((Ipv6Addr::UNSPECIFIED, 0))?;
+    Ok(!probe.only_v6()?)
+}
+
+/// Reads and parses the environment variable `PIGEON_{name}`, if it is set.
+fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, ConfigError> {
+    let var = format!("{}{}", ENV_PREFIX, name);
+    match env::var(&var) {
+        Ok(value) => match value.parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => Err(ConfigError::Env { var, value }),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Starts the server using the [`PigeonServerConfig`], if there is one and the server isn't
+/// started yet.
+pub fn start_server(
+    mut commands: Commands,
+    config: Option<Res<PigeonServerConfig>>,
+    parts: Option<Res<MsgTableParts>>,
+    server: Option<Res<Server>>,
+) {
+    let (config, parts) = match (config, parts, server) {
+        (Some(config), Some(parts), None) => (config, parts),
+        _ => return,
+    };
+    match config.listen((*parts).clone()) {
+        Ok(server) => {
+            info!("Listening on {}", config.addr());
+            commands.insert_resource(server);
+        }
+        Err(e) => error!("Failed to listen on {}: {}", config.addr(), e),
+    }
+}
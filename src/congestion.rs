@@ -0,0 +1,245 @@
+//! Lowering the send rate to clients on bad connections.
+//!
+//! By default, the server sends every changed component to every client each frame, however
+//! well their connection copes. A congested link then loses even more packets and queues the
+//! rest, so the updates arrive later and later. With
+//! [`adaptive_send_rate`](crate::AppExt::adaptive_send_rate), the server uses the packet loss and
+//! round trip time from the [`NetAcks`] to pick a send rate for every client:
+//!
+//! - When a client's loss or round trip time goes over the limits in the [`AdaptiveSendRate`],
+//!   its rate is multiplied by [`backoff`](AdaptiveSendRate::backoff), down to
+//!   [`min_rate`](AdaptiveSendRate::min_rate).
+//! - While the connection is fine, the rate slowly climbs back up to every frame.
+//!
+//! A client with a rate of 0.5 is only sent the synced components every other frame. Changes
+//! that happen in a frame that is skipped are held and sent in the next frame that isn't, so
+//! components using change detection don't miss any. The current rates are in the [`SendRates`]
+//! resource, so other systems can also send less to a client, or switch it to a coarser message
+//! type.
+//!
+//! This needs acks, enabled with [`enable_acks`](crate::AppExt::enable_acks), on both the server
+//! and the clients. Only the updates from the server are throttled.
+
+use crate::ack::NetAcks;
+use crate::lod::{LodTier, LodVariants, SyncLod};
+use crate::priority::DistancePriority;
+use crate::sync::NetId;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::{CId, Server};
+
+/// The limits and speeds of the adaptive send rate.
+#[derive(Resource, Copy, Clone, PartialEq, Debug)]
+pub struct AdaptiveSendRate {
+    /// The packet loss, between 0 and 1, above which a connection is congested. Defaults to 0.05.
+    pub max_loss: f32,
+    /// The round trip time in seconds above which a connection is congested. Defaults to 0.25.
+    pub max_rtt: f32,
+    /// The lowest rate, as a fraction of the frames. Defaults to 0.2.
+    pub min_rate: f32,
+    /// What the rate is multiplied with when the connection is congested. Defaults to 0.5.
+    pub backoff: f32,
+    /// How much the rate goes up per second while the connection is fine. Defaults to 0.25.
+    pub recovery: f32,
+    /// The time in seconds after backing off before the rate is lowered again, since the loss
+    /// estimate takes a while to settle. Defaults to 1.
+    pub cooldown: f32,
+}
+
+impl Default for AdaptiveSendRate {
+    fn default() -> Self {
+        AdaptiveSendRate {
+            max_loss: 0.05,
+            max_rtt: 0.25,
+            min_rate: 0.2,
+            backoff: 0.5,
+            recovery: 0.25,
+            cooldown: 1.0,
+        }
+    }
+}
+
+/// The send rate of a single connection.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct ConnectionRate {
+    /// The fraction of the frames that the connection is sent updates in.
+    rate: f32,
+    /// The accumulated rate; updates are sent once it reaches 1.
+    credit: f32,
+    /// Whether updates are sent this frame.
+    sends: bool,
+    /// The local time of the last backoff, in seconds.
+    last_backoff: Option<f64>,
+}
+
+impl Default for ConnectionRate {
+    fn default() -> Self {
+        ConnectionRate {
+            rate: 1.0,
+            credit: 0.0,
+            sends: true,
+            last_backoff: None,
+        }
+    }
+}
+
+/// The send rate of every client, on the server.
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub struct SendRates {
+    clients: HashMap<CId, ConnectionRate>,
+    /// Whether any client is skipped this frame.
+    throttling: bool,
+}
+
+impl SendRates {
+    /// The fraction of the frames that client `cid` is sent updates in, between
+    /// [`min_rate`](AdaptiveSendRate::min_rate) and 1.
+    pub fn rate(&self, cid: CId) -> f32 {
+        self.clients.get(&cid).map_or(1.0, |c| c.rate)
+    }
+
+    /// Whether client `cid` is sent updates this frame.
+    pub fn sends(&self, cid: CId) -> bool {
+        self.clients.get(&cid).is_none_or(|c| c.sends)
+    }
+
+    /// The send rate of every client that is sent updates less than every frame.
+    pub fn throttled(&self) -> impl Iterator<Item = (CId, f32)> + '_ {
+        self.clients
+            .iter()
+            .filter(|(_, c)| c.rate < 1.0)
+            .map(|(cid, c)| (*cid, c.rate))
+    }
+}
+
+/// Updates the [`SendRates`] from the [`NetAcks`], and picks the clients that are sent updates
+/// this frame.
+pub fn update_send_rates(
+    time: Res<Time>,
+    config: Res<AdaptiveSendRate>,
+    acks: Res<NetAcks>,
+    mut rates: ResMut<SendRates>,
+    server: Option<Res<Server>>,
+) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let now = time.elapsed_seconds_f64();
+    let dt = time.delta_seconds();
+    let cids: HashSet<CId> = server.cids().collect();
+    rates.clients.retain(|cid, _| cids.contains(cid));
+    rates.throttling = false;
+
+    for cid in cids {
+        let congested = acks.client(cid).is_some_and(|info| {
+            info.loss() > config.max_loss || info.rtt().is_some_and(|rtt| rtt > config.max_rtt)
+        });
+        let c = rates.clients.entry(cid).or_default();
+        if congested {
+            let cooled = c
+                .last_backoff
+                .is_none_or(|last| now - last >= config.cooldown as f64);
+            if cooled {
+                c.rate = (c.rate * config.backoff).max(config.min_rate);
+                c.last_backoff = Some(now);
+                debug!("Lowered the send rate of client {} to {:.2}", cid, c.rate);
+            }
+        } else {
+            c.rate = (c.rate + config.recovery * dt).min(1.0);
+        }
+
+        c.credit += c.rate;
+        c.sends = c.credit >= 1.0;
+        if c.sends {
+            c.credit -= 1.0;
+        }
+        rates.throttling |= !c.sends;
+    }
+}
+
+/// The send rates, [`DistancePriority`] and [`SyncLod`] as seen by
+/// [`comp_send`](crate::app::comp_send), with the updates that were held for the clients that
+/// were skipped.
+#[derive(SystemParam, Debug)]
+pub struct SendThrottle<'w, 's> {
+    rates: Option<Res<'w, SendRates>>,
+    priority: Option<Res<'w, DistancePriority>>,
+    lod: Option<Res<'w, SyncLod>>,
+    variants: Option<Res<'w, LodVariants>>,
+    /// The clients that each entity, by [`NetEntity`](crate::sync::NetEntity) id, has a held
+    /// update for.
+    held: Local<'s, HashMap<NetId, HashSet<CId>>>,
+}
+
+impl<'w, 's> SendThrottle<'w, 's> {
+    /// Whether any client may be skipped this frame.
+    pub(crate) fn is_throttling(&self) -> bool {
+        self.rates.as_ref().is_some_and(|r| r.throttling)
+            || self.priority.as_ref().is_some_and(|p| p.is_active())
+            || self.lod.as_ref().is_some_and(|l| l.is_active())
+    }
+
+    /// The [`LodTier`] of the entity with id `id` for client `cid`.
+    pub(crate) fn tier(&self, cid: CId, id: NetId) -> LodTier {
+        let priority = self.priority.as_deref();
+        self.lod
+            .as_ref()
+            .map_or(LodTier::Full, |l| l.tier(cid, id, priority))
+    }
+
+    /// Whether component `T` has a reduced message type for the [`SyncLod`].
+    pub(crate) fn has_reduced<T: Component>(&self) -> bool {
+        self.variants.as_ref().is_some_and(|v| v.has::<T>())
+    }
+
+    /// Whether the entity with id `id` has an update held for any client.
+    pub(crate) fn is_held(&self, id: NetId) -> bool {
+        self.held.contains_key(&id)
+    }
+
+    /// Filters the recipients `cids` of an update of the entity with id `id` to the ones that
+    /// are sent it this frame.
+    ///
+    /// If the component `changed`, it is sent to all of `cids`, otherwise only to the ones it was
+    /// held for. Clients that have the entity in a [`LodTier`] that this update doesn't `serve`
+    /// are skipped too, and so are the clients that the entity isn't due for if the
+    /// [`DistancePriority`] applies (`priority`). If it uses change detection (`cd`), the update
+    /// is held for the skipped clients.
+    pub(crate) fn filter(
+        &mut self,
+        id: NetId,
+        changed: bool,
+        cd: bool,
+        cids: Vec<CId>,
+        serves: impl Fn(LodTier) -> bool,
+        priority: bool,
+    ) -> Vec<CId> {
+        let held = self.held.remove(&id).unwrap_or_default();
+        let rates = self.rates.as_deref();
+        let due = |cid, id| !priority || self.priority.as_ref().is_none_or(|p| p.is_due(cid, id));
+        let priority = self.priority.as_deref();
+        let lod = self.lod.as_deref();
+        let mut still_held = HashSet::default();
+        let cids = cids
+            .into_iter()
+            .filter(|cid| changed || held.contains(cid))
+            .filter(|cid| {
+                let tier = lod.map_or(LodTier::Full, |l| l.tier(*cid, id, priority));
+                let sends = serves(tier)
+                    && rates.is_none_or(|r| r.sends(*cid))
+                    && due(*cid, id)
+                    && lod.is_none_or(|l| l.is_due(tier, id));
+                if !sends && cd {
+                    still_held.insert(*cid);
+                }
+                sends
+            })
+            .collect();
+        if !still_held.is_empty() {
+            self.held.insert(id, still_held);
+        }
+        cids
+    }
+}
@@ -0,0 +1,546 @@
+//! Connection handling.
+//!
+//! Connecting to a server can take a while, and blocking on it would stall the main thread.
+//! Instead, insert a [`Connecting`] resource; the [`ClientPlugin`](crate::ClientPlugin) will
+//! poll it every frame, insert the [`Client`] resource once the connection succeeds, and emit a
+//! [`ConnectSucceeded`] or [`ConnectFailed`] event once the attempt resolves. To connect by
+//! hostname, create it with [`Connecting::resolve`]. A [`ClientAddrs`] resource with the
+//! addresses of the connection is inserted along with the [`Client`].
+//!
+//! The connection message type `C` and response message type `R` that the `MsgTable` is built
+//! with can be registered with [`set_connection_msgs`](crate::AppExt::set_connection_msgs). The
+//! server then handles new connections itself: the [`ConnectionHook`] decides whether to accept
+//! them, and a [`NetConnected<C>`] event is sent for every accepted one, with the connection
+//! message the client sent, such as its player name. On the client, a
+//! [`ConnectionResponse<R>`] event is sent with the server's response once the connection
+//! succeeds. To reject clients with an incompatible protocol version before the hook is asked,
+//! see the [`version`](crate::version) module.
+//!
+//! The hook can be a plain function of the connection message, or a system that can look at the
+//! rest of the [`World`], such as the current player count or a whitelist resource, using
+//! [`ConnectionHook::from_system`].
+//!
+//! ```ignore
+//! app.set_connection_msgs::<Join, Welcome>()
+//!     .insert_resource(ConnectionHook::new(|_cid, join: &Join| {
+//!         (join.version == VERSION, Welcome::default())
+//!     }));
+//! ```
+
+use crate::config::PigeonServerConfig;
+use crate::schema::MsgSchema;
+use crate::version::{Handshake, ProtocolVersion, Rejection, Versioned};
+use bevy::ecs::system::BoxedSystem;
+use bevy::prelude::*;
+use bevy::tasks::{IoTaskPool, Task};
+use carrier_pigeon::net::Config;
+use carrier_pigeon::{CId, Client, MsgTableParts, Server};
+use futures_lite::future;
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// The result of a connection attempt, with the response message type erased.
+///
+/// This has the address that the connection succeeded on, or the last address that was tried.
+pub(crate) type ConnectResult =
+    Result<(SocketAddr, Client, Box<dyn Any + Send + Sync>), (Option<SocketAddr>, io::Error)>;
+
+/// A connection attempt that is running in the background.
+///
+/// While this resource exists, the client is connecting. It is removed once the attempt
+/// resolves.
+#[derive(Resource)]
+pub struct Connecting {
+    host: String,
+    peer: Option<SocketAddr>,
+    task: Task<ConnectResult>,
+}
+
+impl Debug for Connecting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connecting")
+            .field("host", &self.host)
+            .field("peer", &self.peer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Connecting {
+    /// Starts connecting to `peer` on the [`IoTaskPool`].
+    ///
+    /// `C` is the connection message type and `R` is the response message type. These need to
+    /// match the types that the [`MsgTableParts`] were built with.
+    ///
+    /// `peer` can be an IPv4 or IPv6 address. An IPv4-mapped IPv6 address (`::ffff:a.b.c.d`) is
+    /// reported as the IPv4 address it maps to in [`peer`](Self::peer) and the events.
+    pub fn new<C, R>(peer: SocketAddr, parts: MsgTableParts, config: Config, con_msg: C) -> Self
+    where
+        C: Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        let task =
+            IoTaskPool::get().spawn(async move { connect::<C, R>(peer, parts, config, con_msg) });
+        let peer = canonical(peer);
+        Connecting::from_task(peer.to_string(), Some(peer), task)
+    }
+
+    /// Starts connecting to `host` on the [`IoTaskPool`], where `host` is a hostname and port,
+    /// such as `"play.example.com:7777"`, or an address.
+    ///
+    /// The hostname is resolved in the background, and the resolved addresses are tried in
+    /// turn until one succeeds, alternating between IPv6 and IPv4 addresses like happy eyeballs
+    /// does, so a broken IPv6 route doesn't stop IPv4 from being tried. The address that
+    /// succeeded is reported in [`ConnectSucceeded::peer`].
+    ///
+    /// `C` is the connection message type and `R` is the response message type. These need to
+    /// match the types that the [`MsgTableParts`] were built with.
+    pub fn resolve<C, R>(
+        host: impl Into<String>,
+        parts: MsgTableParts,
+        config: Config,
+        con_msg: C,
+    ) -> Self
+    where
+        C: Clone + Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        let host = host.into();
+        let to_resolve = host.clone();
+        let task = IoTaskPool::get().spawn(async move {
+            let addrs = match to_resolve.to_socket_addrs() {
+                Ok(addrs) => interleave(addrs.collect()),
+                Err(e) => return Err((None, e)),
+            };
+            let mut result = Err((
+                None,
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} did not resolve to any address", to_resolve),
+                ),
+            ));
+            for addr in addrs {
+                debug!("Trying to connect to {} ({})", to_resolve, addr);
+                result = connect::<C, R>(addr, parts.clone(), config.clone(), con_msg.clone());
+                if result.is_ok() {
+                    break;
+                }
+            }
+            result
+        });
+        Connecting::from_task(host, None, task)
+    }
+
+    /// Creates a connection attempt from a task that resolves it.
+    pub(crate) fn from_task(
+        host: String,
+        peer: Option<SocketAddr>,
+        task: Task<ConnectResult>,
+    ) -> Self {
+        Connecting { host, peer, task }
+    }
+
+    /// The address or hostname that is being connected to.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The address that is being connected to, or `None` if a hostname is being connected to.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+}
+
+/// Connects to `peer`, blocking until the connection resolves.
+pub(crate) fn connect<C, R>(
+    peer: SocketAddr,
+    parts: MsgTableParts,
+    config: Config,
+    con_msg: C,
+) -> ConnectResult
+where
+    C: Any + Send + Sync,
+    R: Any + Send + Sync,
+{
+    match Client::new(peer, parts, config, con_msg).block::<R>() {
+        Ok((client, response)) => Ok((peer, client, Box::new(response))),
+        Err(e) => Err((Some(peer), e)),
+    }
+}
+
+/// Orders `addrs` so that the address families alternate, starting with the family of the first
+/// address, keeping the order within each family.
+pub(crate) fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = match addrs.first() {
+        Some(addr) => addr.is_ipv6(),
+        None => return addrs,
+    };
+    let (first, second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut second = second.into_iter();
+    let mut ordered = vec![];
+    for addr in first {
+        ordered.push(addr);
+        ordered.extend(second.next());
+    }
+    ordered.extend(second);
+    ordered
+}
+
+/// Converts an IPv4-mapped IPv6 address to the IPv4 address it maps to.
+fn canonical(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), v6.port()),
+            None => addr,
+        },
+        addr => addr,
+    }
+}
+
+/// The addresses of the connection to the server, on a client.
+///
+/// This is inserted and removed along with the [`Client`] resource, whose `Debug` output doesn't
+/// have them.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct ClientAddrs {
+    /// The address of the server. IPv4-mapped IPv6 addresses are reported as IPv4.
+    pub peer: SocketAddr,
+    /// The local IP address that the connection to the server goes out from, if it could be
+    /// found.
+    pub local_ip: Option<IpAddr>,
+}
+
+impl ClientAddrs {
+    /// Gets the addresses of a connection to `peer`.
+    fn of(peer: SocketAddr) -> Self {
+        ClientAddrs {
+            peer: canonical(peer),
+            local_ip: local_ip_to(peer),
+        }
+    }
+}
+
+/// Finds the local IP address that the system routes the traffic to `peer` from.
+///
+/// Connecting a UDP socket doesn't send anything; it only picks the route.
+fn local_ip_to(peer: SocketAddr) -> Option<IpAddr> {
+    let unspecified: IpAddr = match peer {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = UdpSocket::bind((unspecified, 0)).ok()?;
+    socket.connect(peer).ok()?;
+    let local = socket.local_addr().ok()?;
+    Some(canonical(local).ip())
+}
+
+/// An event that is fired when a connection attempt succeeds.
+///
+/// The [`Client`] resource will be inserted the same frame this event is sent.
+pub struct ConnectSucceeded {
+    /// The address or hostname that was connected to.
+    pub host: String,
+    /// The address that the connection succeeded on.
+    pub peer: SocketAddr,
+    response: Box<dyn Any + Send + Sync>,
+}
+
+impl Debug for ConnectSucceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectSucceeded")
+            .field("host", &self.host)
+            .field("peer", &self.peer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConnectSucceeded {
+    /// Gets the response message that the server sent.
+    ///
+    /// Returns `None` if `R` is not the response type used in [`Connecting::new`].
+    pub fn response<R: Any + Send + Sync>(&self) -> Option<&R> {
+        self.response.downcast_ref()
+    }
+}
+
+/// An event that is fired when a connection attempt fails.
+#[derive(Debug)]
+pub struct ConnectFailed {
+    /// The address or hostname that was being connected to.
+    pub host: String,
+    /// The last address that was tried, or `None` if the hostname couldn't be resolved.
+    pub peer: Option<SocketAddr>,
+    /// The error that caused the connection to fail.
+    pub error: io::Error,
+}
+
+/// Polls the [`Connecting`] resource, inserting the [`Client`] once the connection resolves.
+pub fn poll_connecting(
+    mut commands: Commands,
+    connecting: Option<ResMut<Connecting>>,
+    mut succeeded: EventWriter<ConnectSucceeded>,
+    mut failed: EventWriter<ConnectFailed>,
+) {
+    let mut connecting = match connecting {
+        Some(connecting) if connecting.task.is_finished() => connecting,
+        _ => return,
+    };
+    let host = connecting.host.clone();
+
+    match future::block_on(future::poll_once(&mut connecting.task)) {
+        Some(Ok((peer, client, response))) => {
+            let addrs = ClientAddrs::of(peer);
+            let peer = addrs.peer;
+            info!("Connected to {} ({})", host, peer);
+            commands.insert_resource(client);
+            commands.insert_resource(addrs);
+            succeeded.send(ConnectSucceeded {
+                host,
+                peer,
+                response,
+            });
+        }
+        Some(Err((peer, error))) => {
+            error!("Failed to connect to {}: {}", host, error);
+            failed.send(ConnectFailed {
+                host,
+                peer: peer.map(canonical),
+                error,
+            });
+        }
+        None => return,
+    }
+    commands.remove_resource::<Connecting>();
+}
+
+/// A function that decides whether to accept a connection, and what to respond with.
+type HookFn<C, R> = Box<dyn Fn(CId, &C) -> (bool, R) + Send + Sync>;
+
+/// How a [`ConnectionHook`] decides.
+enum Hook<C, R> {
+    Fn(HookFn<C, R>),
+    System {
+        system: BoxedSystem<(CId, C), (bool, R)>,
+        initialized: bool,
+    },
+}
+
+/// Decides whether the server accepts a new connection with connection message type `C`, and
+/// creates the response of type `R`.
+///
+/// By default, every connection is accepted with `R::default()` as the response.
+#[derive(Resource)]
+pub struct ConnectionHook<C, R> {
+    hook: Hook<C, R>,
+}
+
+impl<C, R> Debug for ConnectionHook<C, R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.hook {
+            Hook::Fn(_) => "Fn",
+            Hook::System { .. } => "System",
+        };
+        f.debug_struct("ConnectionHook")
+            .field("kind", &kind)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, R: Default> Default for ConnectionHook<C, R> {
+    fn default() -> Self {
+        ConnectionHook::new(|_, _| (true, R::default()))
+    }
+}
+
+impl<C, R> ConnectionHook<C, R> {
+    /// Creates a hook that accepts a connection if `hook` returns `true`, and responds with the
+    /// returned response either way.
+    pub fn new(hook: impl Fn(CId, &C) -> (bool, R) + Send + Sync + 'static) -> Self {
+        ConnectionHook {
+            hook: Hook::Fn(Box::new(hook)),
+        }
+    }
+}
+
+impl<C: Clone + Any + Send + Sync, R: Any + Send + Sync> ConnectionHook<C, R> {
+    /// Creates a hook that runs `system` to decide. It gets the client and its connection
+    /// message as input, and can access the [`World`] like any other system, for example to
+    /// check the player count or a whitelist.
+    ///
+    /// The system is run once for every new connection, in the same frame the connection
+    /// arrives. The [`Server`] resource is not available to it.
+    ///
+    /// ```ignore
+    /// fn accept(In((_cid, join)): In<(CId, Join)>, lobby: Res<Lobby>) -> (bool, Welcome) {
+    ///     let accept = lobby.players < lobby.max_players && !lobby.banned.contains(&join.name);
+    ///     (accept, Welcome::default())
+    /// }
+    ///
+    /// app.insert_resource(ConnectionHook::from_system(accept));
+    /// ```
+    pub fn from_system<P>(system: impl IntoSystem<(CId, C), (bool, R), P>) -> Self {
+        ConnectionHook {
+            hook: Hook::System {
+                system: Box::new(IntoSystem::into_system(system)),
+                initialized: false,
+            },
+        }
+    }
+
+    /// Decides whether to accept the connection of client `cid` with connection message `msg`.
+    fn decide(&mut self, world: &mut World, cid: CId, msg: &C) -> (bool, R) {
+        match &mut self.hook {
+            Hook::Fn(hook) => hook(cid, msg),
+            Hook::System {
+                system,
+                initialized,
+            } => {
+                if !*initialized {
+                    system.initialize(world);
+                    *initialized = true;
+                }
+                let result = system.run((cid, msg.clone()), world);
+                system.apply_buffers(world);
+                result
+            }
+        }
+    }
+}
+
+/// An event that is sent on the server when a new connection is accepted.
+#[derive(Clone, Debug)]
+pub struct NetConnected<C> {
+    /// The client that connected.
+    pub cid: CId,
+    /// The connection message the client sent.
+    pub msg: C,
+}
+
+/// An event that is sent on the client with the response of the server, when the connection
+/// succeeds.
+#[derive(Clone, Debug)]
+pub struct ConnectionResponse<R> {
+    /// The response message the server sent.
+    pub response: R,
+}
+
+/// Handles the new connections on the server using the [`ConnectionHook`].
+pub fn handle_connections<C, R>(world: &mut World)
+where
+    C: Clone + Any + Send + Sync,
+    R: Any + Send + Sync,
+{
+    accept_connections::<C, C, R, R>(world, Ok, |response| response);
+}
+
+/// Handles the new connections with [`Versioned`] connection messages on the server, rejecting
+/// the clients with an incompatible [`ProtocolVersion`] or [`MsgSchema`], and using the
+/// [`ConnectionHook`] for the rest.
+pub fn handle_versioned_connections<C, R>(world: &mut World)
+where
+    C: Clone + Any + Send + Sync,
+    R: Any + Send + Sync,
+{
+    let server = match world.get_resource::<ProtocolVersion>() {
+        Some(version) => *version,
+        None => return,
+    };
+    let schema = world
+        .get_resource::<MsgSchema>()
+        .cloned()
+        .unwrap_or_default();
+    let hash = schema.hash();
+    accept_connections::<Versioned<C>, C, R, Handshake<R>>(
+        world,
+        |msg| {
+            if !server.is_compatible(&msg.version) {
+                return Err(Handshake::Rejected(Rejection::VersionMismatch {
+                    server,
+                    client: msg.version,
+                }));
+            }
+            if matches!(msg.schema, Some(client) if client != hash) {
+                return Err(Handshake::Rejected(Rejection::SchemaMismatch {
+                    server: schema.entries().to_vec(),
+                }));
+            }
+            Ok(msg.msg)
+        },
+        Handshake::Response,
+    );
+}
+
+/// Handles the new connections with connection message type `W` and response type `WR`.
+///
+/// `check` unwraps the connection message, or returns the response to reject it with before the
+/// [`ConnectionHook`] is asked. `wrap` wraps the response of the hook.
+fn accept_connections<W, C, R, WR>(
+    world: &mut World,
+    check: impl Fn(W) -> Result<C, WR>,
+    wrap: impl Fn(R) -> WR,
+) where
+    W: Any + Send + Sync,
+    C: Clone + Any + Send + Sync,
+    R: Any + Send + Sync,
+    WR: Any + Send + Sync,
+{
+    let mut server = match world.remove_resource::<Server>() {
+        Some(server) => server,
+        None => return,
+    };
+    let mut hook = world.remove_resource::<ConnectionHook<C, R>>().unwrap();
+    let config = world.get_resource::<PigeonServerConfig>().cloned();
+    let mut count = server.cids().count();
+
+    let mut connected = vec![];
+    server.handle_new_cons(|cid, msg: W| {
+        let msg = match check(msg) {
+            Ok(msg) => msg,
+            Err(response) => {
+                debug!("Rejected the connection of client {} early", cid);
+                return (false, response);
+            }
+        };
+        let (mut accept, response) = hook.decide(world, cid, &msg);
+        if accept && matches!(&config, Some(config) if !config.has_room(count)) {
+            debug!("The server is full");
+            accept = false;
+        }
+        if accept {
+            count += 1;
+            connected.push(NetConnected { cid, msg });
+        } else {
+            debug!("Rejected the connection of client {}", cid);
+        }
+        (accept, wrap(response))
+    });
+    world.insert_resource(server);
+    world.insert_resource(hook);
+
+    let mut events = world.resource_mut::<Events<NetConnected<C>>>();
+    for event in connected {
+        debug!("Client {} connected", event.cid);
+        events.send(event);
+    }
+}
+
+/// Sends a [`ConnectionResponse`] for every successful connection.
+pub fn recv_connection_response<R: Clone + Any + Send + Sync>(
+    mut er: EventReader<ConnectSucceeded>,
+    mut ew: EventWriter<ConnectionResponse<R>>,
+) {
+    for event in er.iter() {
+        match event.response::<R>() {
+            Some(response) => ew.send(ConnectionResponse {
+                response: response.clone(),
+            }),
+            None => warn!(
+                "The connection response is not of type {}",
+                std::any::type_name::<R>()
+            ),
+        }
+    }
+}
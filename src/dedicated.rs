@@ -0,0 +1,166 @@
+//! A preset for headless dedicated servers.
+//!
+//! [`DedicatedServerPlugins`] has everything a dedicated server needs and nothing it doesn't: no
+//! window, no rendering and no input. The schedule runs in a loop at a fixed tick rate, sleeping
+//! between ticks, and the server shuts down gracefully when the process is interrupted (Ctrl+C)
+//! or terminated.
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins(DedicatedServerPlugins { tick_rate: 30.0 })
+//!     .insert_resource(ShutdownMsg::new(Disconnect::ServerClosed))
+//!     .run();
+//! ```
+//!
+//! To keep the render dependent mirrors of the [`types`](crate::types) module out of the
+//! server build, depend on `bevy-pigeon` with `default-features = false` and only the
+//! `dedicated` feature.
+
+use crate::config::PigeonServerConfig;
+use crate::disconnect::DisconnectPlugin;
+use crate::ServerPlugin;
+use bevy::app::{AppExit, PluginGroupBuilder, ScheduleRunnerPlugin, ScheduleRunnerSettings};
+use bevy::core::CorePlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::time::TimePlugin;
+use carrier_pigeon::{CId, Server};
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The plugins for a headless dedicated server.
+///
+/// This adds the [`CorePlugin`], [`TimePlugin`], [`LogPlugin`] and [`ScheduleRunnerPlugin`] (the
+/// same as bevy's `MinimalPlugins` with logging), along with the [`ServerPlugin`],
+/// [`DisconnectPlugin`] and [`DedicatedServerPlugin`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DedicatedServerPlugins {
+    /// The number of ticks per second. Defaults to 60.
+    pub tick_rate: f64,
+}
+
+impl Default for DedicatedServerPlugins {
+    fn default() -> Self {
+        DedicatedServerPlugins { tick_rate: 60.0 }
+    }
+}
+
+impl PluginGroup for DedicatedServerPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CorePlugin::default())
+            .add(TimePlugin)
+            .add(LogPlugin::default())
+            .add(ScheduleRunnerPlugin)
+            .add(ServerPlugin)
+            .add(DisconnectPlugin)
+            .add(DedicatedServerPlugin {
+                tick_rate: self.tick_rate,
+            })
+    }
+}
+
+/// Runs the schedule at a fixed tick rate, and shuts the server down gracefully when the process
+/// is interrupted or terminated.
+///
+/// This is part of the [`DedicatedServerPlugins`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DedicatedServerPlugin {
+    /// The number of ticks per second.
+    ///
+    /// This is overridden by the tick rate of the
+    /// [`PigeonServerConfig`](crate::config::PigeonServerConfig) resource, if it exists.
+    pub tick_rate: f64,
+}
+
+impl Plugin for DedicatedServerPlugin {
+    fn build(&self, app: &mut App) {
+        let signal = ShutdownSignal::default();
+        let flag = signal.0.clone();
+        if let Err(e) = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed)) {
+            error!("Failed to set the shutdown signal handler: {}", e);
+        }
+
+        let tick_rate = app
+            .world
+            .get_resource::<PigeonServerConfig>()
+            .map(|config| config.tick_rate)
+            .unwrap_or(self.tick_rate);
+        app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+            1.0 / tick_rate,
+        )))
+        .insert_resource(signal)
+        .add_event::<ServerShutdown>()
+        .add_system_to_stage(CoreStage::First, shutdown_on_signal);
+    }
+}
+
+/// Whether a shutdown signal was received.
+#[derive(Resource, Clone, Debug, Default)]
+struct ShutdownSignal(Arc<AtomicBool>);
+
+/// An event that is sent when the server is shutting down.
+///
+/// The app exits at the end of the frame this is sent in.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct ServerShutdown;
+
+/// A function that disconnects a client with the shutdown message.
+type DisconnectFn = Box<dyn Fn(&mut Server, CId) -> io::Result<()> + Send + Sync>;
+
+/// The disconnect message that is sent to every client when the server shuts down.
+///
+/// If this resource doesn't exist, the connections are closed without a message.
+#[derive(Resource)]
+pub struct ShutdownMsg {
+    disconnect: DisconnectFn,
+}
+
+impl Debug for ShutdownMsg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownMsg").finish_non_exhaustive()
+    }
+}
+
+impl ShutdownMsg {
+    /// Creates a shutdown message from `msg`.
+    ///
+    /// `D` needs to be the disconnect message type that the `MsgTable` was built with.
+    pub fn new<D: Any + Send + Sync>(msg: D) -> Self {
+        ShutdownMsg {
+            disconnect: Box::new(move |server, cid| server.disconnect(&msg, cid)),
+        }
+    }
+}
+
+/// Disconnects all clients and exits the app once a shutdown signal is received.
+fn shutdown_on_signal(
+    mut commands: Commands,
+    signal: Res<ShutdownSignal>,
+    server: Option<ResMut<Server>>,
+    msg: Option<Res<ShutdownMsg>>,
+    mut shutdown: EventWriter<ServerShutdown>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !signal.0.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    info!("Shutting down the server");
+    if let Some(mut server) = server {
+        if let Some(msg) = msg {
+            let cids: Vec<_> = server.cids().collect();
+            for cid in cids {
+                if let Err(e) = (msg.disconnect)(&mut server, cid) {
+                    warn!("Failed to disconnect client {}: {}", cid, e);
+                }
+            }
+        }
+        commands.remove_resource::<Server>();
+    }
+    shutdown.send(ServerShutdown);
+    exit.send(AppExit);
+}
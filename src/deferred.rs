@@ -0,0 +1,123 @@
+//! Spreading the application of heavy component updates over several frames.
+//!
+//! Applying a very large update, like a chunk of tiles or an inventory, can take a noticeable
+//! part of a frame, and a peer that receives many at once stutters. With a
+//! [`DeferredApply<T, M>`], received updates of message type `M` are queued instead of being applied
+//! in [`comp_recv`](crate::app::comp_recv), and [`apply_deferred_updates`] applies them in
+//! [`CoreStage::PreUpdate`] until [`max_time`](DeferredApply::max_time) has been spent, leaving
+//! the rest for the next frame.
+//!
+//! Enable it for a component with [`defer_apply`](crate::AppExt::defer_apply). Only the newest
+//! queued update of every entity is kept, so an entity that is updated faster than the queue is
+//! applied doesn't fall further and further behind. Updates received by the server are validated
+//! before they are queued.
+
+use crate::app::{apply_as, apply_clone, report_applied, Reports, UpdateSender};
+use crate::sync::{ApplyMode, NetEntity};
+use crate::{NetUpdated, SyncInfo};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, Instant};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// A queue of received updates of message type `M` that are applied to component `T` over
+/// several frames.
+#[derive(Resource)]
+pub struct DeferredApply<T, M> {
+    /// The time that is spent applying updates per frame. At least one update is applied every
+    /// frame, however long it takes.
+    pub max_time: Duration,
+    /// The entities with a queued update, in the order they were queued.
+    order: VecDeque<NetEntity>,
+    /// The newest queued update of every entity, with its sender.
+    queued: HashMap<NetEntity, (M, UpdateSender)>,
+    _pd: PhantomData<T>,
+}
+
+impl<T, M> std::fmt::Debug for DeferredApply<T, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeferredApply")
+            .field("max_time", &self.max_time)
+            .field("queued", &self.order.len())
+            .finish()
+    }
+}
+
+impl<T, M> DeferredApply<T, M> {
+    /// Creates a queue that spends at most `max_time` per frame applying updates.
+    pub fn new(max_time: Duration) -> Self {
+        DeferredApply {
+            max_time,
+            order: VecDeque::new(),
+            queued: HashMap::default(),
+            _pd: PhantomData,
+        }
+    }
+
+    /// The number of entities with an update that is waiting to be applied.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Whether there are no updates waiting to be applied.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Queues `msg` from `sender` for `net_e`, replacing its queued update if it has one.
+    pub(crate) fn push(&mut self, net_e: NetEntity, sender: UpdateSender, msg: M) {
+        if self.queued.insert(net_e, (msg, sender)).is_none() {
+            self.order.push_back(net_e);
+        }
+    }
+
+    /// Takes the oldest queued update.
+    fn pop(&mut self) -> Option<(NetEntity, (M, UpdateSender))> {
+        let net_e = self.order.pop_front()?;
+        self.queued.remove(&net_e).map(|queued| (net_e, queued))
+    }
+}
+
+/// Applies the queued updates of the [`DeferredApply<T, M>`] until its time budget is spent, and
+/// reports every applied update with a [`NetUpdated`] event, the
+/// [`NetSource`](crate::NetSource) and the [`OnNetUpdate`](crate::OnNetUpdate) hook.
+pub fn apply_deferred_updates<T, M>(
+    mut deferred: ResMut<DeferredApply<T, M>>,
+    info: Option<Res<SyncInfo<T, M>>>,
+    mut updated: EventWriter<NetUpdated<T>>,
+    mut commands: Commands,
+    mut q: Query<(Entity, &NetEntity, &mut T, Reports<T>)>,
+) where
+    T: Clone + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    if deferred.is_empty() {
+        return;
+    }
+    let (apply, mode) = info
+        .map(|i| (i.apply(), i.apply_mode()))
+        .unwrap_or((apply_clone::<T, M>, ApplyMode::Mutate));
+    // Updates queued for an older entity with the same id don't match.
+    let entities: HashMap<NetEntity, Entity> = q
+        .iter()
+        .map(|(entity, net_e, _, _)| (*net_e, entity))
+        .collect();
+
+    let start = Instant::now();
+    while let Some((net_e, (msg, sender))) = deferred.pop() {
+        let entity = match entities.get(&net_e) {
+            Some(entity) => *entity,
+            None => continue,
+        };
+        if let Ok((_, _, mut comp, (mut source, hook))) = q.get_mut(entity) {
+            apply_as(mode, entity, &msg, &mut comp, apply, &mut commands);
+            let reports = (source.as_deref_mut(), hook);
+            report_applied(entity, sender, reports, &mut updated, &mut commands);
+        }
+        if start.elapsed() >= deferred.max_time {
+            break;
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Events for dropped connections.
+//!
+//! The [`DisconnectPlugin`] sends a [`NetDisconnected`] event whenever a connection ends, with
+//! why it ended, and when the connection was last known to work.
+//!
+//! On the server, the plugin handles the disconnects of the clients, so don't call
+//! `Server::handle_disconnects` yourself; read the events instead. It also removes the client from
+//! the [`NetGroups`], [`ClientInterest`], [`ConnectedPlayers`], [`NetAcks`], [`SyncLod`] and
+//! [`DistancePriority`] resources, and from every [`ClientState`] resource that the app added,
+//! like the [`PlayerInputs`](crate::input::PlayerInputs),
+//! [`ClientCommands`](crate::command::ClientCommands),
+//! [`JitterBuffer`](crate::jitter::JitterBuffer) and [`Resends`](crate::resend::Resends) of every
+//! type. On the client, the [`Client`] resource is removed once the connection is closed.
+
+use crate::ack::NetAcks;
+use crate::app::{client_tick, server_tick};
+use crate::connect::ClientAddrs;
+use crate::group::NetGroups;
+use crate::interest::ClientInterest;
+use crate::lod::SyncLod;
+use crate::player::ConnectedPlayers;
+use crate::priority::DistancePriority;
+use crate::NetLabel;
+use bevy::prelude::*;
+use carrier_pigeon::net::Status;
+use carrier_pigeon::{CId, Client, Server};
+
+/// Which side ended a connection.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum DisconnectInitiator {
+    /// This side closed the connection.
+    Local,
+    /// The peer disconnected gracefully.
+    Remote,
+    /// The connection was dropped because of an error.
+    Error,
+}
+
+/// An event that is sent when a connection ends.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NetDisconnected {
+    /// The client that disconnected, on the server. `None` on the client.
+    pub cid: Option<CId>,
+    /// Which side ended the connection.
+    pub initiator: DisconnectInitiator,
+    /// The error that dropped the connection, if any.
+    pub error: Option<String>,
+    /// The local time that a message was last sent successfully, in seconds.
+    ///
+    /// This is only known if [acks](crate::ack) are enabled.
+    pub last_send: Option<f64>,
+    /// The local time that a message was last received, in seconds.
+    ///
+    /// This is only known if [acks](crate::ack) are enabled.
+    pub last_recv: Option<f64>,
+}
+
+impl NetDisconnected {
+    /// Creates the event for a connection that ended with `status`.
+    ///
+    /// Returns `None` if the connection is still open.
+    fn new(cid: Option<CId>, status: &Status, acks: Option<&NetAcks>) -> Option<Self> {
+        let (initiator, error) = match status {
+            Status::Connected => return None,
+            Status::Disconnected(_) => (DisconnectInitiator::Remote, None),
+            Status::Dropped(e) => (DisconnectInitiator::Error, Some(e.to_string())),
+            Status::Closed => (DisconnectInitiator::Local, None),
+        };
+        let info = acks.and_then(|acks| match cid {
+            Some(cid) => acks.client(cid),
+            None => acks.server(),
+        });
+        Some(NetDisconnected {
+            cid,
+            initiator,
+            error,
+            last_send: info.and_then(|i| i.last_send()),
+            last_recv: info.and_then(|i| i.last_recv()),
+        })
+    }
+}
+
+/// A plugin that sends [`NetDisconnected`] events.
+///
+/// This should be added alongside the [`ClientPlugin`](crate::ClientPlugin) or
+/// [`ServerPlugin`](crate::ServerPlugin).
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct DisconnectPlugin;
+
+impl Plugin for DisconnectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<NetDisconnected>()
+            .add_system_to_stage(
+                CoreStage::First,
+                server_disconnects.label(NetLabel).after(server_tick),
+            )
+            .add_system_to_stage(
+                CoreStage::First,
+                client_disconnect.label(NetLabel).after(client_tick),
+            );
+    }
+}
+
+/// Handles the disconnects of the clients on the server.
+pub fn server_disconnects(
+    server: Option<ResMut<Server>>,
+    mut acks: Option<ResMut<NetAcks>>,
+    mut groups: Option<ResMut<NetGroups>>,
+    mut interest: Option<ResMut<ClientInterest>>,
+    mut players: Option<ResMut<ConnectedPlayers>>,
+    (mut lod, mut priority): (Option<ResMut<SyncLod>>, Option<ResMut<DistancePriority>>),
+    mut ew: EventWriter<NetDisconnected>,
+) {
+    let mut server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let mut events = vec![];
+    server.handle_disconnects(|cid, status| {
+        if let Some(event) = NetDisconnected::new(Some(cid), &status, acks.as_deref()) {
+            events.push((cid, event));
+        }
+    });
+    for (cid, event) in events {
+        debug!("Client {} disconnected ({:?})", cid, event.initiator);
+        if let Some(acks) = acks.as_deref_mut() {
+            acks.remove_client(cid);
+        }
+        if let Some(groups) = groups.as_deref_mut() {
+            groups.remove_client(cid);
+        }
+        if let Some(interest) = interest.as_deref_mut() {
+            interest.untrack(cid);
+        }
+        if let Some(players) = players.as_deref_mut() {
+            players.remove_client(cid);
+        }
+        if let Some(lod) = lod.as_deref_mut() {
+            lod.remove_client(cid);
+        }
+        if let Some(priority) = priority.as_deref_mut() {
+            priority.clear_viewpoint(cid);
+        }
+        ew.send(event);
+    }
+}
+
+/// A resource that keeps state for every client, which is removed when the client disconnects.
+///
+/// Resources of generic types can't be listed in [`server_disconnects`], so they implement this
+/// instead, and [`remove_disconnected`] is added for each of them.
+pub trait ClientState: Resource {
+    /// Removes everything that is kept for client `cid`.
+    fn remove_client(&mut self, cid: CId);
+}
+
+/// Removes the clients that disconnected from resource `R`.
+pub fn remove_disconnected<R: ClientState>(
+    mut er: EventReader<NetDisconnected>,
+    mut state: Option<ResMut<R>>,
+) {
+    for cid in er.iter().filter_map(|event| event.cid) {
+        if let Some(state) = state.as_deref_mut() {
+            state.remove_client(cid);
+        }
+    }
+}
+
+/// Adds [`remove_disconnected`] for resource `R`.
+///
+/// This runs in [`CoreStage::PreUpdate`], after the disconnects were handled in
+/// [`CoreStage::First`], and only does anything if the [`DisconnectPlugin`] is added.
+pub(crate) fn remove_on_disconnect<R: ClientState>(app: &mut App) -> &mut App {
+    app.add_event::<NetDisconnected>().add_system_to_stage(
+        CoreStage::PreUpdate,
+        remove_disconnected::<R>.label(NetLabel),
+    )
+}
+
+/// Sends a [`NetDisconnected`] and removes the [`Client`] when its connection ends.
+pub fn client_disconnect(
+    mut commands: Commands,
+    client: Option<Res<Client>>,
+    mut acks: Option<ResMut<NetAcks>>,
+    mut ew: EventWriter<NetDisconnected>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    if client.open() {
+        return;
+    }
+    if let Some(event) = NetDisconnected::new(None, client.status(), acks.as_deref()) {
+        debug!("Disconnected from the server ({:?})", event.initiator);
+        ew.send(event);
+    }
+    if let Some(acks) = acks.as_deref_mut() {
+        acks.remove_server();
+    }
+    commands.remove_resource::<Client>();
+    commands.remove_resource::<ClientAddrs>();
+}
@@ -0,0 +1,72 @@
+//! Reporting networking errors as events.
+//!
+//! When sending a synced component or another message, like a command, fails, a [`NetErrorEvent`]
+//! is sent, so games can react to it, for example by marking a client as lagging or reconnecting.
+//! The errors are also logged, but at most once per second for each message type, so a broken
+//! connection doesn't flood the log.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::CId;
+
+/// The kind of a [`NetErrorEvent`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum NetErrorKind {
+    /// Sending a message failed.
+    Send,
+}
+
+/// An event that is sent when sending a message fails.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct NetErrorEvent {
+    /// The kind of error.
+    pub kind: NetErrorKind,
+    /// The entity whose component was being sent, or `None` if the message isn't about a known
+    /// entity, like a command.
+    pub entity: Option<Entity>,
+    /// The type name of the component or message.
+    pub type_name: &'static str,
+    /// The client the message was being sent to, or `None` if it was sent to the server, or to
+    /// several clients at once.
+    pub cid: Option<CId>,
+    /// The error message.
+    pub error: String,
+}
+
+/// The errors that happened while sending, with the client each message was sent to.
+pub(crate) type SendErrors = Vec<(Option<CId>, String)>;
+
+/// The number of seconds between logs of the same error kind and component type.
+const LOG_INTERVAL: f64 = 1.0;
+
+/// Logs [`NetErrorEvent`]s, rate limited for each kind and component type.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorLog {
+    /// The time of the last log, and the number of errors that weren't logged since.
+    last: HashMap<(NetErrorKind, &'static str), (f64, u32)>,
+}
+
+impl ErrorLog {
+    /// Logs `event` at the local time `now`, unless the same kind of error was logged recently.
+    pub(crate) fn log(&mut self, event: &NetErrorEvent, now: f64) {
+        let (kind, type_name, error) = (event.kind, event.type_name, &event.error);
+        let (last, suppressed) = self
+            .last
+            .entry((kind, type_name))
+            .or_insert((f64::NEG_INFINITY, 0));
+        if now - *last < LOG_INTERVAL {
+            *suppressed += 1;
+            return;
+        }
+        if *suppressed > 0 {
+            error!(
+                "Failed to send {} ({} similar errors suppressed): {}",
+                type_name, suppressed, error
+            );
+        } else {
+            error!("Failed to send {}: {}", type_name, error);
+        }
+        *last = now;
+        *suppressed = 0;
+    }
+}
@@ -0,0 +1,214 @@
+//! Dead reckoning for synced components.
+//!
+//! Synced components normally snap to every update they get, so a remote entity stops whenever
+//! a packet is late, then jumps ahead when it arrives. Adding an [`Extrapolate<T>`] next to a
+//! `NetComp<T, M>` keeps moving the component with the velocity of the last two updates while
+//! waiting, and blends back towards the received values when they arrive.
+//!
+//! The component type must implement [`Extrapolatable`], which is implemented for [`Transform`]
+//! and some math types. Enable it with
+//! [`extrapolate_comp`](crate::AppExt::extrapolate_comp):
+//!
+//! ```ignore
+//! app.sync_comp::<Transform, NetTransform>(&mut table, Transport::UDP);
+//! app.extrapolate_comp::<Transform, NetTransform>();
+//!
+//! commands.spawn((
+//!     NetEntity::new(id),
+//!     NetComp::<Transform, NetTransform>::default(),
+//!     Extrapolate::<Transform>::default(),
+//! ));
+//! ```
+
+use crate::sync::NetComp;
+use bevy::prelude::*;
+use std::any::Any;
+
+/// A type that can be extrapolated from its previous values.
+pub trait Extrapolatable: Clone {
+    /// Continues the change from `prev` to `latest` (which took `dt` seconds) for `ahead` more
+    /// seconds after `latest`.
+    fn extrapolate(prev: &Self, latest: &Self, dt: f32, ahead: f32) -> Self;
+
+    /// Blends from `self` towards `target`, where `t` is between 0 (`self`) and 1 (`target`).
+    fn blend(&self, target: &Self, t: f32) -> Self;
+}
+
+impl Extrapolatable for f32 {
+    fn extrapolate(prev: &Self, latest: &Self, dt: f32, ahead: f32) -> Self {
+        latest + (latest - prev) / dt * ahead
+    }
+
+    fn blend(&self, target: &Self, t: f32) -> Self {
+        self + (target - self) * t
+    }
+}
+
+impl Extrapolatable for Vec2 {
+    fn extrapolate(prev: &Self, latest: &Self, dt: f32, ahead: f32) -> Self {
+        *latest + (*latest - *prev) / dt * ahead
+    }
+
+    fn blend(&self, target: &Self, t: f32) -> Self {
+        self.lerp(*target, t)
+    }
+}
+
+impl Extrapolatable for Vec3 {
+    fn extrapolate(prev: &Self, latest: &Self, dt: f32, ahead: f32) -> Self {
+        *latest + (*latest - *prev) / dt * ahead
+    }
+
+    fn blend(&self, target: &Self, t: f32) -> Self {
+        self.lerp(*target, t)
+    }
+}
+
+impl Extrapolatable for Quat {
+    fn extrapolate(prev: &Self, latest: &Self, dt: f32, ahead: f32) -> Self {
+        // Keep rotating by the rotation between the updates, scaled to the time ahead.
+        let delta = *latest * prev.inverse();
+        Quat::IDENTITY.slerp(delta, ahead / dt) * *latest
+    }
+
+    fn blend(&self, target: &Self, t: f32) -> Self {
+        self.slerp(*target, t)
+    }
+}
+
+impl Extrapolatable for Transform {
+    fn extrapolate(prev: &Self, latest: &Self, dt: f32, ahead: f32) -> Self {
+        Transform {
+            translation: Vec3::extrapolate(&prev.translation, &latest.translation, dt, ahead),
+            rotation: Quat::extrapolate(&prev.rotation, &latest.rotation, dt, ahead),
+            scale: Vec3::extrapolate(&prev.scale, &latest.scale, dt, ahead),
+        }
+    }
+
+    fn blend(&self, target: &Self, t: f32) -> Self {
+        Transform {
+            translation: self.translation.blend(&target.translation, t),
+            rotation: self.rotation.blend(&target.rotation, t),
+            scale: self.scale.blend(&target.scale, t),
+        }
+    }
+}
+
+/// A received value of the component.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Sample<T> {
+    value: T,
+    /// The time the update was sent at, in ms.
+    sent: u32,
+    /// The local time the update was received at, in seconds.
+    received: f32,
+}
+
+/// A component that extrapolates the synced component `T` between updates.
+///
+/// Requires [`extrapolate_comp`](crate::AppExt::extrapolate_comp) for `T`.
+#[derive(Component, Clone, PartialEq, Debug)]
+pub struct Extrapolate<T: Extrapolatable> {
+    /// The maximum time in seconds to extrapolate for after the last update.
+    ///
+    /// After this, the component stays put until the next update. Defaults to 0.25.
+    pub max_time: f32,
+    /// The time in seconds to blend back to the received values when an update arrives.
+    ///
+    /// Defaults to 0.1. With 0, the component snaps to the new values.
+    pub blend_time: f32,
+    prev: Option<Sample<T>>,
+    latest: Option<Sample<T>>,
+    /// The value that was shown last frame.
+    shown: Option<T>,
+    /// The local time that the blending started at, in seconds.
+    blend_start: Option<f32>,
+}
+
+impl<T: Extrapolatable> Default for Extrapolate<T> {
+    fn default() -> Self {
+        Extrapolate {
+            max_time: 0.25,
+            blend_time: 0.1,
+            prev: None,
+            latest: None,
+            shown: None,
+            blend_start: None,
+        }
+    }
+}
+
+impl<T: Extrapolatable> Extrapolate<T> {
+    /// Creates a new [`Extrapolate`] with the given max extrapolation time and blend time.
+    pub fn new(max_time: f32, blend_time: f32) -> Self {
+        Extrapolate {
+            max_time,
+            blend_time,
+            ..default()
+        }
+    }
+
+    /// Gets where the component should be at the local time `now`.
+    fn target(&self, now: f32) -> Option<T> {
+        let latest = self.latest.as_ref()?;
+        let prev = match &self.prev {
+            Some(prev) => prev,
+            None => return Some(latest.value.clone()),
+        };
+        let dt = latest.sent.wrapping_sub(prev.sent) as f32 / 1000.0;
+        if dt <= 0.0 {
+            return Some(latest.value.clone());
+        }
+        let ahead = (now - latest.received).clamp(0.0, self.max_time);
+        Some(T::extrapolate(&prev.value, &latest.value, dt, ahead))
+    }
+}
+
+/// Extrapolates the components `T` that have an [`Extrapolate<T>`].
+///
+/// This runs after the received updates are applied, so a changed
+/// [`last`](NetComp::last) timestamp means the component holds a fresh update.
+pub fn extrapolate<T, M>(
+    time: Res<Time>,
+    mut q: Query<(&NetComp<T, M>, &mut Extrapolate<T>, &mut T)>,
+) where
+    T: Extrapolatable + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let now = time.elapsed_seconds();
+    for (net_c, mut ext, mut comp) in q.iter_mut() {
+        let sent = match net_c.last {
+            Some(sent) => sent,
+            None => continue,
+        };
+        if ext.latest.as_ref().map(|s| s.sent) != Some(sent) {
+            let sample = Sample {
+                value: comp.clone(),
+                sent,
+                received: now,
+            };
+            ext.prev = ext.latest.replace(sample);
+            if ext.shown.is_some() && ext.blend_time > 0.0 {
+                ext.blend_start = Some(now);
+            }
+        }
+
+        let target = match ext.target(now) {
+            Some(target) => target,
+            None => continue,
+        };
+        let value = match (&ext.shown, ext.blend_start) {
+            (Some(shown), Some(start)) if now - start < ext.blend_time => {
+                // Move part of the way from the last shown value every frame.
+                let t = (time.delta_seconds() / (ext.blend_time - (now - start))).min(1.0);
+                shown.blend(&target, t)
+            }
+            _ => {
+                ext.blend_start = None;
+                target
+            }
+        };
+        *comp = value.clone();
+        ext.shown = Some(value);
+    }
+}
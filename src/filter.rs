@@ -0,0 +1,90 @@
+//! Deciding per entity and client whether the server sends updates, from any state of the world.
+//!
+//! [`ClientInterest`](crate::ClientInterest) and [`NetHidden`](crate::NetHidden) have to be kept
+//! up to date by gameplay code. A send filter instead is asked directly, with read access to the
+//! whole world, whether client `cid` may receive the updates of `entity`. This fits fog of war,
+//! stealth and anti-wallhack checks, which depend on positions, teams and line of sight:
+//!
+//! ```ignore
+//! app.add_send_filter(|world, entity, cid| {
+//!     let stealth = world.get::<Stealth>(entity);
+//!     stealth.is_none() || same_team(world, entity, cid)
+//! });
+//! ```
+//!
+//! An update is sent to a client only if every filter allows it, on top of the other
+//! restrictions. The filters are evaluated once per frame for every [`NetEntity`] and client, in
+//! [`CoreStage::Last`] before the send systems, so they should be cheap. Components that are sent
+//! in [`CoreStage::Last`] or an earlier stage, instead of the
+//! [`NetStage::Send`](crate::NetStage::Send), use the decisions of the previous frame.
+//!
+//! A client that is denied an entity stops receiving its updates, but keeps the last state it
+//! received; use [`NetHidden`](crate::NetHidden) to despawn it on the client instead.
+
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::{CId, Server};
+use std::fmt::{Debug, Formatter};
+
+/// A filter that decides whether client `cid` may receive the updates of `entity`.
+pub type SendFilter = Box<dyn Fn(&World, Entity, CId) -> bool + Send + Sync>;
+
+/// The send filters, and the clients they denied each entity this frame.
+///
+/// Filters are added with [`add_send_filter`](crate::AppExt::add_send_filter).
+#[derive(Resource, Default)]
+pub struct NetFilters {
+    filters: Vec<SendFilter>,
+    /// The clients that may not receive each entity, by [`NetEntity`] id.
+    denied: HashMap<NetId, HashSet<CId>>,
+}
+
+impl Debug for NetFilters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetFilters")
+            .field("filters", &self.filters.len())
+            .field("denied", &self.denied)
+            .finish()
+    }
+}
+
+impl NetFilters {
+    /// Adds a filter.
+    pub fn add(&mut self, filter: impl Fn(&World, Entity, CId) -> bool + Send + Sync + 'static) {
+        self.filters.push(Box::new(filter));
+    }
+
+    /// Whether the filters allowed client `cid` to receive the entity with `id` this frame.
+    pub fn allows(&self, id: NetId, cid: CId) -> bool {
+        self.denied.get(&id).is_none_or(|cids| !cids.contains(&cid))
+    }
+
+    /// Whether the filters denied the entity with `id` to any client this frame.
+    pub(crate) fn denies_any(&self, id: NetId) -> bool {
+        self.denied.contains_key(&id)
+    }
+}
+
+/// Evaluates the [`NetFilters`] for every [`NetEntity`] and client.
+pub fn evaluate_send_filters(world: &mut World) {
+    let cids: Vec<CId> = match world.get_resource::<Server>() {
+        Some(server) => server.cids().collect(),
+        None => return,
+    };
+    let mut q = world.query::<(Entity, &NetEntity)>();
+    world.resource_scope(|world, mut filters: Mut<NetFilters>| {
+        filters.denied.clear();
+        if filters.filters.is_empty() {
+            return;
+        }
+        let filters = &mut *filters;
+        for (entity, net_e) in q.iter(world) {
+            for cid in cids.iter() {
+                if !filters.filters.iter().all(|f| f(world, entity, *cid)) {
+                    filters.denied.entry(net_e.id).or_default().insert(*cid);
+                }
+            }
+        }
+    });
+}
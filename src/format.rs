@@ -0,0 +1,163 @@
+//! Pluggable wire formats for messages.
+//!
+//! `carrier-pigeon` decides how a message is serialized based on its type. To send a message in
+//! a different format, wrap it in [`Encoded<M, F>`], where `F` is a [`WireFormat`]. The message
+//! is first encoded to bytes with `F`, and those bytes are what `carrier-pigeon` sends.
+//!
+//! This lets you trade size (`Postcard`, `MsgPack`) against debuggability (`Json`) per
+//! message type. Since the format is part of the type, it has to be the same on both ends.
+//!
+//! To use a format for a synced component, use [`Encoded`] as the message type:
+//!
+//! ```ignore
+//! impl From<Encoded<Health, Json>> for Health {
+//!     fn from(msg: Encoded<Health, Json>) -> Self {
+//!         msg.into_inner()
+//!     }
+//! }
+//!
+//! app.sync_comp::<Health, Encoded<Health, Json>>(&mut table, Transport::TCP);
+//! ```
+//!
+//! The `Health -> Encoded<Health, Json>` conversion is implemented for you. The orphan rules
+//! don't allow implementing the other direction here, so it has to be implemented in your crate.
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// The error type of a [`WireFormat`].
+pub type FormatError = Box<dyn Error + Send + Sync>;
+
+/// A format that messages can be encoded in.
+pub trait WireFormat: Any + Send + Sync + Debug {
+    /// Encodes `value` into bytes.
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, FormatError>;
+    /// Decodes a value from `bytes`.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FormatError>;
+}
+
+/// The [bincode](https://docs.rs/bincode) format.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Bincode;
+
+impl WireFormat for Bincode {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, FormatError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FormatError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// The [postcard](https://docs.rs/postcard) format. Requires the `postcard` feature.
+///
+/// Uses variable length integers, so it is usually smaller than [`Bincode`].
+#[cfg(feature = "postcard")]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl WireFormat for Postcard {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, FormatError> {
+        Ok(postcard::to_allocvec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FormatError> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+/// The [MessagePack](https://msgpack.org) format. Requires the `msgpack` feature.
+///
+/// Useful for talking to peers that are not written in rust.
+#[cfg(feature = "msgpack")]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl WireFormat for MsgPack {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, FormatError> {
+        Ok(rmp_serde::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FormatError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+/// The JSON format. Requires the `json` feature.
+///
+/// This is much larger than the binary formats, but is human readable, which makes it handy for
+/// debugging with a packet sniffer.
+#[cfg(feature = "json")]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl WireFormat for Json {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, FormatError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, FormatError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// A message `M` that is sent encoded with the [`WireFormat`] `F`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Encoded<M, F: WireFormat>(pub M, PhantomData<F>);
+
+impl<M, F: WireFormat> Encoded<M, F> {
+    /// Wraps `msg` to be encoded with `F`.
+    pub fn new(msg: M) -> Self {
+        Encoded(msg, PhantomData)
+    }
+
+    /// Gets the inner message.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M, F: WireFormat> From<M> for Encoded<M, F> {
+    fn from(msg: M) -> Self {
+        Encoded::new(msg)
+    }
+}
+
+impl<M, F: WireFormat> Deref for Encoded<M, F> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M, F: WireFormat> DerefMut for Encoded<M, F> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.0
+    }
+}
+
+impl<M: Serialize, F: WireFormat> Serialize for Encoded<M, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = F::encode(&self.0).map_err(S::Error::custom)?;
+        bytes.serialize(serializer)
+    }
+}
+
+impl<'de, M: DeserializeOwned, F: WireFormat> Deserialize<'de> for Encoded<M, F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        let msg = F::decode(&bytes).map_err(D::Error::custom)?;
+        Ok(Encoded::new(msg))
+    }
+}
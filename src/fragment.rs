@@ -0,0 +1,346 @@
+//! Fragmentation of large component messages on the unreliable path.
+//!
+//! UDP packets that are larger than the MTU are likely to be dropped. When a synced component is
+//! sent over UDP and its message is larger than [`FragmentConfig::payload_budget`], it is split
+//! into multiple fragments that are reassembled on the other end. If any fragment is lost, the
+//! whole message is lost, just like any other unreliable message.
+//!
+//! Fragmented messages that exceed the [`NetLimits`] are dropped with a
+//! [`MalformedMsg`](crate::limits::MalformedMsg) event.
+
+use crate::limits::NetLimits;
+use crate::sync::NetCompMsg;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::CId;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::marker::PhantomData;
+
+/// The estimated number of bytes used by everything in a fragment other than the payload.
+pub const FRAGMENT_OVERHEAD: usize = 64;
+
+/// The number of frames a partially received message is kept before it is dropped.
+const MAX_PARTIAL_AGE: u32 = 60;
+
+/// Configuration for the fragmentation of large messages.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct FragmentConfig {
+    /// The maximum size of a UDP payload, in bytes.
+    pub mtu: usize,
+}
+
+impl Default for FragmentConfig {
+    fn default() -> Self {
+        FragmentConfig { mtu: 1200 }
+    }
+}
+
+impl FragmentConfig {
+    /// The number of bytes of a message that fit in a single packet.
+    ///
+    /// Messages larger than this are fragmented.
+    pub fn payload_budget(&self) -> usize {
+        self.mtu.saturating_sub(FRAGMENT_OVERHEAD).max(1)
+    }
+}
+
+/// A piece of a [`NetCompMsg<M>`] that was too large to send in one packet.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct NetCompFragment<M> {
+    /// The sequence number of the message this fragment is part of.
+    #[serde(with = "crate::varint")]
+    seq: u32,
+    /// The index of this fragment.
+    #[serde(with = "crate::varint")]
+    index: u16,
+    /// The number of fragments in the message.
+    #[serde(with = "crate::varint")]
+    count: u16,
+    bytes: Vec<u8>,
+    #[serde(skip)]
+    _pd: PhantomData<M>,
+}
+
+/// A reassembled message, with the time it was sent.
+type Reassembled<M> = (Option<u32>, NetCompMsg<M>);
+
+/// A partially received message.
+#[derive(Debug)]
+struct Partial {
+    time: Option<u32>,
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// The number of bytes received so far.
+    size: usize,
+    age: u32,
+}
+
+/// The fragmentation state for messages of type `M`.
+///
+/// This holds the partially received messages and the sequence number of the next fragmented
+/// message. It is added by [`sync_comp`](crate::AppExt::sync_comp).
+#[derive(Resource, Debug)]
+pub struct Fragments<M> {
+    next_seq: u32,
+    partial: HashMap<(CId, u32), Partial>,
+    _pd: PhantomData<M>,
+}
+
+impl<M> Default for Fragments<M> {
+    fn default() -> Self {
+        Fragments {
+            next_seq: 0,
+            partial: HashMap::default(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<M> Fragments<M>
+where
+    M: Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    /// Splits `msg` into fragments if it is larger than `budget` bytes.
+    ///
+    /// Returns `None` if the message fits in one packet.
+    pub(crate) fn split(
+        &mut self,
+        msg: &NetCompMsg<M>,
+        budget: usize,
+    ) -> Option<Vec<NetCompFragment<M>>> {
+        let size = bincode::serialized_size(msg).ok()? as usize;
+        if size <= budget {
+            return None;
+        }
+        let bytes = match bincode::serialize(msg) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Failed to serialize message for fragmentation: {}", e);
+                return None;
+            }
+        };
+
+        let count = bytes.len().div_ceil(budget);
+        if count > u16::MAX as usize {
+            error!(
+                "Message of {} bytes is too large to fragment. Sending anyway.",
+                bytes.len()
+            );
+            return None;
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        Some(
+            bytes
+                .chunks(budget)
+                .enumerate()
+                .map(|(index, chunk)| NetCompFragment {
+                    seq,
+                    index: index as u16,
+                    count: count as u16,
+                    bytes: chunk.to_vec(),
+                    _pd: PhantomData,
+                })
+                .collect(),
+        )
+    }
+
+    /// Adds a received fragment from `cid`.
+    ///
+    /// Returns the reassembled message if this was the last missing fragment, or an error if the
+    /// fragment is malformed or the message exceeds the `limits`. When there is an error, the
+    /// partially received message is dropped.
+    pub(crate) fn push(
+        &mut self,
+        cid: CId,
+        time: Option<u32>,
+        fragment: &NetCompFragment<M>,
+        limits: &NetLimits,
+    ) -> Result<Option<Reassembled<M>>, String> {
+        let count = fragment.count as usize;
+        let index = fragment.index as usize;
+        if index >= count {
+            return Err("invalid fragment index".to_owned());
+        }
+        // Every fragment holds at least one byte.
+        if count > limits.max_msg_size {
+            return Err(format!("too many fragments ({})", count));
+        }
+
+        let key = (cid, fragment.seq);
+        if !self.partial.contains_key(&key) {
+            let partials = self.partial.keys().filter(|(c, _)| *c == cid).count();
+            if partials >= limits.max_partials {
+                return Err("too many partially received messages".to_owned());
+            }
+        }
+        let partial = self.partial.entry(key).or_insert_with(|| Partial {
+            time,
+            fragments: vec![None; count],
+            received: 0,
+            size: 0,
+            age: 0,
+        });
+        if partial.fragments.len() != count {
+            self.partial.remove(&key);
+            return Err("mismatched fragment counts".to_owned());
+        }
+        if partial.fragments[index].is_none() {
+            partial.size += fragment.bytes.len();
+            if partial.size > limits.max_msg_size {
+                self.partial.remove(&key);
+                return Err(format!(
+                    "reassembled message is larger than the limit of {} bytes",
+                    limits.max_msg_size
+                ));
+            }
+            partial.fragments[index] = Some(fragment.bytes.clone());
+            partial.received += 1;
+        }
+        if partial.received < count {
+            return Ok(None);
+        }
+
+        let partial = match self.partial.remove(&key) {
+            Some(partial) => partial,
+            None => return Ok(None),
+        };
+        let bytes: Vec<u8> = partial.fragments.into_iter().flatten().flatten().collect();
+        let msg = limits.decode(&bytes)?;
+        Ok(Some((partial.time, msg)))
+    }
+
+    /// Drops partially received messages that have been waiting for too long.
+    pub(crate) fn age(&mut self) {
+        self.partial.retain(|_, partial| {
+            partial.age += 1;
+            partial.age <= MAX_PARTIAL_AGE
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::NetEntity;
+
+    /// A message that is split into 4 fragments of 16 bytes or less.
+    fn split() -> (NetCompMsg<Vec<u8>>, Vec<NetCompFragment<Vec<u8>>>) {
+        let msg = NetCompMsg::new(NetEntity::new(1), (0..50).collect());
+        let fragments = Fragments::default().split(&msg, 16).unwrap();
+        assert_eq!(fragments.len(), 4);
+        (msg, fragments)
+    }
+
+    #[test]
+    fn out_of_order_fragments_are_reassembled() {
+        let (msg, fragments) = split();
+        let mut frags = Fragments::<Vec<u8>>::default();
+        let limits = NetLimits::default();
+        for fragment in fragments[1..].iter().rev() {
+            assert_eq!(frags.push(1, Some(7), fragment, &limits), Ok(None));
+        }
+        let reassembled = frags.push(1, Some(7), &fragments[0], &limits).unwrap();
+        assert_eq!(reassembled, Some((Some(7), msg)));
+        assert!(frags.partial.is_empty());
+    }
+
+    #[test]
+    fn duplicate_fragments_are_ignored() {
+        let (msg, fragments) = split();
+        let mut frags = Fragments::<Vec<u8>>::default();
+        let limits = NetLimits::default();
+        for fragment in fragments[..3].iter() {
+            assert_eq!(frags.push(1, None, fragment, &limits), Ok(None));
+            assert_eq!(frags.push(1, None, fragment, &limits), Ok(None));
+        }
+        let partial = &frags.partial[&(1, 0)];
+        assert_eq!(partial.received, 3);
+        assert_eq!(partial.size, 48);
+        let reassembled = frags.push(1, None, &fragments[3], &limits).unwrap();
+        assert_eq!(reassembled, Some((None, msg)));
+    }
+
+    #[test]
+    fn messages_missing_a_fragment_expire() {
+        let (_, fragments) = split();
+        let mut frags = Fragments::<Vec<u8>>::default();
+        let limits = NetLimits::default();
+        for fragment in fragments[..3].iter() {
+            assert_eq!(frags.push(1, None, fragment, &limits), Ok(None));
+        }
+        for _ in 0..MAX_PARTIAL_AGE {
+            frags.age();
+        }
+        assert_eq!(frags.partial.len(), 1);
+        frags.age();
+        assert!(frags.partial.is_empty());
+    }
+
+    #[test]
+    fn partial_messages_are_bounded_per_client() {
+        let mut frags = Fragments::<Vec<u8>>::default();
+        let limits = NetLimits::default();
+        let mut push = |cid, seq| {
+            let fragment = NetCompFragment {
+                seq,
+                index: 0,
+                count: 2,
+                bytes: vec![0],
+                _pd: PhantomData,
+            };
+            frags.push(cid, None, &fragment, &limits)
+        };
+        for seq in 0..limits.max_partials as u32 {
+            assert_eq!(push(1, seq), Ok(None));
+        }
+        assert!(push(1, 100).is_err());
+        // Other clients have their own limit.
+        assert_eq!(push(2, 100), Ok(None));
+    }
+
+    #[test]
+    fn lying_counts_and_indices_are_rejected() {
+        let (_, fragments) = split();
+        let mut frags = Fragments::<Vec<u8>>::default();
+        let limits = NetLimits::default();
+
+        let mut out_of_range = fragments[0].clone();
+        out_of_range.index = out_of_range.count;
+        assert!(frags.push(1, None, &out_of_range, &limits).is_err());
+
+        let mut huge = fragments[0].clone();
+        huge.count = u16::MAX;
+        let limits_small = NetLimits {
+            max_msg_size: 1000,
+            ..limits
+        };
+        assert!(frags.push(1, None, &huge, &limits_small).is_err());
+        assert!(frags.partial.is_empty());
+
+        // A fragment that disagrees on the count drops the whole message.
+        assert_eq!(frags.push(1, None, &fragments[0], &limits), Ok(None));
+        let mut mismatched = fragments[1].clone();
+        mismatched.count = 5;
+        assert!(frags.push(1, None, &mismatched, &limits).is_err());
+        assert!(frags.partial.is_empty());
+    }
+
+    #[test]
+    fn reassembled_messages_over_the_limit_are_dropped() {
+        let (_, fragments) = split();
+        let mut frags = Fragments::<Vec<u8>>::default();
+        let limits = NetLimits {
+            max_msg_size: 40,
+            ..NetLimits::default()
+        };
+        for fragment in fragments[..2].iter() {
+            assert_eq!(frags.push(1, None, fragment, &limits), Ok(None));
+        }
+        assert!(frags.push(1, None, &fragments[2], &limits).is_err());
+        assert!(frags.partial.is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! Named groups of clients, such as teams.
+//!
+//! A [`NetComp`](crate::sync::NetComp) can be sent to a group with
+//! [`with_group`](crate::sync::NetComp::with_group), so it is only sent to the clients in the
+//! group that also match its [`SNetDir`](crate::sync::SNetDir). Since the members are looked up
+//! every time the component is sent, clients can join and leave groups at any time.
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::CId;
+
+/// The groups of clients on the server.
+///
+/// This is added by the [`ServerPlugin`](crate::ServerPlugin).
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct NetGroups {
+    groups: HashMap<&'static str, HashSet<CId>>,
+}
+
+impl NetGroups {
+    /// Adds client `cid` to `group`, creating the group if it doesn't exist.
+    ///
+    /// Returns whether the client was newly added.
+    pub fn add(&mut self, group: &'static str, cid: CId) -> bool {
+        self.groups.entry(group).or_default().insert(cid)
+    }
+
+    /// Removes client `cid` from `group`.
+    ///
+    /// Returns whether the client was in the group.
+    pub fn remove(&mut self, group: &str, cid: CId) -> bool {
+        match self.groups.get_mut(group) {
+            Some(members) => members.remove(&cid),
+            None => false,
+        }
+    }
+
+    /// Removes client `cid` from all groups.
+    pub fn remove_client(&mut self, cid: CId) {
+        for members in self.groups.values_mut() {
+            members.remove(&cid);
+        }
+    }
+
+    /// Removes `group` and all of its members.
+    pub fn clear(&mut self, group: &str) {
+        self.groups.remove(group);
+    }
+
+    /// Whether client `cid` is in `group`.
+    pub fn contains(&self, group: &str, cid: CId) -> bool {
+        self.groups
+            .get(group)
+            .map(|members| members.contains(&cid))
+            .unwrap_or(false)
+    }
+
+    /// Gets the members of `group`.
+    pub fn members(&self, group: &str) -> impl Iterator<Item = CId> + '_ {
+        self.groups.get(group).into_iter().flatten().copied()
+    }
+
+    /// Gets the groups that client `cid` is in.
+    pub fn groups_of(&self, cid: CId) -> impl Iterator<Item = &'static str> + '_ {
+        self.groups
+            .iter()
+            .filter(move |(_, members)| members.contains(&cid))
+            .map(|(group, _)| *group)
+    }
+}
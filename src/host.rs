@@ -0,0 +1,94 @@
+//! Hosting the server in the same process as a client.
+//!
+//! For host-and-play, the authoritative simulation can run in its own [`App`] as a sub app of the
+//! client, instead of mixing the server and client systems in one world. The two worlds stay
+//! separate, just like with a dedicated server, and talk to each other over the loopback
+//! interface:
+//!
+//! ```ignore
+//! let mut server = App::new();
+//! server
+//!     .add_plugins(MinimalPlugins)
+//!     .add_plugin(ServerPlugin)
+//!     .set_connection_msgs::<Connect, Response>();
+//! // Register the same synced components and messages on both apps.
+//! server.sync_comp::<Transform, Transform>(&mut table, Transport::UDP);
+//! app.sync_comp::<Transform, Transform>(&mut MsgTable::new(), Transport::UDP);
+//! let parts = table.build::<Connect, Response, Disconnect>().unwrap();
+//!
+//! app.add_plugins(DefaultPlugins)
+//!     .add_plugin(ClientPlugin)
+//!     .host_server::<Connect, Response>(server, parts, Config::default(), Connect::new("host"));
+//! ```
+//!
+//! The server app is updated after every update of the client app. Its world can be reached with
+//! `app.sub_app_mut(HostedServer)`. Other clients can't join the hosted server, since it only
+//! listens on the loopback interface; start a [`Server`] on a public address yourself for that.
+
+use crate::connect::Connecting;
+use bevy::app::AppLabel;
+use bevy::prelude::*;
+use carrier_pigeon::net::Config;
+use carrier_pigeon::{MsgTableParts, Server};
+use std::any::Any;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+
+/// The label of the sub app that the server runs in.
+///
+/// This is added by [`host_server`](crate::AppExt::host_server).
+#[derive(AppLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct HostedServer;
+
+/// Starts a [`Server`] in `server` on the loopback interface, adds `server` as a sub app of
+/// `app`, and connects `app` to it.
+pub(crate) fn host<C, R>(
+    app: &mut App,
+    mut server: App,
+    parts: MsgTableParts,
+    config: Config,
+    con_msg: C,
+) -> io::Result<()>
+where
+    C: Any + Send + Sync,
+    R: Any + Send + Sync,
+{
+    let (listening, addr) = loopback_server(&parts, &config)?;
+    server.insert_resource(listening);
+    info!("Hosting a server on {}", addr);
+    app.insert_resource(Connecting::new::<C, R>(addr, parts, config, con_msg));
+    app.add_sub_app(HostedServer, server, |_, server| server.update());
+    Ok(())
+}
+
+/// The number of ports that [`loopback_server`] tries.
+const BIND_ATTEMPTS: usize = 8;
+
+/// Starts a [`Server`] on a free port of the loopback interface, and returns it with its address.
+///
+/// The port can be taken by someone else between finding it and starting the server on it, so
+/// this tries another port when that happens.
+pub(crate) fn loopback_server(
+    parts: &MsgTableParts,
+    config: &Config,
+) -> io::Result<(Server, SocketAddr)> {
+    let mut attempt = 1;
+    loop {
+        let started = free_loopback_addr()
+            .and_then(|addr| Ok((Server::new(addr, parts.clone(), config.clone())?, addr)));
+        match started {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && attempt < BIND_ATTEMPTS => {
+                attempt += 1;
+            }
+            started => return started,
+        }
+    }
+}
+
+/// Finds a port on the loopback interface that is free for both TCP and UDP.
+fn free_loopback_addr() -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let addr = listener.local_addr()?;
+    UdpSocket::bind(addr)?;
+    Ok(addr)
+}
@@ -0,0 +1,350 @@
+//! Allocating [`NetEntity`] ids.
+//!
+//! [`NetEntity`] ids can be anything, as long as they are the same on all connected instances.
+//! Since ids are sent as [varints](crate::varint), small ids take fewer bytes on the wire than
+//! random ones. The [`NetIds`] resource hands out sequential ids on the server:
+//!
+//! ```ignore
+//! app.insert_resource(NetIds::new(IdWidth::U32));
+//!
+//! fn spawn_enemy(mut commands: Commands, mut ids: ResMut<NetIds>) {
+//!     commands.spawn((NetEntity::new(ids.alloc()), EnemyBundle::default()));
+//! }
+//! ```
+//!
+//! With [`IdWidth::U32`], ids never exceed `u32::MAX`, so they take at most 5 bytes instead of
+//! up to 10. This is enough for games that never spawn more than 4 billion entities in total.
+//! The default, [`IdWidth::U64`], hands out ids up to the [`PROVISIONAL_BIT`].
+//!
+//! If the [`NetIds`] resource exists, it is also used for the ids of the player entities.
+//!
+//! ### Recycling
+//! Ids of despawned entities can be reused, by giving them back with [`free`](NetIds::free) and
+//! getting entities with [`alloc_entity`](NetIds::alloc_entity). A reused id comes with the next
+//! [`generation`](NetEntity::generation), so updates that were still in flight for the old
+//! entity are dropped instead of being applied to the new one.
+//!
+//! ```ignore
+//! fn despawn_dead(
+//!     mut commands: Commands,
+//!     mut ids: ResMut<NetIds>,
+//!     q: Query<(Entity, &NetEntity, &Health)>,
+//! ) {
+//!     for (entity, net_e, health) in q.iter() {
+//!         if health.0 <= 0 {
+//!             commands.entity(entity).despawn();
+//!             ids.free(*net_e);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! ### Missing ids
+//! An entity with a [`NetComp`] but no [`NetEntity`] isn't synced. On the server, such entities
+//! get a [`NetEntity`] at the end of [`CoreStage::Last`] of the frame they got the [`NetComp`] in,
+//! before the updates are sent, from the [`NetIds`] if the resource exists, or a random one
+//! otherwise. This is done by one system for all the [`NetComp`] types, so an entity with several
+//! of them gets a single id. Entities with a
+//! [`NetKey`](crate::key::NetKey) are left to get theirs from the key. Clients can't make up ids
+//! that the server agrees with, so there a missing [`NetEntity`] is only reported with an error.
+
+use crate::key::NetKey;
+use crate::spawn::PROVISIONAL_BIT;
+use crate::sync::{NetComp, NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::Client;
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::BuildHasher;
+
+/// The range of the ids that are handed out by [`NetIds`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub enum IdWidth {
+    /// Ids fit in a `u32`.
+    U32,
+    /// Ids fit in a `u64`, without the [`PROVISIONAL_BIT`].
+    #[default]
+    U64,
+}
+
+impl IdWidth {
+    /// The largest id of this width.
+    pub fn max_id(self) -> NetId {
+        match self {
+            IdWidth::U32 => u32::MAX as NetId,
+            IdWidth::U64 => u64::MAX as NetId & (PROVISIONAL_BIT - 1),
+        }
+    }
+}
+
+/// Hands out sequential [`NetEntity`] ids on the server.
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct NetIds {
+    width: IdWidth,
+    next: NetId,
+    /// The freed ids, oldest first, so that ids are reused as late as possible.
+    free: VecDeque<NetId>,
+    /// The next generation of every id that was freed.
+    generations: HashMap<NetId, u32>,
+}
+
+impl NetIds {
+    /// Creates a new [`NetIds`] that hands out ids of `width`, starting at 0.
+    pub fn new(width: IdWidth) -> Self {
+        NetIds::starting_at(width, 0)
+    }
+
+    /// Creates a new [`NetIds`] that hands out ids of `width`, starting at `first`.
+    ///
+    /// This is useful for keeping a range of ids for entities that are spawned with fixed ids.
+    pub fn starting_at(width: IdWidth, first: NetId) -> Self {
+        NetIds {
+            width,
+            next: first,
+            free: VecDeque::new(),
+            generations: HashMap::default(),
+        }
+    }
+
+    /// The width of the ids that are handed out.
+    pub fn width(&self) -> IdWidth {
+        self.width
+    }
+
+    /// Gets a new id that was never handed out before, or `None` if all ids of the width are used
+    /// up.
+    pub fn try_alloc(&mut self) -> Option<NetId> {
+        if self.next > self.width.max_id() {
+            return None;
+        }
+        let id = self.next;
+        self.next += 1;
+        Some(id)
+    }
+
+    /// Gets a new id that was never handed out before.
+    ///
+    /// ### Panics
+    /// panics if all ids of the width are used up.
+    pub fn alloc(&mut self) -> NetId {
+        self.try_alloc()
+            .unwrap_or_else(|| panic!("ran out of {:?} NetEntity ids", self.width))
+    }
+
+    /// Gets a new [`NetEntity`], reusing a freed id if there is one, or `None` if all ids of the
+    /// width are used up.
+    pub fn try_alloc_entity(&mut self) -> Option<NetEntity> {
+        match self.free.pop_front() {
+            Some(id) => {
+                let generation = self.generations.get(&id).copied().unwrap_or_default();
+                Some(NetEntity::with_generation(id, generation))
+            }
+            None => self.try_alloc().map(NetEntity::new),
+        }
+    }
+
+    /// Gets a new [`NetEntity`], reusing a freed id if there is one.
+    ///
+    /// ### Panics
+    /// panics if all ids of the width are used up.
+    pub fn alloc_entity(&mut self) -> NetEntity {
+        self.try_alloc_entity()
+            .unwrap_or_else(|| panic!("ran out of {:?} NetEntity ids", self.width))
+    }
+
+    /// Frees the id of `net_e`, once its entity is despawned, so that it can be reused with the
+    /// next generation.
+    pub fn free(&mut self, net_e: NetEntity) {
+        let next = net_e.generation.wrapping_add(1);
+        let generation = self.generations.entry(net_e.id).or_default();
+        // Don't free the same entity twice.
+        if *generation == next {
+            return;
+        }
+        *generation = next;
+        self.free.push_back(net_e.id);
+    }
+
+    /// The number of freed ids that are waiting to be reused.
+    pub fn freed(&self) -> usize {
+        self.free.len()
+    }
+}
+
+/// Finds the entities with a [`NetComp`] of one type but no [`NetEntity`] or [`NetKey`], along with
+/// whether the [`NetComp`] was added since the change tick.
+type MissingFn = fn(&mut World, u32) -> Vec<(Entity, bool)>;
+
+/// The [`NetComp`] types whose entities [`require_net_entity`] looks at.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct NetCompTypes {
+    missing: HashMap<&'static str, MissingFn>,
+    /// The change tick of the last run of [`require_net_entity`].
+    last_run: u32,
+}
+
+impl NetCompTypes {
+    /// Registers component `T` with message type `M`.
+    pub(crate) fn register<T, M>(&mut self)
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        self.missing.insert(
+            std::any::type_name::<NetComp<T, M>>(),
+            missing_net_entity::<T, M>,
+        );
+    }
+}
+
+/// Finds the entities with a [`NetComp<T, M>`] but no [`NetEntity`] or [`NetKey`], along with
+/// whether the [`NetComp<T, M>`] was added since change tick `since`.
+fn missing_net_entity<T, M>(world: &mut World, since: u32) -> Vec<(Entity, bool)>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let change_tick = world.read_change_tick();
+    let mut q = world
+        .query_filtered::<Entity, (With<NetComp<T, M>>, Without<NetEntity>, Without<NetKey>)>();
+    q.iter(world)
+        .map(|entity| {
+            let added = world
+                .entity(entity)
+                .get_change_ticks::<NetComp<T, M>>()
+                .is_some_and(|ticks| ticks.is_added(since, change_tick));
+            (entity, added)
+        })
+        .collect()
+}
+
+/// Gives the entities that have a [`NetComp`] of any type without a [`NetEntity`] one, or reports
+/// the ones that just got it on a client.
+///
+/// Every entity gets a single [`NetEntity`], however many [`NetComp`]s it has.
+///
+/// See [Missing ids](self#missing-ids).
+pub fn require_net_entity(world: &mut World) {
+    let change_tick = world.read_change_tick();
+    let (checks, since): (Vec<_>, _) = match world.get_resource_mut::<NetCompTypes>() {
+        Some(mut types) => {
+            let since = std::mem::replace(&mut types.last_run, change_tick);
+            (types.missing.values().copied().collect(), since)
+        }
+        None => return,
+    };
+    // An entity can have several `NetComp`s, so it can be found by several checks.
+    let mut missing: Vec<Entity> = vec![];
+    let mut seen: HashSet<Entity> = HashSet::default();
+    let mut just_added: HashSet<Entity> = HashSet::default();
+    for check in checks {
+        for (entity, added) in check(world, since) {
+            if seen.insert(entity) {
+                missing.push(entity);
+            }
+            if added {
+                just_added.insert(entity);
+            }
+        }
+    }
+
+    if world.contains_resource::<Client>() {
+        for entity in just_added {
+            error!(
+                "Entity {:?} has a NetComp but no NetEntity, so it isn't synced.",
+                entity
+            );
+        }
+        return;
+    }
+    for entity in missing {
+        let net_e = match world.get_resource_mut::<NetIds>() {
+            Some(mut ids) => match ids.try_alloc_entity() {
+                Some(net_e) => net_e,
+                None => {
+                    error!(
+                        "Ran out of NetEntity ids for entity {:?}, so it isn't synced.",
+                        entity
+                    );
+                    continue;
+                }
+            },
+            None => NetEntity::new(RandomState::new().hash_one(entity) as NetId & !PROVISIONAL_BIT),
+        };
+        debug!("Gave entity {:?} the missing {:?}", entity, net_e);
+        world.entity_mut(entity).insert(net_e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::RecvNetComp;
+
+    #[test]
+    fn reused_ids_get_the_next_generation() {
+        let mut ids = NetIds::new(IdWidth::U32);
+        let first = ids.alloc_entity();
+        let second = ids.alloc_entity();
+        assert_eq!(first, NetEntity::new(0));
+        assert_eq!(second, NetEntity::new(1));
+
+        ids.free(first);
+        // Freeing the same entity twice only queues its id once.
+        ids.free(first);
+        assert_eq!(ids.freed(), 1);
+        let reused = ids.alloc_entity();
+        assert_eq!(reused, NetEntity::with_generation(0, 1));
+        assert_eq!(ids.alloc_entity(), NetEntity::new(2));
+
+        ids.free(reused);
+        assert_eq!(ids.alloc_entity(), NetEntity::with_generation(0, 2));
+    }
+
+    #[test]
+    fn updates_from_a_stale_generation_are_rejected() {
+        let mut ids = NetIds::new(IdWidth::U32);
+        let old = ids.alloc_entity();
+        ids.free(old);
+        let new = ids.alloc_entity();
+        assert_eq!(old.id, new.id);
+
+        let in_flight = RecvNetComp {
+            cid: 0,
+            time: None,
+            id: old.id,
+            generation: old.generation,
+            tick: None,
+            msg: &(),
+        };
+        assert!(in_flight.is_for(&old));
+        assert!(!in_flight.is_for(&new));
+    }
+
+    #[test]
+    fn exhausted_ids_are_none_until_one_is_freed() {
+        let max = IdWidth::U32.max_id();
+        let mut ids = NetIds::starting_at(IdWidth::U32, max);
+        let last = ids.alloc_entity();
+        assert_eq!(last, NetEntity::new(max));
+        assert_eq!(ids.try_alloc(), None);
+        assert_eq!(ids.try_alloc_entity(), None);
+
+        ids.free(last);
+        assert_eq!(
+            ids.try_alloc_entity(),
+            Some(NetEntity::with_generation(max, 1))
+        );
+        assert_eq!(ids.try_alloc_entity(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ran out of U64 NetEntity ids")]
+    fn alloc_panics_when_ids_are_exhausted() {
+        let mut ids = NetIds::starting_at(IdWidth::U64, IdWidth::U64.max_id());
+        assert_eq!(ids.alloc() & PROVISIONAL_BIT, 0);
+        ids.alloc();
+    }
+}
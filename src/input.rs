@@ -0,0 +1,394 @@
+//! Sending player inputs to the server.
+//!
+//! In a server-authoritative game, clients only send their inputs, and the server simulates
+//! everything. Register an input type with [`add_input`](crate::AppExt::add_input), then write
+//! the current input to the [`LocalInput<I>`] resource on the client every frame. It is stamped
+//! with the current [`NetTick`] and sent to the server, where it is queued per client in the
+//! [`PlayerInputs<I>`] resource.
+//!
+//! To make up for lost packets, every input message also carries the previous
+//! [`InputConfig::redundancy`] inputs. The server drops the ones it already has, so a single
+//! lost packet doesn't cause a missed input.
+//!
+//! On the client:
+//!
+//! ```ignore
+//! fn read_input(keys: Res<Input<KeyCode>>, mut input: ResMut<LocalInput<Movement>>) {
+//!     input.0 = Movement {
+//!         left: keys.pressed(KeyCode::A),
+//!         right: keys.pressed(KeyCode::D),
+//!     };
+//! }
+//! ```
+//!
+//! On the server:
+//!
+//! ```ignore
+//! fn apply_input(mut inputs: ResMut<PlayerInputs<Movement>>, /* ... */) {
+//!     for (cid, input) in inputs.drain_all() {
+//!         // move the player of `cid` using `input`.
+//!     }
+//! }
+//! ```
+//!
+//! ### Owned entities
+//! Looking up the entity of a client by hand leaves room for mistakes that let a client drive
+//! someone else's character. Instead, give the entities that a client controls an
+//! [`OwnedInputs<I>`] component along with their [`OwnedBy`] on the server. The inputs of a client
+//! are then also queued on the entities that it owns, and never on any other entity:
+//!
+//! ```ignore
+//! fn apply_input(mut q: Query<(&mut OwnedInputs<Movement>, &mut Transform)>) {
+//!     for (mut inputs, mut transform) in q.iter_mut() {
+//!         for input in inputs.drain() {
+//!             // move `transform` using `input`.
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::disconnect::ClientState;
+use crate::player::OwnedBy;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::{CId, Client, Server};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A counter that is advanced once every frame, at the start of the frame.
+///
+/// On the server, it is advanced by [`server_tick`](crate::app::server_tick), and every synced
+/// update that the server sends is stamped with it, so the clients know which server tick an
+/// update is from; see [`ServerTick`]. On a client, it is advanced by [`advance_tick`], and
+/// inputs are stamped with the tick of the frame they were sent on.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct NetTick(pub u32);
+
+/// The newest [`NetTick`] of the server that this client received an update from.
+///
+/// The tick of the last update of every component is also kept in its
+/// [`NetComp::tick`](crate::sync::NetComp::tick).
+#[derive(Resource, Debug, Default)]
+pub struct ServerTick {
+    /// This is atomic so the receive systems of different components can share it while running
+    /// in parallel.
+    newest: AtomicU32,
+}
+
+impl ServerTick {
+    /// The newest server tick, or 0 if no update was received yet.
+    pub fn get(&self) -> u32 {
+        self.newest.load(Ordering::Relaxed)
+    }
+
+    /// Records that an update from server tick `tick` was received.
+    pub(crate) fn observe(&self, tick: u32) {
+        self.newest.fetch_max(tick, Ordering::Relaxed);
+    }
+}
+
+/// An input, stamped with the tick it was made on.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct TickedInput<I> {
+    /// The [`NetTick`] of the client when the input was made.
+    #[serde(with = "crate::varint")]
+    pub tick: u32,
+    /// The input.
+    pub input: I,
+}
+
+/// The message that inputs are sent as.
+///
+/// Holds the newest input along with the redundant ones, oldest first.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct InputMsg<I> {
+    inputs: Vec<TickedInput<I>>,
+}
+
+/// The configuration for sending inputs.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct InputConfig {
+    /// The number of previous inputs that are sent again along with every input.
+    ///
+    /// Every extra input makes the message bigger, but lets the server recover from one more
+    /// lost packet in a row. Defaults to 2.
+    pub redundancy: usize,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig { redundancy: 2 }
+    }
+}
+
+/// The inputs that this client has sent, oldest first.
+#[derive(Resource, Clone, Debug)]
+pub struct InputHistory<I> {
+    inputs: VecDeque<TickedInput<I>>,
+    /// The maximum number of inputs that are kept.
+    pub max_len: usize,
+}
+
+impl<I> Default for InputHistory<I> {
+    fn default() -> Self {
+        InputHistory {
+            inputs: VecDeque::new(),
+            max_len: 64,
+        }
+    }
+}
+
+impl<I> InputHistory<I> {
+    /// Adds a sent input.
+    fn push(&mut self, input: TickedInput<I>) {
+        while self.inputs.len() >= self.max_len.max(1) {
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back(input);
+    }
+
+    /// Gets all kept inputs, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &TickedInput<I>> + '_ {
+        self.inputs.iter()
+    }
+
+    /// Gets the kept inputs that were made after `tick`, oldest first.
+    pub fn after(&self, tick: u32) -> impl Iterator<Item = &TickedInput<I>> + '_ {
+        self.inputs.iter().filter(move |input| input.tick > tick)
+    }
+
+    /// Gets the newest `n` inputs, oldest first.
+    fn newest(&self, n: usize) -> impl Iterator<Item = &TickedInput<I>> + '_ {
+        self.inputs.iter().skip(self.inputs.len().saturating_sub(n))
+    }
+}
+
+/// The current input of this client.
+///
+/// This is sent to the server every frame while connected.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct LocalInput<I>(pub I);
+
+/// The queue of inputs of a single client.
+#[derive(Clone, Debug)]
+struct InputQueue<I> {
+    /// The newest tick that was received, used to drop late and duplicate inputs.
+    last_tick: Option<u32>,
+    queue: VecDeque<TickedInput<I>>,
+}
+
+impl<I> Default for InputQueue<I> {
+    fn default() -> Self {
+        InputQueue {
+            last_tick: None,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<I> InputQueue<I> {
+    /// Queues `input`, keeping at most `max_queued` inputs.
+    ///
+    /// Returns false if it was dropped for being older than, or the same as, the newest input.
+    fn push(&mut self, input: TickedInput<I>, max_queued: usize) -> bool {
+        if matches!(self.last_tick, Some(last) if input.tick <= last) {
+            return false;
+        }
+        self.last_tick = Some(input.tick);
+        if self.queue.len() >= max_queued {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(input);
+        true
+    }
+}
+
+/// The received inputs of every client, oldest first.
+///
+/// Inputs that arrive out of order, after a newer input, are dropped. At most
+/// [`max_queued`](Self::max_queued) inputs are kept per client; once full, the oldest input is
+/// dropped. Consume the inputs every frame on the server so they don't pile up.
+#[derive(Resource, Clone, Debug)]
+pub struct PlayerInputs<I> {
+    clients: HashMap<CId, InputQueue<I>>,
+    /// The maximum number of inputs that are kept for each client.
+    pub max_queued: usize,
+}
+
+impl<I> Default for PlayerInputs<I> {
+    fn default() -> Self {
+        PlayerInputs {
+            clients: HashMap::default(),
+            max_queued: 64,
+        }
+    }
+}
+
+impl<I> PlayerInputs<I> {
+    /// Queues `input` from client `cid`.
+    ///
+    /// Returns false if it was dropped for being older than, or the same as, the newest input.
+    pub fn push(&mut self, cid: CId, input: TickedInput<I>) -> bool {
+        self.clients
+            .entry(cid)
+            .or_default()
+            .push(input, self.max_queued)
+    }
+
+    /// Takes the oldest queued input of client `cid`.
+    pub fn pop(&mut self, cid: CId) -> Option<TickedInput<I>> {
+        self.clients.get_mut(&cid)?.queue.pop_front()
+    }
+
+    /// Takes all queued inputs of client `cid`, oldest first.
+    pub fn drain(&mut self, cid: CId) -> impl Iterator<Item = TickedInput<I>> + '_ {
+        self.clients
+            .get_mut(&cid)
+            .into_iter()
+            .flat_map(|client| client.queue.drain(..))
+    }
+
+    /// Takes all queued inputs of all clients, oldest first for each client.
+    pub fn drain_all(&mut self) -> impl Iterator<Item = (CId, TickedInput<I>)> + '_ {
+        self.clients
+            .iter_mut()
+            .flat_map(|(cid, client)| client.queue.drain(..).map(move |input| (*cid, input)))
+    }
+
+    /// Gets the queued inputs of client `cid`, oldest first.
+    pub fn get(&self, cid: CId) -> impl Iterator<Item = &TickedInput<I>> + '_ {
+        self.clients
+            .get(&cid)
+            .into_iter()
+            .flat_map(|client| client.queue.iter())
+    }
+
+    /// The newest tick that was received from client `cid`.
+    pub fn last_tick(&self, cid: CId) -> Option<u32> {
+        self.clients.get(&cid)?.last_tick
+    }
+
+    /// Removes all inputs of client `cid`.
+    pub fn remove_client(&mut self, cid: CId) {
+        self.clients.remove(&cid);
+    }
+}
+
+impl<I: Send + Sync + 'static> ClientState for PlayerInputs<I> {
+    fn remove_client(&mut self, cid: CId) {
+        PlayerInputs::remove_client(self, cid);
+    }
+}
+
+/// The received inputs of the client that owns this entity, oldest first.
+///
+/// On the server, the inputs of a client are queued on every entity with this component and an
+/// [`OwnedBy`] of that client. Like with [`PlayerInputs<I>`], inputs that arrive out of order are
+/// dropped, and at most [`max_queued`](Self::max_queued) inputs are kept.
+#[derive(Component, Clone, Debug)]
+pub struct OwnedInputs<I> {
+    inputs: InputQueue<I>,
+    /// The maximum number of inputs that are kept.
+    pub max_queued: usize,
+}
+
+impl<I> Default for OwnedInputs<I> {
+    fn default() -> Self {
+        OwnedInputs {
+            inputs: InputQueue::default(),
+            max_queued: 64,
+        }
+    }
+}
+
+impl<I> OwnedInputs<I> {
+    /// Takes the oldest queued input.
+    pub fn pop(&mut self) -> Option<TickedInput<I>> {
+        self.inputs.queue.pop_front()
+    }
+
+    /// Takes all queued inputs, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = TickedInput<I>> + '_ {
+        self.inputs.queue.drain(..)
+    }
+
+    /// Gets the queued inputs, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &TickedInput<I>> + '_ {
+        self.inputs.queue.iter()
+    }
+
+    /// The newest tick that was received.
+    pub fn last_tick(&self) -> Option<u32> {
+        self.inputs.last_tick
+    }
+}
+
+/// Advances the [`NetTick`] on a client.
+///
+/// The tick of the server is advanced by [`server_tick`](crate::app::server_tick) instead.
+pub fn advance_tick(server: Option<Res<Server>>, mut tick: ResMut<NetTick>) {
+    if server.is_none() {
+        tick.0 = tick.0.wrapping_add(1);
+    }
+}
+
+/// Sends the [`LocalInput<I>`] to the server, stamped with the current [`NetTick`], along with
+/// the redundant previous inputs.
+pub fn send_input<I: Clone + Any + Send + Sync>(
+    client: Option<Res<Client>>,
+    tick: Res<NetTick>,
+    config: Res<InputConfig>,
+    input: Res<LocalInput<I>>,
+    mut history: ResMut<InputHistory<I>>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    history.push(TickedInput {
+        tick: tick.0,
+        input: input.0.clone(),
+    });
+    let msg = InputMsg {
+        inputs: history.newest(config.redundancy + 1).cloned().collect(),
+    };
+    if let Err(e) = client.send(&msg) {
+        error!("{}", e);
+    }
+}
+
+/// Receives the inputs of the clients into [`PlayerInputs<I>`], and the [`OwnedInputs<I>`] of
+/// the entities they own.
+pub fn recv_inputs<I: Clone + Any + Send + Sync>(
+    server: Option<Res<Server>>,
+    mut inputs: ResMut<PlayerInputs<I>>,
+    mut q: Query<(&OwnedBy, &mut OwnedInputs<I>)>,
+) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let msgs: Vec<_> = server.recv::<InputMsg<I>>().collect();
+    let mut by_cid: HashMap<CId, Vec<&TickedInput<I>>> = HashMap::default();
+    for msg in msgs.iter() {
+        // The redundant inputs that were already received are dropped by `push`.
+        for input in msg.inputs.iter() {
+            inputs.push(msg.cid, input.clone());
+        }
+        by_cid.entry(msg.cid).or_default().extend(msg.inputs.iter());
+    }
+    if by_cid.is_empty() {
+        return;
+    }
+    // Only the owner's inputs are queued on an entity, so a client can't drive any other.
+    for (owned_by, mut owned) in q.iter_mut() {
+        if let Some(received) = by_cid.get(&owned_by.0) {
+            let max_queued = owned.max_queued;
+            for input in received.iter() {
+                owned.inputs.push((*input).clone(), max_queued);
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Manual control over which entities each client receives.
+//!
+//! By default, every client receives every synced component that its [`CIdSpec`] matches. Once a
+//! client has an interest set in the [`ClientInterest`] resource, the server only sends it the
+//! components of the entities it is interested in. This is useful for fog of war or stealth,
+//! where gameplay code decides what each player is allowed to know about.
+//!
+//! ```ignore
+//! fn fog_of_war(mut interest: ResMut<ClientInterest>, /* ... */) {
+//!     interest.add(cid, enemy.id);
+//!     interest.remove(cid, hidden_enemy.id);
+//! }
+//! ```
+//!
+//! [`CIdSpec`]: carrier_pigeon::net::CIdSpec
+
+use crate::sync::NetId;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::CId;
+use std::fmt::{Debug, Formatter};
+
+/// A rule that decides if a client is interested in the entity with the given id.
+pub type InterestRule = Box<dyn Fn(NetId) -> bool + Send + Sync>;
+
+/// The interest of a single client.
+#[derive(Default)]
+struct Interest {
+    ids: HashSet<NetId>,
+    rules: Vec<InterestRule>,
+}
+
+/// The entities that each client is interested in.
+///
+/// A client is interested in an entity if its [`NetEntity`](crate::sync::NetEntity) id was added
+/// with [`add`](Self::add), or if any of its rules match. Clients that were never added receive
+/// everything. This is added by the [`ServerPlugin`](crate::ServerPlugin).
+#[derive(Resource, Default)]
+pub struct ClientInterest {
+    clients: HashMap<CId, Interest>,
+}
+
+impl Debug for ClientInterest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ids: HashMap<_, _> = self.clients.iter().map(|(cid, i)| (cid, &i.ids)).collect();
+        f.debug_struct("ClientInterest").field("ids", &ids).finish()
+    }
+}
+
+impl ClientInterest {
+    /// Starts tracking the interest of client `cid`, without adding any interest.
+    ///
+    /// The client will not receive anything until interest is added.
+    pub fn track(&mut self, cid: CId) {
+        self.clients.entry(cid).or_default();
+    }
+
+    /// Stops tracking the interest of client `cid`, so it receives everything again.
+    pub fn untrack(&mut self, cid: CId) {
+        self.clients.remove(&cid);
+    }
+
+    /// Whether the interest of client `cid` is being tracked.
+    pub fn is_tracked(&self, cid: CId) -> bool {
+        self.clients.contains_key(&cid)
+    }
+
+    /// Whether the interest of any client is being tracked.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Makes client `cid` interested in the entity with `id`.
+    ///
+    /// Returns whether the client was newly interested.
+    pub fn add(&mut self, cid: CId, id: NetId) -> bool {
+        self.clients.entry(cid).or_default().ids.insert(id)
+    }
+
+    /// Makes client `cid` no longer interested in the entity with `id`.
+    ///
+    /// This doesn't affect the client's rules. Returns whether the client was interested.
+    pub fn remove(&mut self, cid: CId, id: NetId) -> bool {
+        match self.clients.get_mut(&cid) {
+            Some(interest) => interest.ids.remove(&id),
+            None => false,
+        }
+    }
+
+    /// Adds a rule that makes client `cid` interested in the entities it matches.
+    pub fn add_rule(&mut self, cid: CId, rule: impl Fn(NetId) -> bool + Send + Sync + 'static) {
+        self.clients
+            .entry(cid)
+            .or_default()
+            .rules
+            .push(Box::new(rule));
+    }
+
+    /// Removes all interest of client `cid`, while still tracking it.
+    pub fn clear(&mut self, cid: CId) {
+        if let Some(interest) = self.clients.get_mut(&cid) {
+            interest.ids.clear();
+            interest.rules.clear();
+        }
+    }
+
+    /// Whether client `cid` should receive the entity with `id`.
+    pub fn is_interested(&self, cid: CId, id: NetId) -> bool {
+        match self.clients.get(&cid) {
+            Some(interest) => interest.ids.contains(&id) || interest.rules.iter().any(|r| r(id)),
+            None => true,
+        }
+    }
+}
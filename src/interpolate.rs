@@ -0,0 +1,397 @@
+//! Interpolating synced components between updates.
+//!
+//! Adding an [`Interpolate<T>`] next to a `NetComp<T, M>` buffers the received updates, and shows
+//! the component a little in the past, blending between the two updates around that time. This
+//! trades a bit of latency for smooth movement, even when updates arrive unevenly.
+//!
+//! The delay is set per entity, and can be changed at any time, since a vehicle and a UI element
+//! tolerate very different latencies. It can also be tuned automatically from the measured
+//! jitter of the updates with [`auto_delay`](Interpolate::auto_delay), within
+//! [`min_delay`](Interpolate::min_delay) and [`max_delay`](Interpolate::max_delay), so it fits
+//! the connection of every player. An [`InterpolationDelayChanged`] event is sent whenever the
+//! tuned delay changes significantly.
+//!
+//! The component type must implement [`Extrapolatable`]. Enable it with
+//! [`interpolate_comp`](crate::AppExt::interpolate_comp).
+//!
+//! ### Ticks
+//! The send times are measured by the network layer, so they still carry some of the delivery
+//! jitter and the frame rate of the server. With a [`TickTimeline`], added with
+//! [`add_tick_timeline`](crate::AppExt::add_tick_timeline), the updates are placed by the server
+//! [`NetTick`](crate::NetTick) they were sent on instead. The timeline follows the newest
+//! [`ServerTick`] at the server's tick rate, and an [`Interpolate::ticks`] shows the component
+//! [`delay_ticks`](Interpolate::delay_ticks) ticks behind it. Instead of snapping when it drifts
+//! from the server, the timeline is sped up or slowed down by a few percent until it catches up;
+//! see [`TickTimeline::dilation`].
+//!
+//! ```ignore
+//! app.interpolate_comp::<Transform, NetTransform>()
+//!     .add_tick_timeline(60.0);
+//!
+//! commands.spawn((
+//!     NetEntity::new(id),
+//!     NetComp::<Transform, NetTransform>::server_to_all(),
+//!     Interpolate::<Transform>::ticks(6.0),
+//! ));
+//! ```
+
+use crate::extrapolate::Extrapolatable;
+use crate::input::ServerTick;
+use crate::sync::NetComp;
+use bevy::prelude::*;
+use std::any::Any;
+use std::collections::VecDeque;
+
+/// The number of updates that are kept in the buffer at most.
+const MAX_BUFFERED: usize = 32;
+
+/// The fraction that a tuned delay has to change by, since the last
+/// [`InterpolationDelayChanged`], to send another one.
+const SIGNIFICANT_CHANGE: f32 = 0.2;
+
+/// An event that is sent when the tuned delay of an [`Interpolate`] changes significantly.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct InterpolationDelayChanged {
+    /// The entity of the [`Interpolate`].
+    pub entity: Entity,
+    /// The type name of the interpolated component.
+    pub component: &'static str,
+    /// The new delay, in seconds.
+    pub delay: f32,
+}
+
+/// A received value of the component.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Sample<T> {
+    value: T,
+    /// The time the update was sent at, in seconds, or its server tick.
+    sent: f64,
+}
+
+/// An estimate of the server tick that is advanced smoothly every frame, for the
+/// [`Interpolate`]s that use ticks.
+///
+/// It advances at [`tick_rate`](Self::tick_rate) ticks per second, times the current
+/// [`dilation`](Self::dilation). When it falls behind the newest [`ServerTick`], so the
+/// interpolation buffer fills up, it runs slightly faster to catch up; when it gets ahead, so the
+/// buffer runs low, it runs slightly slower. It only snaps to the newest tick when it is more than
+/// a second off, like after a pause.
+#[derive(Resource, Copy, Clone, PartialEq, Debug)]
+pub struct TickTimeline {
+    /// The number of times per second that the server advances its tick.
+    pub tick_rate: f32,
+    /// The most that the timeline is sped up or slowed down, as a fraction of the tick rate.
+    /// Defaults to 0.05.
+    pub max_dilation: f32,
+    /// The current estimate of the server tick, if an update was received yet.
+    tick: Option<f64>,
+    /// The smoothed distance from the timeline to the newest server tick, in ticks.
+    error: f64,
+    /// The current speed of the timeline, as a factor of the tick rate.
+    dilation: f32,
+}
+
+impl TickTimeline {
+    /// Creates a new [`TickTimeline`] for a server that ticks `tick_rate` times per second.
+    pub fn new(tick_rate: f32) -> Self {
+        TickTimeline {
+            tick_rate,
+            max_dilation: 0.05,
+            tick: None,
+            error: 0.0,
+            dilation: 1.0,
+        }
+    }
+
+    /// The current estimate of the server tick, if an update was received yet.
+    pub fn tick(&self) -> Option<f64> {
+        self.tick
+    }
+
+    /// The current speed of the timeline, as a factor of the tick rate.
+    ///
+    /// This is 1 while the timeline keeps up with the server, and at most
+    /// [`max_dilation`](Self::max_dilation) away from 1 while it catches up. Gameplay or audio
+    /// that follows the interpolated entities can scale their own speed by it.
+    pub fn dilation(&self) -> f32 {
+        self.dilation
+    }
+
+    /// Advances the estimate by `dt` seconds, given that the newest server tick is `newest`.
+    fn advance(&mut self, dt: f64, newest: u32) {
+        let newest = newest as f64;
+        let tick = match self.tick {
+            Some(tick) => tick,
+            None => {
+                self.tick = Some(newest);
+                return;
+            }
+        };
+        let error = newest - tick;
+        if error.abs() > self.tick_rate as f64 {
+            self.tick = Some(newest);
+            self.error = 0.0;
+            self.dilation = 1.0;
+            return;
+        }
+        // Smooth the error over about half a second, so the jitter of single updates is ignored.
+        self.error += (error - self.error) * (dt * 2.0).min(1.0);
+        // Ignore the first tick of error, and dilate fully from three ticks off.
+        let amount = ((self.error.abs() - 1.0) / 2.0).clamp(0.0, 1.0) as f32;
+        self.dilation = 1.0 + amount * self.max_dilation * self.error.signum() as f32;
+        self.tick = Some(tick + dt * self.tick_rate as f64 * self.dilation as f64);
+    }
+}
+
+/// A component that interpolates the synced component `T` between updates, shown
+/// [`delay`](Self::delay) seconds in the past.
+///
+/// Requires [`interpolate_comp`](crate::AppExt::interpolate_comp) for `T`.
+#[derive(Component, Clone, PartialEq, Debug)]
+pub struct Interpolate<T: Extrapolatable> {
+    /// How far in the past the component is shown, in seconds.
+    ///
+    /// This should be a bit more than the time between updates. Defaults to 0.1.
+    pub delay: f32,
+    /// Whether to tune the [`delay`](Self::delay) automatically from the time between updates
+    /// and the measured jitter.
+    ///
+    /// With a [`delay_ticks`](Self::delay_ticks), that is tuned too, at the rate of the
+    /// [`TickTimeline`].
+    pub auto_delay: bool,
+    /// The smallest delay that [`auto_delay`](Self::auto_delay) tunes to, in seconds. Defaults
+    /// to 0.
+    pub min_delay: f32,
+    /// The largest delay that [`auto_delay`](Self::auto_delay) tunes to, in seconds. Defaults
+    /// to 0.5.
+    pub max_delay: f32,
+    /// How far behind the [`TickTimeline`] the component is shown, in server ticks.
+    ///
+    /// When set, this is used instead of the [`delay`](Self::delay), and the updates are placed
+    /// by their server tick instead of their send time. This should be set when the
+    /// [`Interpolate`] is created.
+    pub delay_ticks: Option<f32>,
+    buffer: VecDeque<Sample<T>>,
+    /// The smallest difference between the receive and send times seen, in seconds.
+    ///
+    /// This maps the send times to local times, without the clocks needing to be in sync.
+    offset: Option<f64>,
+    /// The smoothed time between updates, in seconds.
+    interval: f32,
+    /// The smoothed jitter of the updates, in seconds.
+    jitter: f32,
+    /// The sent time of the previous update, in seconds.
+    prev_sent: Option<f64>,
+    /// The delay that was last sent in an [`InterpolationDelayChanged`].
+    reported: f32,
+    /// The sent time of the newest update, in ms, or its server tick.
+    last: Option<u32>,
+}
+
+impl<T: Extrapolatable> Default for Interpolate<T> {
+    fn default() -> Self {
+        Interpolate::new(0.1)
+    }
+}
+
+impl<T: Extrapolatable> Interpolate<T> {
+    /// Creates a new [`Interpolate`] that shows the component `delay` seconds in the past.
+    pub fn new(delay: f32) -> Self {
+        Interpolate {
+            delay,
+            auto_delay: false,
+            min_delay: 0.0,
+            max_delay: 0.5,
+            delay_ticks: None,
+            buffer: VecDeque::new(),
+            offset: None,
+            interval: 0.0,
+            jitter: 0.0,
+            prev_sent: None,
+            reported: delay,
+            last: None,
+        }
+    }
+
+    /// Creates a new [`Interpolate`] that tunes its delay automatically, starting at `delay`.
+    pub fn auto(delay: f32) -> Self {
+        Interpolate {
+            auto_delay: true,
+            ..Interpolate::new(delay)
+        }
+    }
+
+    /// Creates a new [`Interpolate`] that shows the component `delay_ticks` server ticks behind
+    /// the [`TickTimeline`].
+    pub fn ticks(delay_ticks: f32) -> Self {
+        Interpolate {
+            delay_ticks: Some(delay_ticks),
+            ..Interpolate::default()
+        }
+    }
+
+    /// Sets the bounds that [`auto_delay`](Self::auto_delay) tunes the delay within, in
+    /// seconds.
+    pub fn with_delay_bounds(mut self, min: f32, max: f32) -> Self {
+        self.min_delay = min;
+        self.max_delay = max;
+        self
+    }
+
+    /// The measured jitter of the updates, in seconds.
+    pub fn jitter(&self) -> f32 {
+        self.jitter
+    }
+
+    /// Adds a received update, sent at `sent` ms and received at the local time `now`.
+    fn push(&mut self, value: T, sent: u32, now: f64) {
+        let sent_secs = self.measure(sent, now);
+        self.buffer_sample(value, sent_secs);
+    }
+
+    /// Measures the jitter of an update sent at `sent` ms and received at the local time `now`,
+    /// and tunes the delay. Returns the sent time in seconds.
+    fn measure(&mut self, sent: u32, now: f64) -> f64 {
+        let sent_secs = sent as f64 / 1000.0;
+        let transit = now - sent_secs;
+        let offset = *self.offset.get_or_insert(transit);
+        // Slowly let the offset drift up, so a single fast update doesn't skew it forever.
+        let offset = offset.min(transit) + 0.001 * (transit - offset).max(0.0);
+        self.offset = Some(offset);
+        self.jitter += ((transit - offset) as f32 - self.jitter) / 16.0;
+
+        if let Some(prev) = self.prev_sent {
+            let interval = (sent_secs - prev) as f32;
+            self.interval += (interval - self.interval) / 16.0;
+        }
+        self.prev_sent = Some(sent_secs);
+        if self.auto_delay {
+            self.delay = (self.interval + 2.0 * self.jitter).clamp(self.min_delay, self.max_delay);
+        }
+        sent_secs
+    }
+
+    /// Whether the delay changed significantly since it was last reported, in which case it is
+    /// reported now.
+    fn report(&mut self) -> bool {
+        let change = (self.delay - self.reported).abs();
+        if change > SIGNIFICANT_CHANGE * self.reported.max(0.01) {
+            self.reported = self.delay;
+            return true;
+        }
+        false
+    }
+
+    /// Adds a received update, sent on server tick `tick`.
+    fn push_tick(&mut self, value: T, tick: u32) {
+        self.buffer_sample(value, tick as f64);
+    }
+
+    /// Adds `value` to the buffer at `sent`.
+    fn buffer_sample(&mut self, value: T, sent: f64) {
+        if self.buffer.len() >= MAX_BUFFERED {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(Sample { value, sent });
+    }
+
+    /// Gets the value to show at the local time `now`.
+    fn sample(&mut self, now: f64) -> Option<T> {
+        let render = now - self.offset? - self.delay as f64;
+        self.sample_at(render)
+    }
+
+    /// Gets the value to show at `render`, in the units of the buffered samples.
+    fn sample_at(&mut self, render: f64) -> Option<T> {
+        // Drop the updates that are no longer needed to interpolate.
+        while self.buffer.len() > 2 && self.buffer[1].sent <= render {
+            self.buffer.pop_front();
+        }
+        let mut iter = self.buffer.iter();
+        let from = iter.next()?;
+        let to = match iter.next() {
+            Some(to) if render > from.sent => to,
+            _ => return Some(from.value.clone()),
+        };
+        let t = ((render - from.sent) / (to.sent - from.sent)).clamp(0.0, 1.0);
+        Some(from.value.blend(&to.value, t as f32))
+    }
+}
+
+/// Interpolates the components `T` that have an [`Interpolate<T>`].
+///
+/// This runs after the received updates are applied, so a changed
+/// [`last`](NetComp::last) timestamp, or [`tick`](NetComp::tick), means the component holds a
+/// fresh update.
+#[allow(clippy::type_complexity)]
+pub fn interpolate<T, M>(
+    time: Res<Time>,
+    timeline: Option<Res<TickTimeline>>,
+    mut ew: EventWriter<InterpolationDelayChanged>,
+    mut q: Query<(Entity, &NetComp<T, M>, &mut Interpolate<T>, &mut T)>,
+) where
+    T: Extrapolatable + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let now = time.elapsed_seconds_f64();
+    let tick_rate = timeline.as_ref().map(|t| t.tick_rate);
+    let timeline = timeline.and_then(|t| t.tick);
+    for (entity, net_c, mut interp, mut comp) in q.iter_mut() {
+        if interp.auto_delay && interp.report() {
+            ew.send(InterpolationDelayChanged {
+                entity,
+                component: std::any::type_name::<T>(),
+                delay: interp.delay,
+            });
+        }
+        if let Some(delay_ticks) = interp.delay_ticks {
+            let tick = match net_c.tick {
+                Some(tick) => tick,
+                None => continue,
+            };
+            if interp.last != Some(tick) {
+                interp.last = Some(tick);
+                if let Some(sent) = net_c.last {
+                    interp.measure(sent, now);
+                }
+                if let (true, Some(tick_rate)) = (interp.auto_delay, tick_rate) {
+                    interp.delay_ticks = Some(interp.delay * tick_rate);
+                }
+                interp.push_tick(comp.clone(), tick);
+            }
+            // Without a timeline, the newest update is shown as is.
+            let render = match timeline {
+                Some(timeline) => timeline - delay_ticks as f64,
+                None => continue,
+            };
+            if let Some(value) = interp.sample_at(render) {
+                *comp = value;
+            }
+            continue;
+        }
+        let sent = match net_c.last {
+            Some(sent) => sent,
+            None => continue,
+        };
+        if interp.last != Some(sent) {
+            interp.last = Some(sent);
+            interp.push(comp.clone(), sent, now);
+        }
+        if let Some(value) = interp.sample(now) {
+            *comp = value;
+        }
+    }
+}
+
+/// Advances the [`TickTimeline`] towards the newest [`ServerTick`].
+pub fn advance_timeline(
+    time: Res<Time>,
+    server_tick: Option<Res<ServerTick>>,
+    mut timeline: ResMut<TickTimeline>,
+) {
+    let newest = match server_tick.map(|t| t.get()) {
+        Some(newest) if newest > 0 => newest,
+        _ => return,
+    };
+    timeline.advance(time.delta_seconds_f64(), newest);
+}
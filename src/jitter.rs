@@ -0,0 +1,129 @@
+//! Smoothing out bursty delivery of synced components.
+//!
+//! Updates rarely arrive as evenly as they were sent. Some frames get none, and the next gets
+//! two, so a remote entity visibly slows down and speeds up. With a [`JitterBuffer<M>`], received
+//! updates are held back and released on a timeline derived from their send times, plus a small
+//! delay that covers the measured jitter.
+//!
+//! Enable it for a message type with [`add_jitter_buffer`](crate::AppExt::add_jitter_buffer).
+//! Only updates that have a send time are buffered.
+
+use crate::disconnect::ClientState;
+use crate::sync::{NetCompMsg, RecvNetComp};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::CId;
+use std::any::Any;
+
+/// The timing of the updates from a single sender.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+struct SenderTiming {
+    /// The smallest difference between the receive and send times seen, in seconds.
+    offset: Option<f64>,
+    /// The smoothed jitter, in seconds.
+    jitter: f32,
+}
+
+/// A buffered update.
+struct Buffered<M: Any + Send + Sync> {
+    cid: CId,
+    time: u32,
+    msg: NetCompMsg<M>,
+    /// The local time to release this at, in seconds.
+    release: f64,
+}
+
+/// A buffer that releases the received updates of message type `M` on a smoothed timeline.
+#[derive(Resource)]
+pub struct JitterBuffer<M: Any + Send + Sync> {
+    /// A delay in seconds that is always added, on top of the one covering the jitter.
+    ///
+    /// Defaults to 0.
+    pub min_delay: f32,
+    /// How many times the measured jitter the updates are delayed by. Defaults to 2.
+    pub jitter_scale: f32,
+    senders: HashMap<CId, SenderTiming>,
+    buffered: Vec<Buffered<M>>,
+}
+
+impl<M: Any + Send + Sync> Default for JitterBuffer<M> {
+    fn default() -> Self {
+        JitterBuffer {
+            min_delay: 0.0,
+            jitter_scale: 2.0,
+            senders: HashMap::default(),
+            buffered: vec![],
+        }
+    }
+}
+
+impl<M: Any + Send + Sync> std::fmt::Debug for JitterBuffer<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JitterBuffer")
+            .field("min_delay", &self.min_delay)
+            .field("jitter_scale", &self.jitter_scale)
+            .field("senders", &self.senders)
+            .field("buffered", &self.buffered.len())
+            .finish()
+    }
+}
+
+impl<M: Clone + Any + Send + Sync> JitterBuffer<M> {
+    /// The measured jitter of the updates from client `cid` (or the server, on a client), in
+    /// seconds.
+    pub fn jitter(&self, cid: CId) -> Option<f32> {
+        self.senders.get(&cid).map(|s| s.jitter)
+    }
+
+    /// Removes the timing of client `cid`.
+    pub fn remove_client(&mut self, cid: CId) {
+        self.senders.remove(&cid);
+        self.buffered.retain(|b| b.cid != cid);
+    }
+
+    /// Buffers the received `msgs` at the local time `now`, and returns the ones that are due, in
+    /// the order they were sent.
+    pub(crate) fn buffer(
+        &mut self,
+        msgs: &[RecvNetComp<M>],
+        now: f64,
+    ) -> Vec<(CId, Option<u32>, NetCompMsg<M>)> {
+        let mut released = vec![];
+        for m in msgs {
+            let time = match m.time {
+                Some(time) => time,
+                None => {
+                    released.push((m.cid, None, m.to_msg()));
+                    continue;
+                }
+            };
+            let sent = time as f64 / 1000.0;
+            let transit = now - sent;
+            let sender = self.senders.entry(m.cid).or_default();
+            let offset = sender.offset.map_or(transit, |o| o.min(transit));
+            sender.offset = Some(offset);
+            sender.jitter += ((transit - offset) as f32 - sender.jitter) / 16.0;
+
+            let delay = self.min_delay + self.jitter_scale * sender.jitter;
+            self.buffered.push(Buffered {
+                cid: m.cid,
+                time,
+                msg: m.to_msg(),
+                release: sent + offset + delay as f64,
+            });
+        }
+
+        let (mut due, held): (Vec<_>, Vec<_>) =
+            self.buffered.drain(..).partition(|b| b.release <= now);
+        self.buffered = held;
+        due.sort_by_key(|b| b.time);
+        released.extend(due.into_iter().map(|b| (b.cid, Some(b.time), b.msg)));
+        released
+    }
+}
+
+impl<M: Clone + Any + Send + Sync> ClientState for JitterBuffer<M> {
+    fn remove_client(&mut self, cid: CId) {
+        JitterBuffer::remove_client(self, cid);
+    }
+}
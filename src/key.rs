@@ -0,0 +1,206 @@
+//! Identifying entities by a stable key, instead of a hand-assigned id.
+//!
+//! Entities that are placed in a level, like doors and switches, exist on the server and the
+//! clients before anything is sent, so they have to get the same [`NetEntity`] id everywhere.
+//! Hand-assigned ids get lost or duplicated when the level is edited, and the ids of
+//! [`NetIds`](crate::ids::NetIds) depend on the spawn order. A [`NetKey`] identifies the entity by
+//! a UUID, a name or its place in the scene instead, and its [`NetEntity`] id is derived from the
+//! key, the same on every instance:
+//!
+//! ```ignore
+//! app.add_net_keys();
+//!
+//! const FRONT_DOOR: u128 = 0x6f1c_2b7e_4d0a_4c1e_9f3b_81d2_c5a7_9a2e;
+//!
+//! fn spawn_level(mut commands: Commands) {
+//!     commands.spawn((NetKey::Uuid(FRONT_DOOR), DoorBundle::default()));
+//!     commands.spawn((Name::new("switch_3"), NetKey::FromName, SwitchBundle::default()));
+//! }
+//! ```
+//!
+//! Entities that are loaded from a scene usually only have a [`Name`], which doesn't have to be
+//! unique among the whole level. [`NetKey::FromPath`] uses the [scene path](scene_path) of the
+//! entity instead: the names of its named ancestors and its own name, like `"level_2/hall/door"`.
+//! It can be added to the entities of a scene, or to all named entities below a root with a
+//! system.
+//!
+//! Entities with a [`NetKey`] and no [`NetEntity`] get one in [`CoreStage::First`], before the
+//! received updates are applied. From then on they are like any other networked entity: received
+//! updates and [`NetEntityRef`](crate::mapping::NetEntityRef)s resolve to them through the
+//! [`NetEntityMap`](crate::mapping::NetEntityMap), which can also look them up by key with
+//! [`get_uuid`](crate::mapping::NetEntityMap::get_uuid),
+//! [`get_named`](crate::mapping::NetEntityMap::get_named) and
+//! [`get_path`](crate::mapping::NetEntityMap::get_path).
+//!
+//! The derived ids are hashes, so they take up to 9 bytes to send, instead of the few bytes of a
+//! small sequential id. They never have the [`PROVISIONAL_BIT`], and two different keys
+//! practically never share an id. Two entities with the same key do, like two doors with the same
+//! path, so every entity that derives an id that another entity already has is reported with an
+//! error and a [`KeyCollision`] event, in all builds.
+
+use crate::spawn::PROVISIONAL_BIT;
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// The stable key that the [`NetEntity`] id of an entity is derived from.
+///
+/// See the [module docs](self).
+#[derive(Component, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum NetKey {
+    /// A UUID, as a `u128`.
+    Uuid(u128),
+    /// A name that is unique among the keyed entities.
+    Name(String),
+    /// The [`Name`] component of the entity, which is then unique among the keyed entities.
+    ///
+    /// This is the same key as [`NetKey::Name`] with that name.
+    FromName,
+    /// The [scene path](scene_path) of the entity, which is then unique among the keyed
+    /// entities.
+    FromPath,
+}
+
+impl NetKey {
+    /// Creates a [`NetKey`] from `name`.
+    pub fn name(name: impl Into<String>) -> Self {
+        NetKey::Name(name.into())
+    }
+
+    /// Gets the [`NetEntity`] id derived from this key, as the key of `entity` in `hierarchy`.
+    ///
+    /// Returns `None` if the key is [`NetKey::FromName`] or [`NetKey::FromPath`] and the entity
+    /// has no [`Name`].
+    pub fn id(&self, entity: Entity, hierarchy: &Hierarchy) -> Option<NetId> {
+        match self {
+            NetKey::Uuid(uuid) => Some(NetKey::uuid_id(*uuid)),
+            NetKey::Name(name) => Some(NetKey::name_id(name)),
+            NetKey::FromName => match hierarchy.get(entity) {
+                Ok((Some(name), _)) => Some(NetKey::name_id(name.as_str())),
+                _ => None,
+            },
+            NetKey::FromPath => scene_path(entity, hierarchy).map(|path| NetKey::path_id(&path)),
+        }
+    }
+
+    /// Gets the [`NetEntity`] id derived from `uuid`.
+    ///
+    /// With the `wide-ids` feature, this is `uuid` itself, without the [`PROVISIONAL_BIT`].
+    pub fn uuid_id(uuid: u128) -> NetId {
+        #[cfg(feature = "wide-ids")]
+        return uuid & (PROVISIONAL_BIT - 1);
+        #[cfg(not(feature = "wide-ids"))]
+        return hash(0, &uuid.to_le_bytes());
+    }
+
+    /// Gets the [`NetEntity`] id derived from `name`.
+    pub fn name_id(name: &str) -> NetId {
+        hash(1, name.as_bytes())
+    }
+
+    /// Gets the [`NetEntity`] id derived from the [scene path](scene_path) `path`.
+    pub fn path_id(path: &str) -> NetId {
+        hash(2, path.as_bytes())
+    }
+}
+
+/// The names and parents of entities, to build [scene paths](scene_path) with.
+pub type Hierarchy<'w, 's> = Query<'w, 's, (Option<&'static Name>, Option<&'static Parent>)>;
+
+/// Gets the scene path of `entity`: the [`Name`]s of its ancestors that have one, from the root
+/// down, and its own name, joined by `/`.
+///
+/// Returns `None` if `entity` has no [`Name`]. Names that contain a `/` can make two paths equal.
+pub fn scene_path(entity: Entity, hierarchy: &Hierarchy) -> Option<String> {
+    let mut names = vec![];
+    let mut next = Some(entity);
+    while let Some(entity) = next {
+        let (name, parent) = match hierarchy.get(entity) {
+            Ok(item) => item,
+            Err(_) => break,
+        };
+        match name {
+            Some(name) => names.push(name.as_str()),
+            None if names.is_empty() => return None,
+            None => {}
+        }
+        next = parent.map(|parent| parent.get());
+    }
+    names.reverse();
+    Some(names.join("/"))
+}
+
+/// An event that is sent when an entity derives a [`NetEntity`] id from its [`NetKey`] that
+/// another entity already has.
+///
+/// The entity still gets the id, so both of them get each other's updates.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct KeyCollision {
+    /// The shared id.
+    pub id: NetId,
+    /// The entity that had the id first.
+    pub first: Entity,
+    /// The entity that derived the id.
+    pub second: Entity,
+}
+
+/// Hashes `bytes`, with `kind` telling the kinds of keys apart, into an id without the
+/// [`PROVISIONAL_BIT`].
+fn hash(kind: u8, bytes: &[u8]) -> NetId {
+    // FNV-1a, which unlike the std hashers is guaranteed to be stable.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in std::iter::once(kind).chain(bytes.iter().copied()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash as NetId & (PROVISIONAL_BIT - 1)
+}
+
+/// Gives the entities with a [`NetKey`] and no [`NetEntity`] the [`NetEntity`] derived from their
+/// key, and reports the ones that collide with another entity.
+#[allow(clippy::type_complexity)]
+pub fn assign_key_ids(
+    mut commands: Commands,
+    q: Query<
+        (Entity, &NetKey),
+        (
+            Without<NetEntity>,
+            Or<(Added<NetKey>, Changed<Name>, Changed<Parent>)>,
+        ),
+    >,
+    hierarchy: Hierarchy,
+    net_entities: Query<(Entity, &NetEntity)>,
+    mut ew: EventWriter<KeyCollision>,
+) {
+    if q.is_empty() {
+        return;
+    }
+    let mut taken: HashMap<NetId, Entity> = net_entities
+        .iter()
+        .map(|(entity, net_e)| (net_e.id, entity))
+        .collect();
+    for (entity, key) in q.iter() {
+        let id = match key.id(entity, &hierarchy) {
+            Some(id) => id,
+            None => {
+                warn!(
+                    "Entity {:?} is keyed by its name, but doesn't have a Name.",
+                    entity
+                );
+                continue;
+            }
+        };
+        if let Some(first) = taken.insert(id, entity) {
+            error!(
+                "Entity {:?} has the same {:?} key as {:?}, so they share NetEntity {{ id: {} }}.",
+                entity, key, first, id
+            );
+            ew.send(KeyCollision {
+                id,
+                first,
+                second: entity,
+            });
+        }
+        commands.entity(entity).insert(NetEntity::new(id));
+    }
+}
@@ -16,9 +16,151 @@
 //! on the GitHub repo.
 
 #![warn(missing_debug_implementations, missing_copy_implementations)]
+pub mod ack;
 pub mod app;
+pub mod atomic;
+pub mod authority;
+pub mod background;
+pub mod bits;
+#[cfg(feature = "testing")]
+pub mod bot;
+pub mod budget;
+pub mod bundle;
+pub mod cadence;
+pub mod channel;
+pub mod command;
+pub mod conditions;
+pub mod config;
+pub mod congestion;
+pub mod connect;
+#[cfg(feature = "dedicated")]
+pub mod dedicated;
+pub mod deferred;
+pub mod disconnect;
+pub mod error;
+pub mod extrapolate;
+pub mod filter;
+pub mod format;
+pub mod fragment;
+pub mod group;
+pub mod host;
+pub mod ids;
+pub mod input;
+pub mod interest;
+pub mod interpolate;
+pub mod jitter;
+pub mod key;
+pub mod limits;
+pub mod lod;
+pub mod mapping;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod middleware;
+pub mod movement;
+pub mod ordering;
+pub mod persist;
+pub mod player;
+pub mod priority;
+pub mod profiler;
+pub mod resend;
+#[cfg(feature = "async")]
+pub mod runtime;
+pub mod schema;
+pub mod session;
+pub mod snapshot;
+pub mod spawn;
+pub mod spec;
+pub mod stage;
+pub mod staging;
+pub mod stale;
+pub mod state;
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod stress;
 pub mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod threshold;
+pub mod transform_sync;
 #[cfg(feature = "types")]
 pub mod types;
+pub mod validate;
+pub mod varint;
+pub mod version;
+pub mod visibility;
 
-pub use app::{AppExt, ClientPlugin, NetLabel, ServerPlugin, SyncC};
+pub use ack::{AckInfo, NetAcks};
+pub use app::{
+    AppExt, ClientPlugin, NetLabel, NetSource, NetUpdated, OnNetUpdate, ServerPlugin, SyncC,
+    SyncInfo,
+};
+pub use atomic::{GroupBuffer, SyncGroups};
+pub use authority::{AuthorityGained, AuthorityLost};
+pub use background::{BackgroundRecvPlugin, BackgroundRecvStage};
+pub use bits::{BitPack, BitReader, BitWriter, OutOfBits, Packed, PackedVec};
+pub use budget::{RecvBudget, RecvOverflow};
+pub use bundle::{SyncBundle, SyncBundleItem, Synced};
+pub use cadence::SendCadence;
+pub use channel::{ChannelConfig, ChannelMsg, ChannelRecvExt, NetChannels};
+pub use command::{ClientCommands, CommandAcked, CommandOutcome, CommandQueue, ReceivedCommand};
+pub use config::{AddressFamily, ConfigError, PigeonServerConfig};
+pub use congestion::{AdaptiveSendRate, SendRates};
+pub use connect::{
+    ClientAddrs, ConnectFailed, ConnectSucceeded, Connecting, ConnectionHook, ConnectionResponse,
+    NetConnected,
+};
+#[cfg(feature = "dedicated")]
+pub use dedicated::{DedicatedServerPlugin, DedicatedServerPlugins, ServerShutdown, ShutdownMsg};
+pub use deferred::DeferredApply;
+pub use disconnect::{ClientState, DisconnectInitiator, DisconnectPlugin, NetDisconnected};
+pub use error::{NetErrorEvent, NetErrorKind};
+pub use extrapolate::{Extrapolatable, Extrapolate};
+pub use filter::{NetFilters, SendFilter};
+pub use format::{Bincode, Encoded, WireFormat};
+pub use fragment::{FragmentConfig, Fragments};
+pub use group::NetGroups;
+pub use host::HostedServer;
+pub use ids::{IdWidth, NetIds};
+pub use input::{
+    InputConfig, InputHistory, LocalInput, NetTick, OwnedInputs, PlayerInputs, ServerTick,
+    TickedInput,
+};
+pub use interest::ClientInterest;
+pub use interpolate::{Interpolate, InterpolationDelayChanged, TickTimeline};
+pub use jitter::JitterBuffer;
+pub use key::{KeyCollision, NetKey};
+pub use limits::{Limited, MalformedMsg, NetLimits};
+pub use lod::{LodTier, SyncLod};
+pub use mapping::{DuplicateNetEntity, MapNetEntities, NetEntityMap, NetEntityRef};
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsPlugin;
+pub use middleware::{LogBytes, Middleware, MiddlewareCtx, MiddlewareError, NetPipeline, PipedNet};
+pub use movement::{KinematicController, LocalPlayer, MoveInput, MovementConfig};
+pub use ordering::OrderedUpdates;
+pub use persist::{WorldFile, WorldLoaded};
+pub use player::{ConnectedPlayers, OwnedBy, PlayerFactory};
+pub use priority::DistancePriority;
+pub use profiler::{FrameProfile, NetProfiler, NetProfilerPlugin, ProfileEntry, ProfiledSystem};
+pub use resend::{ResendConfig, Resends};
+#[cfg(feature = "async")]
+pub use runtime::{AsyncBridge, AsyncRuntime};
+pub use schema::{MsgSchema, MsgTableLike, SchemaEntry};
+pub use session::{SessionExpired, SessionIdentity, SessionResumed, Sessions};
+pub use snapshot::{NetSpawn, SendDelta, SendSnapshot, SnapshotApplied};
+pub use spawn::{
+    Predicted, ProvisionalIds, RequestSpawn, RespondSpawn, SpawnRequested, SpawnResolved,
+};
+pub use spec::{NetSendTo, NetSpec, ServerSendExt};
+pub use stage::NetStage;
+pub use staging::StagedUpdates;
+pub use stale::{NetResumed, NetStale, NetStaleness, StaleTimeout};
+pub use state::{NetworkState, NetworkStatePlugin, NetworkStateScoped};
+pub use stats::{MsgStats, NetStats};
+pub use sync::{ApplyMode, Channel, NetId, NetWriteAccess, SyncConfig, SyncSchedule};
+pub use threshold::Delta;
+pub use transform_sync::{TransformSmoothing, TransformSyncConfig, TransformSyncPlugin};
+pub use validate::{RecvSanitizers, SyncValidators, SyncViolation, Update, Validation};
+pub use version::{ConnectionRejected, Handshake, ProtocolVersion, Rejection, Versioned};
+pub use visibility::NetHidden;
+#[cfg(feature = "visibility")]
+pub use visibility::NetVisibilityPlugin;
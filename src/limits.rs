@@ -0,0 +1,558 @@
+//! Limits on the size of received messages.
+//!
+//! A hostile peer can craft a message that claims to hold a huge collection, or send the fragments
+//! of a message that is never finished. [`NetLimits`] bounds the memory that is used while
+//! decoding received messages, and messages that exceed it are dropped with a [`MalformedMsg`]
+//! event instead.
+//!
+//! - [`max_msg_size`](NetLimits::max_msg_size) limits the size of messages that are reassembled
+//!   from [fragments](crate::fragment), and of the components in a
+//!   [snapshot](crate::snapshot). The size of every other message is limited by
+//!   `carrier-pigeon`'s `Config::max_msg_size`.
+//! - [`max_elements`](NetLimits::max_elements) limits the total number of elements in the
+//!   sequences and maps of a synced component message. To limit a user message, wrap it in
+//!   [`Limited`].
+//! - [`max_partials`](NetLimits::max_partials) limits the number of fragmented messages that are
+//!   being reassembled for a client at once.
+//!
+//! ```ignore
+//! app.insert_resource(NetLimits {
+//!     max_elements: 1024,
+//!     ..default()
+//! });
+//! ```
+//!
+//! Messages that are received through `carrier-pigeon` are decoded before the app gets them, so
+//! the element limit of those is shared by every app in the process, and their [`MalformedMsg`]
+//! events don't know which client sent them.
+
+use bevy::prelude::*;
+use bincode::Options;
+use carrier_pigeon::CId;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess,
+    Visitor,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cell::Cell;
+use std::fmt::Formatter;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The default of [`NetLimits::max_elements`].
+const DEFAULT_MAX_ELEMENTS: usize = 65_536;
+
+/// The element limit of the messages that are decoded by `carrier-pigeon`.
+static MAX_ELEMENTS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ELEMENTS);
+
+/// The messages that `carrier-pigeon` failed to decode, with their type name and the error.
+static REJECTED: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Whether [`NetLimits::decode`] is running on this thread, which already applies the limits.
+    static DECODING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The limits on received messages.
+///
+/// This is added by the [`ClientPlugin`](crate::ClientPlugin) and
+/// [`ServerPlugin`](crate::ServerPlugin) with the default limits.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetLimits {
+    /// The maximum size of a message in bytes. Defaults to 1 MiB.
+    pub max_msg_size: usize,
+    /// The maximum number of elements in all the sequences and maps of a message. Defaults to
+    /// 65536.
+    pub max_elements: usize,
+    /// The maximum number of fragmented messages that are reassembled for a client at once.
+    /// Defaults to 8.
+    pub max_partials: usize,
+}
+
+impl Default for NetLimits {
+    fn default() -> Self {
+        NetLimits {
+            max_msg_size: 1 << 20,
+            max_elements: DEFAULT_MAX_ELEMENTS,
+            max_partials: 8,
+        }
+    }
+}
+
+impl NetLimits {
+    /// Decodes a bincode encoded value from `bytes`, within these limits.
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        if bytes.len() > self.max_msg_size {
+            return Err(format!(
+                "message of {} bytes is larger than the limit of {} bytes",
+                bytes.len(),
+                self.max_msg_size
+            ));
+        }
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(self.max_msg_size as u64);
+        let mut deserializer = bincode::Deserializer::from_slice(bytes, options);
+        let budget = Cell::new(self.max_elements);
+        DECODING.with(|decoding| decoding.set(true));
+        let result = T::deserialize(Counting::new(&mut deserializer, &budget));
+        DECODING.with(|decoding| decoding.set(false));
+        result.map_err(|e| e.to_string())
+    }
+}
+
+/// An event that is sent when a received message is dropped because it was malformed or too
+/// large.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MalformedMsg {
+    /// The client that sent the message, or `None` if it was sent by the server, or if it isn't
+    /// known.
+    pub cid: Option<CId>,
+    /// The type name of the message.
+    pub type_name: &'static str,
+    /// Why the message was dropped.
+    pub error: String,
+}
+
+/// A user message `M` whose sequences and maps are limited to
+/// [`max_elements`](NetLimits::max_elements) elements when it is received.
+///
+/// Register and send `Limited<M>` instead of `M` to limit it.
+#[derive(Serialize, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+#[serde(transparent)]
+pub struct Limited<M>(pub M);
+
+impl<M> Limited<M> {
+    /// Gets the inner message.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M> From<M> for Limited<M> {
+    fn from(msg: M) -> Self {
+        Limited(msg)
+    }
+}
+
+impl<M> Deref for Limited<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> DerefMut for Limited<M> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.0
+    }
+}
+
+impl<'de, M: Deserialize<'de>> Deserialize<'de> for Limited<M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        limited(deserializer).map(Limited)
+    }
+}
+
+/// Deserializes `M` within the element limit of messages that are decoded by `carrier-pigeon`,
+/// and records it if it fails.
+pub(crate) fn limited<'de, D, M>(deserializer: D) -> Result<M, D::Error>
+where
+    D: Deserializer<'de>,
+    M: Deserialize<'de>,
+{
+    if DECODING.with(Cell::get) {
+        return M::deserialize(deserializer);
+    }
+    let budget = Cell::new(MAX_ELEMENTS.load(Ordering::Relaxed));
+    let result = M::deserialize(Counting::new(deserializer, &budget));
+    if let Err(e) = &result {
+        REJECTED
+            .lock()
+            .unwrap()
+            .push((std::any::type_name::<M>(), e.to_string()));
+    }
+    result
+}
+
+/// Applies the element limit of [`NetLimits`] to the messages that are decoded by
+/// `carrier-pigeon`, and sends a [`MalformedMsg`] for each one that was dropped.
+pub fn report_malformed(limits: Res<NetLimits>, mut ew: EventWriter<MalformedMsg>) {
+    if limits.is_changed() {
+        MAX_ELEMENTS.store(limits.max_elements, Ordering::Relaxed);
+    }
+    for (type_name, error) in REJECTED.lock().unwrap().drain(..) {
+        warn!("Dropped a malformed {}: {}", type_name, error);
+        ew.send(MalformedMsg {
+            cid: None,
+            type_name,
+            error,
+        });
+    }
+}
+
+/// Takes `n` elements from `budget`.
+fn take<E: de::Error>(budget: &Cell<usize>, n: usize) -> Result<(), E> {
+    match budget.get().checked_sub(n) {
+        Some(left) => {
+            budget.set(left);
+            Ok(())
+        }
+        None => Err(E::custom("too many elements")),
+    }
+}
+
+/// A [`Deserializer`] that counts the elements of sequences and maps against a budget.
+struct Counting<'b, D> {
+    inner: D,
+    budget: &'b Cell<usize>,
+}
+
+impl<'b, D> Counting<'b, D> {
+    fn new(inner: D, budget: &'b Cell<usize>) -> Self {
+        Counting { inner, budget }
+    }
+}
+
+/// Wraps the visitor of every deserialize method, and forwards it to the inner deserializer.
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+            let visitor = Counted::new(visitor, self.budget);
+            self.inner.$method($($arg,)* visitor)
+        }
+    )*};
+}
+
+impl<'de, 'b, D: Deserializer<'de>> Deserializer<'de> for Counting<'b, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// A [`Visitor`] that counts the elements of the sequences and maps it visits.
+struct Counted<'b, V> {
+    inner: V,
+    budget: &'b Cell<usize>,
+}
+
+impl<'b, V> Counted<'b, V> {
+    fn new(inner: V, budget: &'b Cell<usize>) -> Self {
+        Counted { inner, budget }
+    }
+}
+
+/// Forwards the visit methods of values without elements to the inner visitor.
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+impl<'de, 'b, V: Visitor<'de>> Visitor<'de> for Counted<'b, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.inner
+            .visit_some(Counting::new(deserializer, self.budget))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        self.inner
+            .visit_newtype_struct(Counting::new(deserializer, self.budget))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        // Fail before the visitor allocates space for the claimed length.
+        if seq.size_hint().unwrap_or(0) > self.budget.get() {
+            return Err(de::Error::custom("too many elements"));
+        }
+        self.inner.visit_seq(Counted::new(seq, self.budget))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        if map.size_hint().unwrap_or(0) > self.budget.get() {
+            return Err(de::Error::custom("too many elements"));
+        }
+        self.inner.visit_map(Counted::new(map, self.budget))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_enum(Counted::new(data, self.budget))
+    }
+}
+
+impl<'de, 'b, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Counted<'b, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        self.inner
+            .deserialize(Counting::new(deserializer, self.budget))
+    }
+}
+
+impl<'de, 'b, A: SeqAccess<'de>> SeqAccess<'de> for Counted<'b, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        let element = self
+            .inner
+            .next_element_seed(Counted::new(seed, self.budget))?;
+        if element.is_some() {
+            take(self.budget, 1)?;
+        }
+        Ok(element)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint().map(|n| n.min(self.budget.get()))
+    }
+}
+
+impl<'de, 'b, A: MapAccess<'de>> MapAccess<'de> for Counted<'b, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        let key = self.inner.next_key_seed(Counted::new(seed, self.budget))?;
+        if key.is_some() {
+            take(self.budget, 1)?;
+        }
+        Ok(key)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        self.inner.next_value_seed(Counted::new(seed, self.budget))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint().map(|n| n.min(self.budget.get()))
+    }
+}
+
+impl<'de, 'b, A: EnumAccess<'de>> EnumAccess<'de> for Counted<'b, A> {
+    type Error = A::Error;
+    type Variant = Counted<'b, A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self::Variant), A::Error> {
+        let budget = self.budget;
+        let (value, variant) = self.inner.variant_seed(Counted::new(seed, budget))?;
+        Ok((value, Counted::new(variant, budget)))
+    }
+}
+
+impl<'de, 'b, A: VariantAccess<'de>> VariantAccess<'de> for Counted<'b, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        self.inner
+            .newtype_variant_seed(Counted::new(seed, self.budget))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.inner
+            .tuple_variant(len, Counted::new(visitor, self.budget))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.inner
+            .struct_variant(fields, Counted::new(visitor, self.budget))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::collections::HashMap;
+
+    /// An allocator that counts the bytes allocated on every thread.
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATED.try_with(|a| a.set(a.get() + layout.size()));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    /// Runs `f`, returning its result and the number of bytes it allocated.
+    fn allocated<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATED.with(Cell::get);
+        let result = f();
+        (result, ALLOCATED.with(Cell::get) - before)
+    }
+
+    /// A bincode message that claims to hold `len` elements, but holds only a few bytes.
+    fn claims(len: u64) -> Vec<u8> {
+        let mut bytes = len.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[1, 2, 3, 4]);
+        bytes
+    }
+
+    #[test]
+    fn oversized_sequence_prefix_is_rejected_without_allocating() {
+        let bytes = claims(u64::MAX >> 4);
+        let (result, bytes_allocated) =
+            allocated(|| bincode::deserialize::<Limited<Vec<u64>>>(&bytes));
+        assert!(result.is_err());
+        // serde would reserve up to 1 MiB for the claimed length; only the error is allocated.
+        assert!(
+            bytes_allocated < 4096,
+            "allocated {} bytes",
+            bytes_allocated
+        );
+        let rejected = REJECTED.lock().unwrap().drain(..).count();
+        assert!(rejected >= 1);
+    }
+
+    #[test]
+    fn oversized_map_prefix_is_rejected_without_allocating() {
+        let bytes = claims(1 << 40);
+        let (result, bytes_allocated) =
+            allocated(|| bincode::deserialize::<Limited<HashMap<u8, u8>>>(&bytes));
+        assert!(result.is_err());
+        assert!(
+            bytes_allocated < 4096,
+            "allocated {} bytes",
+            bytes_allocated
+        );
+    }
+
+    #[test]
+    fn elements_of_nested_sequences_share_the_budget() {
+        let limits = NetLimits {
+            max_elements: 10,
+            ..default()
+        };
+        let fits = bincode::serialize(&vec![vec![0u8; 4]; 2]).unwrap();
+        assert!(limits.decode::<Vec<Vec<u8>>>(&fits).is_ok());
+        let too_many = bincode::serialize(&vec![vec![0u8; 4]; 3]).unwrap();
+        assert!(limits.decode::<Vec<Vec<u8>>>(&too_many).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_oversized_prefixes_and_messages() {
+        let limits = NetLimits::default();
+        let (result, bytes_allocated) =
+            allocated(|| limits.decode::<Vec<u64>>(&claims(u64::MAX >> 4)));
+        assert!(result.is_err());
+        assert!(
+            bytes_allocated < 4096,
+            "allocated {} bytes",
+            bytes_allocated
+        );
+
+        let limits = NetLimits {
+            max_msg_size: 8,
+            ..default()
+        };
+        assert!(limits.decode::<Vec<u8>>(&[0; 9]).is_err());
+    }
+}
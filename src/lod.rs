@@ -0,0 +1,299 @@
+//! Syncing entities at a lower level of detail to some clients.
+//!
+//! Not every client needs every entity in full detail. The [`SyncLod`] resource puts every
+//! entity-client pair in a [`LodTier`]:
+//!
+//! - [`Full`](LodTier::Full): the components are sent every frame, as their message type.
+//! - [`Reduced`](LodTier::Reduced): the components are sent every
+//!   [`reduced_interval`](SyncLod::reduced_interval) frames. Components with a reduced message
+//!   type, added with [`add_lod_variant`](crate::AppExt::add_lod_variant), are sent as that
+//!   instead, such as a quantized transform.
+//! - [`Presence`](LodTier::Presence): the components aren't sent at all; the client only knows
+//!   that the entity exists.
+//!
+//! The [`SyncLod`] is inserted with [`sync_lod`](crate::AppExt::sync_lod). The tier comes from, in order, the tiers set by hand with [`set`](SyncLod::set), the rule set
+//! with [`set_rule`](SyncLod::set_rule), and the distances set with
+//! [`set_distances`](SyncLod::set_distances), which use the viewpoints of the
+//! [`DistancePriority`]. Entities are [`Full`](LodTier::Full) otherwise.
+//!
+//! ```ignore
+//! app.sync_comp::<Transform, NetTransform>(&mut table, Transport::UDP)
+//!     .add_lod_variant::<Transform, NetTransform, NetTransformQ>(&mut table)
+//!     .sync_lod(SyncLod::default().with_distances(50.0, 300.0))
+//!     .prioritize_by_distance(DistancePriority::default());
+//! ```
+//!
+//! Changes of components using change detection that aren't sent to a client because of its
+//! tier are held, so they are sent once the entity moves up to a higher tier.
+
+use crate::app::{Recipients, SendFilters};
+use crate::congestion::SendThrottle;
+use crate::filter::NetFilters;
+use crate::group::NetGroups;
+use crate::interest::ClientInterest;
+use crate::priority::DistancePriority;
+use crate::sync::{CNetDir, NetComp, NetCompMsg, NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::{CId, Client, Server};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::{Any, TypeId};
+use std::fmt::{Debug, Formatter};
+
+/// How much detail of an entity a client receives.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub enum LodTier {
+    /// Every update, as the component's message type.
+    #[default]
+    Full,
+    /// Fewer updates, as the component's reduced message type if it has one.
+    Reduced,
+    /// No updates.
+    Presence,
+}
+
+/// A rule that decides the [`LodTier`] of the entity with the given id for a client.
+pub type LodRule = Box<dyn Fn(CId, NetId) -> LodTier + Send + Sync>;
+
+/// The [`LodTier`] of every entity-client pair.
+#[derive(Resource)]
+pub struct SyncLod {
+    /// The number of frames between the updates of [`Reduced`](LodTier::Reduced) entities.
+    /// Defaults to 2.
+    pub reduced_interval: u32,
+    overrides: HashMap<(CId, NetId), LodTier>,
+    rule: Option<LodRule>,
+    /// The distances from which entities are reduced and presence only.
+    distances: Option<(f32, f32)>,
+    /// The frame count, which staggers the entities that are sent.
+    frame: u64,
+}
+
+impl Default for SyncLod {
+    fn default() -> Self {
+        SyncLod {
+            reduced_interval: 2,
+            overrides: HashMap::default(),
+            rule: None,
+            distances: None,
+            frame: 0,
+        }
+    }
+}
+
+impl Debug for SyncLod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncLod")
+            .field("reduced_interval", &self.reduced_interval)
+            .field("overrides", &self.overrides)
+            .field("rule", &self.rule.is_some())
+            .field("distances", &self.distances)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SyncLod {
+    /// Uses `rule` to decide the tier of the entities that aren't set by hand.
+    pub fn set_rule(&mut self, rule: impl Fn(CId, NetId) -> LodTier + Send + Sync + 'static) {
+        self.rule = Some(Box::new(rule));
+    }
+
+    /// Reduces the entities from `reduced` away from the client's viewpoint in the
+    /// [`DistancePriority`], and makes them presence only from `presence` away.
+    pub fn set_distances(&mut self, reduced: f32, presence: f32) {
+        self.distances = Some((reduced, presence));
+    }
+
+    /// Same as [`set_distances`](Self::set_distances), but returns `self`.
+    pub fn with_distances(mut self, reduced: f32, presence: f32) -> Self {
+        self.set_distances(reduced, presence);
+        self
+    }
+
+    /// Sets the tier of the entity with id `id` for client `cid`.
+    pub fn set(&mut self, cid: CId, id: NetId, tier: LodTier) {
+        self.overrides.insert((cid, id), tier);
+    }
+
+    /// Clears the tier that was set for the entity with id `id` for client `cid`.
+    pub fn clear(&mut self, cid: CId, id: NetId) {
+        self.overrides.remove(&(cid, id));
+    }
+
+    /// Clears the tiers that were set for client `cid`.
+    pub fn remove_client(&mut self, cid: CId) {
+        self.overrides.retain(|(c, _), _| *c != cid);
+    }
+
+    /// Gets the tier of the entity with id `id` for client `cid`.
+    pub fn tier(&self, cid: CId, id: NetId, priority: Option<&DistancePriority>) -> LodTier {
+        if let Some(tier) = self.overrides.get(&(cid, id)) {
+            return *tier;
+        }
+        if let Some(rule) = &self.rule {
+            return rule(cid, id);
+        }
+        let dist = self
+            .distances
+            .zip(priority.and_then(|p| p.distance(cid, id)));
+        match dist {
+            Some(((_, presence), dist)) if dist >= presence => LodTier::Presence,
+            Some(((reduced, _), dist)) if dist >= reduced => LodTier::Reduced,
+            _ => LodTier::Full,
+        }
+    }
+
+    /// Whether an entity with id `id` in `tier` is sent this frame.
+    // The cast is only needed when `NetId` is a `u128`.
+    #[allow(clippy::unnecessary_cast)]
+    pub(crate) fn is_due(&self, tier: LodTier, id: NetId) -> bool {
+        match tier {
+            LodTier::Full => true,
+            LodTier::Reduced => self
+                .frame
+                .wrapping_add(id as u64)
+                .is_multiple_of(self.reduced_interval.max(1) as u64),
+            LodTier::Presence => false,
+        }
+    }
+
+    /// Whether any entity can be in a tier other than [`Full`](LodTier::Full).
+    pub(crate) fn is_active(&self) -> bool {
+        !self.overrides.is_empty() || self.rule.is_some() || self.distances.is_some()
+    }
+}
+
+/// The components that have a reduced message type.
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct LodVariants(HashSet<TypeId>);
+
+impl LodVariants {
+    /// Whether component `T` has a reduced message type.
+    pub(crate) fn has<T: Component>(&self) -> bool {
+        self.0.contains(&TypeId::of::<T>())
+    }
+
+    /// Marks component `T` as having a reduced message type.
+    pub(crate) fn add<T: Component>(&mut self) {
+        self.0.insert(TypeId::of::<T>());
+    }
+}
+
+/// The message type that the reduced updates are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(transparent)]
+pub(crate) struct ReducedNetCompMsg<R: Any + Send + Sync>(pub(crate) NetCompMsg<R>);
+
+/// The items of the query in [`lod_send`].
+type LodSendItem<'a, T, M> = (
+    &'a NetEntity,
+    &'a NetComp<T, M>,
+    &'a T,
+    ChangeTrackers<T>,
+    SendFilters<'a>,
+);
+
+/// Advances the frame count of the [`SyncLod`].
+pub fn update_lod(mut lod: ResMut<SyncLod>) {
+    lod.frame = lod.frame.wrapping_add(1);
+}
+
+/// Sends component `T` as its reduced message type `R` to the clients that have the entity in
+/// the [`Reduced`](LodTier::Reduced) tier.
+pub fn lod_send<T, M, R>(
+    server: Option<Res<Server>>,
+    groups: Option<Res<NetGroups>>,
+    (interest, net_filters): (Option<Res<ClientInterest>>, Option<Res<NetFilters>>),
+    mut throttle: SendThrottle,
+    q: Query<LodSendItem<'_, T, M>>,
+) where
+    T: Clone + Into<M> + Into<R> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+    R: Clone + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let mut errors = vec![];
+    for (net_e, net_c, comp, ct, filters) in q.iter() {
+        let changed = !net_c.cd || ct.is_changed();
+        if !changed && !throttle.is_held(net_e.id) {
+            continue;
+        }
+        let to_spec = match net_c.s_dir.to() {
+            Some(to_spec) => *to_spec,
+            None => continue,
+        };
+        let recipients = Recipients::of(
+            &server,
+            net_e.id,
+            net_c,
+            to_spec,
+            filters,
+            groups.as_deref(),
+            (interest.as_deref(), net_filters.as_deref()),
+        );
+        // The full clients get the full update, so there is no need to hold it for them.
+        let mut cids = recipients.cids(&server);
+        cids.retain(|cid| throttle.tier(*cid, net_e.id) != LodTier::Full);
+        let cids = throttle.filter(
+            net_e.id,
+            changed,
+            net_c.cd,
+            cids,
+            |tier| tier == LodTier::Reduced,
+            true,
+        );
+        if cids.is_empty() {
+            continue;
+        }
+        let msg = ReducedNetCompMsg(NetCompMsg::<R>::new(*net_e, comp.clone().into()));
+        Recipients::Cids(cids).send(&server, &msg, &mut errors);
+        for (_, e) in errors.drain(..) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Applies the reduced updates of message type `R` to component `T` on the client.
+pub fn lod_recv<T, M, R>(
+    client: Option<Res<Client>>,
+    mut q: Query<(&NetEntity, &mut NetComp<T, M>, &mut T)>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+    R: Clone + Into<T> + Any + Send + Sync,
+{
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    let msgs: Vec<_> = client.recv::<ReducedNetCompMsg<R>>().collect();
+    let mut latest: HashMap<NetEntity, (Option<u32>, &R)> = HashMap::default();
+    for m in msgs.iter() {
+        let newer = latest
+            .get(&m.0.net_e())
+            .is_none_or(|(time, _)| m.time.is_none() || m.time > *time);
+        if newer {
+            latest.insert(m.0.net_e(), (m.time, &m.0.msg));
+        }
+    }
+    if latest.is_empty() {
+        return;
+    }
+    for (net_e, mut net_c, mut comp) in q.iter_mut() {
+        if net_c.c_dir != CNetDir::From {
+            continue;
+        }
+        if let Some((time, msg)) = latest.get(net_e) {
+            // Drop updates that are older than the last full one.
+            if matches!((time, net_c.last), (Some(time), Some(last)) if *time <= last) {
+                continue;
+            }
+            net_c.last = *time;
+            *comp = (*msg).clone().into();
+        }
+    }
+}
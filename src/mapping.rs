@@ -0,0 +1,226 @@
+//! Sending references to other entities.
+//!
+//! An [`Entity`] is only meaningful in the world it came from, so it can't be sent over the
+//! network. Use a [`NetEntityRef`] instead, which refers to an entity by its
+//! [`NetEntity`] id, and is resolved to the local [`Entity`] on the other end.
+//!
+//! For a synced component that contains references, implement [`MapNetEntities`] and call
+//! [`map_net_entities`](crate::AppExt::map_net_entities). The references are then resolved after
+//! every update. If a referenced entity doesn't exist yet, the component gets an
+//! [`Unresolved<T>`] marker and resolving is retried every frame until it does.
+//!
+//! ```ignore
+//! #[derive(Component, Serialize, Deserialize, Clone)]
+//! struct Target(NetEntityRef);
+//!
+//! impl MapNetEntities for Target {
+//!     fn map_net_entities(&mut self, map: &NetEntityMap) -> bool {
+//!         self.0.map(map)
+//!     }
+//! }
+//! ```
+//!
+//! Two live entities sharing a [`NetEntity`] id get each other's updates, and only one of them
+//! can be referenced. In debug builds, every entity that gets a [`NetEntity`] id that another
+//! entity already has is reported with an error and a [`DuplicateNetEntity`] event.
+
+use crate::key::NetKey;
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// A mapping from [`NetEntity`] ids to local [`Entity`]s.
+///
+/// This is kept up to date by the [`ClientPlugin`](crate::ClientPlugin) and
+/// [`ServerPlugin`](crate::ServerPlugin).
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct NetEntityMap {
+    entities: HashMap<NetId, Entity>,
+    ids: HashMap<Entity, NetId>,
+}
+
+impl NetEntityMap {
+    /// Gets the local entity with the [`NetEntity`] id `id`.
+    pub fn get(&self, id: NetId) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+
+    /// Gets the local entity with the [`NetKey::Uuid`] `uuid`.
+    pub fn get_uuid(&self, uuid: u128) -> Option<Entity> {
+        self.get(NetKey::uuid_id(uuid))
+    }
+
+    /// Gets the local entity with the [`NetKey::Name`] `name`, or the [`NetKey::FromName`] of
+    /// that [`Name`].
+    pub fn get_named(&self, name: &str) -> Option<Entity> {
+        self.get(NetKey::name_id(name))
+    }
+
+    /// Gets the local entity with the [`NetKey::FromPath`] of the scene path `path`.
+    pub fn get_path(&self, path: &str) -> Option<Entity> {
+        self.get(NetKey::path_id(path))
+    }
+
+    /// Gets the [`NetEntity`] id of the local entity `entity`.
+    pub fn id_of(&self, entity: Entity) -> Option<NetId> {
+        self.ids.get(&entity).copied()
+    }
+
+    fn insert(&mut self, id: NetId, entity: Entity) {
+        if let Some(old) = self.ids.insert(entity, id) {
+            self.entities.remove(&old);
+        }
+        self.entities.insert(id, entity);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(id) = self.ids.remove(&entity) {
+            self.entities.remove(&id);
+        }
+    }
+}
+
+/// An event that is sent when two live entities have the same [`NetEntity`] id.
+///
+/// This is only detected in debug builds.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct DuplicateNetEntity {
+    /// The shared id.
+    pub id: NetId,
+    /// The entity that had the id first.
+    pub first: Entity,
+    /// The entity that got the id while `first` had it.
+    pub second: Entity,
+}
+
+/// A reference to a networked entity that can be sent over the network.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetEntityRef {
+    /// The [`NetEntity`] id of the referenced entity.
+    pub id: NetId,
+    /// The local entity, once resolved.
+    #[serde(skip)]
+    entity: Option<Entity>,
+}
+
+impl NetEntityRef {
+    /// Creates a reference to the entity with the [`NetEntity`] id `id`.
+    pub fn new(id: NetId) -> Self {
+        NetEntityRef { id, entity: None }
+    }
+
+    /// Gets the local entity, if it has been resolved.
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    /// Resolves the local entity using `map`.
+    ///
+    /// Returns whether the entity exists.
+    pub fn map(&mut self, map: &NetEntityMap) -> bool {
+        self.entity = map.get(self.id);
+        self.entity.is_some()
+    }
+}
+
+impl From<NetEntity> for NetEntityRef {
+    fn from(net_e: NetEntity) -> Self {
+        NetEntityRef::new(net_e.id)
+    }
+}
+
+/// A type that contains [`NetEntityRef`]s.
+pub trait MapNetEntities {
+    /// Resolves all [`NetEntityRef`]s using `map`.
+    ///
+    /// Returns whether all of them were resolved.
+    fn map_net_entities(&mut self, map: &NetEntityMap) -> bool;
+}
+
+impl MapNetEntities for NetEntityRef {
+    fn map_net_entities(&mut self, map: &NetEntityMap) -> bool {
+        self.map(map)
+    }
+}
+
+/// A marker for an entity whose component `T` has references that couldn't be resolved yet.
+#[derive(Component, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Unresolved<T> {
+    _pd: PhantomData<T>,
+}
+
+/// Keeps the [`NetEntityMap`] up to date.
+pub fn update_net_entity_map(
+    mut map: ResMut<NetEntityMap>,
+    q: Query<(Entity, &NetEntity), Changed<NetEntity>>,
+    removed: RemovedComponents<NetEntity>,
+) {
+    for entity in removed.iter() {
+        map.remove(entity);
+    }
+    for (entity, net_e) in q.iter() {
+        map.insert(net_e.id, entity);
+    }
+}
+
+/// Reports the entities that got a [`NetEntity`] id that another entity already has.
+///
+/// This runs before [`update_net_entity_map`], so the map still has the entity that had the id
+/// first.
+pub fn detect_duplicate_ids(
+    map: Res<NetEntityMap>,
+    q: Query<(Entity, &NetEntity), Changed<NetEntity>>,
+    all: Query<&NetEntity>,
+    mut ew: EventWriter<DuplicateNetEntity>,
+) {
+    // The entities that got their id this frame, for duplicates that are both new.
+    let mut changed: HashMap<NetId, Entity> = HashMap::default();
+    for (entity, net_e) in q.iter() {
+        let first = changed
+            .get(&net_e.id)
+            .copied()
+            .or_else(|| map.get(net_e.id))
+            // The entity in the map might be despawned, or have another id by now.
+            .filter(|first| *first != entity && all.get(*first).is_ok_and(|n| n.id == net_e.id));
+        changed.entry(net_e.id).or_insert(entity);
+        if let Some(first) = first {
+            error!(
+                "NetEntity {{ id: {} }} is used by both {:?} and {:?}. Updates for it will be applied to the wrong entity.",
+                net_e.id, first, entity
+            );
+            ew.send(DuplicateNetEntity {
+                id: net_e.id,
+                first,
+                second: entity,
+            });
+        }
+    }
+}
+
+/// Resolves the [`NetEntityRef`]s in component `T` when it changes, or if it has unresolved
+/// references.
+#[allow(clippy::type_complexity)]
+pub fn resolve_net_entities<T: MapNetEntities + Component>(
+    mut commands: Commands,
+    map: Res<NetEntityMap>,
+    mut q: Query<(Entity, &mut T, Option<&Unresolved<T>>), Or<(Changed<T>, With<Unresolved<T>>)>>,
+) {
+    for (entity, mut comp, unresolved) in q.iter_mut() {
+        // Bypass change detection so this doesn't trigger itself next frame.
+        let resolved = comp.bypass_change_detection().map_net_entities(&map);
+        match (resolved, unresolved.is_some()) {
+            (true, true) => {
+                comp.set_changed();
+                commands.entity(entity).remove::<Unresolved<T>>();
+            }
+            (false, false) => {
+                commands
+                    .entity(entity)
+                    .insert(Unresolved::<T> { _pd: PhantomData });
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Exporting server stats for Prometheus. Requires the `metrics` feature.
+//!
+//! The [`MetricsPlugin`] serves the server's stats in the Prometheus text format on an HTTP
+//! endpoint, so a dedicated server can be scraped by Prometheus, or anything else that reads
+//! OpenMetrics, and shown on the usual dashboards.
+//!
+//! ```ignore
+//! app.add_plugin(MetricsPlugin::new("0.0.0.0:9100".parse().unwrap()));
+//! ```
+//!
+//! The exported metrics are:
+//! - `pigeon_connections`: the number of connected clients.
+//! - `pigeon_frame_seconds`: the duration of the last frame.
+//! - `pigeon_messages_sent_total`, `pigeon_bytes_sent_total` and
+//!   `pigeon_messages_received_total`: the counters of [`NetStats`], by component type.
+//! - `pigeon_rtt_seconds`: the 50th, 90th and 99th percentile round trip time of the clients.
+//!   This requires [acks](crate::ack) to be enabled.
+//! - `pigeon_packet_loss`: the average packet loss of the clients. This also requires acks.
+
+use crate::ack::NetAcks;
+use crate::stats::{MsgStats, NetStats};
+use bevy::prelude::*;
+use carrier_pigeon::Server;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A plugin that serves the server's stats on an HTTP endpoint in the Prometheus text format.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MetricsPlugin {
+    /// The address to serve the metrics on.
+    pub addr: SocketAddr,
+    /// The time in seconds between updates of the metrics. Defaults to 1.
+    pub interval: f32,
+}
+
+impl MetricsPlugin {
+    /// Creates a [`MetricsPlugin`] that serves the metrics on `addr`.
+    pub fn new(addr: SocketAddr) -> Self {
+        MetricsPlugin {
+            addr,
+            interval: 1.0,
+        }
+    }
+}
+
+impl Plugin for MetricsPlugin {
+    fn build(&self, app: &mut App) {
+        let metrics = Metrics {
+            text: Arc::default(),
+            interval: self.interval as f64,
+            last: f64::NEG_INFINITY,
+        };
+        match TcpListener::bind(self.addr) {
+            Ok(listener) => {
+                info!("Serving metrics on {}", self.addr);
+                let text = metrics.text.clone();
+                thread::spawn(move || serve(listener, text));
+            }
+            Err(e) => error!("Failed to serve metrics on {}: {}", self.addr, e),
+        }
+        app.insert_resource(metrics)
+            .add_system_to_stage(CoreStage::Last, update_metrics);
+    }
+}
+
+/// A counter of [`MsgStats`] that is exported, with its name and help text.
+type Counter = (&'static str, &'static str, fn(&MsgStats) -> u64);
+
+/// The counters of [`MsgStats`] that are exported.
+const COUNTERS: [Counter; 3] = [
+    ("pigeon_messages_sent_total", "Updates sent.", |s| s.sent),
+    ("pigeon_bytes_sent_total", "Estimated bytes sent.", |s| {
+        s.sent_bytes
+    }),
+    ("pigeon_messages_received_total", "Updates received.", |s| {
+        s.received
+    }),
+];
+
+/// The latest rendered metrics.
+#[derive(Resource, Debug)]
+struct Metrics {
+    text: Arc<Mutex<String>>,
+    interval: f64,
+    /// The local time of the last update, in seconds.
+    last: f64,
+}
+
+/// Serves the metrics to every request on `listener`.
+fn serve(listener: TcpListener, text: Arc<Mutex<String>>) {
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| respond(stream, &text.lock().unwrap()));
+        if let Err(e) = result {
+            debug!("Failed to serve metrics: {}", e);
+        }
+    }
+}
+
+/// Responds to a request on `stream` with `body`.
+fn respond(mut stream: TcpStream, body: &str) -> io::Result<()> {
+    // The request itself doesn't matter; every path serves the metrics.
+    let mut buf = [0; 1024];
+    let _ = stream.read(&mut buf)?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// Renders the metrics.
+fn update_metrics(
+    time: Res<Time>,
+    mut metrics: ResMut<Metrics>,
+    server: Option<Res<Server>>,
+    stats: Option<Res<NetStats>>,
+    acks: Option<Res<NetAcks>>,
+) {
+    let now = time.elapsed_seconds_f64();
+    if now - metrics.last < metrics.interval {
+        return;
+    }
+    metrics.last = now;
+
+    let mut text = String::new();
+    let connections = server.map(|s| s.cids().count()).unwrap_or(0);
+    gauge(&mut text, "pigeon_connections", "Connected clients.");
+    let _ = writeln!(text, "pigeon_connections {}", connections);
+    gauge(
+        &mut text,
+        "pigeon_frame_seconds",
+        "Duration of the last frame.",
+    );
+    let _ = writeln!(text, "pigeon_frame_seconds {}", time.delta_seconds_f64());
+
+    if let Some(stats) = stats {
+        let mut stats: Vec<_> = stats.iter().collect();
+        stats.sort_by_key(|(name, _)| *name);
+        for (name, help, value) in COUNTERS {
+            counter(&mut text, name, help);
+            for (ty, s) in stats.iter() {
+                let _ = writeln!(text, "{}{{type=\"{}\"}} {}", name, escape(ty), value(s));
+            }
+        }
+    }
+
+    if let Some(acks) = acks {
+        let mut rtts: Vec<f32> = acks.clients().filter_map(|(_, info)| info.rtt()).collect();
+        rtts.sort_by(|a, b| a.total_cmp(b));
+        let _ = writeln!(
+            text,
+            "# HELP pigeon_rtt_seconds Round trip time of the clients."
+        );
+        let _ = writeln!(text, "# TYPE pigeon_rtt_seconds summary");
+        for q in [0.5, 0.9, 0.99] {
+            if let Some(rtt) = percentile(&rtts, q) {
+                let _ = writeln!(text, "pigeon_rtt_seconds{{quantile=\"{}\"}} {}", q, rtt);
+            }
+        }
+        let _ = writeln!(text, "pigeon_rtt_seconds_count {}", rtts.len());
+
+        let losses: Vec<f32> = acks.clients().map(|(_, info)| info.loss()).collect();
+        if !losses.is_empty() {
+            gauge(
+                &mut text,
+                "pigeon_packet_loss",
+                "Average packet loss of the clients.",
+            );
+            let loss = losses.iter().sum::<f32>() / losses.len() as f32;
+            let _ = writeln!(text, "pigeon_packet_loss {}", loss);
+        }
+    }
+
+    *metrics.text.lock().unwrap() = text;
+}
+
+/// Writes the header of a gauge.
+fn gauge(text: &mut String, name: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+}
+
+/// Writes the header of a counter.
+fn counter(text: &mut String, name: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} counter", name);
+}
+
+/// Gets the `q` percentile of the sorted `values`.
+fn percentile(values: &[f32], q: f32) -> Option<f32> {
+    let last = values.len().checked_sub(1)?;
+    values.get((last as f32 * q).round() as usize).copied()
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,314 @@
+//! Transforming the bytes of messages on their way to and from the network.
+//!
+//! A [`Middleware`] sees the encoded bytes of every piped message that is sent or received, and
+//! can replace them. Middlewares are stacked in the [`NetPipeline`] of the app with
+//! [`add_middleware`](crate::AppExt::add_middleware), so compression, encryption, metrics and
+//! logging can be combined instead of being built into each other:
+//!
+//! ```ignore
+//! app.add_middleware(LogBytes)
+//!     .add_middleware(Deflate::default())
+//!     .add_middleware(ChaCha::new(key))
+//!     .register_piped_msg::<Chat>(&mut table, Transport::TCP);
+//! ```
+//!
+//! A sent message goes through the middlewares in the order they were added, and a received
+//! message in the reverse order, so the server and clients should add the same middlewares in
+//! the same order. Every app has its own pipeline.
+//!
+//! `carrier-pigeon` doesn't expose the bytes of the connection itself, so only messages that are
+//! registered with [`register_piped_msg`](crate::AppExt::register_piped_msg), and sent and
+//! received through [`PipedNet`], go through the pipeline:
+//!
+//! ```ignore
+//! fn chat(mut net: PipedNet) {
+//!     for (cid, msg) in net.recv::<Chat>() {
+//!         net.broadcast(&msg);
+//!     }
+//! }
+//! ```
+//!
+//! A received message whose bytes a middleware fails to transform, or that fails to decode, is
+//! dropped with a warning.
+
+use crate::limits::NetLimits;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use carrier_pigeon::{CId, Client, Server};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+
+/// The error type of a [`Middleware`].
+pub type MiddlewareError = Box<dyn Error + Send + Sync>;
+
+/// The message that is being transformed by a [`Middleware`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct MiddlewareCtx {
+    /// The type name of the message.
+    pub type_name: &'static str,
+    /// The client that the message is sent to or received from, or 0 for the server.
+    pub cid: CId,
+}
+
+/// A transformation of the bytes of piped messages.
+///
+/// See the [module docs](self).
+pub trait Middleware: Send + Sync + 'static {
+    /// Transforms the `bytes` of a message that is about to be sent.
+    ///
+    /// Returns the bytes unchanged by default.
+    fn on_send(&mut self, bytes: Vec<u8>, ctx: &MiddlewareCtx) -> Result<Vec<u8>, MiddlewareError> {
+        let _ = ctx;
+        Ok(bytes)
+    }
+
+    /// Transforms the `bytes` of a received message, undoing [`on_send`](Self::on_send).
+    ///
+    /// Returns the bytes unchanged by default.
+    fn on_recv(&mut self, bytes: Vec<u8>, ctx: &MiddlewareCtx) -> Result<Vec<u8>, MiddlewareError> {
+        let _ = ctx;
+        Ok(bytes)
+    }
+}
+
+/// A [`Middleware`] that logs the size of every piped message, at the debug level.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct LogBytes;
+
+impl Middleware for LogBytes {
+    fn on_send(&mut self, bytes: Vec<u8>, ctx: &MiddlewareCtx) -> Result<Vec<u8>, MiddlewareError> {
+        debug!(
+            "Sending {} ({} bytes) to {}",
+            ctx.type_name,
+            bytes.len(),
+            ctx.cid
+        );
+        Ok(bytes)
+    }
+
+    fn on_recv(&mut self, bytes: Vec<u8>, ctx: &MiddlewareCtx) -> Result<Vec<u8>, MiddlewareError> {
+        debug!(
+            "Received {} ({} bytes) from {}",
+            ctx.type_name,
+            bytes.len(),
+            ctx.cid
+        );
+        Ok(bytes)
+    }
+}
+
+/// The stack of [`Middleware`]s of this app.
+///
+/// Middlewares are added with [`add_middleware`](crate::AppExt::add_middleware).
+#[derive(Resource, Default)]
+pub struct NetPipeline {
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl Debug for NetPipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetPipeline")
+            .field("middlewares", &self.middlewares.len())
+            .finish()
+    }
+}
+
+impl NetPipeline {
+    /// Adds `middleware` to the end of the stack.
+    pub fn push(&mut self, middleware: impl Middleware) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    /// The number of middlewares.
+    pub fn len(&self) -> usize {
+        self.middlewares.len()
+    }
+
+    /// Whether there are no middlewares.
+    pub fn is_empty(&self) -> bool {
+        self.middlewares.is_empty()
+    }
+
+    /// Passes the `bytes` of a sent message through every middleware, in the order they were
+    /// added.
+    pub fn on_send(
+        &mut self,
+        bytes: Vec<u8>,
+        ctx: &MiddlewareCtx,
+    ) -> Result<Vec<u8>, MiddlewareError> {
+        self.middlewares
+            .iter_mut()
+            .try_fold(bytes, |bytes, m| m.on_send(bytes, ctx))
+    }
+
+    /// Passes the `bytes` of a received message through every middleware, in the reverse order
+    /// they were added.
+    pub fn on_recv(
+        &mut self,
+        bytes: Vec<u8>,
+        ctx: &MiddlewareCtx,
+    ) -> Result<Vec<u8>, MiddlewareError> {
+        self.middlewares
+            .iter_mut()
+            .rev()
+            .try_fold(bytes, |bytes, m| m.on_recv(bytes, ctx))
+    }
+}
+
+/// The message that piped messages of type `T` are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct PipedMsg<T> {
+    bytes: Vec<u8>,
+    #[serde(skip)]
+    _pd: PhantomData<T>,
+}
+
+/// Sends and receives piped messages through the [`NetPipeline`].
+///
+/// Works on both the client and the server; the methods that don't apply to this instance do
+/// nothing.
+#[derive(SystemParam)]
+pub struct PipedNet<'w, 's> {
+    pipeline: ResMut<'w, NetPipeline>,
+    client: Option<Res<'w, Client>>,
+    server: Option<Res<'w, Server>>,
+    limits: Option<Res<'w, NetLimits>>,
+    #[system_param(ignore)]
+    _pd: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> Debug for PipedNet<'w, 's> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipedNet")
+            .field("pipeline", &*self.pipeline)
+            .field("client", &self.client.is_some())
+            .field("server", &self.server.is_some())
+            .finish()
+    }
+}
+
+impl<'w, 's> PipedNet<'w, 's> {
+    /// Sends `msg` to the server.
+    ///
+    /// This does nothing if this instance is not a client.
+    pub fn send<T: Serialize + Any + Send + Sync>(&mut self, msg: &T) {
+        let client = match self.client.as_deref() {
+            Some(client) => client,
+            None => return,
+        };
+        if let Some(piped) = encode(&mut self.pipeline, msg, 0) {
+            if let Err(e) = client.send(&piped) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// Sends `msg` to the client `cid`.
+    ///
+    /// This does nothing if this instance is not a server.
+    pub fn send_to<T: Serialize + Any + Send + Sync>(&mut self, cid: CId, msg: &T) {
+        let server = match self.server.as_deref() {
+            Some(server) => server,
+            None => return,
+        };
+        if let Some(piped) = encode(&mut self.pipeline, msg, cid) {
+            if let Err(e) = server.send_to(cid, &piped) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// Sends `msg` to every client.
+    ///
+    /// The message goes through the pipeline once for each client, so a middleware can
+    /// transform it differently per client, such as encrypting it with the client's key.
+    ///
+    /// This does nothing if this instance is not a server.
+    pub fn broadcast<T: Serialize + Any + Send + Sync>(&mut self, msg: &T) {
+        let server = match self.server.as_deref() {
+            Some(server) => server,
+            None => return,
+        };
+        for cid in server.cids() {
+            if let Some(piped) = encode(&mut self.pipeline, msg, cid) {
+                if let Err(e) = server.send_to(cid, &piped) {
+                    error!("{}", e);
+                }
+            }
+        }
+    }
+
+    /// Gets the received messages of type `T`, along with the [`CId`] of the sender.
+    pub fn recv<T: DeserializeOwned + Any + Send + Sync>(&mut self) -> Vec<(CId, T)> {
+        let received: Vec<(CId, Vec<u8>)> = match (self.client.as_deref(), self.server.as_deref()) {
+            (Some(client), _) => client
+                .recv::<PipedMsg<T>>()
+                .map(|m| (m.cid, m.bytes.clone()))
+                .collect(),
+            (None, Some(server)) => server
+                .recv::<PipedMsg<T>>()
+                .map(|m| (m.cid, m.bytes.clone()))
+                .collect(),
+            (None, None) => return vec![],
+        };
+        let default_limits = NetLimits::default();
+        let limits = self.limits.as_deref().unwrap_or(&default_limits);
+        received
+            .into_iter()
+            .filter_map(|(cid, bytes)| {
+                let ctx = MiddlewareCtx {
+                    type_name: std::any::type_name::<T>(),
+                    cid,
+                };
+                let decoded = self
+                    .pipeline
+                    .on_recv(bytes, &ctx)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| limits.decode(&bytes));
+                match decoded {
+                    Ok(msg) => Some((cid, msg)),
+                    Err(e) => {
+                        warn!(
+                            "Dropped {} from {} that the pipeline failed to decode: {}",
+                            ctx.type_name, cid, e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Encodes `msg` to be sent to `cid`, passing it through `pipeline`.
+fn encode<T: Serialize + Any + Send + Sync>(
+    pipeline: &mut NetPipeline,
+    msg: &T,
+    cid: CId,
+) -> Option<PipedMsg<T>> {
+    let ctx = MiddlewareCtx {
+        type_name: std::any::type_name::<T>(),
+        cid,
+    };
+    let bytes = match bincode::serialize(msg) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to encode {}: {}", ctx.type_name, e);
+            return None;
+        }
+    };
+    match pipeline.on_send(bytes, &ctx) {
+        Ok(bytes) => Some(PipedMsg {
+            bytes,
+            _pd: PhantomData,
+        }),
+        Err(e) => {
+            error!("The pipeline failed to encode {}: {}", ctx.type_name, e);
+            None
+        }
+    }
+}
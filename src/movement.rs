@@ -0,0 +1,166 @@
+//! A simple server-authoritative kinematic character controller.
+//!
+//! This wires the [`input`](crate::input) module together into the usual architecture for
+//! moving characters:
+//! - The client sends a [`MoveInput`] every tick, and immediately moves its own character with
+//!   it (prediction).
+//! - The server moves each character with the inputs of the client that owns it, and sends the
+//!   resulting position back to that client, along with the tick of the last input it used.
+//! - The client resets its character to that position and re-applies the inputs the server
+//!   hasn't used yet (reconciliation).
+//!
+//! Set it up with [`add_movement`](crate::AppExt::add_movement). On the server, give each
+//! character a [`KinematicController`] and an [`OwnedBy`]; only the inputs of the owning client
+//! are queued on it, in its [`OwnedInputs<MoveInput>`]. On the client, give its own character a
+//! [`KinematicController`] and a [`LocalPlayer`], and write the movement direction to
+//! `LocalInput<MoveInput>` every frame. Other clients' characters can be synced with a regular
+//! `NetComp<Transform>`; use a [`NetSendTo`](crate::NetSendTo) that excludes the owning client,
+//! so it doesn't fight the prediction.
+//!
+//! Every input moves a character by exactly [`MovementConfig::step`] seconds, so the client and
+//! server get the same result no matter their frame rates. The server applies at most one input
+//! of every client per tick, so both should run at `1 / step` ticks per second; a client that
+//! sends more inputs than that only builds up a queue on the server, instead of moving faster.
+
+use crate::input::{InputHistory, LocalInput, OwnedInputs, TickedInput};
+use crate::player::OwnedBy;
+use bevy::prelude::*;
+use carrier_pigeon::{Client, Server};
+use serde::{Deserialize, Serialize};
+
+/// The input of a [`KinematicController`].
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug, Default)]
+pub struct MoveInput {
+    /// The direction to move in.
+    ///
+    /// This is clamped to a length of 1, so clients can't move faster than the speed.
+    pub dir: Vec3,
+}
+
+/// A character that moves at a constant speed in the direction of its [`MoveInput`].
+#[derive(Component, Copy, Clone, PartialEq, Debug)]
+pub struct KinematicController {
+    /// The speed in units per second.
+    pub speed: f32,
+}
+
+impl KinematicController {
+    /// Creates a new controller that moves at `speed` units per second.
+    pub fn new(speed: f32) -> Self {
+        KinematicController { speed }
+    }
+
+    /// Moves `transform` using `input` for one step of `step` seconds.
+    pub fn apply(&self, input: &MoveInput, step: f32, transform: &mut Transform) {
+        if input.dir.is_finite() {
+            transform.translation += input.dir.clamp_length_max(1.0) * self.speed * step;
+        }
+    }
+}
+
+/// A marker for the character controlled by this client.
+#[derive(Component, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct LocalPlayer;
+
+/// The configuration for the [`KinematicController`]s.
+#[derive(Resource, Copy, Clone, PartialEq, Debug)]
+pub struct MovementConfig {
+    /// The time in seconds that every input moves a character for. Defaults to 1/60.
+    pub step: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig { step: 1.0 / 60.0 }
+    }
+}
+
+/// The message that the server sends to the owning client of a character.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub(crate) struct MoveState {
+    /// The tick of the last input that was applied.
+    tick: u32,
+    translation: Vec3,
+}
+
+/// Moves the characters on the server with the oldest queued input of the clients that own them,
+/// and sends the results back.
+///
+/// Characters without [`OwnedInputs<MoveInput>`] get them here, so they start moving on the next
+/// tick.
+#[allow(clippy::type_complexity)]
+pub fn server_move(
+    mut commands: Commands,
+    server: Option<Res<Server>>,
+    config: Res<MovementConfig>,
+    mut q: Query<(
+        Entity,
+        &OwnedBy,
+        &KinematicController,
+        Option<&mut OwnedInputs<MoveInput>>,
+        &mut Transform,
+    )>,
+) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    for (entity, owned_by, controller, inputs, mut transform) in q.iter_mut() {
+        let mut inputs = match inputs {
+            Some(inputs) => inputs,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(OwnedInputs::<MoveInput>::default());
+                continue;
+            }
+        };
+        let cid = owned_by.0;
+        // One input per tick, so a client can't move faster by sending a burst of inputs. The
+        // rest stay queued, up to `max_queued`, which drops the oldest ones.
+        if let Some(TickedInput { tick, input }) = inputs.pop() {
+            controller.apply(&input, config.step, &mut transform);
+            let msg = MoveState {
+                tick,
+                translation: transform.translation,
+            };
+            if let Err(e) = server.send_to(cid, &msg) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+/// Predicts the movement of the [`LocalPlayer`] with the current input, and reconciles it with
+/// the positions received from the server.
+///
+/// This runs in `PostUpdate`, so the `LocalInput<MoveInput>` can be written in `Update`.
+pub fn client_move(
+    client: Option<Res<Client>>,
+    config: Res<MovementConfig>,
+    history: Res<InputHistory<MoveInput>>,
+    input: Res<LocalInput<MoveInput>>,
+    mut q: Query<(&KinematicController, &mut Transform), With<LocalPlayer>>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    let state = client
+        .recv::<MoveState>()
+        .map(|msg| *msg)
+        .max_by_key(|state| state.tick);
+
+    for (controller, mut transform) in q.iter_mut() {
+        if let Some(state) = state {
+            // Replay the inputs that the server hasn't applied yet on top of its position.
+            transform.translation = state.translation;
+            for TickedInput { input, .. } in history.after(state.tick) {
+                controller.apply(input, config.step, &mut transform);
+            }
+        }
+        // The input for this tick is only added to the history when it is sent, at the end of
+        // the frame.
+        controller.apply(&input.0, config.step, &mut transform);
+    }
+}
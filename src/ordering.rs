@@ -0,0 +1,94 @@
+//! Applying the updates of an entity's components in the order they were sent.
+//!
+//! Every component type is sent as its own message, and received independently of the others. An
+//! update of one component can then be applied after a newer update of another component of the
+//! same entity, such as a door that is opened by a newer `Interaction` before an older `Locked`
+//! arrives. Entities with an [`OrderedUpdates`] component are kept in order instead:
+//!
+//! - Every update is stamped with the [`NetTick`](crate::NetTick) of the sender.
+//! - The receiver drops updates that were sent before the newest update it has applied to the
+//!   entity, for any of its components.
+//!
+//! ```ignore
+//! commands.spawn((
+//!     NetEntity::new(id),
+//!     NetComp::<Door>::server_to_all(),
+//!     NetComp::<Locked>::server_to_all(),
+//!     OrderedUpdates::default(),
+//! ));
+//! ```
+//!
+//! Updates sent in the same frame are never dropped, and a dropped component catches up with its
+//! next change. Only the updates of [`NetCompMsg`](crate::sync::NetCompMsg)s are ordered; reduced
+//! [LOD](crate::lod) updates, [snapshots](crate::snapshot) and updates sent over
+//! [`UnreliableAcked`](crate::Channel::UnreliableAcked) are applied as before. The component is
+//! only needed on the receiving side.
+
+use crate::sync::SendTick;
+use bevy::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Makes the updates of every component of this entity apply in the order they were sent.
+///
+/// See the [module docs](self).
+#[derive(Component, Debug, Default)]
+pub struct OrderedUpdates {
+    /// The tick of the newest applied update. This is atomic so the receive systems of different
+    /// components can share it while running in parallel.
+    newest: AtomicU32,
+}
+
+impl OrderedUpdates {
+    /// The tick of the newest update that was applied to the entity, or 0 if there is none.
+    pub fn newest(&self) -> u32 {
+        self.newest.load(Ordering::Relaxed)
+    }
+
+    /// Whether an update sent on `tick` is in order.
+    ///
+    /// Updates without a tick are always in order. This doesn't change the newest tick, so an
+    /// update that is rejected later, like by a validator, doesn't drop the updates after it;
+    /// [`commit`](Self::commit) it once it is applied.
+    pub(crate) fn check(&self, tick: Option<SendTick>) -> bool {
+        match tick {
+            Some(SendTick { tick }) => self.newest() <= tick,
+            None => true,
+        }
+    }
+
+    /// Makes an applied update sent on `tick` the newest, if it is newer.
+    pub(crate) fn commit(&self, tick: Option<SendTick>) {
+        if let Some(SendTick { tick }) = tick {
+            self.newest.fetch_max(tick, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(tick: u32) -> Option<SendTick> {
+        Some(SendTick { tick })
+    }
+
+    #[test]
+    fn check_doesnt_advance_the_newest_tick() {
+        let ordered = OrderedUpdates::default();
+        assert!(ordered.check(tick(5)));
+        assert_eq!(ordered.newest(), 0);
+        assert!(ordered.check(tick(3)));
+    }
+
+    #[test]
+    fn committed_ticks_drop_older_updates() {
+        let ordered = OrderedUpdates::default();
+        ordered.commit(tick(5));
+        assert!(!ordered.check(tick(4)));
+        assert!(ordered.check(tick(5)));
+        assert!(ordered.check(None));
+
+        ordered.commit(tick(2));
+        assert_eq!(ordered.newest(), 5);
+    }
+}
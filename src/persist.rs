@@ -0,0 +1,131 @@
+//! Saving the networked world to disk and loading it again.
+//!
+//! A dedicated server with a persistent world can save all
+//! [`NetEntity`](crate::sync::NetEntity)s with their synced components, and load them when it
+//! starts again. This uses the same message types as networking, so every component that is
+//! synced with [`sync_comp`](crate::AppExt::sync_comp) is saved, and no other serialization
+//! needs to be written.
+//!
+//! ```ignore
+//! app.load_world_on_startup("world.bin");
+//!
+//! fn autosave(world: &mut World) {
+//!     if let Err(e) = save_world_file(world, "world.bin") {
+//!         error!("Failed to save the world: {}", e);
+//!     }
+//! }
+//! ```
+//!
+//! Like with [snapshots](crate::snapshot), loaded entities only get their
+//! [`NetEntity`](crate::sync::NetEntity) and the synced components, so add the
+//! [`NetComp`](crate::sync::NetComp)s you need when you get the [`WorldLoaded`] event.
+//! Components are identified by the type name of their message type, so renaming or moving a
+//! message type makes its saved components unknown; they are skipped with a warning.
+
+use crate::snapshot::{apply_snapshot, build_snapshot, SnapshotRegistry, WorldSnapshot};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The version of the save format, which is changed whenever it changes.
+const FORMAT_VERSION: u32 = 2;
+
+/// The saved world, as it is written to disk.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+struct SavedWorld {
+    version: u32,
+    snapshot: WorldSnapshot,
+}
+
+/// The file to load the world from at startup.
+///
+/// This is added by [`load_world_on_startup`](crate::AppExt::load_world_on_startup).
+#[derive(Resource, Clone, Eq, PartialEq, Debug)]
+pub struct WorldFile {
+    /// The path of the file.
+    pub path: PathBuf,
+}
+
+/// An event that is sent when the world was loaded at startup.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WorldLoaded {
+    /// The entities that were spawned for the saved entities.
+    pub spawned: Vec<Entity>,
+}
+
+/// Encodes all [`NetEntity`](crate::sync::NetEntity)s in `world` with their synced components.
+pub fn save_world(world: &mut World) -> io::Result<Vec<u8>> {
+    world.init_resource::<SnapshotRegistry>();
+    let saved = SavedWorld {
+        version: FORMAT_VERSION,
+        snapshot: build_snapshot(world, None, None),
+    };
+    bincode::serialize(&saved).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Decodes a world encoded by [`save_world`] and applies it to `world`.
+///
+/// Entities whose [`NetEntity`](crate::sync::NetEntity) already exists get the saved
+/// components; the others are spawned, replacing any entity with the same id but another
+/// generation. Returns the spawned entities.
+pub fn load_world(world: &mut World, bytes: &[u8]) -> io::Result<Vec<Entity>> {
+    let saved: SavedWorld =
+        bincode::deserialize(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if saved.version != FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the world was saved with format version {}, but this is version {}",
+                saved.version, FORMAT_VERSION
+            ),
+        ));
+    }
+    world.init_resource::<SnapshotRegistry>();
+    Ok(apply_snapshot(world, saved.snapshot))
+}
+
+/// Saves all [`NetEntity`](crate::sync::NetEntity)s in `world` with their synced components to
+/// the file at `path`.
+///
+/// The file is written next to `path` first and then moved over it, so a crash while saving
+/// doesn't corrupt the previous save.
+pub fn save_world_file(world: &mut World, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let bytes = save_world(world)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+/// Loads a world saved by [`save_world_file`] from the file at `path` into `world`.
+///
+/// Returns the spawned entities.
+pub fn load_world_file(world: &mut World, path: impl AsRef<Path>) -> io::Result<Vec<Entity>> {
+    let bytes = fs::read(path)?;
+    load_world(world, &bytes)
+}
+
+/// Loads the world from the [`WorldFile`], if it exists.
+pub fn load_world_at_startup(world: &mut World) {
+    let path = match world.get_resource::<WorldFile>() {
+        Some(file) => file.path.clone(),
+        None => return,
+    };
+    match load_world_file(world, &path) {
+        Ok(spawned) => {
+            info!(
+                "Loaded the world from {}, spawning {} entities",
+                path.display(),
+                spawned.len()
+            );
+            world.send_event(WorldLoaded { spawned });
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("{} doesn't exist; starting a new world", path.display());
+        }
+        Err(e) => error!("Failed to load the world from {}: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,213 @@
+//! Finding the player entity of a client.
+//!
+//! Give the entity that represents a client, such as its avatar, an [`OwnedBy`] component on the
+//! server. The [`ConnectedPlayers`] resource then maps the client to that entity and back, so
+//! systems handling client messages can find the sender's entity right away.
+//!
+//! ```ignore
+//! fn handle_chat(server: Res<Server>, players: Res<ConnectedPlayers>, q: Query<&Name>) {
+//!     for msg in server.recv::<Chat>() {
+//!         if let Some(name) = players.entity(msg.cid).and_then(|e| q.get(e).ok()) {
+//!             info!("{}: {}", name, msg.text);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! The server can also spawn the player entities itself with
+//! [`spawn_players`](crate::AppExt::spawn_players). It then spawns the bundle returned by the
+//! factory for every new client, with a new [`NetEntity`] and an [`OwnedBy`], and despawns it
+//! once the client disconnects.
+//!
+//! ```ignore
+//! app.spawn_players(|cid| PlayerBundle::new(cid));
+//! ```
+
+use crate::ids::NetIds;
+use crate::session::Sessions;
+use crate::spawn::PROVISIONAL_BIT;
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::{CId, Server};
+use std::collections::hash_map::RandomState;
+use std::fmt::{Debug, Formatter};
+use std::hash::BuildHasher;
+
+/// A component that marks an entity as the player entity of client `0`.
+///
+/// A client should own at most one entity; if several entities are owned by the same client, the
+/// most recently added one is used by [`ConnectedPlayers`].
+#[derive(Component, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub struct OwnedBy(pub CId);
+
+/// A mapping from clients to their player entities.
+///
+/// This is kept up to date by the [`ServerPlugin`](crate::ServerPlugin) from the [`OwnedBy`]
+/// components. Clients are removed when they disconnect if the
+/// [`DisconnectPlugin`](crate::DisconnectPlugin) is added.
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct ConnectedPlayers {
+    entities: HashMap<CId, Entity>,
+    cids: HashMap<Entity, CId>,
+}
+
+impl ConnectedPlayers {
+    /// Gets the player entity of client `cid`.
+    pub fn entity(&self, cid: CId) -> Option<Entity> {
+        self.entities.get(&cid).copied()
+    }
+
+    /// Gets the client that owns `entity`.
+    pub fn cid(&self, entity: Entity) -> Option<CId> {
+        self.cids.get(&entity).copied()
+    }
+
+    /// Gets all clients and their player entities.
+    pub fn iter(&self) -> impl Iterator<Item = (CId, Entity)> + '_ {
+        self.entities.iter().map(|(cid, entity)| (*cid, *entity))
+    }
+
+    /// The number of clients with a player entity.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether no client has a player entity.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Removes client `cid`, returning its player entity.
+    ///
+    /// You should call this when a client disconnects, unless the
+    /// [`DisconnectPlugin`](crate::DisconnectPlugin) is added.
+    pub fn remove_client(&mut self, cid: CId) -> Option<Entity> {
+        let entity = self.entities.remove(&cid)?;
+        self.cids.remove(&entity);
+        Some(entity)
+    }
+
+    fn insert(&mut self, cid: CId, entity: Entity) {
+        if let Some(old) = self.cids.insert(entity, cid) {
+            self.entities.remove(&old);
+        }
+        if let Some(old) = self.entities.insert(cid, entity) {
+            if old != entity {
+                self.cids.remove(&old);
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(cid) = self.cids.remove(&entity) {
+            self.entities.remove(&cid);
+        }
+    }
+}
+
+/// Keeps the [`ConnectedPlayers`] up to date.
+pub fn update_connected_players(
+    mut players: ResMut<ConnectedPlayers>,
+    q: Query<(Entity, &OwnedBy), Changed<OwnedBy>>,
+    removed: RemovedComponents<OwnedBy>,
+) {
+    for entity in removed.iter() {
+        players.remove(entity);
+    }
+    for (entity, owned_by) in q.iter() {
+        players.insert(owned_by.0, entity);
+    }
+}
+
+/// A function that spawns the player entity of a client, returning the entity.
+type SpawnFn = Box<dyn Fn(&mut Commands, CId) -> Entity + Send + Sync>;
+
+/// Spawns a player entity for every client that connects to the server.
+///
+/// This is added by [`spawn_players`](crate::AppExt::spawn_players).
+#[derive(Resource)]
+pub struct PlayerFactory {
+    spawn: SpawnFn,
+    /// The player entities that were spawned, by client.
+    spawned: HashMap<CId, Entity>,
+}
+
+impl Debug for PlayerFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlayerFactory")
+            .field("spawned", &self.spawned)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PlayerFactory {
+    /// Creates a factory that spawns the bundle returned by `factory` for each new client.
+    pub fn new<B, F>(factory: F) -> Self
+    where
+        B: Bundle,
+        F: Fn(CId) -> B + Send + Sync + 'static,
+    {
+        PlayerFactory {
+            spawn: Box::new(move |commands, cid| commands.spawn(factory(cid)).id()),
+            spawned: HashMap::default(),
+        }
+    }
+
+    /// Moves the player entity of client `old` to client `new`, when `new` resumes its session.
+    pub(crate) fn rekey(&mut self, old: CId, new: CId) {
+        if let Some(entity) = self.spawned.remove(&old) {
+            self.spawned.insert(new, entity);
+        }
+    }
+}
+
+/// Creates a new authoritative [`NetEntity`] id for the player entity of client `cid`.
+fn player_id(cid: CId) -> NetId {
+    RandomState::new().hash_one(cid) as NetId & !PROVISIONAL_BIT
+}
+
+/// Spawns the player entities of new clients, and despawns the ones of disconnected clients.
+///
+/// The player entities of clients whose [session](crate::session) is suspended are kept.
+pub fn spawn_players(
+    mut commands: Commands,
+    server: Option<Res<Server>>,
+    sessions: Option<Res<Sessions>>,
+    mut factory: ResMut<PlayerFactory>,
+    mut ids: Option<ResMut<NetIds>>,
+) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let cids: Vec<_> = server.cids().collect();
+
+    let factory = &mut *factory;
+    factory.spawned.retain(|cid, entity| {
+        let connected = cids.contains(cid);
+        let suspended = sessions.as_ref().is_some_and(|s| s.is_suspended(*cid));
+        if !connected && !suspended {
+            if let Some(entity) = commands.get_entity(*entity) {
+                debug!("Despawning the player entity of client {}", cid);
+                entity.despawn_recursive();
+            }
+        }
+        connected || suspended
+    });
+    for cid in cids {
+        if factory.spawned.contains_key(&cid) {
+            continue;
+        }
+        let entity = (factory.spawn)(&mut commands, cid);
+        let id = match ids.as_mut() {
+            Some(ids) => ids.alloc(),
+            None => player_id(cid),
+        };
+        commands
+            .entity(entity)
+            .insert((NetEntity::new(id), OwnedBy(cid)));
+        debug!("Spawned the player entity of client {}", cid);
+        factory.spawned.insert(cid, entity);
+    }
+}
@@ -0,0 +1,152 @@
+//! Sending far away entities less often.
+//!
+//! With many players, sending every entity to every client each frame doesn't scale, and a
+//! client rarely needs to know exactly where something on the other side of the map is. With
+//! [`prioritize_by_distance`](crate::AppExt::prioritize_by_distance), the server weighs every
+//! entity by its distance to each client's viewpoint:
+//!
+//! - Entities within [`near`](DistancePriority::near) are sent every frame.
+//! - Entities past [`far`](DistancePriority::far) are sent every
+//!   [`max_interval`](DistancePriority::max_interval) frames.
+//! - In between, the interval grows linearly with the distance.
+//!
+//! Changes of a component using change detection are held until the entity is next sent to the
+//! client, the same way as with the [`congestion`](crate::congestion) send rates, which this
+//! combines with.
+//!
+//! A client's viewpoint is the [`GlobalTransform`] of its player entity in the
+//! [`ConnectedPlayers`], unless it is set by hand with
+//! [`set_viewpoint`](DistancePriority::set_viewpoint), such as for a spectator camera. Entities
+//! without a [`GlobalTransform`], and clients without a viewpoint, are sent every frame.
+
+use crate::player::ConnectedPlayers;
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::CId;
+
+/// The distances at which entities are sent less often, and the state to do so.
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct DistancePriority {
+    /// The distance within which entities are sent every frame. Defaults to 20.
+    pub near: f32,
+    /// The distance from which entities are sent every `max_interval` frames. Defaults to 200.
+    pub far: f32,
+    /// The most frames between sending an entity. Defaults to 10.
+    pub max_interval: u32,
+    /// The frame count, which staggers the entities that are sent.
+    frame: u64,
+    /// The viewpoints that were set by hand.
+    overrides: HashMap<CId, Vec3>,
+    /// The viewpoint of every client this frame.
+    viewpoints: HashMap<CId, Vec3>,
+    /// The position of every entity, by [`NetEntity`] id, this frame.
+    positions: HashMap<NetId, Vec3>,
+}
+
+impl Default for DistancePriority {
+    fn default() -> Self {
+        DistancePriority::new(20.0, 200.0, 10)
+    }
+}
+
+impl DistancePriority {
+    /// Creates a new [`DistancePriority`] that sends entities within `near` every frame, and
+    /// entities past `far` every `max_interval` frames.
+    pub fn new(near: f32, far: f32, max_interval: u32) -> Self {
+        DistancePriority {
+            near,
+            far,
+            max_interval,
+            frame: 0,
+            overrides: HashMap::default(),
+            viewpoints: HashMap::default(),
+            positions: HashMap::default(),
+        }
+    }
+
+    /// Sets the viewpoint of client `cid` to `pos`, instead of the position of its player
+    /// entity.
+    pub fn set_viewpoint(&mut self, cid: CId, pos: Vec3) {
+        self.overrides.insert(cid, pos);
+    }
+
+    /// Goes back to using the position of the player entity as the viewpoint of client `cid`.
+    pub fn clear_viewpoint(&mut self, cid: CId) {
+        self.overrides.remove(&cid);
+    }
+
+    /// Gets the viewpoint of client `cid` this frame.
+    pub fn viewpoint(&self, cid: CId) -> Option<Vec3> {
+        self.viewpoints.get(&cid).copied()
+    }
+
+    /// The distance between the entity with id `id` and the viewpoint of client `cid` this
+    /// frame.
+    pub fn distance(&self, cid: CId, id: NetId) -> Option<f32> {
+        let viewpoint = self.viewpoints.get(&cid)?;
+        let pos = self.positions.get(&id)?;
+        Some(viewpoint.distance(*pos))
+    }
+
+    /// The number of frames between sending the entity with id `id` to client `cid`.
+    pub fn interval(&self, cid: CId, id: NetId) -> u32 {
+        let dist = match self.distance(cid, id) {
+            Some(dist) => dist,
+            None => return 1,
+        };
+        if dist <= self.near {
+            return 1;
+        }
+        let t = ((dist - self.near) / (self.far - self.near).max(f32::EPSILON)).min(1.0);
+        1 + (t * self.max_interval.saturating_sub(1) as f32).round() as u32
+    }
+
+    /// Whether the entity with id `id` is sent to client `cid` this frame.
+    ///
+    /// The entities are staggered by their id, so that the far ones aren't all sent in the same
+    /// frame.
+    // The cast is only needed when `NetId` is a `u128`.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn is_due(&self, cid: CId, id: NetId) -> bool {
+        self.frame
+            .wrapping_add(id as u64)
+            .is_multiple_of(self.interval(cid, id) as u64)
+    }
+
+    /// Whether any client has a viewpoint this frame.
+    pub(crate) fn is_active(&self) -> bool {
+        !self.viewpoints.is_empty()
+    }
+}
+
+/// Updates the viewpoints of the clients and the positions of the entities in the
+/// [`DistancePriority`].
+pub fn update_priorities(
+    mut priority: ResMut<DistancePriority>,
+    players: Option<Res<ConnectedPlayers>>,
+    transforms: Query<&GlobalTransform>,
+    q: Query<(&NetEntity, &GlobalTransform)>,
+) {
+    let priority = &mut *priority;
+    priority.frame = priority.frame.wrapping_add(1);
+
+    priority.viewpoints.clone_from(&priority.overrides);
+    for (cid, entity) in players.iter().flat_map(|players| players.iter()) {
+        if let Ok(transform) = transforms.get(entity) {
+            priority
+                .viewpoints
+                .entry(cid)
+                .or_insert_with(|| transform.translation());
+        }
+    }
+
+    priority.positions.clear();
+    if priority.viewpoints.is_empty() {
+        return;
+    }
+    priority.positions.extend(
+        q.iter()
+            .map(|(net_e, transform)| (net_e.id, transform.translation())),
+    );
+}
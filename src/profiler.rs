@@ -0,0 +1,168 @@
+//! A frame-by-frame profiler for the networking systems.
+//!
+//! With the [`NetProfilerPlugin`] added, every [`comp_send`](crate::app::comp_send) and
+//! [`comp_recv`](crate::app::comp_recv) instance records how many messages it processed, their
+//! estimated size, and how long it took, every frame. The last frames are kept in the
+//! [`NetProfiler`] resource, so when a frame spikes, the synced type that caused it can be found.
+//!
+//! ```ignore
+//! fn dump_on_spike(time: Res<Time>, profiler: Res<NetProfiler>) {
+//!     if time.delta_seconds() > 0.05 {
+//!         profiler.dump("net_profile.csv").unwrap();
+//!     }
+//! }
+//! ```
+
+use crate::app::{client_tick, server_tick};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// A plugin that adds the [`NetProfiler`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetProfilerPlugin {
+    /// The number of frames to keep. Defaults to 300.
+    pub capacity: usize,
+}
+
+impl Default for NetProfilerPlugin {
+    fn default() -> Self {
+        NetProfilerPlugin { capacity: 300 }
+    }
+}
+
+impl Plugin for NetProfilerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(NetProfiler::new(self.capacity))
+            .add_system_to_stage(
+                CoreStage::First,
+                advance_profiler.before(client_tick).before(server_tick),
+            );
+    }
+}
+
+/// The kind of system that was profiled.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub enum ProfiledSystem {
+    /// [`comp_send`](crate::app::comp_send).
+    Send,
+    /// [`comp_recv`](crate::app::comp_recv).
+    Recv,
+}
+
+/// What a single system instance did in a frame.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct ProfileEntry {
+    /// The kind of system.
+    pub system: ProfiledSystem,
+    /// The type name of the synced component.
+    pub type_name: &'static str,
+    /// The number of messages sent or received.
+    pub msgs: u32,
+    /// The estimated size of the messages in bytes.
+    ///
+    /// This is the bincode encoded size of the messages, without the overhead of the transport.
+    pub bytes: u64,
+    /// How long the system took.
+    pub duration: Duration,
+}
+
+/// The profile of a single frame.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct FrameProfile {
+    /// The number of the frame, counting from 0 when the profiler was added.
+    pub frame: u64,
+    /// What each system instance did in the frame.
+    pub entries: Vec<ProfileEntry>,
+}
+
+impl FrameProfile {
+    /// The total time that the profiled systems took in this frame.
+    pub fn duration(&self) -> Duration {
+        self.entries.iter().map(|e| e.duration).sum()
+    }
+}
+
+/// The profiles of the last frames.
+///
+/// This is added by the [`NetProfilerPlugin`].
+#[derive(Resource, Clone, Eq, PartialEq, Debug)]
+pub struct NetProfiler {
+    capacity: usize,
+    frames: VecDeque<FrameProfile>,
+    next_frame: u64,
+}
+
+impl NetProfiler {
+    /// Creates a profiler that keeps the last `capacity` frames.
+    pub fn new(capacity: usize) -> Self {
+        NetProfiler {
+            capacity: capacity.max(1),
+            frames: VecDeque::with_capacity(capacity),
+            next_frame: 0,
+        }
+    }
+
+    /// Gets the kept frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> + '_ {
+        self.frames.iter()
+    }
+
+    /// Gets the current frame.
+    pub fn current(&self) -> Option<&FrameProfile> {
+        self.frames.back()
+    }
+
+    /// Removes all kept frames.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Writes the kept frames to the file at `path` as CSV, one row per entry.
+    pub fn dump(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "frame,system,type,msgs,bytes,micros")?;
+        for frame in self.frames.iter() {
+            for entry in frame.entries.iter() {
+                writeln!(
+                    file,
+                    "{},{:?},\"{}\",{},{},{}",
+                    frame.frame,
+                    entry.system,
+                    entry.type_name.replace('"', "\"\""),
+                    entry.msgs,
+                    entry.bytes,
+                    entry.duration.as_micros()
+                )?;
+            }
+        }
+        file.flush()
+    }
+
+    /// Records `entry` in the current frame.
+    pub(crate) fn record(&mut self, entry: ProfileEntry) {
+        if let Some(frame) = self.frames.back_mut() {
+            frame.entries.push(entry);
+        }
+    }
+
+    /// Starts a new frame, dropping the oldest one if there are too many.
+    fn advance(&mut self) {
+        if self.frames.len() >= self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(FrameProfile {
+            frame: self.next_frame,
+            entries: vec![],
+        });
+        self.next_frame += 1;
+    }
+}
+
+/// Starts a new frame in the [`NetProfiler`].
+pub fn advance_profiler(mut profiler: ResMut<NetProfiler>) {
+    profiler.advance();
+}
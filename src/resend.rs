@@ -0,0 +1,294 @@
+//! Resending important component updates over UDP until they are acknowledged.
+//!
+//! Some components, like health or score, change rarely but must arrive eventually. Sending
+//! them over TCP works, but one lost packet then holds up everything behind it. With
+//! [`Channel::UnreliableAcked`](crate::Channel::UnreliableAcked), updates are sent over UDP, and
+//! the last value sent to each peer is kept and resent every [`ResendConfig::interval`] seconds
+//! until the peer acknowledges it. Only the newest value is ever resent, so the component is
+//! eventually consistent without ever replaying stale values.
+//!
+//! Updates on this channel are never split into fragments.
+
+use crate::disconnect::ClientState;
+use crate::error::{ErrorLog, NetErrorEvent, NetErrorKind, SendErrors};
+use crate::limits::limited;
+use crate::mapping::NetEntityMap;
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::{CId, Client, Server};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::marker::PhantomData;
+
+/// The configuration for resending updates on
+/// [`Channel::UnreliableAcked`](crate::Channel::UnreliableAcked).
+#[derive(Resource, Copy, Clone, PartialEq, Debug)]
+pub struct ResendConfig {
+    /// The time in seconds to wait for an acknowledgement before resending. Defaults to 0.2.
+    pub interval: f32,
+}
+
+impl Default for ResendConfig {
+    fn default() -> Self {
+        ResendConfig { interval: 0.2 }
+    }
+}
+
+/// The message type that updates on
+/// [`Channel::UnreliableAcked`](crate::Channel::UnreliableAcked) are sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct AckedNetCompMsg<M: Any + Send + Sync> {
+    #[serde(with = "crate::varint")]
+    pub(crate) id: NetId,
+    #[serde(with = "crate::varint")]
+    pub(crate) generation: u32,
+    #[serde(with = "crate::varint")]
+    version: u32,
+    #[serde(
+        deserialize_with = "limited",
+        bound(deserialize = "M: Deserialize<'de>")
+    )]
+    pub(crate) msg: M,
+}
+
+/// The acknowledgement of an [`AckedNetCompMsg`].
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct NetCompAck<M: Any + Send + Sync> {
+    #[serde(with = "crate::varint")]
+    id: NetId,
+    #[serde(with = "crate::varint")]
+    version: u32,
+    _pd: PhantomData<M>,
+}
+
+/// An update that hasn't been acknowledged yet.
+#[derive(Clone, Debug)]
+struct Pending<M> {
+    /// The local entity the update is of.
+    entity: Entity,
+    generation: u32,
+    version: u32,
+    msg: M,
+    /// The local time this was last sent at, in seconds.
+    sent: f64,
+}
+
+/// The updates of message type `M` that are waiting for an acknowledgement.
+///
+/// These are keyed by the client they were sent to (or `None` for the server) and the
+/// [`NetEntity`](crate::sync::NetEntity) id.
+#[derive(Resource)]
+pub struct Resends<M: Any + Send + Sync> {
+    next_version: u32,
+    pending: HashMap<(Option<CId>, NetId), Pending<M>>,
+}
+
+impl<M: Any + Send + Sync> Default for Resends<M> {
+    fn default() -> Self {
+        Resends {
+            next_version: 0,
+            pending: HashMap::default(),
+        }
+    }
+}
+
+impl<M: Any + Send + Sync> std::fmt::Debug for Resends<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resends")
+            .field("next_version", &self.next_version)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl<M: Clone + Any + Send + Sync + Serialize> Resends<M> {
+    /// The number of updates waiting for an acknowledgement.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Drops the updates waiting for an acknowledgement from client `cid`.
+    pub fn remove_client(&mut self, cid: CId) {
+        self.pending.retain(|(to, _), _| *to != Some(cid));
+    }
+
+    /// Creates the message for a new update, and keeps it until it is acknowledged by `to`.
+    ///
+    /// This replaces the pending update of an older entity with the same id.
+    fn track(
+        &mut self,
+        to: Option<CId>,
+        entity: Entity,
+        (net_e, msg): (NetEntity, M),
+        now: f64,
+    ) -> AckedNetCompMsg<M> {
+        let version = self.next_version;
+        self.next_version = self.next_version.wrapping_add(1);
+        self.pending.insert(
+            (to, net_e.id),
+            Pending {
+                entity,
+                generation: net_e.generation,
+                version,
+                msg: msg.clone(),
+                sent: now,
+            },
+        );
+        AckedNetCompMsg {
+            id: net_e.id,
+            generation: net_e.generation,
+            version,
+            msg,
+        }
+    }
+
+    /// Sends an update of `net_e` to clients `cids` from the server.
+    pub(crate) fn server_send(
+        &mut self,
+        server: &Server,
+        cids: &[CId],
+        entity: Entity,
+        (net_e, msg): (NetEntity, M),
+        now: f64,
+        errors: &mut SendErrors,
+    ) {
+        for &cid in cids {
+            let msg = self.track(Some(cid), entity, (net_e, msg.clone()), now);
+            if let Err(e) = server.send_to(cid, &msg) {
+                errors.push((Some(cid), e.to_string()));
+            }
+        }
+    }
+
+    /// Sends an update of `net_e` to the server from the client.
+    pub(crate) fn client_send(
+        &mut self,
+        client: &Client,
+        entity: Entity,
+        (net_e, msg): (NetEntity, M),
+        now: f64,
+        errors: &mut SendErrors,
+    ) {
+        let msg = self.track(None, entity, (net_e, msg), now);
+        if let Err(e) = client.send(&msg) {
+            errors.push((None, e.to_string()));
+        }
+    }
+
+    /// Marks the update of the entity with `id` sent to `from` as acknowledged.
+    fn ack(&mut self, from: Option<CId>, id: NetId, version: u32) {
+        if matches!(self.pending.get(&(from, id)), Some(p) if p.version == version) {
+            self.pending.remove(&(from, id));
+        }
+    }
+}
+
+impl<M: Clone + Any + Send + Sync + Serialize> ClientState for Resends<M> {
+    fn remove_client(&mut self, cid: CId) {
+        Resends::remove_client(self, cid);
+    }
+}
+
+/// Resends the updates that haven't been acknowledged in time.
+pub fn resend<M: Clone + Any + Send + Sync + Serialize>(
+    time: Res<Time>,
+    config: Res<ResendConfig>,
+    mut resends: ResMut<Resends<M>>,
+    server: Option<Res<Server>>,
+    client: Option<Res<Client>>,
+    mut ew: EventWriter<NetErrorEvent>,
+    mut error_log: Local<ErrorLog>,
+) {
+    if let Some(server) = server.as_deref() {
+        // Clients that disconnected never acknowledge, so stop sending to them.
+        let cids: HashSet<CId> = server.cids().collect();
+        resends
+            .pending
+            .retain(|(to, _), _| to.is_none_or(|cid| cids.contains(&cid)));
+    }
+    let now = time.elapsed_seconds_f64();
+    for ((to, id), pending) in resends.pending.iter_mut() {
+        if now - pending.sent < config.interval as f64 {
+            continue;
+        }
+        pending.sent = now;
+        let msg = AckedNetCompMsg {
+            id: *id,
+            generation: pending.generation,
+            version: pending.version,
+            msg: pending.msg.clone(),
+        };
+        let result = match (to, &server, &client) {
+            (Some(cid), Some(server), _) => server.send_to(*cid, &msg),
+            (None, None, Some(client)) => client.send(&msg),
+            _ => continue,
+        };
+        if let Err(e) = result {
+            let event = NetErrorEvent {
+                kind: NetErrorKind::Send,
+                entity: Some(pending.entity),
+                type_name: std::any::type_name::<M>(),
+                cid: *to,
+                error: e.to_string(),
+            };
+            error_log.log(&event, now);
+            ew.send(event);
+        }
+    }
+}
+
+/// Acknowledges the received [`AckedNetCompMsg`]s, and handles the received acknowledgements.
+pub fn ack_resends<M: Clone + Any + Send + Sync + Serialize>(
+    time: Res<Time>,
+    mut resends: ResMut<Resends<M>>,
+    server: Option<Res<Server>>,
+    client: Option<Res<Client>>,
+    map: Option<Res<NetEntityMap>>,
+    mut ew: EventWriter<NetErrorEvent>,
+    mut error_log: Local<ErrorLog>,
+) {
+    let now = time.elapsed_seconds_f64();
+    // The acknowledgements are about the local entity with the id, if there is one yet.
+    let mut report = |cid: Option<CId>, id: NetId, error: String| {
+        let type_name = std::any::type_name::<NetCompAck<M>>();
+        let event = NetErrorEvent {
+            kind: NetErrorKind::Send,
+            entity: map.as_deref().and_then(|map| map.get(id)),
+            type_name,
+            cid,
+            error,
+        };
+        error_log.log(&event, now);
+        ew.send(event);
+    };
+    if let Some(server) = server {
+        for m in server.recv::<AckedNetCompMsg<M>>() {
+            let ack = NetCompAck::<M> {
+                id: m.id,
+                version: m.version,
+                _pd: PhantomData,
+            };
+            if let Err(e) = server.send_to(m.cid, &ack) {
+                report(Some(m.cid), m.id, e.to_string());
+            }
+        }
+        for m in server.recv::<NetCompAck<M>>() {
+            resends.ack(Some(m.cid), m.id, m.version);
+        }
+    } else if let Some(client) = client {
+        for m in client.recv::<AckedNetCompMsg<M>>() {
+            let ack = NetCompAck::<M> {
+                id: m.id,
+                version: m.version,
+                _pd: PhantomData,
+            };
+            if let Err(e) = client.send(&ack) {
+                report(None, m.id, e.to_string());
+            }
+        }
+        for m in client.recv::<NetCompAck<M>>() {
+            resends.ack(None, m.id, m.version);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Running networking work on a tokio runtime. Requires the `async` feature.
+//!
+//! Bevy's task pools can run futures, but not the ones that need the tokio reactor, like tokio's
+//! DNS resolution and sockets, or async libraries built on them. With the `async` feature, an
+//! [`AsyncRuntime`] can be inserted to run that work on a tokio runtime, and [`AsyncBridge`]s
+//! carry the results into the ECS.
+//!
+//! [`Connecting::resolve_async`] connects like [`Connecting::resolve`], but resolves the hostname
+//! and performs the connection handshake on the runtime:
+//!
+//! ```ignore
+//! let runtime = AsyncRuntime::new()?;
+//! let connecting = Connecting::resolve_async::<Join, Welcome>(
+//!     &runtime, "play.example.com:7777", parts, Config::default(), Join::new("player"),
+//! );
+//! app.insert_resource(runtime).insert_resource(connecting);
+//! ```
+//!
+//! An [`AsyncBridge<T>`], added with [`add_async_bridge`](crate::AppExt::add_async_bridge), is a
+//! bounded channel from async tasks to the ECS. Every message sent on it is sent as a `T` event
+//! at the start of the next frame. Once the bridge is full, `send` waits until the ECS has caught
+//! up, so a fast producer can't grow the queue without bound:
+//!
+//! ```ignore
+//! app.add_async_bridge::<Chat>(256);
+//! let sender = app.world.resource::<AsyncBridge<Chat>>().sender();
+//! app.world.resource::<AsyncRuntime>().spawn(async move {
+//!     while let Some(chat) = read_chat(&mut socket).await {
+//!         if sender.send(chat).await.is_err() {
+//!             break;
+//!         }
+//!     }
+//! });
+//! ```
+//!
+//! The [`Client`](carrier_pigeon::Client) and [`Server`](carrier_pigeon::Server) themselves still
+//! use `carrier-pigeon`'s blocking sockets; an async transport can feed its messages into the
+//! ECS through a bridge.
+
+use crate::connect::{connect, interleave, ConnectResult, Connecting};
+use bevy::prelude::*;
+use bevy::tasks::IoTaskPool;
+use carrier_pigeon::net::Config;
+use carrier_pigeon::MsgTableParts;
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::io;
+use tokio::runtime::{Builder, Handle, Runtime};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// The tokio runtime that networking work runs on.
+#[derive(Resource)]
+pub struct AsyncRuntime {
+    /// The runtime, if it is owned by this resource.
+    runtime: Option<Runtime>,
+    handle: Handle,
+}
+
+impl Debug for AsyncRuntime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncRuntime")
+            .field("owned", &self.runtime.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncRuntime {
+    /// Starts a new multi-threaded tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        let runtime = Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("bevy-pigeon")
+            .build()?;
+        let handle = runtime.handle().clone();
+        Ok(AsyncRuntime {
+            runtime: Some(runtime),
+            handle,
+        })
+    }
+
+    /// Uses an existing runtime, such as the one the game's `main` runs on.
+    pub fn from_handle(handle: Handle) -> Self {
+        AsyncRuntime {
+            runtime: None,
+            handle,
+        }
+    }
+
+    /// Gets the handle of the runtime.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Spawns `future` on the runtime.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.handle.spawn(future)
+    }
+}
+
+impl Connecting {
+    /// Starts connecting to `host` on the tokio runtime, where `host` is a hostname and port,
+    /// such as `"play.example.com:7777"`, or an address.
+    ///
+    /// This is the same as [`Connecting::resolve`], but the hostname is resolved with tokio, and
+    /// the connection handshake runs on the runtime's blocking threads instead of the
+    /// [`IoTaskPool`].
+    ///
+    /// `C` is the connection message type and `R` is the response message type. These need to
+    /// match the types that the [`MsgTableParts`] were built with.
+    pub fn resolve_async<C, R>(
+        runtime: &AsyncRuntime,
+        host: impl Into<String>,
+        parts: MsgTableParts,
+        config: Config,
+        con_msg: C,
+    ) -> Self
+    where
+        C: Clone + Any + Send + Sync,
+        R: Any + Send + Sync,
+    {
+        let host = host.into();
+        let to_resolve = host.clone();
+        let (tx, rx) = oneshot::channel::<ConnectResult>();
+        runtime.spawn(async move {
+            let result = connect_async::<C, R>(to_resolve, parts, config, con_msg).await;
+            // The receiver is gone if the connection attempt was cancelled.
+            let _ = tx.send(result);
+        });
+        // Only wait for the result on the task pool, so it can be polled like any other attempt.
+        let task = IoTaskPool::get().spawn(async move {
+            rx.await.unwrap_or_else(|_| {
+                Err((
+                    None,
+                    io::Error::new(io::ErrorKind::Interrupted, "the runtime was shut down"),
+                ))
+            })
+        });
+        Connecting::from_task(host, None, task)
+    }
+}
+
+/// Resolves `host` and tries the resolved addresses in turn until a connection succeeds.
+async fn connect_async<C, R>(
+    host: String,
+    parts: MsgTableParts,
+    config: Config,
+    con_msg: C,
+) -> ConnectResult
+where
+    C: Clone + Any + Send + Sync,
+    R: Any + Send + Sync,
+{
+    let addrs = match tokio::net::lookup_host(host.as_str()).await {
+        Ok(addrs) => interleave(addrs.collect()),
+        Err(e) => return Err((None, e)),
+    };
+    let mut result = Err((
+        None,
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve to any address", host),
+        ),
+    ));
+    for addr in addrs {
+        debug!("Trying to connect to {} ({})", host, addr);
+        let (parts, config, con_msg) = (parts.clone(), config.clone(), con_msg.clone());
+        result = tokio::task::spawn_blocking(move || connect::<C, R>(addr, parts, config, con_msg))
+            .await
+            .unwrap_or_else(|e| Err((Some(addr), io::Error::other(e))));
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// A bounded channel that carries messages of type `T` from async tasks into the ECS, where they
+/// are sent as `T` events.
+///
+/// This is added by [`add_async_bridge`](crate::AppExt::add_async_bridge).
+#[derive(Resource)]
+pub struct AsyncBridge<T> {
+    sender: mpsc::Sender<T>,
+    receiver: mpsc::Receiver<T>,
+}
+
+impl<T> Debug for AsyncBridge<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncBridge")
+            .field("capacity", &self.sender.max_capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> AsyncBridge<T> {
+    /// Creates a bridge that holds at most `capacity` messages.
+    ///
+    /// ### Panics
+    /// panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        AsyncBridge { sender, receiver }
+    }
+
+    /// Gets a sender that async tasks can send messages into the ECS with.
+    pub fn sender(&self) -> mpsc::Sender<T> {
+        self.sender.clone()
+    }
+}
+
+/// Sends every message in the [`AsyncBridge<T>`] as a `T` event.
+pub fn drain_bridge<T: Send + Sync + 'static>(
+    mut bridge: ResMut<AsyncBridge<T>>,
+    mut events: EventWriter<T>,
+) {
+    // The bridge holds a sender itself, so the channel is never disconnected.
+    while let Ok(msg) = bridge.receiver.try_recv() {
+        events.send(msg);
+    }
+}
@@ -0,0 +1,212 @@
+//! Verifying that the client and server built the same message table.
+//!
+//! `carrier-pigeon` identifies messages by the order they were registered in, so a client that
+//! registered different types, or the same types in a different order, silently decodes messages
+//! as the wrong type. Registering into a `SortedMsgTable` fixes the order, but not a missing or
+//! extra type.
+//!
+//! Every message type that `bevy-pigeon` registers is recorded in the [`MsgSchema`] resource,
+//! along with the user messages registered through
+//! [`register_msg`](crate::AppExt::register_msg). When the protocol version is
+//! [negotiated](crate::version), the client can send the hash of its schema:
+//!
+//! ```ignore
+//! let schema = app.world.resource::<MsgSchema>();
+//! let con_msg = Versioned::new(GAME_VERSION, Join::new("player")).with_schema(schema);
+//! ```
+//!
+//! If the hash doesn't match the server's, the server rejects the client with a
+//! [`Rejection::SchemaMismatch`](crate::version::Rejection::SchemaMismatch) that holds the
+//! server's schema. The client logs how the two differ, and lists it in the
+//! [`ConnectionRejected`](crate::version::ConnectionRejected) event.
+//!
+//! Messages that are registered on the table directly are not recorded, so they are not checked.
+//!
+//! Both kinds of tables implement [`MsgTableLike`], and every registration method of
+//! [`AppExt`](crate::AppExt) takes either.
+
+use bevy::prelude::*;
+use carrier_pigeon::{MsgRegError, MsgTable, SortedMsgTable, Transport};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+
+/// A registered message type.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SchemaEntry {
+    /// The type name of the message, or its identifier in a `SortedMsgTable`.
+    pub name: String,
+    /// Whether the message is sent over TCP, rather than UDP.
+    pub tcp: bool,
+}
+
+/// The message types that were registered into the message table, in order.
+///
+/// This is added the first time a message type is registered.
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct MsgSchema {
+    entries: Vec<SchemaEntry>,
+}
+
+impl MsgSchema {
+    /// Gets the registered message types, in the order they are identified by.
+    pub fn entries(&self) -> &[SchemaEntry] {
+        &self.entries
+    }
+
+    /// A hash of the schema.
+    ///
+    /// This is the same for equal schemas on every platform, but type names can change between
+    /// compiler versions, so the client and server should be built with the same one.
+    pub fn hash(&self) -> u64 {
+        // FNV-1a, which unlike the std hashers is guaranteed to be stable.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for entry in self.entries.iter() {
+            let bytes = entry.name.bytes().chain([0, entry.tcp as u8]);
+            for byte in bytes {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Describes how `other` differs from this schema, one line per difference.
+    ///
+    /// Returns an empty list if they are equal.
+    pub fn diff(&self, other: &[SchemaEntry]) -> Vec<String> {
+        let mut diff = vec![];
+        for (i, (ours, theirs)) in self.entries.iter().zip(other).enumerate() {
+            if ours != theirs {
+                diff.push(format!(
+                    "message {}: {} ({}) here, but {} ({}) there",
+                    i,
+                    ours.name,
+                    transport_name(ours.tcp),
+                    theirs.name,
+                    transport_name(theirs.tcp)
+                ));
+            }
+        }
+        for entry in self.entries.iter().skip(other.len()) {
+            diff.push(format!("{} is only registered here", entry.name));
+        }
+        for entry in other.iter().skip(self.entries.len()) {
+            diff.push(format!("{} is only registered there", entry.name));
+        }
+        diff
+    }
+
+    /// Records the message types recorded in `other`, after the ones in this schema.
+    pub(crate) fn append(&mut self, other: &MsgSchema) {
+        self.entries.extend(other.entries.iter().cloned());
+    }
+
+    /// Records a message type registered into a `MsgTable`.
+    fn push(&mut self, name: &str, transport: Transport) {
+        self.entries.push(SchemaEntry {
+            name: name.to_owned(),
+            tcp: transport == Transport::TCP,
+        });
+    }
+
+    /// Records a message type registered into a `SortedMsgTable`, which is identified by the
+    /// sorted position of `id`.
+    fn insert_sorted(&mut self, id: &str, transport: Transport) {
+        let i = self.entries.partition_point(|e| e.name.as_str() < id);
+        self.entries.insert(
+            i,
+            SchemaEntry {
+                name: id.to_owned(),
+                tcp: transport == Transport::TCP,
+            },
+        );
+    }
+}
+
+/// A message table that messages can be registered into: a [`MsgTable`] or a [`SortedMsgTable`].
+///
+/// See the [module docs](self).
+pub trait MsgTableLike {
+    /// Registers `T` into this table and records it in `schema`.
+    ///
+    /// A [`SortedMsgTable`] identifies `T` by `id`. A [`MsgTable`] identifies it by the order it
+    /// was registered in, and ignores `id`.
+    fn register_recorded<T>(
+        &mut self,
+        schema: &mut MsgSchema,
+        transport: Transport,
+        id: &str,
+    ) -> Result<(), MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned;
+}
+
+impl MsgTableLike for MsgTable {
+    fn register_recorded<T>(
+        &mut self,
+        schema: &mut MsgSchema,
+        transport: Transport,
+        _id: &str,
+    ) -> Result<(), MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        register_in::<T>(schema, self, transport)
+    }
+}
+
+impl MsgTableLike for SortedMsgTable {
+    fn register_recorded<T>(
+        &mut self,
+        schema: &mut MsgSchema,
+        transport: Transport,
+        id: &str,
+    ) -> Result<(), MsgRegError>
+    where
+        T: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.register::<T>(transport, id)?;
+        schema.insert_sorted(id, transport);
+        Ok(())
+    }
+}
+
+/// Gets the name of a transport.
+fn transport_name(tcp: bool) -> &'static str {
+    if tcp {
+        "TCP"
+    } else {
+        "UDP"
+    }
+}
+
+/// Registers `T` into `table` with the identifier `id` and records it in the [`MsgSchema`].
+///
+/// A [`MsgTable`] ignores `id`; see [`MsgTableLike`].
+pub(crate) fn register<T>(
+    app: &mut App,
+    table: &mut impl MsgTableLike,
+    transport: Transport,
+    id: &str,
+) -> Result<(), MsgRegError>
+where
+    T: Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let mut schema = app.world.get_resource_or_insert_with(MsgSchema::default);
+    table.register_recorded::<T>(&mut schema, transport, id)
+}
+
+/// Registers `T` into `table` and records it in `schema`.
+pub(crate) fn register_in<T>(
+    schema: &mut MsgSchema,
+    table: &mut MsgTable,
+    transport: Transport,
+) -> Result<(), MsgRegError>
+where
+    T: Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    table.register::<T>(transport)?;
+    schema.push(std::any::type_name::<T>(), transport);
+    Ok(())
+}
@@ -0,0 +1,210 @@
+//! Resuming the session of a client that reconnects.
+//!
+//! Normally, a client that drops and reconnects is a new client: it gets a new `CId`, its player
+//! entity is despawned and spawned again, and it needs a full [snapshot](crate::snapshot). With
+//! sessions enabled using [`enable_sessions`](crate::AppExt::enable_sessions), the server keeps
+//! the entities [owned](crate::player::OwnedBy) by a disconnected client alive for a grace
+//! period instead. If the client reconnects with the same session id within that time, its
+//! entities are given back to it under the new `CId`, and it is only sent the entities that
+//! changed while it was gone.
+//!
+//! The session id is chosen by the client, usually randomly when the game starts, and sent in its
+//! connection message. The server gets it from the connection message with the function given to
+//! `enable_sessions`:
+//!
+//! ```ignore
+//! app.set_connection_msgs::<Join, Welcome>()
+//!     .enable_sessions::<Join, _>(30.0, |join: &Join| Some(join.session));
+//! ```
+//!
+//! A [`SessionResumed`] event is sent when a session is resumed, and a [`SessionExpired`] event
+//! when the grace period runs out and the entities are despawned. The changes are only sent if
+//! snapshots are [enabled](crate::AppExt::enable_snapshots); otherwise, use
+//! [`SyncC`](crate::SyncC) on [`SessionResumed`]. Group memberships and interest are not kept.
+
+use crate::connect::NetConnected;
+use crate::disconnect::NetDisconnected;
+use crate::player::{OwnedBy, PlayerFactory};
+use crate::snapshot::SendDelta;
+use bevy::ecs::system::SystemChangeTick;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use carrier_pigeon::CId;
+use std::fmt::{Debug, Formatter};
+
+/// A suspended session of a disconnected client.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Suspended {
+    /// The `CId` the client had.
+    cid: CId,
+    /// The local time the client disconnected, in seconds.
+    since: f64,
+    /// The change tick when the client disconnected.
+    tick: u32,
+}
+
+/// The sessions of the clients.
+///
+/// This is added by [`enable_sessions`](crate::AppExt::enable_sessions).
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct Sessions {
+    /// The time in seconds that the entities of a disconnected client are kept.
+    pub grace_period: f64,
+    active: HashMap<CId, u64>,
+    suspended: HashMap<u64, Suspended>,
+}
+
+impl Sessions {
+    /// Creates the sessions with the given grace period in seconds.
+    pub fn new(grace_period: f64) -> Self {
+        Sessions {
+            grace_period,
+            active: HashMap::default(),
+            suspended: HashMap::default(),
+        }
+    }
+
+    /// Gets the session id of the connected client `cid`.
+    pub fn session(&self, cid: CId) -> Option<u64> {
+        self.active.get(&cid).copied()
+    }
+
+    /// Whether `cid` is the `CId` of a disconnected client whose session is waiting to be
+    /// resumed.
+    pub fn is_suspended(&self, cid: CId) -> bool {
+        self.suspended.values().any(|s| s.cid == cid)
+    }
+}
+
+/// A function that gets the session id from a connection message of type `C`.
+type IdentityFn<C> = Box<dyn Fn(&C) -> Option<u64> + Send + Sync>;
+
+/// Gets the session id from a connection message of type `C`.
+#[derive(Resource)]
+pub struct SessionIdentity<C> {
+    identity: IdentityFn<C>,
+}
+
+impl<C> Debug for SessionIdentity<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionIdentity").finish_non_exhaustive()
+    }
+}
+
+impl<C> SessionIdentity<C> {
+    /// Creates a [`SessionIdentity`] from a function that gets the session id from a connection
+    /// message, or returns `None` if the client doesn't have one.
+    pub fn new(identity: impl Fn(&C) -> Option<u64> + Send + Sync + 'static) -> Self {
+        SessionIdentity {
+            identity: Box::new(identity),
+        }
+    }
+}
+
+/// An event that is sent on the server when a client resumes its session.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SessionResumed {
+    /// The new `CId` of the client.
+    pub cid: CId,
+    /// The `CId` the client had before it disconnected.
+    pub old_cid: CId,
+    /// The session id.
+    pub session: u64,
+}
+
+/// An event that is sent on the server when the grace period of a disconnected client runs out.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SessionExpired {
+    /// The `CId` the client had.
+    pub cid: CId,
+    /// The session id.
+    pub session: u64,
+}
+
+/// Starts or resumes the sessions of the new clients.
+pub fn start_sessions<C: Clone + Send + Sync + 'static>(
+    mut er: EventReader<NetConnected<C>>,
+    identity: Res<SessionIdentity<C>>,
+    mut sessions: ResMut<Sessions>,
+    mut factory: Option<ResMut<PlayerFactory>>,
+    mut resumed: EventWriter<SessionResumed>,
+    mut deltas: Option<ResMut<Events<SendDelta>>>,
+    mut q: Query<&mut OwnedBy>,
+) {
+    for event in er.iter() {
+        let session = match (identity.identity)(&event.msg) {
+            Some(session) => session,
+            None => continue,
+        };
+        sessions.active.insert(event.cid, session);
+        let suspended = match sessions.suspended.remove(&session) {
+            Some(suspended) => suspended,
+            None => continue,
+        };
+
+        debug!(
+            "Client {} resumed the session of client {}",
+            event.cid, suspended.cid
+        );
+        for mut owned_by in q.iter_mut().filter(|o| o.0 == suspended.cid) {
+            owned_by.0 = event.cid;
+        }
+        if let Some(factory) = factory.as_deref_mut() {
+            factory.rekey(suspended.cid, event.cid);
+        }
+        if let Some(deltas) = deltas.as_deref_mut() {
+            deltas.send(SendDelta {
+                cid: event.cid,
+                since: suspended.tick,
+            });
+        }
+        resumed.send(SessionResumed {
+            cid: event.cid,
+            old_cid: suspended.cid,
+            session,
+        });
+    }
+}
+
+/// Suspends the sessions of the disconnected clients, and ends the ones whose grace period ran
+/// out, despawning the entities they owned.
+pub fn suspend_sessions(
+    mut commands: Commands,
+    time: Res<Time>,
+    ticks: SystemChangeTick,
+    mut er: EventReader<NetDisconnected>,
+    mut sessions: ResMut<Sessions>,
+    mut expired: EventWriter<SessionExpired>,
+    q: Query<(Entity, &OwnedBy)>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for cid in er.iter().filter_map(|e| e.cid) {
+        if let Some(session) = sessions.active.remove(&cid) {
+            debug!("Suspending the session of client {}", cid);
+            sessions.suspended.insert(
+                session,
+                Suspended {
+                    cid,
+                    since: now,
+                    tick: ticks.change_tick(),
+                },
+            );
+        }
+    }
+
+    let grace_period = sessions.grace_period;
+    sessions.suspended.retain(|session, suspended| {
+        if now - suspended.since <= grace_period {
+            return true;
+        }
+        debug!("The session of client {} expired", suspended.cid);
+        for (entity, _) in q.iter().filter(|(_, o)| o.0 == suspended.cid) {
+            commands.entity(entity).despawn_recursive();
+        }
+        expired.send(SessionExpired {
+            cid: suspended.cid,
+            session: *session,
+        });
+        false
+    });
+}
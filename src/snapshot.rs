@@ -0,0 +1,442 @@
+//! Sending a snapshot of the networked world to clients that join late.
+//!
+//! Without snapshots, a client that joins late only sees the components that change after it
+//! joined (or that are force synced with [`SyncC`](crate::SyncC)). With snapshots enabled using
+//! [`enable_snapshots`](crate::AppExt::enable_snapshots), the server can send all synced
+//! components of all [`NetEntity`]s in a single message, and the client spawns any entities it
+//! doesn't have yet.
+//!
+//! The server sends a snapshot when it receives a [`SendSnapshot`] event, usually right after
+//! accepting a new connection:
+//!
+//! ```ignore
+//! server.handle_new_cons(|cid, con_msg: Connect| {
+//!     snapshots.send(SendSnapshot { cid });
+//!     (true, Response::Accepted)
+//! });
+//! ```
+//!
+//! Only components that would be sent to that client as updates are included, so entities that
+//! are hidden from it, or filtered out by its interest, aren't. On the client, a
+//! [`SnapshotApplied`] event is sent once the snapshot has been applied. Spawned entities only get
+//! their [`NetEntity`] and the synced components, so add the [`NetComp`]s you need when you get
+//! this event.
+//!
+//! Entities spawned after a client joined can be sent the same way by adding a [`NetSpawn`].
+//!
+//! A client that [resumes its session](crate::session) is sent a [`SendDelta`] instead, which
+//! only has the entities that changed since it disconnected. The client despawns the entities
+//! that it has but are no longer on the server.
+
+use crate::app::{Recipients, SendFilters};
+use crate::filter::NetFilters;
+use crate::group::NetGroups;
+use crate::interest::ClientInterest;
+use crate::limits::{MalformedMsg, NetLimits};
+use crate::spawn::is_provisional;
+use crate::sync::{NetComp, NetEntity, NetId};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use carrier_pigeon::{CId, Client, Server};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+
+/// An event that tells the server to send a snapshot to client `cid`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SendSnapshot {
+    /// The client to send the snapshot to.
+    pub cid: CId,
+}
+
+/// An event that tells the server to send client `cid` the entities whose synced components
+/// changed since change tick `since`.
+///
+/// This is sent when a client [resumes its session](crate::session).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SendDelta {
+    /// The client to send the changes to.
+    pub cid: CId,
+    /// The change tick to send the changes since.
+    pub since: u32,
+}
+
+/// A marker that makes the server send its entity to all clients when it is added, with the
+/// initial values of all of its synced components in a single message.
+///
+/// The clients spawn the entity with all of its components at once, instead of having them pop
+/// in one by one. Requires [`enable_snapshots`](crate::AppExt::enable_snapshots).
+#[derive(Component, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct NetSpawn;
+
+/// An event that is sent on the client when a snapshot or a [`NetSpawn`] has been applied.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SnapshotApplied {
+    /// The entities that were spawned for the snapshot.
+    pub spawned: Vec<Entity>,
+}
+
+/// A single synced component of an entity in a snapshot.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+struct SnapshotComp {
+    /// The type name of the message type.
+    key: String,
+    bytes: Vec<u8>,
+}
+
+/// An entity in a snapshot.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+struct SnapshotEntity {
+    #[serde(with = "crate::varint")]
+    id: NetId,
+    #[serde(with = "crate::varint")]
+    generation: u32,
+    comps: Vec<SnapshotComp>,
+}
+
+/// The message that a snapshot is sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, Default)]
+pub(crate) struct WorldSnapshot {
+    entities: Vec<SnapshotEntity>,
+    /// The ids of all [`NetEntity`]s on the server, if the client should despawn the others.
+    live: Option<Vec<NetId>>,
+}
+
+/// Gets the encoded components that would be sent to client `cid`, by [`NetEntity`] id, or all
+/// of them if `cid` is `None`.
+///
+/// If given, only the entities with the given ids are included.
+type WriteFn = fn(&mut World, Option<CId>, Option<&[NetId]>) -> Vec<(NetId, Vec<u8>)>;
+/// Decodes a component and inserts it on an entity.
+type ReadFn = fn(&mut World, Entity, &[u8]);
+/// Gets the [`NetEntity`] ids of the entities whose component changed since the given tick.
+type ChangedFn = fn(&mut World, u32) -> Vec<NetId>;
+
+/// The synced components that can be put in a snapshot.
+///
+/// This is filled in by [`sync_comp`](crate::AppExt::sync_comp).
+#[derive(Resource, Default)]
+pub struct SnapshotRegistry {
+    comps: HashMap<&'static str, (WriteFn, ReadFn, ChangedFn)>,
+}
+
+impl std::fmt::Debug for SnapshotRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SnapshotRegistry")
+            .field("comps", &self.comps.keys())
+            .finish()
+    }
+}
+
+impl SnapshotRegistry {
+    /// Registers component `T` with message type `M`.
+    pub(crate) fn register<T, M>(&mut self)
+    where
+        T: Clone + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        self.comps.insert(
+            std::any::type_name::<M>(),
+            (write_comp::<T, M>, read_comp::<T, M>, changed_ids::<T>),
+        );
+    }
+}
+
+/// Encodes every component `T` that would be sent to `cid` (or every one if `None`), on the
+/// entities with `ids` if given.
+fn write_comp<T, M>(
+    world: &mut World,
+    cid: Option<CId>,
+    ids: Option<&[NetId]>,
+) -> Vec<(NetId, Vec<u8>)>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let mut q = world.query::<(&NetEntity, &NetComp<T, M>, &T, SendFilters)>();
+    let world: &World = world;
+    // Only what the delta path would send to `cid`, so hidden and filtered out entities stay
+    // hidden.
+    let sees = |net_e: &NetEntity, net_c: &NetComp<T, M>, filters: SendFilters| {
+        let (cid, server) = match (cid, world.get_resource::<Server>()) {
+            (None, _) => return true,
+            (Some(cid), Some(server)) => (cid, server),
+            (Some(_), None) => return false,
+        };
+        let spec = match net_c.s_dir.to() {
+            Some(spec) if spec.matches(cid) => *spec,
+            _ => return false,
+        };
+        Recipients::of(
+            server,
+            net_e.id,
+            net_c,
+            spec,
+            filters,
+            world.get_resource::<NetGroups>(),
+            (
+                world.get_resource::<ClientInterest>(),
+                world.get_resource::<NetFilters>(),
+            ),
+        )
+        .includes(cid)
+    };
+    q.iter(world)
+        .filter(|(net_e, _, _, _)| ids.iter().all(|ids| ids.contains(&net_e.id)))
+        .filter(|(net_e, net_c, _, filters)| sees(net_e, net_c, *filters))
+        .filter_map(|(net_e, _, comp, _)| {
+            let msg: M = comp.clone().into();
+            match bincode::serialize(&msg) {
+                Ok(bytes) => Some((net_e.id, bytes)),
+                Err(e) => {
+                    error!("Failed to serialize a component for a snapshot: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Decodes component `T` from `bytes` and inserts it on `entity`.
+fn read_comp<T, M>(world: &mut World, entity: Entity, bytes: &[u8])
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    let limits = world
+        .get_resource::<NetLimits>()
+        .copied()
+        .unwrap_or_default();
+    match limits.decode::<M>(bytes) {
+        Ok(msg) => {
+            world.entity_mut(entity).insert(msg.into());
+        }
+        Err(error) => {
+            warn!("Failed to deserialize a component in a snapshot: {}", error);
+            if let Some(mut events) = world.get_resource_mut::<Events<MalformedMsg>>() {
+                events.send(MalformedMsg {
+                    cid: None,
+                    type_name: std::any::type_name::<M>(),
+                    error,
+                });
+            }
+        }
+    }
+}
+
+/// Gets the [`NetEntity`] ids of the entities whose component `T` changed since change tick
+/// `since`.
+fn changed_ids<T: Component>(world: &mut World, since: u32) -> Vec<NetId> {
+    let change_tick = world.read_change_tick();
+    let mut q = world.query_filtered::<(Entity, &NetEntity), With<T>>();
+    q.iter(world)
+        .filter(|(entity, _)| {
+            world
+                .entity(*entity)
+                .get_change_ticks::<T>()
+                .is_some_and(|ticks| ticks.is_changed(since, change_tick))
+        })
+        .map(|(_, net_e)| net_e.id)
+        .collect()
+}
+
+/// Sends a snapshot for every [`SendSnapshot`] event.
+pub fn send_snapshots(world: &mut World) {
+    let cids: Vec<CId> = match world.get_resource_mut::<Events<SendSnapshot>>() {
+        Some(mut events) => events.drain().map(|e| e.cid).collect(),
+        None => return,
+    };
+    let deltas: Vec<SendDelta> = match world.get_resource_mut::<Events<SendDelta>>() {
+        Some(mut events) => events.drain().collect(),
+        None => vec![],
+    };
+    if !world.contains_resource::<Server>() {
+        return;
+    }
+    for delta in deltas {
+        let snapshot = build_delta(world, delta.cid, delta.since);
+        debug!(
+            "Sending {} changed entities to client {}",
+            snapshot.entities.len(),
+            delta.cid
+        );
+        if let Err(e) = world.resource::<Server>().send_to(delta.cid, &snapshot) {
+            error!("{}", e);
+        }
+    }
+    for cid in cids {
+        let snapshot = build_snapshot(world, Some(cid), None);
+        debug!(
+            "Sending a snapshot of {} entities to client {}",
+            snapshot.entities.len(),
+            cid
+        );
+        if let Err(e) = world.resource::<Server>().send_to(cid, &snapshot) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Sends the entities that just got a [`NetSpawn`] to all clients, with all of their synced
+/// components.
+pub fn send_spawns(world: &mut World) {
+    if !world.contains_resource::<Server>() {
+        return;
+    }
+    let ids: Vec<NetId> = world
+        .query_filtered::<&NetEntity, Added<NetSpawn>>()
+        .iter(world)
+        .map(|net_e| net_e.id)
+        .collect();
+    if ids.is_empty() {
+        return;
+    }
+
+    let cids: Vec<CId> = world.resource::<Server>().cids().collect();
+    for cid in cids {
+        let snapshot = build_snapshot(world, Some(cid), Some(&ids));
+        if snapshot.entities.is_empty() {
+            continue;
+        }
+        if let Err(e) = world.resource::<Server>().send_to(cid, &snapshot) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Builds a snapshot of the components that would be sent to `cid` (or all of them if `None`), of
+/// the entities with `ids` if given.
+pub(crate) fn build_snapshot(
+    world: &mut World,
+    cid: Option<CId>,
+    ids: Option<&[NetId]>,
+) -> WorldSnapshot {
+    let writers: Vec<(&'static str, WriteFn)> = world
+        .resource::<SnapshotRegistry>()
+        .comps
+        .iter()
+        .map(|(key, (write, _, _))| (*key, *write))
+        .collect();
+
+    let generations: HashMap<NetId, u32> = world
+        .query::<&NetEntity>()
+        .iter(world)
+        .map(|net_e| (net_e.id, net_e.generation))
+        .collect();
+    let mut entities: HashMap<NetId, Vec<SnapshotComp>> = HashMap::default();
+    for (key, write) in writers.iter() {
+        for (id, bytes) in write(world, cid, ids) {
+            entities.entry(id).or_default().push(SnapshotComp {
+                key: key.to_string(),
+                bytes,
+            });
+        }
+    }
+    WorldSnapshot {
+        entities: entities
+            .into_iter()
+            .map(|(id, comps)| SnapshotEntity {
+                id,
+                generation: generations.get(&id).copied().unwrap_or_default(),
+                comps,
+            })
+            .collect(),
+        live: None,
+    }
+}
+
+/// Builds a snapshot of the entities that changed since change tick `since`, with the
+/// components that would be sent to `cid`.
+fn build_delta(world: &mut World, cid: CId, since: u32) -> WorldSnapshot {
+    let changed: Vec<ChangedFn> = world
+        .resource::<SnapshotRegistry>()
+        .comps
+        .values()
+        .map(|(_, _, changed)| *changed)
+        .collect();
+
+    let mut ids = changed_ids::<NetEntity>(world, since);
+    for changed in changed {
+        ids.extend(changed(world, since));
+    }
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut snapshot = build_snapshot(world, Some(cid), Some(&ids));
+    snapshot.live = Some(
+        world
+            .query::<&NetEntity>()
+            .iter(world)
+            .map(|net_e| net_e.id)
+            .collect(),
+    );
+    snapshot
+}
+
+/// Applies received snapshots, spawning any entities that don't exist yet.
+pub fn recv_snapshots(world: &mut World) {
+    let snapshots: Vec<WorldSnapshot> = match world.get_resource::<Client>() {
+        Some(client) => client
+            .recv::<WorldSnapshot>()
+            .map(|m| (*m).clone())
+            .collect(),
+        None => return,
+    };
+
+    for snapshot in snapshots {
+        let spawned = apply_snapshot(world, snapshot);
+        world.send_event(SnapshotApplied { spawned });
+    }
+}
+
+/// Applies `snapshot` to `world`, returning the entities that were spawned for it.
+pub(crate) fn apply_snapshot(world: &mut World, snapshot: WorldSnapshot) -> Vec<Entity> {
+    let mut ids: HashMap<NetId, (Entity, u32)> = world
+        .query::<(Entity, &NetEntity)>()
+        .iter(world)
+        .map(|(entity, net_e)| (net_e.id, (entity, net_e.generation)))
+        .collect();
+    let mut spawned = vec![];
+
+    for snapshot_e in snapshot.entities {
+        // An entity of an older generation was despawned on the server, and its id reused.
+        if let Some((entity, generation)) = ids.get(&snapshot_e.id) {
+            if *generation != snapshot_e.generation {
+                debug!(
+                    "Replacing NetEntity {} of generation {} with generation {}",
+                    snapshot_e.id, generation, snapshot_e.generation
+                );
+                world.entity_mut(*entity).despawn_recursive();
+                ids.remove(&snapshot_e.id);
+            }
+        }
+        let (entity, _) = *ids.entry(snapshot_e.id).or_insert_with(|| {
+            let net_e = NetEntity::with_generation(snapshot_e.id, snapshot_e.generation);
+            let entity = world.spawn(net_e).id();
+            spawned.push(entity);
+            (entity, snapshot_e.generation)
+        });
+        for comp in snapshot_e.comps {
+            let read = world
+                .resource::<SnapshotRegistry>()
+                .comps
+                .get(comp.key.as_str())
+                .map(|(_, read, _)| *read);
+            match read {
+                Some(read) => read(world, entity, &comp.bytes),
+                None => warn!("Unknown component {} in a snapshot", comp.key),
+            }
+        }
+    }
+
+    if let Some(live) = snapshot.live {
+        let live: HashSet<NetId> = live.into_iter().collect();
+        for (id, (entity, _)) in ids {
+            if !is_provisional(id) && !live.contains(&id) {
+                debug!("Despawning NetEntity {} that is gone on the server", id);
+                world.entity_mut(entity).despawn_recursive();
+            }
+        }
+    }
+    spawned
+}
@@ -0,0 +1,243 @@
+//! Client-predicted spawning.
+//!
+//! Waiting for the server to spawn something the client caused, such as its own projectile,
+//! makes the game feel unresponsive. Instead, the client can spawn a predicted entity right away
+//! with a provisional id, and ask the server to spawn it for real. The server then either
+//! confirms the spawn, and the predicted entity's [`NetEntity`] id is changed to the
+//! authoritative one, or rejects it, and the predicted entity is despawned.
+//!
+//! Request types are registered with
+//! [`add_spawn_request`](crate::AppExt::add_spawn_request). On the client:
+//!
+//! ```ignore
+//! let id = ids.new_id();
+//! commands.spawn((NetEntity::new(id), Predicted, ProjectileBundle::default()));
+//! requests.send(RequestSpawn::new(id, FireProjectile { dir }));
+//! ```
+//!
+//! On the server, with the [`NetIds`](crate::ids::NetIds) allocator:
+//!
+//! ```ignore
+//! for req in requested.iter() {
+//!     let net_e = ids.alloc_entity();
+//!     commands.spawn((net_e, ProjectileBundle::default()));
+//!     responses.send(RespondSpawn::confirm(req, net_e));
+//! }
+//! ```
+
+use crate::sync::{NetEntity, NetId};
+use bevy::prelude::*;
+use carrier_pigeon::{CId, Client, Server};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+
+/// The bit that is set in all provisional ids: the highest bit of a [`NetId`].
+///
+/// Authoritative ids should not have this bit set, so they never collide with provisional ones.
+pub const PROVISIONAL_BIT: NetId = 1 << (NetId::BITS - 1);
+
+/// Whether `id` is a provisional id.
+pub fn is_provisional(id: NetId) -> bool {
+    id & PROVISIONAL_BIT != 0
+}
+
+/// Generates provisional ids for predicted entities on the client.
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct ProvisionalIds {
+    next: NetId,
+}
+
+impl ProvisionalIds {
+    /// Gets a new provisional id.
+    pub fn new_id(&mut self) -> NetId {
+        let id = self.next | PROVISIONAL_BIT;
+        self.next = (self.next + 1) & !PROVISIONAL_BIT;
+        id
+    }
+}
+
+/// A marker for an entity that was spawned by the client and is waiting for the server to
+/// confirm it.
+#[derive(Component, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct Predicted;
+
+/// The message that a spawn request is sent as.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct SpawnRequestMsg<R> {
+    provisional: NetId,
+    req: R,
+}
+
+/// The message that a response to a spawn request is sent as.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub(crate) struct SpawnResponseMsg {
+    provisional: NetId,
+    id: Option<NetId>,
+    generation: u32,
+}
+
+/// An event that sends a spawn request from the client.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RequestSpawn<R> {
+    /// The provisional id of the predicted entity.
+    pub provisional: NetId,
+    /// The request.
+    pub req: R,
+}
+
+impl<R> RequestSpawn<R> {
+    /// Creates a new request for the predicted entity with id `provisional`.
+    pub fn new(provisional: NetId, req: R) -> Self {
+        RequestSpawn { provisional, req }
+    }
+}
+
+/// An event that is sent on the server when a client requests a spawn.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SpawnRequested<R> {
+    /// The client that requested the spawn.
+    pub cid: CId,
+    /// The provisional id of the client's predicted entity.
+    pub provisional: NetId,
+    /// The request.
+    pub req: R,
+}
+
+/// An event that responds to a spawn request from the server.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct RespondSpawn {
+    /// The client that requested the spawn.
+    pub cid: CId,
+    /// The provisional id of the client's predicted entity.
+    pub provisional: NetId,
+    /// The authoritative id if the spawn is confirmed, or `None` if it is rejected.
+    pub id: Option<NetId>,
+    /// The generation of the authoritative id.
+    pub generation: u32,
+}
+
+impl RespondSpawn {
+    /// Confirms the spawn requested by `req`, with the authoritative [`NetEntity`] `net_e`, or
+    /// just its id.
+    pub fn confirm<R>(req: &SpawnRequested<R>, net_e: impl Into<NetEntity>) -> Self {
+        let net_e = net_e.into();
+        RespondSpawn {
+            cid: req.cid,
+            provisional: req.provisional,
+            id: Some(net_e.id),
+            generation: net_e.generation,
+        }
+    }
+
+    /// Rejects the spawn requested by `req`.
+    pub fn reject<R>(req: &SpawnRequested<R>) -> Self {
+        RespondSpawn {
+            cid: req.cid,
+            provisional: req.provisional,
+            id: None,
+            generation: 0,
+        }
+    }
+}
+
+/// An event that is sent on the client when the server responds to a spawn request.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SpawnResolved {
+    /// The predicted entity.
+    pub entity: Entity,
+    /// The authoritative id if the spawn was confirmed, or `None` if it was rejected, in which
+    /// case the entity has been despawned.
+    pub id: Option<NetId>,
+}
+
+/// Sends the [`RequestSpawn<R>`] events to the server.
+pub fn send_spawn_requests<R: Clone + Any + Send + Sync>(
+    mut er: EventReader<RequestSpawn<R>>,
+    client: Option<Res<Client>>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    for req in er.iter() {
+        let msg = SpawnRequestMsg {
+            provisional: req.provisional,
+            req: req.req.clone(),
+        };
+        if let Err(e) = client.send(&msg) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Receives spawn requests on the server, and sends them as [`SpawnRequested<R>`] events.
+pub fn recv_spawn_requests<R: Clone + Any + Send + Sync>(
+    mut ew: EventWriter<SpawnRequested<R>>,
+    server: Option<Res<Server>>,
+) {
+    if let Some(server) = server {
+        ew.send_batch(
+            server
+                .recv::<SpawnRequestMsg<R>>()
+                .map(|msg| SpawnRequested {
+                    cid: msg.cid,
+                    provisional: msg.provisional,
+                    req: msg.req.clone(),
+                }),
+        );
+    }
+}
+
+/// Sends the [`RespondSpawn`] events to the clients.
+pub fn send_spawn_responses(mut er: EventReader<RespondSpawn>, server: Option<Res<Server>>) {
+    let server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    for resp in er.iter() {
+        let msg = SpawnResponseMsg {
+            provisional: resp.provisional,
+            id: resp.id,
+            generation: resp.generation,
+        };
+        if let Err(e) = server.send_to(resp.cid, &msg) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Confirms or despawns predicted entities when the server responds.
+pub fn recv_spawn_responses(
+    mut commands: Commands,
+    mut ew: EventWriter<SpawnResolved>,
+    client: Option<Res<Client>>,
+    mut q: Query<(Entity, &mut NetEntity), With<Predicted>>,
+) {
+    let client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    for resp in client.recv::<SpawnResponseMsg>() {
+        let (entity, mut net_e) = match q.iter_mut().find(|(_, n)| n.id == resp.provisional) {
+            Some(found) => found,
+            None => {
+                debug!(
+                    "Got a spawn response for an unknown predicted entity {}",
+                    resp.provisional
+                );
+                continue;
+            }
+        };
+        match resp.id {
+            Some(id) => {
+                *net_e = NetEntity::with_generation(id, resp.generation);
+                commands.entity(entity).remove::<Predicted>();
+            }
+            None => commands.entity(entity).despawn_recursive(),
+        }
+        ew.send(SpawnResolved {
+            entity,
+            id: resp.id,
+        });
+    }
+}
@@ -0,0 +1,122 @@
+//! Client specifications that are more expressive than a [`CIdSpec`].
+//!
+//! A [`CIdSpec`] can only name a single client. [`NetSpec`] can also name an explicit set of
+//! clients, everyone except a set of clients, or a union of other specs. For example, to send to
+//! everyone except the two players in a duel:
+//!
+//! ```ignore
+//! commands.entity(e).insert(NetSendTo(NetSpec::Except(vec![player_a, player_b])));
+//! ```
+//!
+//! [`ServerSendExt`] adds shorthands for sending messages to such sets of clients:
+//!
+//! ```ignore
+//! server.broadcast_except(shooter, &ShotFired { pos })?;
+//! server.send_to_many(&[player_a, player_b], &DuelStarted)?;
+//! ```
+
+use bevy::prelude::*;
+use carrier_pigeon::net::CIdSpec;
+use carrier_pigeon::{CId, Server};
+use std::any::Any;
+use std::io;
+
+/// A specification of a set of clients.
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub enum NetSpec {
+    /// The clients matching a [`CIdSpec`].
+    Spec(CIdSpec),
+    /// Only the given clients.
+    Many(Vec<CId>),
+    /// All clients except the given ones.
+    Except(Vec<CId>),
+    /// The clients matching any of the given specs.
+    Union(Vec<NetSpec>),
+}
+
+impl Default for NetSpec {
+    fn default() -> Self {
+        NetSpec::Spec(CIdSpec::All)
+    }
+}
+
+impl From<CIdSpec> for NetSpec {
+    fn from(spec: CIdSpec) -> Self {
+        NetSpec::Spec(spec)
+    }
+}
+
+impl NetSpec {
+    /// Whether client `cid` matches this spec.
+    pub fn matches(&self, cid: CId) -> bool {
+        match self {
+            NetSpec::Spec(spec) => spec.matches(cid),
+            NetSpec::Many(cids) => cids.contains(&cid),
+            NetSpec::Except(cids) => !cids.contains(&cid),
+            NetSpec::Union(specs) => specs.iter().any(|spec| spec.matches(cid)),
+        }
+    }
+
+    /// Gets the equivalent [`CIdSpec`], if there is one.
+    pub fn as_cid_spec(&self) -> Option<CIdSpec> {
+        match self {
+            NetSpec::Spec(spec) => Some(*spec),
+            NetSpec::Many(cids) if cids.is_empty() => Some(CIdSpec::None),
+            NetSpec::Many(cids) if cids.len() == 1 => Some(CIdSpec::Only(cids[0])),
+            NetSpec::Except(cids) if cids.is_empty() => Some(CIdSpec::All),
+            NetSpec::Except(cids) if cids.len() == 1 => Some(CIdSpec::Except(cids[0])),
+            _ => None,
+        }
+    }
+}
+
+/// A component that restricts who the synced components of an entity are sent to by the server.
+///
+/// Components are sent to the clients that match both the [`SNetDir`](crate::sync::SNetDir) of
+/// the [`NetComp`](crate::sync::NetComp) and this spec.
+#[derive(Component, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct NetSendTo(pub NetSpec);
+
+/// An extension trait for sending a message to a set of clients.
+///
+/// To send to every client, use `Server::broadcast`.
+pub trait ServerSendExt {
+    /// Sends `msg` to every client except `cid`.
+    fn broadcast_except<T: Any + Send + Sync>(&self, cid: CId, msg: &T) -> io::Result<()>;
+
+    /// Sends `msg` to the clients `cids`.
+    ///
+    /// The message is sent to every client even if sending to one fails. The first error is
+    /// returned.
+    fn send_to_many<T: Any + Send + Sync>(&self, cids: &[CId], msg: &T) -> io::Result<()>;
+
+    /// Sends `msg` to the clients matching `spec`.
+    ///
+    /// The message is sent to every client even if sending to one fails. The first error is
+    /// returned.
+    fn send_net_spec<T: Any + Send + Sync>(&self, spec: &NetSpec, msg: &T) -> io::Result<()>;
+}
+
+impl ServerSendExt for Server {
+    fn broadcast_except<T: Any + Send + Sync>(&self, cid: CId, msg: &T) -> io::Result<()> {
+        self.send_spec(CIdSpec::Except(cid), msg)
+    }
+
+    fn send_to_many<T: Any + Send + Sync>(&self, cids: &[CId], msg: &T) -> io::Result<()> {
+        let mut result = Ok(());
+        for &cid in cids {
+            if let Err(e) = self.send_to(cid, msg) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    fn send_net_spec<T: Any + Send + Sync>(&self, spec: &NetSpec, msg: &T) -> io::Result<()> {
+        if let Some(spec) = spec.as_cid_spec() {
+            return self.send_spec(spec, msg);
+        }
+        let cids: Vec<_> = self.cids().filter(|cid| spec.matches(*cid)).collect();
+        self.send_to_many(&cids, msg)
+    }
+}
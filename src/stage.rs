@@ -0,0 +1,65 @@
+//! The stages that the network is received and sent in.
+//!
+//! The [`ClientPlugin`](crate::ClientPlugin) and [`ServerPlugin`](crate::ServerPlugin) add two
+//! stages to the app: [`NetStage::Receive`] right after [`CoreStage::First`], which applies the
+//! received updates, and [`NetStage::Send`] right after [`CoreStage::Last`], which sends the
+//! changes of the frame. The systems of every synced component run in them by default, so
+//! systems can be ordered against the network as a whole by picking a stage, and exclusive systems
+//! can be added before or after all of it:
+//!
+//! ```ignore
+//! app.add_system_to_stage(CoreStage::PreUpdate, react_to_updates)
+//!     .add_system_to_stage(NetStage::Send, log_world.at_start());
+//! ```
+//!
+//! The messages are still received from the connection in [`CoreStage::First`], so systems that
+//! read the received components should run in [`CoreStage::PreUpdate`] or later.
+//!
+//! The stages can be placed elsewhere with [`add_net_stages`](crate::AppExt::add_net_stages),
+//! which has to be called before the plugins are added:
+//!
+//! ```ignore
+//! app.add_net_stages(CoreStage::First, CoreStage::PostUpdate)
+//!     .add_plugin(ServerPlugin);
+//! ```
+//!
+//! They can also be run on their own with [`run_net_stage`](crate::AppExt::run_net_stage), such as
+//! stepping the network without the rest of the frame in a test.
+
+use bevy::prelude::*;
+
+/// The stages that the network is received and sent in.
+///
+/// See the [module docs](self).
+#[derive(StageLabel, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub enum NetStage {
+    /// The stage that received updates are applied in.
+    Receive,
+    /// The stage that updates are sent in.
+    Send,
+}
+
+/// Adds the [`NetStage`]s after `recv_after` and `send_after`, unless they were already added.
+///
+/// Returns whether the stages were added.
+pub(crate) fn add_net_stages(
+    app: &mut App,
+    recv_after: impl StageLabel,
+    send_after: impl StageLabel,
+) -> bool {
+    if app
+        .schedule
+        .get_stage::<SystemStage>(NetStage::Receive)
+        .is_some()
+    {
+        return false;
+    }
+    app.add_stage_after(recv_after, NetStage::Receive, SystemStage::parallel())
+        .add_stage_after(send_after, NetStage::Send, SystemStage::parallel());
+    true
+}
+
+/// Adds the [`NetStage`]s at their default places, unless they were already added.
+pub(crate) fn init_net_stages(app: &mut App) {
+    add_net_stages(app, CoreStage::First, CoreStage::Last);
+}
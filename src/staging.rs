@@ -0,0 +1,94 @@
+//! Applying all received updates of a frame at once.
+//!
+//! The received updates of every component type are applied by their own system, so a system
+//! that runs between two of them sees some components of the network frame applied and others
+//! not. With [`stage_net_updates`](crate::AppExt::stage_net_updates), the updates are staged in
+//! the [`StagedUpdates`] instead, and [`apply_staged_updates`] applies all of them in one
+//! exclusive system at the end of the [`NetStage::Receive`], after every receive system. No
+//! other system can run while they are applied, so user systems always see either none or all
+//! of the updates of a frame.
+//!
+//! ```ignore
+//! app.sync_comp::<Transform, NetTransform>(&mut table, Transport::UDP)
+//!     .sync_comp::<Health, Health>(&mut table, Transport::TCP)
+//!     .stage_net_updates();
+//! ```
+//!
+//! This stages the updates that are applied by [`comp_recv`](crate::app::comp_recv), including
+//! the validated updates received by the server, and the updates of [bundles](crate::atomic).
+//! Updates of components with a [`DeferredApply`](crate::deferred::DeferredApply), which are
+//! spread over several frames on purpose, are not staged. Components received in a stage after
+//! [`NetStage::Receive`] have their updates applied at the end of it in the next frame.
+//!
+//! [`NetStage::Receive`]: crate::NetStage::Receive
+
+use crate::sync::ApplyMode;
+use bevy::prelude::*;
+use std::any::Any;
+
+/// A staged update, which applies itself to the world.
+type StagedUpdate = Box<dyn FnOnce(&mut World) + Send + Sync>;
+
+/// The received updates that are waiting to be applied at the end of the
+/// [`NetStage::Receive`](crate::NetStage::Receive).
+#[derive(Resource, Default)]
+pub struct StagedUpdates {
+    updates: Vec<StagedUpdate>,
+}
+
+impl std::fmt::Debug for StagedUpdates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StagedUpdates")
+            .field("updates", &self.updates.len())
+            .finish()
+    }
+}
+
+impl StagedUpdates {
+    /// The number of staged updates.
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Whether there are no staged updates.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Stages `msg` to be applied to component `T` of `entity` with `apply`.
+    pub(crate) fn stage<T, M>(
+        &mut self,
+        entity: Entity,
+        msg: M,
+        apply: fn(&M, &mut T),
+        mode: ApplyMode,
+    ) where
+        T: Clone + Component,
+        M: Any + Send + Sync,
+    {
+        self.updates
+            .push(Box::new(move |world: &mut World| match mode {
+                ApplyMode::Mutate => {
+                    // The entity could have been despawned since.
+                    if let Some(mut comp) = world.get_mut::<T>(entity) {
+                        apply(&msg, &mut comp);
+                    }
+                }
+                ApplyMode::Insert => {
+                    if let Some(comp) = world.get::<T>(entity) {
+                        let mut new = comp.clone();
+                        apply(&msg, &mut new);
+                        world.entity_mut(entity).insert(new);
+                    }
+                }
+            }));
+    }
+}
+
+/// Applies all [`StagedUpdates`], in the order they were received.
+pub fn apply_staged_updates(world: &mut World) {
+    let updates = std::mem::take(&mut world.resource_mut::<StagedUpdates>().updates);
+    for update in updates {
+        update(world);
+    }
+}
@@ -0,0 +1,253 @@
+//! Detecting entities whose updates stopped arriving.
+//!
+//! When the owner of an entity lags out, its components just stop changing. With
+//! [`detect_stale`](crate::AppExt::detect_stale), a component that expects updates from the other
+//! side, but hasn't received one within the window, makes its entity stale. A [`NetStale`] event
+//! is sent, and a [`NetResumed`] event once every stale component of the entity receives updates
+//! again, so games can grey out or freeze the entity in the meantime:
+//!
+//! ```ignore
+//! app.sync_comp::<Transform, NetTransform>(&mut table, Transport::UDP)
+//!     .detect_stale::<Transform, NetTransform>(0.5);
+//!
+//! fn grey_out(mut stale: EventReader<NetStale>, mut q: Query<&mut Handle<StandardMaterial>>) {
+//!     for NetStale(entity) in stale.iter() {
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! A component expects updates on the server if its [`SNetDir`](crate::sync::SNetDir) receives
+//! from clients, and on a client if its [`CNetDir`] is [`From`](CNetDir::From). The staleness of
+//! every entity can also be checked with the [`NetStaleness`] resource.
+//!
+//! Entities can also be cleaned up once they stay stale for longer, so ghost entities don't pile
+//! up when their despawn messages are lost.
+//! [`despawn_stale_after`](crate::AppExt::despawn_stale_after) despawns them, and
+//! [`on_stale_timeout`](crate::AppExt::on_stale_timeout) calls a function instead:
+//!
+//! ```ignore
+//! app.detect_stale::<Transform, NetTransform>(0.5)
+//!     .despawn_stale_after(10.0);
+//! ```
+
+use crate::app::NetSource;
+use crate::sync::{CNetDir, NetComp};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet, Instant};
+use carrier_pigeon::Server;
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+
+/// An event that is sent when an entity becomes stale.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetStale(pub Entity);
+
+/// An event that is sent when a stale entity is no longer stale, because its stale components
+/// receive updates again, or stopped being synced.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetResumed(pub Entity);
+
+/// A function that is called with an entity that stayed stale for longer than the timeout.
+pub type StaleCallback = Box<dyn Fn(Entity, &mut Commands) + Send + Sync>;
+
+/// What happens to an entity that stays stale for longer than the timeout.
+pub enum StaleTimeout {
+    /// Despawn the entity, and its children.
+    Despawn,
+    /// Call the function with the entity.
+    Call(StaleCallback),
+}
+
+impl Debug for StaleTimeout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StaleTimeout::Despawn => f.write_str("Despawn"),
+            StaleTimeout::Call(_) => f.write_str("Call"),
+        }
+    }
+}
+
+/// A stale entity.
+#[derive(Clone, Debug)]
+struct Stale {
+    /// The type names of the stale components.
+    comps: HashSet<&'static str>,
+    /// When the entity became stale.
+    since: Instant,
+}
+
+/// The windows after which components become stale, and the entities that are stale.
+///
+/// This is added by [`detect_stale`](crate::AppExt::detect_stale).
+#[derive(Resource, Debug, Default)]
+pub struct NetStaleness {
+    /// The seconds without updates after which a component is stale, by type name.
+    windows: HashMap<&'static str, f32>,
+    /// The stale entities.
+    stale: HashMap<Entity, Stale>,
+    /// The seconds after which stale entities time out, and what happens to them.
+    timeout: Option<(f32, StaleTimeout)>,
+}
+
+impl NetStaleness {
+    /// Sets the seconds without updates after which component `T` is stale.
+    pub fn set_window<T: Component>(&mut self, window: f32) {
+        self.windows.insert(std::any::type_name::<T>(), window);
+    }
+
+    /// Gets the seconds without updates after which component `T` is stale.
+    pub fn window<T: Component>(&self) -> Option<f32> {
+        self.windows.get(std::any::type_name::<T>()).copied()
+    }
+
+    /// Sets the seconds after which a stale entity times out, and what happens to it then.
+    ///
+    /// `None` keeps stale entities around until they resume.
+    pub fn set_timeout(&mut self, timeout: Option<(f32, StaleTimeout)>) {
+        self.timeout = timeout;
+    }
+
+    /// The seconds after which a stale entity times out, if it does.
+    pub fn timeout(&self) -> Option<f32> {
+        self.timeout.as_ref().map(|(timeout, _)| *timeout)
+    }
+
+    /// Whether any component of `entity` is stale.
+    pub fn is_stale(&self, entity: Entity) -> bool {
+        self.stale.contains_key(&entity)
+    }
+
+    /// Whether component `T` of `entity` is stale.
+    pub fn is_comp_stale<T: Component>(&self, entity: Entity) -> bool {
+        self.stale
+            .get(&entity)
+            .is_some_and(|stale| stale.comps.contains(std::any::type_name::<T>()))
+    }
+
+    /// The seconds since `entity` became stale, or `None` if it isn't stale.
+    pub fn stale_for(&self, entity: Entity) -> Option<f32> {
+        self.stale
+            .get(&entity)
+            .map(|stale| stale.since.elapsed().as_secs_f32())
+    }
+
+    /// Gets the stale entities.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.stale.keys().copied()
+    }
+
+    /// Marks component `comp` of `entity` as stale. Returns whether the entity became stale.
+    fn mark(&mut self, entity: Entity, comp: &'static str) -> bool {
+        let newly = !self.stale.contains_key(&entity);
+        self.stale
+            .entry(entity)
+            .or_insert_with(|| Stale {
+                comps: HashSet::default(),
+                since: Instant::now(),
+            })
+            .comps
+            .insert(comp);
+        newly
+    }
+
+    /// Marks component `comp` of `entity` as not stale. Returns whether the entity stopped
+    /// being stale.
+    fn unmark(&mut self, entity: Entity, comp: &'static str) -> bool {
+        let stale = match self.stale.get_mut(&entity) {
+            Some(stale) => stale,
+            None => return false,
+        };
+        if !stale.comps.remove(comp) || !stale.comps.is_empty() {
+            return false;
+        }
+        self.stale.remove(&entity);
+        true
+    }
+}
+
+/// Marks the entities whose component `T` hasn't received an update within its window as stale.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn detect_stale<T, M>(
+    server: Option<Res<Server>>,
+    mut staleness: ResMut<NetStaleness>,
+    mut first_seen: Local<HashMap<Entity, Instant>>,
+    removed: RemovedComponents<NetComp<T, M>>,
+    alive: Query<Entity>,
+    q: Query<(Entity, &NetComp<T, M>, Option<&NetSource<T>>)>,
+    mut ew_stale: EventWriter<NetStale>,
+    mut ew_resumed: EventWriter<NetResumed>,
+) where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    let comp = std::any::type_name::<T>();
+    for entity in removed.iter() {
+        first_seen.remove(&entity);
+        // The entity stopped syncing this component, so it can't be stale because of it.
+        if staleness.unmark(entity, comp) {
+            ew_resumed.send(NetResumed(entity));
+        }
+    }
+    // Entities despawned after this system ran never show up in `removed`, so drop them here.
+    first_seen.retain(|entity, _| alive.contains(*entity));
+    staleness.stale.retain(|entity, _| alive.contains(*entity));
+    let window = match staleness.window::<T>() {
+        Some(window) => window,
+        None => return,
+    };
+    let now = Instant::now();
+    let is_server = server.is_some();
+    for (entity, net_c, source) in q.iter() {
+        let expects = match is_server {
+            true => net_c.s_dir.from().is_some(),
+            false => net_c.c_dir == CNetDir::From,
+        };
+        // Entities that never received an update count from when they were first seen.
+        let last = match source {
+            Some(source) => source.at,
+            None => *first_seen.entry(entity).or_insert(now),
+        };
+        let stale = expects && now.duration_since(last).as_secs_f32() > window;
+        if stale {
+            if staleness.mark(entity, comp) {
+                ew_stale.send(NetStale(entity));
+            }
+        } else if staleness.unmark(entity, comp) {
+            ew_resumed.send(NetResumed(entity));
+        }
+    }
+}
+
+/// Despawns the entities that have been stale for longer than the timeout of the
+/// [`NetStaleness`], or calls its function on them.
+pub fn expire_stale(
+    mut staleness: ResMut<NetStaleness>,
+    mut commands: Commands,
+    alive: Query<Entity>,
+) {
+    let staleness = &mut *staleness;
+    let (timeout, action) = match staleness.timeout.as_ref() {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    staleness.stale.retain(|entity, stale| {
+        // The entity could have been despawned since.
+        if !alive.contains(*entity) {
+            return false;
+        }
+        if stale.since.elapsed().as_secs_f32() <= *timeout {
+            return true;
+        }
+        debug!("Entity {:?} timed out after being stale.", entity);
+        match action {
+            StaleTimeout::Despawn => {
+                if let Some(entity) = commands.get_entity(*entity) {
+                    entity.despawn_recursive();
+                }
+            }
+            StaleTimeout::Call(call) => call(*entity, &mut commands),
+        }
+        false
+    });
+}
@@ -0,0 +1,92 @@
+//! The connection status of the client, exposed as a bevy [`State`].
+
+use crate::app::client_tick;
+use crate::connect::{poll_connecting, Connecting};
+use crate::NetLabel;
+use bevy::prelude::*;
+use carrier_pigeon::Client;
+
+/// The connection status of the client.
+///
+/// This is kept up to date by the [`NetworkStatePlugin`], so you can use
+/// `SystemSet::on_enter(NetworkState::Connected)` and friends for connection UI and cleanup.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub enum NetworkState {
+    /// There is no [`Client`] and no connection is being made.
+    #[default]
+    Disconnected,
+    /// A connection is being made for the first time.
+    Connecting,
+    /// The [`Client`] resource exists.
+    Connected,
+    /// A new connection is being made after having been connected.
+    Reconnecting,
+}
+
+/// A component that marks an entity to be despawned when the [`NetworkState`] is no longer the
+/// given state.
+///
+/// Useful for connection UI, such as a "Connecting..." screen.
+#[derive(Component, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetworkStateScoped(pub NetworkState);
+
+/// A plugin that adds the [`NetworkState`] state and keeps it in sync with the [`Client`]
+/// resource.
+///
+/// This is optional and should be added alongside the [`ClientPlugin`](crate::ClientPlugin).
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct NetworkStatePlugin;
+
+impl Plugin for NetworkStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(NetworkState::default())
+            .add_system_to_stage(
+                CoreStage::First,
+                update_network_state
+                    .label(NetLabel)
+                    .after(poll_connecting)
+                    .after(client_tick),
+            )
+            .add_system(despawn_state_scoped);
+    }
+}
+
+/// Updates the [`NetworkState`] based on the [`Client`] and [`Connecting`] resources.
+pub fn update_network_state(
+    client: Option<Res<Client>>,
+    connecting: Option<Res<Connecting>>,
+    mut state: ResMut<State<NetworkState>>,
+) {
+    let current = *state.current();
+    let new = if client.is_some() {
+        NetworkState::Connected
+    } else if connecting.is_some() {
+        match current {
+            NetworkState::Connected | NetworkState::Reconnecting => NetworkState::Reconnecting,
+            NetworkState::Disconnected | NetworkState::Connecting => NetworkState::Connecting,
+        }
+    } else {
+        NetworkState::Disconnected
+    };
+
+    if new != current {
+        // This only fails if a transition is already queued; the next frame will catch up.
+        let _ = state.set(new);
+    }
+}
+
+/// Despawns entities with a [`NetworkStateScoped`] that doesn't match the current state.
+fn despawn_state_scoped(
+    mut commands: Commands,
+    state: Res<State<NetworkState>>,
+    q: Query<(Entity, &NetworkStateScoped)>,
+) {
+    if !state.is_changed() {
+        return;
+    }
+    for (entity, scope) in q.iter() {
+        if scope.0 != *state.current() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Counters for the received component updates.
+//!
+//! The [`NetStats`] resource counts, for every synced component type, how many updates were
+//! sent and received, and what happened to the received ones. This is useful for tuning send rates: many stale updates
+//! mean more are sent than can be applied, and many out of order ones point to a bad connection.
+//!
+//! ```ignore
+//! fn print_stats(stats: Res<NetStats>) {
+//!     if let Some(stats) = stats.get::<Transform>() {
+//!         info!("{} of {} transform updates arrived out of order", stats.out_of_order, stats.received);
+//!     }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// The counters of the received updates of a single component type.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct MsgStats {
+    /// The number of updates received.
+    pub received: u64,
+    /// The number of updates that were applied.
+    pub applied: u64,
+    /// The number of updates that were dropped because a newer one arrived in the same frame.
+    pub stale: u64,
+    /// The number of updates that had the same send time as one that was already received.
+    pub duplicate: u64,
+    /// The number of updates that were dropped because they arrived after a newer one.
+    pub out_of_order: u64,
+    /// The number of updates sent, counted once for every recipient.
+    pub sent: u64,
+    /// The estimated size in bytes of the updates sent, counted once for every recipient.
+    ///
+    /// This is the bincode encoded size of the messages, without the overhead of the transport.
+    pub sent_bytes: u64,
+}
+
+/// The counters of the received updates of every synced component type.
+///
+/// This is added by [`sync_comp`](crate::AppExt::sync_comp).
+#[derive(Resource, Clone, Eq, PartialEq, Debug, Default)]
+pub struct NetStats {
+    comps: HashMap<&'static str, MsgStats>,
+}
+
+impl NetStats {
+    /// Gets the counters of component `T`.
+    pub fn get<T>(&self) -> Option<&MsgStats> {
+        self.comps.get(std::any::type_name::<T>())
+    }
+
+    /// Gets the counters of all components, by type name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &MsgStats)> + '_ {
+        self.comps.iter().map(|(name, stats)| (*name, stats))
+    }
+
+    /// Resets all counters to 0.
+    pub fn reset(&mut self) {
+        self.comps.clear();
+    }
+
+    /// Gets the counters of component `T` to update.
+    pub(crate) fn comp_mut<T>(&mut self) -> &mut MsgStats {
+        self.comps.entry(std::any::type_name::<T>()).or_default()
+    }
+}
+
+impl MsgStats {
+    /// Counts the received updates with the send `times`, in the order they arrived, given that
+    /// the newest applied update was sent at `current`.
+    ///
+    /// Only updates with a send time are counted as stale, duplicate or out of order. If
+    /// `ordered`, every update that arrived in order is applied, so none of them are stale.
+    pub(crate) fn count(
+        &mut self,
+        times: impl Iterator<Item = Option<u32>>,
+        current: Option<u32>,
+        ordered: bool,
+    ) {
+        let mut newest = current;
+        let mut in_order = 0;
+        for time in times {
+            self.received += 1;
+            let time = match time {
+                Some(time) => time,
+                None => continue,
+            };
+            match newest {
+                Some(newest) if time == newest => self.duplicate += 1,
+                Some(newest) if time < newest => self.out_of_order += 1,
+                _ => {
+                    newest = Some(time);
+                    in_order += 1;
+                }
+            }
+        }
+        // All but the newest of the updates that arrived in order were skipped.
+        if !ordered {
+            self.stale += (in_order as u64).saturating_sub(1);
+        }
+    }
+}
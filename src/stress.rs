@@ -0,0 +1,186 @@
+//! Measuring how many clients a server can sustain. Requires the `testing` feature.
+//!
+//! A [`StressTest`] starts a server and many clients in this process, like a
+//! [`TestNet`], with the same synced components registered everywhere, so every client receives
+//! every registered sync type. It then steps them for a number of ticks and reports:
+//!
+//! - the time the server spends on a tick,
+//! - the bytes of component updates that the server sends to every client per tick,
+//! - the latency of the updates, as the number of server ticks that a client lags behind.
+//!
+//! ```ignore
+//! let mut test = StressTest::new::<Connect, Response, Disconnect>(64, |app, table| {
+//!     app.sync_comp::<Transform, NetTransform>(table, Transport::UDP);
+//! })?;
+//! test.net.server.add_startup_system(spawn_1000_entities);
+//! let report = test.run(600);
+//! println!("{}", report);
+//! ```
+//!
+//! The clients run in the same process and on the same thread as the server, so the tick time
+//! is only the server's share, but the bandwidth and latency are what real clients would see
+//! over the loopback interface. Run it with `--release` for meaningful times.
+
+use crate::input::{NetTick, ServerTick};
+use crate::stats::NetStats;
+use crate::testing::TestNet;
+use bevy::prelude::*;
+use carrier_pigeon::MsgTable;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The time to wait between ticks, so the messages can arrive.
+const TICK_WAIT: Duration = Duration::from_millis(1);
+
+/// A server and many simulated clients, to measure the load of a sync setup.
+#[derive(Debug)]
+pub struct StressTest {
+    /// The server and client apps.
+    pub net: TestNet,
+}
+
+impl StressTest {
+    /// Creates a server app and `clients` client apps, and connects the clients to the server.
+    ///
+    /// See [`TestNet::new`] for `setup` and the message types.
+    pub fn new<C, R, D>(clients: usize, setup: impl Fn(&mut App, &mut MsgTable)) -> io::Result<Self>
+    where
+        C: Default + Any + Send + Sync + Serialize + DeserializeOwned,
+        R: Default + Any + Send + Sync + Serialize + DeserializeOwned,
+        D: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        Ok(StressTest {
+            net: TestNet::new::<C, R, D>(clients, setup)?,
+        })
+    }
+
+    /// Steps the server and clients `ticks` times, and reports the load.
+    pub fn run(&mut self, ticks: usize) -> StressReport {
+        if let Some(mut stats) = self.net.server.world.get_resource_mut::<NetStats>() {
+            stats.reset();
+        }
+        let mut tick_times = Vec::with_capacity(ticks);
+        let mut latencies = vec![];
+        for _ in 0..ticks {
+            let start = Instant::now();
+            self.net.server.update();
+            tick_times.push(start.elapsed());
+            for client in self.net.clients.iter_mut() {
+                client.update();
+            }
+            let server_tick = self.net.server.world.get_resource::<NetTick>().map(|t| t.0);
+            for client in self.net.clients.iter() {
+                let client_tick = client.world.get_resource::<ServerTick>().map(|t| t.get());
+                // 0 means that the client hasn't received anything yet.
+                if let (Some(server_tick), Some(client_tick)) = (server_tick, client_tick) {
+                    if client_tick > 0 {
+                        latencies.push(server_tick.saturating_sub(client_tick));
+                    }
+                }
+            }
+            thread::sleep(TICK_WAIT);
+        }
+
+        let sent_bytes = self
+            .net
+            .server
+            .world
+            .get_resource::<NetStats>()
+            .map(|stats| stats.iter().map(|(_, s)| s.sent_bytes).sum())
+            .unwrap_or(0);
+        let clients = self.net.clients.len();
+        StressReport {
+            clients,
+            ticks,
+            tick_time: Summary::of_times(tick_times),
+            bytes_per_client: match clients * ticks {
+                0 => 0.0,
+                n => sent_bytes as f64 / n as f64,
+            },
+            latency: Summary::of_ticks(latencies),
+        }
+    }
+}
+
+/// The mean, 99th percentile and largest of some measurements.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Summary<T> {
+    /// The mean.
+    pub mean: T,
+    /// The 99th percentile.
+    pub p99: T,
+    /// The largest measurement.
+    pub max: T,
+}
+
+impl Summary<Duration> {
+    fn of_times(mut times: Vec<Duration>) -> Self {
+        if times.is_empty() {
+            return Summary::default();
+        }
+        times.sort();
+        Summary {
+            mean: times.iter().sum::<Duration>() / times.len() as u32,
+            p99: times[(times.len() - 1) * 99 / 100],
+            max: times[times.len() - 1],
+        }
+    }
+}
+
+impl Summary<f32> {
+    fn of_ticks(mut ticks: Vec<u32>) -> Self {
+        if ticks.is_empty() {
+            return Summary::default();
+        }
+        ticks.sort_unstable();
+        Summary {
+            mean: ticks.iter().map(|t| *t as f32).sum::<f32>() / ticks.len() as f32,
+            p99: ticks[(ticks.len() - 1) * 99 / 100] as f32,
+            max: ticks[ticks.len() - 1] as f32,
+        }
+    }
+}
+
+/// The load measured by a [`StressTest`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StressReport {
+    /// The number of clients.
+    pub clients: usize,
+    /// The number of ticks that were run.
+    pub ticks: usize,
+    /// The time the server spent on a tick.
+    pub tick_time: Summary<Duration>,
+    /// The mean bytes of component updates that the server sent to every client per tick.
+    ///
+    /// This is the encoded size of the messages, as counted in the [`NetStats`], without the
+    /// overhead of the transport.
+    pub bytes_per_client: f64,
+    /// The number of server ticks that the newest update of a client was behind the server.
+    pub latency: Summary<f32>,
+}
+
+impl Display for StressReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} clients, {} ticks", self.clients, self.ticks)?;
+        writeln!(
+            f,
+            "server tick: mean {:?}, p99 {:?}, max {:?}",
+            self.tick_time.mean, self.tick_time.p99, self.tick_time.max
+        )?;
+        writeln!(
+            f,
+            "bandwidth: {:.0} bytes per client per tick",
+            self.bytes_per_client
+        )?;
+        write!(
+            f,
+            "latency: mean {:.1}, p99 {}, max {} ticks",
+            self.latency.mean, self.latency.p99, self.latency.max
+        )
+    }
+}
@@ -1,13 +1,36 @@
 //! The things needed to sync components.
 
-use bevy::prelude::Component;
+use crate::app::NetLabel;
+use crate::extrapolate::Extrapolatable;
+use crate::input::NetTick;
+use crate::limits::limited;
+use crate::stage::NetStage;
+use crate::threshold::Delta;
+use crate::AppExt;
+use bevy::ecs::schedule::{IntoSystemDescriptor, StageLabelId, SystemDescriptor, SystemLabelId};
+use bevy::prelude::{App, Component, StageLabel, SystemLabel};
 use carrier_pigeon::net::CIdSpec;
+use carrier_pigeon::{CId, Transport};
 use serde::{Deserialize, Serialize};
 use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
 /// A component that tells `bevy-pigeon` to sync the component `T` which is sent as `M`.
-#[derive(Component, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+///
+/// The constructors and `with_` methods configure it in one go:
+///
+/// ```ignore
+/// commands.spawn((
+///     NetEntity::new(id),
+///     NetComp::<Transform, NetTransform>::server_to_all()
+///         .with_rate(20.0)
+///         .with_threshold(0.01)
+///         .owned_by(cid),
+/// ));
+/// ```
+#[derive(Component, Copy, Clone, Debug)]
 pub struct NetComp<T, M = T>
 where
     T: Clone + Into<M> + Component,
@@ -20,10 +43,34 @@ where
     pub cd: bool,
     /// The timestamp of the last message received and written to this component.
     pub last: Option<u32>,
+    /// The [`NetTick`] of the peer that sent the last message received and written to this
+    /// component. On a client, this is the server tick.
+    pub tick: Option<u32>,
     /// The net direction for the client.
     pub c_dir: CNetDir,
     /// The net direction for the server.
     pub s_dir: SNetDir,
+    /// Overrides the transport that this component is sent with.
+    ///
+    /// If `None`, the transport given to [`sync_comp`](crate::AppExt::sync_comp) is used. This
+    /// can be changed at any time.
+    pub transport: Option<Transport>,
+    /// The delivery guarantees for this component.
+    ///
+    /// If `None`, messages are sequenced (stale messages are dropped) and sent with the
+    /// transport given to [`sync_comp`](crate::AppExt::sync_comp).
+    pub channel: Option<Channel>,
+    /// The [`NetGroups`](crate::group::NetGroups) group that this component is sent to.
+    ///
+    /// If set, this is only sent to the clients in the group that also match the [`SNetDir`].
+    pub group: Option<&'static str>,
+    /// The most times per second that this component is sent.
+    ///
+    /// If `None`, it is sent as often as the [`SyncConfig`] allows.
+    pub rate: Option<f32>,
+    /// The smallest change of this component that is sent, overriding the one of the
+    /// [`SyncConfig`].
+    threshold: Option<Threshold<T>>,
     _pd: PhantomData<(T, M)>,
 }
 
@@ -36,13 +83,64 @@ where
         NetComp {
             cd: true,
             last: None,
+            tick: None,
             c_dir: CNetDir::From,
             s_dir: SNetDir::To(CIdSpec::All),
+            transport: None,
+            channel: None,
+            group: None,
+            rate: None,
+            threshold: None,
             _pd: PhantomData,
         }
     }
 }
 
+// The threshold is left out of the comparisons and the hash, since its delta function can't be
+// compared.
+impl<T, M> PartialEq for NetComp<T, M>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cd == other.cd
+            && self.last == other.last
+            && self.tick == other.tick
+            && self.c_dir == other.c_dir
+            && self.s_dir == other.s_dir
+            && self.transport == other.transport
+            && self.channel == other.channel
+            && self.group == other.group
+            && self.rate.map(f32::to_bits) == other.rate.map(f32::to_bits)
+    }
+}
+
+impl<T, M> Eq for NetComp<T, M>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+}
+
+impl<T, M> Hash for NetComp<T, M>
+where
+    T: Clone + Into<M> + Component,
+    M: Clone + Into<T> + Any + Send + Sync,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cd.hash(state);
+        self.last.hash(state);
+        self.tick.hash(state);
+        self.c_dir.hash(state);
+        self.s_dir.hash(state);
+        self.transport.hash(state);
+        self.channel.hash(state);
+        self.group.hash(state);
+        self.rate.map(f32::to_bits).hash(state);
+    }
+}
+
 impl<T, M> NetComp<T, M>
 where
     T: Clone + Into<M> + Component,
@@ -54,11 +152,171 @@ where
         NetComp {
             cd,
             last: None,
+            tick: None,
             c_dir,
             s_dir,
+            transport: None,
+            channel: None,
+            group: None,
+            rate: None,
+            threshold: None,
             _pd: PhantomData,
         }
     }
+
+    /// Creates a new [`NetComp`] that the server sends to all clients, and the clients receive.
+    pub fn server_to_all() -> Self {
+        NetComp::new(true, CNetDir::From, SNetDir::to_all())
+    }
+
+    /// Creates a new [`NetComp`] that the server sends to the clients matching `spec`, and the
+    /// clients receive.
+    pub fn server_to(spec: CIdSpec) -> Self {
+        NetComp::new(true, CNetDir::From, SNetDir::To(spec))
+    }
+
+    /// Creates a new [`NetComp`] that the clients send, and the server receives from all
+    /// clients.
+    ///
+    /// Use this on the client that owns the entity, and [`owned_by`](Self::owned_by) on the
+    /// server.
+    pub fn client_to_server() -> Self {
+        NetComp::new(true, CNetDir::To, SNetDir::from_all())
+    }
+
+    /// Makes client `cid` the owner of this component on the server: updates are only received
+    /// from `cid`, and sent to all other clients.
+    pub fn owned_by(mut self, cid: CId) -> Self {
+        self.s_dir = SNetDir::ToFrom(CIdSpec::Except(cid), CIdSpec::Only(cid));
+        self
+    }
+
+    /// Sets whether change detection is used.
+    pub fn with_cd(mut self, cd: bool) -> Self {
+        self.cd = cd;
+        self
+    }
+
+    /// Sends this component at most `rate` times per second.
+    ///
+    /// Changes made in between are sent on the next send.
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Only sends changes of this component that are at least `threshold`, as measured by
+    /// [`Delta`], since the value that was last sent.
+    ///
+    /// See the [`threshold`](crate::threshold) module for more info.
+    pub fn with_threshold(mut self, threshold: f32) -> Self
+    where
+        T: Delta,
+    {
+        self.threshold = Some((threshold, T::delta));
+        self
+    }
+
+    /// The smallest change of this component that is sent, if set.
+    pub fn threshold(&self) -> Option<f32> {
+        self.threshold.map(|(threshold, _)| threshold)
+    }
+
+    /// Sets the transport that this component is sent with, overriding the one given to
+    /// [`sync_comp`](crate::AppExt::sync_comp).
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Sets the [`Channel`] that this component is synced on.
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Sets the [`NetGroups`](crate::group::NetGroups) group that this component is sent to.
+    pub fn with_group(mut self, group: &'static str) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Gets the transport override for this component.
+    ///
+    /// This is [`transport`](Self::transport) if set, otherwise the transport of the
+    /// [`channel`](Self::channel).
+    pub fn send_transport(&self) -> Option<Transport> {
+        self.transport
+            .or_else(|| self.channel.map(Channel::transport))
+    }
+
+    /// Whether stale messages for this component should be dropped.
+    pub fn sequenced(&self) -> bool {
+        self.channel
+            .unwrap_or(Channel::UnreliableSequenced)
+            .sequenced()
+    }
+
+    /// Whether every received message for this component should be applied in order, rather than
+    /// only the newest one.
+    pub fn ordered(&self) -> bool {
+        self.channel.is_some_and(Channel::ordered)
+    }
+
+    /// The smallest change of this component that is sent, and how changes are measured.
+    pub(crate) fn threshold_fn(&self) -> Option<Threshold<T>> {
+        self.threshold
+    }
+}
+
+/// The delivery guarantees for a synced component.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Channel {
+    /// Sent over UDP. Messages may be lost, and messages older than the last applied one are
+    /// dropped.
+    ///
+    /// This is best for components that change constantly, like transforms.
+    UnreliableSequenced,
+    /// Sent over TCP. Every message arrives, and they are applied in the order they were sent.
+    ReliableOrdered,
+    /// Sent over TCP. Every message arrives, and the last one received is applied.
+    ///
+    /// `carrier-pigeon`'s TCP transport is always ordered, so this currently behaves like
+    /// [`ReliableOrdered`](Channel::ReliableOrdered) on the wire, but skips the sequencing check
+    /// when receiving.
+    ReliableUnordered,
+    /// Sent over UDP, and resent until acknowledged. Messages older than the last applied one are
+    /// dropped.
+    ///
+    /// This is best for components that change rarely but must arrive eventually, like health or
+    /// score, without the head-of-line blocking of TCP. See the [`resend`](crate::resend) module.
+    UnreliableAcked,
+}
+
+impl Channel {
+    /// The transport that this channel sends on.
+    pub fn transport(self) -> Transport {
+        match self {
+            Channel::UnreliableSequenced | Channel::UnreliableAcked => Transport::UDP,
+            Channel::ReliableOrdered | Channel::ReliableUnordered => Transport::TCP,
+        }
+    }
+
+    /// Whether messages older than the last applied message are dropped.
+    pub fn sequenced(self) -> bool {
+        match self {
+            Channel::UnreliableSequenced | Channel::ReliableOrdered | Channel::UnreliableAcked => {
+                true
+            }
+            Channel::ReliableUnordered => false,
+        }
+    }
+
+    /// Whether every message received in a frame is applied in the order it was sent, rather
+    /// than only the newest one.
+    pub fn ordered(self) -> bool {
+        self == Channel::ReliableOrdered
+    }
 }
 
 /// Client Net Direction.
@@ -121,36 +379,500 @@ impl SNetDir {
     }
 }
 
+#[cfg(not(feature = "wide-ids"))]
+type NetIdRepr = u64;
+#[cfg(feature = "wide-ids")]
+type NetIdRepr = u128;
+
+/// The type of [`NetEntity`] ids.
+///
+/// This is a `u64`, or a `u128` with the `wide-ids` feature. Wide ids fit UUIDs, for when ids are
+/// generated by several authoritative processes, like sharded servers or backend services, and
+/// have to be random to not collide.
+pub type NetId = NetIdRepr;
+
 /// A networked entity.
 ///
 /// Used to link entities across connected instances.
 ///
-/// Any entity using [`NetComp`] needs to have one of these.
+/// Any entity using [`NetComp`] needs to have one of these. On the server, a missing one is
+/// [added](crate::ids#missing-ids) at the end of the frame.
 #[derive(Component, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub struct NetEntity {
     /// A unique identifier that needs to be the same on all connected instances of the game.
-    /// A random `u64` provides a very low collision rate.
-    pub id: u64,
+    /// A random [`NetId`] provides a very low collision rate, and [`NetIds`](crate::ids::NetIds)
+    /// hands out small ids, which take fewer bytes to send.
+    pub id: NetId,
+    /// The number of times `id` was used before, by entities that are gone now.
+    ///
+    /// Updates are only applied to the entity with the same generation as the one they were sent
+    /// from, so updates to an entity that was despawned never apply to the next entity that gets
+    /// its id.
+    pub generation: u32,
 }
 
 impl NetEntity {
-    /// Creates a new [`NetEntity`] with `id`.
-    pub fn new(id: u64) -> Self {
-        NetEntity { id }
+    /// Creates a new [`NetEntity`] with `id`, in generation 0.
+    pub fn new(id: NetId) -> Self {
+        NetEntity { id, generation: 0 }
+    }
+
+    /// Creates a new [`NetEntity`] with `id`, in `generation`.
+    pub fn with_generation(id: NetId, generation: u32) -> Self {
+        NetEntity { id, generation }
+    }
+}
+
+impl From<NetId> for NetEntity {
+    fn from(id: NetId) -> Self {
+        NetEntity::new(id)
+    }
+}
+
+/// Restricts which clients can write to the synced components of an entity.
+///
+/// On the server, updates to any [`NetComp`] on this entity that are sent by a client not matching
+/// the [`CIdSpec`] are dropped with a warning, even if the [`SNetDir`] would accept them. This is
+/// useful for making sure only the owning client can move its avatar.
+#[derive(Component, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct NetWriteAccess(pub CIdSpec);
+
+impl NetWriteAccess {
+    /// Creates a [`NetWriteAccess`] that only allows client `cid` to write.
+    pub fn only(cid: CId) -> Self {
+        NetWriteAccess(CIdSpec::Only(cid))
+    }
+
+    /// Whether client `cid` is allowed to write.
+    pub fn allows(&self, cid: CId) -> bool {
+        self.0.matches(cid)
+    }
+}
+
+/// How component `T` is synced using message type `M`, for
+/// [`sync_comp_cfg`](crate::AppExt::sync_comp_cfg).
+///
+/// ```ignore
+/// app.sync_comp_cfg::<Transform, NetTransform>(
+///     &mut table,
+///     SyncConfig::new(Transport::UDP)
+///         .with_rate(20.0)
+///         .with_threshold(0.01)
+///         .interpolated(),
+/// );
+/// ```
+pub struct SyncConfig<T, M = T> {
+    /// The transport that the component is sent with. Defaults to UDP.
+    pub transport: Transport,
+    /// The most times per second that the component is sent, or `None` to send it every frame.
+    /// Defaults to `None`.
+    ///
+    /// Changes of components using change detection are sent on the next send.
+    pub rate: Option<f32>,
+    /// Whether the [`DistancePriority`](crate::priority::DistancePriority), if there is one,
+    /// sends this component less often for far away entities. Defaults to true.
+    pub priority: bool,
+    /// The stages that the systems run in.
+    pub schedule: SyncSchedule,
+    /// How received updates are applied to the component. Defaults to [`ApplyMode::Mutate`].
+    pub apply_mode: ApplyMode,
+    /// The smallest change that is sent, and how changes are measured.
+    threshold: Option<Threshold<T>>,
+    /// Adds the systems that smooth the received values.
+    smoothing: Option<fn(&mut App)>,
+    _pd: PhantomData<M>,
+}
+
+impl<T, M> Clone for SyncConfig<T, M> {
+    fn clone(&self) -> Self {
+        SyncConfig {
+            transport: self.transport,
+            rate: self.rate,
+            priority: self.priority,
+            schedule: self.schedule,
+            apply_mode: self.apply_mode,
+            threshold: self.threshold,
+            smoothing: self.smoothing,
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T, M> Debug for SyncConfig<T, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncConfig")
+            .field("transport", &self.transport)
+            .field("rate", &self.rate)
+            .field("priority", &self.priority)
+            .field("schedule", &self.schedule)
+            .field("apply_mode", &self.apply_mode)
+            .field("threshold", &self.threshold.map(|(threshold, _)| threshold))
+            .field("smoothing", &self.smoothing.is_some())
+            .finish()
+    }
+}
+
+impl<T, M> Default for SyncConfig<T, M> {
+    fn default() -> Self {
+        SyncConfig::new(Transport::UDP)
+    }
+}
+
+impl<T, M> SyncConfig<T, M> {
+    /// Creates a new [`SyncConfig`] that sends the component with `transport`, with the
+    /// defaults for everything else.
+    pub fn new(transport: Transport) -> Self {
+        SyncConfig {
+            transport,
+            rate: None,
+            priority: true,
+            schedule: SyncSchedule::default(),
+            apply_mode: ApplyMode::default(),
+            threshold: None,
+            smoothing: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Sends the component at most `rate` times per second.
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Sets whether the [`DistancePriority`](crate::priority::DistancePriority) applies to this
+    /// component.
+    pub fn with_priority(mut self, priority: bool) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Runs the systems in the stages of `schedule`.
+    pub fn with_schedule(mut self, schedule: SyncSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Applies received updates to the component as `mode` says.
+    pub fn with_apply_mode(mut self, mode: ApplyMode) -> Self {
+        self.apply_mode = mode;
+        self
+    }
+
+    /// Only sends changes of the component that are at least `threshold`, as measured by
+    /// [`Delta`], since the value that was last sent.
+    ///
+    /// See the [`threshold`](crate::threshold) module for more info.
+    pub fn with_threshold(mut self, threshold: f32) -> Self
+    where
+        T: Delta,
+    {
+        self.threshold = Some((threshold, T::delta));
+        self
+    }
+
+    /// Interpolates the component on the entities with an
+    /// [`Interpolate<T>`](crate::interpolate::Interpolate).
+    pub fn interpolated(mut self) -> Self
+    where
+        T: Extrapolatable + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        self.smoothing = Some(|app| {
+            app.interpolate_comp::<T, M>();
+        });
+        self
+    }
+
+    /// Extrapolates the component on the entities with an
+    /// [`Extrapolate<T>`](crate::extrapolate::Extrapolate).
+    pub fn extrapolated(mut self) -> Self
+    where
+        T: Extrapolatable + Into<M> + Component,
+        M: Clone + Into<T> + Any + Send + Sync,
+    {
+        self.smoothing = Some(|app| {
+            app.extrapolate_comp::<T, M>();
+        });
+        self
+    }
+
+    /// Copies the settings that don't depend on the component type to a config for component
+    /// `U` with message type `N`.
+    ///
+    /// The threshold and smoothing are not copied.
+    pub fn cast<U, N>(&self) -> SyncConfig<U, N> {
+        SyncConfig {
+            transport: self.transport,
+            rate: self.rate,
+            priority: self.priority,
+            schedule: self.schedule,
+            apply_mode: self.apply_mode,
+            threshold: None,
+            smoothing: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// The smallest change that is sent, and how changes are measured.
+    pub(crate) fn threshold(&self) -> Option<Threshold<T>> {
+        self.threshold
+    }
+
+    /// Adds the systems that smooth the received values, if any.
+    pub(crate) fn add_smoothing(&self, app: &mut App) {
+        if let Some(smoothing) = self.smoothing {
+            smoothing(app);
+        }
+    }
+}
+
+/// How received updates are applied to a synced component.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub enum ApplyMode {
+    /// The component is changed in place, through its `Mut<T>`. This is the cheapest.
+    #[default]
+    Mutate,
+    /// A changed copy of the component is inserted with [`Commands`](bevy::prelude::Commands),
+    /// replacing the old one.
+    ///
+    /// This is for components that are treated as immutable values, which gameplay code only
+    /// ever replaces with an insert: received updates take the same path, so the component is
+    /// never changed in place, and the new value shows up when the commands are applied, at the
+    /// end of the receive stage. It costs a clone and a command per update.
+    Insert,
+}
+
+/// The smallest change of component `T` that is sent, and the function that measures changes.
+pub(crate) type Threshold<T> = (f32, fn(&T, &T) -> f32);
+
+/// The stages that the systems syncing a component run in.
+///
+/// By default, these are the [`NetStage`]s, which all synced components share. Any stage can be
+/// used, such as stages that run on a fixed timestep, so that the sent state always belongs to a
+/// completed simulation step instead of a partly advanced frame:
+///
+/// ```ignore
+/// app.add_stage_after(
+///     CoreStage::Update,
+///     FixedPostUpdate,
+///     SystemStage::parallel().with_run_criteria(FixedTimestep::step(1.0 / 60.0)),
+/// );
+/// app.sync_comp_cfg(
+///     &mut table,
+///     SyncConfig::<Transform, NetTransform>::new(Transport::UDP)
+///         .with_schedule(SyncSchedule::new(NetStage::Receive, FixedPostUpdate)),
+/// );
+/// ```
+///
+/// The messages of a frame are only received in that frame, so a receive stage that doesn't run
+/// every frame misses the updates of the frames it skips. Receiving is best left in a stage that
+/// runs every frame.
+///
+/// Within their stages, the systems can be ordered against other systems with a
+/// [`label`](Self::with_label), such as sending after the physics:
+///
+/// ```ignore
+/// let schedule =
+///     SyncSchedule::new(NetStage::Receive, CoreStage::PostUpdate).with_label(BodySync);
+/// app.sync_comp_cfg(
+///     &mut table,
+///     SyncConfig::<Body, NetBody>::new(Transport::UDP).with_schedule(schedule),
+/// )
+/// .add_system_to_stage(CoreStage::PostUpdate, step_physics.before(BodySync));
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SyncSchedule {
+    /// The stage that received updates are applied in. Defaults to [`NetStage::Receive`].
+    pub recv: StageLabelId,
+    /// The stage that updates are sent in. Defaults to [`NetStage::Send`].
+    pub send: StageLabelId,
+    /// A label that the send and receive systems have, besides [`NetLabel`]. Defaults to `None`.
+    pub label: Option<SystemLabelId>,
+}
+
+impl Default for SyncSchedule {
+    fn default() -> Self {
+        SyncSchedule::new(NetStage::Receive, NetStage::Send)
+    }
+}
+
+impl SyncSchedule {
+    /// Creates a [`SyncSchedule`] that receives updates in stage `recv` and sends them in stage
+    /// `send`.
+    pub fn new(recv: impl StageLabel, send: impl StageLabel) -> Self {
+        SyncSchedule {
+            recv: recv.as_label(),
+            send: send.as_label(),
+            label: None,
+        }
+    }
+
+    /// Gives the send and receive systems `label`, so they can be ordered against other
+    /// systems.
+    pub fn with_label(mut self, label: impl SystemLabel) -> Self {
+        self.label = Some(label.as_label());
+        self
+    }
+
+    /// Labels `system` with [`NetLabel`], and with the label of this schedule, if there is one.
+    pub(crate) fn join<Params>(
+        &self,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> SystemDescriptor {
+        let system = system.label(NetLabel);
+        match self.label {
+            Some(label) => system.label(label),
+            None => system,
+        }
     }
 }
 
 /// The message type to be sent.
 ///
-/// This wraps the component message type with the entity's `id`.
+/// This wraps the component message type with the entity's `id` and `generation`, and the
+/// [`SendTick`] that it was sent on.
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub(crate) struct NetCompMsg<M: Any + Send + Sync> {
-    pub(crate) id: u64,
+    #[serde(with = "crate::varint")]
+    pub(crate) id: NetId,
+    #[serde(with = "crate::varint")]
+    pub(crate) generation: u32,
+    pub(crate) tick: Option<SendTick>,
+    #[serde(
+        deserialize_with = "limited",
+        bound(deserialize = "M: Deserialize<'de>")
+    )]
     pub(crate) msg: M,
 }
 
 impl<M: Any + Send + Sync> NetCompMsg<M> {
-    pub(crate) fn new(id: u64, msg: M) -> Self {
-        NetCompMsg { id, msg }
+    pub(crate) fn new(net_e: NetEntity, msg: M) -> Self {
+        NetCompMsg {
+            id: net_e.id,
+            generation: net_e.generation,
+            tick: None,
+            msg,
+        }
+    }
+
+    /// Stamps this message with `tick`.
+    pub(crate) fn with_tick(mut self, tick: Option<SendTick>) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// The [`NetEntity`] that this message was sent from.
+    pub(crate) fn net_e(&self) -> NetEntity {
+        NetEntity::with_generation(self.id, self.generation)
+    }
+}
+
+/// The [`NetTick`] of the peer that sent an update.
+///
+/// The tick is the same for all updates that are sent in a frame, so the updates of different
+/// components can be ordered. Updates sent by the server carry the server's tick.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub(crate) struct SendTick {
+    #[serde(with = "crate::varint")]
+    pub(crate) tick: u32,
+}
+
+impl SendTick {
+    /// The tick of the current frame.
+    pub(crate) fn now(tick: &NetTick) -> Self {
+        SendTick { tick: tick.0 }
+    }
+}
+
+/// The message type used when a [`NetComp`] overrides the transport it was registered with.
+///
+/// This is registered with the opposite transport of [`NetCompMsg`].
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(transparent)]
+pub(crate) struct AltNetCompMsg<M: Any + Send + Sync>(pub(crate) NetCompMsg<M>);
+
+/// A received [`NetCompMsg`], regardless of which message type it was received as.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct RecvNetComp<'a, M: Any + Send + Sync> {
+    pub(crate) cid: CId,
+    pub(crate) time: Option<u32>,
+    pub(crate) id: NetId,
+    pub(crate) generation: u32,
+    pub(crate) tick: Option<SendTick>,
+    pub(crate) msg: &'a M,
+}
+
+impl<'a, M: Any + Send + Sync> RecvNetComp<'a, M> {
+    /// The [`NetEntity`] that this message was sent from.
+    pub(crate) fn net_e(&self) -> NetEntity {
+        NetEntity::with_generation(self.id, self.generation)
+    }
+
+    /// Whether this message is for `net_e`.
+    ///
+    /// Messages sent from an older entity with the same id aren't.
+    pub(crate) fn is_for(&self, net_e: &NetEntity) -> bool {
+        self.id == net_e.id && self.generation == net_e.generation
+    }
+
+    /// Clones this back into a [`NetCompMsg`], with its [`SendTick`].
+    pub(crate) fn to_msg(&self) -> NetCompMsg<M>
+    where
+        M: Clone,
+    {
+        NetCompMsg::new(self.net_e(), self.msg.clone()).with_tick(self.tick)
+    }
+}
+
+/// Gets the opposite transport of `transport`.
+pub(crate) fn alt_transport(transport: Transport) -> Transport {
+    match transport {
+        Transport::TCP => Transport::UDP,
+        Transport::UDP => Transport::TCP,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::Transform;
+    use bevy::utils::HashSet;
+
+    #[test]
+    fn net_comp_equality_ignores_the_threshold() {
+        let net_c = NetComp::<Transform>::server_to_all();
+        let with_threshold = net_c.with_threshold(0.1);
+        assert_eq!(net_c, with_threshold);
+        let set: HashSet<_> = [net_c, with_threshold].into_iter().collect();
+        assert_eq!(set.len(), 1);
+
+        assert_ne!(net_c, net_c.with_rate(10.0));
+        assert_eq!(net_c.with_rate(10.0), net_c.with_rate(10.0));
+    }
+
+    #[cfg(feature = "wide-ids")]
+    #[test]
+    fn wide_ids_round_trip_on_the_wire() {
+        use crate::spawn::PROVISIONAL_BIT;
+
+        let uuid: NetId = 0x6f1c_2a3e_94b7_4d0e_8f25_b1c3_d4e5_f607;
+        for id in [
+            0,
+            u64::MAX as NetId + 1,
+            uuid,
+            NetId::MAX & !PROVISIONAL_BIT,
+            NetId::MAX,
+        ] {
+            let net_e = NetEntity::with_generation(id, 7);
+            let msg = NetCompMsg::new(net_e, 42u8).with_tick(Some(SendTick { tick: 3 }));
+            let bytes = bincode::serialize(&msg).unwrap();
+            let received: NetCompMsg<u8> = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(received, msg);
+            assert_eq!(received.net_e(), net_e);
+
+            let bytes = bincode::serialize(&net_e).unwrap();
+            assert_eq!(bincode::deserialize::<NetEntity>(&bytes).unwrap(), net_e);
+        }
     }
 }
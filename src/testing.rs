@@ -0,0 +1,279 @@
+//! A harness for multiplayer integration tests. Requires the `testing` feature.
+//!
+//! [`TestNet`] creates a server [`App`] and any number of client [`App`]s, connected to each other
+//! over the loopback interface, and steps them in lockstep. Every [`step`](TestNet::step) updates
+//! the server, then every client, and each update sees every TCP message that was sent before it:
+//! the messages the server sent in the same step, and the messages the clients sent in the
+//! previous one. Tests never sleep; they step the apps a fixed number of times, or until a
+//! condition holds.
+//!
+//! ```ignore
+//! #[test]
+//! fn health_is_synced() {
+//!     let mut net = TestNet::new::<Connect, Response, Disconnect>(2, |app, table| {
+//!         app.sync_comp::<Health, Health>(table, Transport::TCP);
+//!     })
+//!     .unwrap();
+//!     net.server
+//!         .world
+//!         .spawn((NetEntity::new(1), NetComp::<Health>::default(), Health(10)));
+//!     net.assert_synced::<Health>(1, 1);
+//! }
+//! ```
+//!
+//! This is not an in-memory transport; `carrier-pigeon` doesn't have one. The apps use real
+//! sockets, on a new port for every [`TestNet`], so tests can run in parallel. The lockstep comes
+//! from a marker message that every app sends over TCP at the end of its update: the next app
+//! receives messages until the markers it waits for have arrived, and only then updates. The TCP
+//! messages of an update arrive before its marker, but UDP messages aren't ordered with it, and
+//! can arrive in a later step or not at all. Components that are synced over UDP should be
+//! checked with [`assert_synced`](TestNet::assert_synced) or
+//! [`step_until`](TestNet::step_until), which step until the values match, instead of after a
+//! fixed number of steps.
+//!
+//! The apps can't use the [`BackgroundRecvPlugin`](crate::BackgroundRecvPlugin), since the
+//! harness receives their messages itself.
+
+use crate::background::{Peer, Received};
+use crate::host::loopback_server;
+use crate::sync::{NetEntity, NetId};
+use crate::{ClientPlugin, ServerPlugin};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use carrier_pigeon::net::Config;
+use carrier_pigeon::{Client, MsgTable, Server, Transport};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt::Debug;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The time to wait for the clients to connect.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The time to wait for the messages of a step to arrive.
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The marker that an app sends at the end of its update in a step.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+struct StepDone(u64);
+
+/// A server app and client apps that are connected to it.
+#[derive(Debug)]
+pub struct TestNet {
+    /// The server app.
+    pub server: App,
+    /// The client apps.
+    pub clients: Vec<App>,
+    /// The number of steps that were taken.
+    steps: u64,
+}
+
+impl TestNet {
+    /// Creates a server app and `clients` client apps, and connects the clients to the server.
+    ///
+    /// `setup` is called for every app with a message table, and should register the synced
+    /// components and messages, like `sync_comp`. It needs to do the same for every app, so the
+    /// tables match. `C`, `R` and `D` are the connection, response and disconnect message types;
+    /// the clients connect with `C::default()` and are accepted with `R::default()`.
+    pub fn new<C, R, D>(clients: usize, setup: impl Fn(&mut App, &mut MsgTable)) -> io::Result<Self>
+    where
+        C: Default + Any + Send + Sync + Serialize + DeserializeOwned,
+        R: Default + Any + Send + Sync + Serialize + DeserializeOwned,
+        D: Any + Send + Sync + Serialize + DeserializeOwned,
+    {
+        let mut table = MsgTable::new();
+        let mut server_app = App::new();
+        server_app
+            .add_plugins(MinimalPlugins)
+            .add_plugin(ServerPlugin);
+        setup(&mut server_app, &mut table);
+        let parts = table
+            .register::<StepDone>(Transport::TCP)
+            .and_then(|_| table.build::<C, R, D>())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        let mut client_apps = vec![];
+        for _ in 0..clients {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins).add_plugin(ClientPlugin);
+            // The table is the same as the server's; only the systems are needed.
+            setup(&mut app, &mut MsgTable::new());
+            client_apps.push(app);
+        }
+
+        let (mut server, addr) = loopback_server(&parts, &Config::default())?;
+        let pending: Vec<_> = (0..clients)
+            .map(|_| {
+                let parts = parts.clone();
+                thread::spawn(move || {
+                    Client::new(addr, parts, Config::default(), C::default()).block::<R>()
+                })
+            })
+            .collect();
+
+        let start = Instant::now();
+        let mut accepted = 0;
+        while (accepted as usize) < clients {
+            if start.elapsed() > CONNECT_TIMEOUT {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the clients didn't connect in time",
+                ));
+            }
+            accepted += server.handle_new_cons(|_, _: C| (true, R::default()));
+            thread::yield_now();
+        }
+
+        for (app, pending) in client_apps.iter_mut().zip(pending) {
+            let (client, _) = pending
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+            app.insert_resource(client);
+        }
+        server_app.insert_resource(server);
+
+        let mut net = TestNet {
+            server: server_app,
+            clients: client_apps,
+            steps: 0,
+        };
+        // Run the startup systems.
+        net.step();
+        Ok(net)
+    }
+
+    /// Updates the server app, then every client app, once.
+    ///
+    /// The server first waits for the TCP messages that the clients sent in the last step, and
+    /// every client waits for the TCP messages that the server sent in this step. UDP messages
+    /// that have arrived by then are received as well. Clients that aren't connected anymore are
+    /// only updated.
+    ///
+    /// ### Panics
+    /// panics if the messages don't arrive within a few seconds.
+    pub fn step(&mut self) {
+        let step = self.steps;
+        update_when(&mut self.server, "the server", step, |server: &Server| {
+            let done: HashSet<_> = server
+                .recv::<StepDone>()
+                .filter(|msg| msg.0 + 1 == step)
+                .map(|msg| msg.cid)
+                .collect();
+            step == 0 || server.cids().all(|cid| done.contains(&cid))
+        });
+        if let Some(server) = self.server.world.get_resource::<Server>() {
+            if let Err(e) = server.broadcast(&StepDone(step)) {
+                warn!("The test server failed to end step {}: {}", step, e);
+            }
+        }
+
+        for (i, app) in self.clients.iter_mut().enumerate() {
+            update_when(app, &format!("client {}", i), step, |client: &Client| {
+                !client.open() || client.recv::<StepDone>().any(|msg| msg.0 == step)
+            });
+            if let Some(client) = app.world.get_resource::<Client>() {
+                if let Err(e) = client.send(&StepDone(step)) {
+                    warn!("Test client {} failed to end step {}: {}", i, step, e);
+                }
+            }
+        }
+        self.steps += 1;
+    }
+
+    /// Steps until `done` returns `true`, at most `max_steps` times.
+    ///
+    /// Returns whether `done` returned `true`.
+    pub fn step_until(
+        &mut self,
+        max_steps: usize,
+        mut done: impl FnMut(&mut Self) -> bool,
+    ) -> bool {
+        for _ in 0..max_steps {
+            if done(self) {
+                return true;
+            }
+            self.step();
+        }
+        done(self)
+    }
+
+    /// Gets component `T` of the entity with [`NetEntity`] id `id` on the server.
+    pub fn server_comp<T: Component + Clone>(&mut self, id: NetId) -> Option<T> {
+        find_comp(&mut self.server, id)
+    }
+
+    /// Gets component `T` of the entity with [`NetEntity`] id `id` on client `client`.
+    ///
+    /// ### Panics
+    /// panics if there is no client with index `client`.
+    pub fn client_comp<T: Component + Clone>(&mut self, client: usize, id: NetId) -> Option<T> {
+        find_comp(&mut self.clients[client], id)
+    }
+
+    /// Steps until component `T` of the entity with [`NetEntity`] id `id` is equal on the server
+    /// and every client, at most `max_steps` times.
+    ///
+    /// ### Panics
+    /// panics if the component isn't equal everywhere after `max_steps` steps.
+    pub fn assert_synced<T: Component + Clone + PartialEq + Debug>(
+        &mut self,
+        id: NetId,
+        max_steps: usize,
+    ) {
+        let synced = self.step_until(max_steps, |net| {
+            let server = net.server_comp::<T>(id);
+            server.is_some()
+                && (0..net.clients.len()).all(|i| net.client_comp::<T>(i, id) == server)
+        });
+        if !synced {
+            let clients: Vec<_> = (0..self.clients.len())
+                .map(|i| self.client_comp::<T>(i, id))
+                .collect();
+            panic!(
+                "{} of NetEntity {{ id: {} }} wasn't synced after {} steps.\nserver: {:?}\nclients: {:?}",
+                std::any::type_name::<T>(),
+                id,
+                max_steps,
+                self.server_comp::<T>(id),
+                clients
+            );
+        }
+    }
+}
+
+/// Gets component `T` of the entity with [`NetEntity`] id `id` in `app`.
+fn find_comp<T: Component + Clone>(app: &mut App, id: NetId) -> Option<T> {
+    app.world
+        .query::<(&NetEntity, &T)>()
+        .iter(&app.world)
+        .find(|(net_e, _)| net_e.id == id)
+        .map(|(_, comp)| comp.clone())
+}
+
+/// Receives the messages of connection `P` in `app` until `arrived` returns `true`, then updates
+/// `app` with them.
+///
+/// If `app` has no connection `P`, it is only updated.
+fn update_when<P: Peer>(app: &mut App, name: &str, step: u64, arrived: impl Fn(&P) -> bool) {
+    if let Some(mut peer) = app.world.get_resource_mut::<P>() {
+        let start = Instant::now();
+        peer.clear();
+        let mut msgs = peer.recv_more();
+        while !arrived(&peer) {
+            if start.elapsed() > STEP_TIMEOUT {
+                panic!(
+                    "{} didn't receive the messages of step {} in time",
+                    name, step
+                );
+            }
+            thread::yield_now();
+            msgs += peer.recv_more();
+        }
+        app.insert_resource(Received::<P>::new(msgs));
+    }
+    app.update();
+    app.world.remove_resource::<Received<P>>();
+}
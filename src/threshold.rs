@@ -0,0 +1,59 @@
+//! Skipping updates for changes that are too small to matter.
+//!
+//! Components like a [`Transform`] change a tiny bit every frame while an entity settles, and
+//! sending each of those changes wastes bandwidth. With a threshold set in the
+//! [`SyncConfig`](crate::SyncConfig), a changed component is only sent once it differs from the
+//! value that was last sent by at least the threshold, as measured by [`Delta`].
+//!
+//! ```ignore
+//! app.sync_comp_cfg::<Transform, NetTransform>(
+//!     &mut table,
+//!     SyncConfig::new(Transport::UDP).with_threshold(0.01),
+//! );
+//! ```
+//!
+//! The receiving side can end up to the threshold away from the sender, until the next change
+//! that is large enough. Components that don't use change detection are always sent.
+
+use bevy::prelude::*;
+
+/// A type whose values can be compared by how much they differ.
+pub trait Delta {
+    /// How much `self` differs from `other`. This is 0 for equal values.
+    fn delta(&self, other: &Self) -> f32;
+}
+
+impl Delta for f32 {
+    fn delta(&self, other: &Self) -> f32 {
+        (self - other).abs()
+    }
+}
+
+impl Delta for Vec2 {
+    fn delta(&self, other: &Self) -> f32 {
+        self.distance(*other)
+    }
+}
+
+impl Delta for Vec3 {
+    fn delta(&self, other: &Self) -> f32 {
+        self.distance(*other)
+    }
+}
+
+impl Delta for Quat {
+    /// The angle between the rotations, in radians.
+    fn delta(&self, other: &Self) -> f32 {
+        self.angle_between(*other)
+    }
+}
+
+impl Delta for Transform {
+    /// The largest delta of the translation, rotation and scale.
+    fn delta(&self, other: &Self) -> f32 {
+        self.translation
+            .delta(&other.translation)
+            .max(self.rotation.delta(&other.rotation))
+            .max(self.scale.delta(&other.scale))
+    }
+}
@@ -0,0 +1,193 @@
+//! Smooth, server-authoritative transform syncing in one plugin.
+//!
+//! Syncing the [`Transform`] of moving entities needs several pieces: the component sync itself,
+//! a compact message type, and interpolation or extrapolation on the clients so the entities
+//! don't jump between updates. The [`TransformSyncPlugin`] sets all of them up with defaults
+//! that suit most games:
+//!
+//! ```ignore
+//! app.add_plugin(TransformSyncPlugin::<NetTransformQ>::new(&mut table, TransformSyncConfig::default())?);
+//!
+//! commands.spawn((
+//!     NetEntity::new(1),
+//!     NetComp::<Transform, NetTransformQ>::default(),
+//!     TransformBundle::default(),
+//! ));
+//! ```
+//!
+//! [`NetTransformQ`](crate::types::NetTransformQ), from the `types` feature, quantizes the
+//! rotation; use another mirror in [`types`](crate::types) if the entities need their scale
+//! synced, or are 2d. On the clients,
+//! every entity that receives its transform gets an [`Interpolate<Transform>`] or
+//! [`Extrapolate<Transform>`], depending on the [`TransformSmoothing`], unless it already has
+//! one.
+//!
+//! The plugin registers the message types into the table when it is created, so it needs to be
+//! created in the same order on the server and the clients, like
+//! [`sync_comp`](crate::AppExt::sync_comp).
+
+use crate::app::{add_sync_systems, register_sync_msgs, NetLabel};
+use crate::extrapolate::{extrapolate, Extrapolate};
+use crate::interpolate::{interpolate, Interpolate, InterpolationDelayChanged};
+use crate::schema::{MsgSchema, MsgTableLike};
+use crate::sync::{CNetDir, NetComp, SyncConfig};
+use bevy::prelude::*;
+use carrier_pigeon::{Client, MsgRegError, Transport};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::Any;
+use std::marker::PhantomData;
+
+/// The filter of newly synced transforms that don't have a smoothing component yet.
+type Unsmoothed<M> = (
+    Added<NetComp<Transform, M>>,
+    Without<Interpolate<Transform>>,
+    Without<Extrapolate<Transform>>,
+);
+
+/// How the clients smooth the transforms between updates.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TransformSmoothing {
+    /// Show the transforms a little in the past, blending between the updates. This is the
+    /// smoothest, at the cost of some latency.
+    Interpolate {
+        /// How far in the past the transforms are shown, in seconds.
+        delay: f32,
+        /// Whether to tune the delay automatically from the measured jitter.
+        auto_delay: bool,
+    },
+    /// Predict where the transforms are going from the last two updates.
+    Extrapolate {
+        /// The maximum time in seconds to extrapolate for after the last update.
+        max_time: f32,
+        /// The time in seconds to blend back to the received values when an update arrives.
+        blend_time: f32,
+    },
+    /// Apply the updates as they arrive.
+    None,
+}
+
+/// The configuration of the [`TransformSyncPlugin`].
+///
+/// The plugin inserts this as a resource.
+#[derive(Resource, Copy, Clone, PartialEq, Debug)]
+pub struct TransformSyncConfig {
+    /// The transport that the transforms are sent with. Defaults to UDP.
+    pub transport: Transport,
+    /// How the clients smooth the transforms. Defaults to interpolating with an automatic delay,
+    /// starting at 0.1 seconds.
+    pub smoothing: TransformSmoothing,
+}
+
+impl Default for TransformSyncConfig {
+    fn default() -> Self {
+        TransformSyncConfig {
+            transport: Transport::UDP,
+            smoothing: TransformSmoothing::Interpolate {
+                delay: 0.1,
+                auto_delay: true,
+            },
+        }
+    }
+}
+
+/// A plugin that syncs [`Transform`]s using message type `M`, and smooths them on the clients.
+///
+/// See the [`transform_sync`](crate::transform_sync) module for more info.
+#[derive(Clone, Debug)]
+pub struct TransformSyncPlugin<M> {
+    config: TransformSyncConfig,
+    schema: MsgSchema,
+    _pd: PhantomData<M>,
+}
+
+impl<M> TransformSyncPlugin<M>
+where
+    M: Clone + From<Transform> + Into<Transform> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    /// Creates the plugin, registering the message types for `M` into `table`.
+    pub fn new(
+        table: &mut impl MsgTableLike,
+        config: TransformSyncConfig,
+    ) -> Result<Self, MsgRegError> {
+        let mut schema = MsgSchema::default();
+        let name = std::any::type_name::<M>();
+        register_sync_msgs::<M>(&mut schema, table, config.transport, name)?;
+        Ok(TransformSyncPlugin {
+            config,
+            schema,
+            _pd: PhantomData,
+        })
+    }
+}
+
+impl<M> Plugin for TransformSyncPlugin<M>
+where
+    M: Clone + From<Transform> + Into<Transform> + Any + Send + Sync + Serialize + DeserializeOwned,
+{
+    fn build(&self, app: &mut App) {
+        app.world
+            .get_resource_or_insert_with(MsgSchema::default)
+            .append(&self.schema);
+        add_sync_systems::<Transform, M>(app, &SyncConfig::new(self.config.transport));
+        app.insert_resource(self.config)
+            .add_system_to_stage(CoreStage::PreUpdate, add_smoothing::<M>.label(NetLabel));
+        match self.config.smoothing {
+            TransformSmoothing::Interpolate { .. } => {
+                app.add_event::<InterpolationDelayChanged>();
+                app.add_system_to_stage(
+                    CoreStage::PreUpdate,
+                    interpolate::<Transform, M>
+                        .label(NetLabel)
+                        .after(add_smoothing::<M>),
+                );
+            }
+            TransformSmoothing::Extrapolate { .. } => {
+                app.add_system_to_stage(
+                    CoreStage::PreUpdate,
+                    extrapolate::<Transform, M>
+                        .label(NetLabel)
+                        .after(add_smoothing::<M>),
+                );
+            }
+            TransformSmoothing::None => {}
+        }
+    }
+}
+
+/// Adds the smoothing component to the entities whose transform the client receives.
+pub fn add_smoothing<M>(
+    mut commands: Commands,
+    client: Option<Res<Client>>,
+    config: Res<TransformSyncConfig>,
+    q: Query<(Entity, &NetComp<Transform, M>), Unsmoothed<M>>,
+) where
+    M: Clone + From<Transform> + Into<Transform> + Any + Send + Sync,
+{
+    if client.is_none() {
+        return;
+    }
+    for (entity, net_c) in q.iter() {
+        if net_c.c_dir != CNetDir::From {
+            continue;
+        }
+        match config.smoothing {
+            TransformSmoothing::Interpolate { delay, auto_delay } => {
+                let interp = match auto_delay {
+                    true => Interpolate::<Transform>::auto(delay),
+                    false => Interpolate::<Transform>::new(delay),
+                };
+                commands.entity(entity).insert(interp);
+            }
+            TransformSmoothing::Extrapolate {
+                max_time,
+                blend_time,
+            } => {
+                commands
+                    .entity(entity)
+                    .insert(Extrapolate::<Transform>::new(max_time, blend_time));
+            }
+            TransformSmoothing::None => {}
+        }
+    }
+}
@@ -7,6 +7,7 @@
 //! - [AlphaMode]
 //! - [EulerRot]
 
+use crate::bits::{BitPack, BitReader, BitWriter, OutOfBits, Packed};
 use bevy::prelude::*;
 use bevy::render::camera::{ScalingMode, WindowOrigin};
 use serde::{Deserialize, Serialize};
@@ -99,6 +100,32 @@ impl From<NetVisibility> for Visibility {
     }
 }
 
+impl BitPack for NetVisibility {
+    const BITS: u32 = 1;
+
+    fn pack(&self, w: &mut BitWriter) {
+        self.is_visible.pack(w);
+    }
+
+    fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+        Ok(NetVisibility {
+            is_visible: bool::unpack(r)?,
+        })
+    }
+}
+
+impl From<Visibility> for Packed<NetVisibility> {
+    fn from(o: Visibility) -> Self {
+        Packed(o.into())
+    }
+}
+
+impl From<Packed<NetVisibility>> for Visibility {
+    fn from(o: Packed<NetVisibility>) -> Self {
+        o.0.into()
+    }
+}
+
 /// The network-able version of [AlphaMode].
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum NetAlphaMode {
@@ -129,6 +156,43 @@ impl From<NetAlphaMode> for AlphaMode {
     }
 }
 
+impl BitPack for NetAlphaMode {
+    /// 2 bits for the variant, and 32 for the cutoff of [`NetAlphaMode::Mask`].
+    const BITS: u32 = 34;
+
+    fn pack(&self, w: &mut BitWriter) {
+        let (variant, cutoff) = match self {
+            NetAlphaMode::Opaque => (0, 0.0),
+            NetAlphaMode::Mask(cutoff) => (1, *cutoff),
+            NetAlphaMode::Blend => (2, 0.0),
+        };
+        w.write_bits(variant, 2);
+        cutoff.pack(w);
+    }
+
+    fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+        let variant = r.read_bits(2)?;
+        let cutoff = f32::unpack(r)?;
+        Ok(match variant {
+            1 => NetAlphaMode::Mask(cutoff),
+            2 => NetAlphaMode::Blend,
+            _ => NetAlphaMode::Opaque,
+        })
+    }
+}
+
+impl From<AlphaMode> for Packed<NetAlphaMode> {
+    fn from(o: AlphaMode) -> Self {
+        Packed(o.into())
+    }
+}
+
+impl From<Packed<NetAlphaMode>> for AlphaMode {
+    fn from(o: Packed<NetAlphaMode>) -> Self {
+        o.0.into()
+    }
+}
+
 /// The network-able version of [EulerRot].
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum NetEulerRot {
@@ -171,3 +235,34 @@ impl From<NetEulerRot> for EulerRot {
         }
     }
 }
+
+impl BitPack for NetEulerRot {
+    const BITS: u32 = 3;
+
+    fn pack(&self, w: &mut BitWriter) {
+        w.write_bits(*self as u64, 3);
+    }
+
+    fn unpack(r: &mut BitReader) -> Result<Self, OutOfBits> {
+        Ok(match r.read_bits(3)? {
+            0 => NetEulerRot::ZYX,
+            1 => NetEulerRot::ZXY,
+            2 => NetEulerRot::YXZ,
+            3 => NetEulerRot::YZX,
+            4 => NetEulerRot::XYZ,
+            _ => NetEulerRot::XZY,
+        })
+    }
+}
+
+impl From<EulerRot> for Packed<NetEulerRot> {
+    fn from(o: EulerRot) -> Self {
+        Packed(o.into())
+    }
+}
+
+impl From<Packed<NetEulerRot>> for EulerRot {
+    fn from(o: Packed<NetEulerRot>) -> Self {
+        o.0.into()
+    }
+}
@@ -7,7 +7,9 @@
 //! - [NetTransform2d]
 //! - [NetTransform2dTR]
 //! - [NetTransform2dT]
+//! - [NetTransformQ]
 
+use crate::validate::Finite;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -23,6 +25,7 @@ use serde::{Deserialize, Serialize};
 /// - [NetTransform2d]
 /// - [NetTransform2dTR]
 /// - [NetTransform2dT]
+/// - [NetTransformQ]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NetTransform {
     /// Position of the entity. In 2d, the last value of the `Vec3` is used for z-ordering.
@@ -66,6 +69,7 @@ impl From<NetTransform> for Transform {
 /// - [NetTransform2d]
 /// - [NetTransform2dTR]
 /// - [NetTransform2dT]
+/// - [NetTransformQ]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NetTransformTR {
     /// Position of the entity. In 2d, the last value of the `Vec3` is used for z-ordering.
@@ -106,6 +110,7 @@ impl From<NetTransformTR> for Transform {
 /// - [NetTransform2d]
 /// - [NetTransform2dTR]
 /// - [NetTransform2dT]
+/// - [NetTransformQ]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NetTransformT {
     /// Position of the entity. In 2d, the last value of the `Vec3` is used for z-ordering.
@@ -142,6 +147,7 @@ impl From<NetTransformT> for Transform {
 /// - [NetTransform2d]
 /// - [NetTransform2dTR]
 /// - [NetTransform2dT]
+/// - [NetTransformQ]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NetTransform2d {
     /// Position of the entity. In 2d, the last value of the `Vec3` is used for z-ordering.
@@ -185,6 +191,7 @@ impl From<NetTransform2d> for Transform {
 /// - [NetTransform2d]
 /// - [NetTransform2dTR]
 /// - [NetTransform2dT]
+/// - [NetTransformQ]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NetTransform2dTR {
     /// Position of the entity. In 2d, the last value of the `Vec3` is used for z-ordering.
@@ -226,6 +233,7 @@ impl From<NetTransform2dTR> for Transform {
 /// - [NetTransform2d]
 /// - [NetTransform2dTR]
 /// - [NetTransform2dT]
+/// - [NetTransformQ]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NetTransform2dT {
     /// Position of the entity. In 2d, the last value of the `Vec3` is used for z-ordering.
@@ -248,3 +256,93 @@ impl From<NetTransform2dT> for Transform {
         }
     }
 }
+
+/// The network-able version of [Transform].
+///
+/// Contains translation and a quantized rotation, which is sent as four `i16`s instead of four
+/// `f32`s. The rotation is accurate to about 0.005 degrees, which is plenty for showing remote
+/// entities.
+///
+/// Only works if scale is always `Vec3::ONE`.
+///
+/// Several different versions with different fields are available:
+/// - [NetTransform]
+/// - [NetTransformTR]
+/// - [NetTransformT]
+/// - [NetTransform2d]
+/// - [NetTransform2dTR]
+/// - [NetTransform2dT]
+/// - [NetTransformQ]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NetTransformQ {
+    /// Position of the entity.
+    pub translation: Vec3,
+    /// Rotation of the entity, as the quantized `x`, `y`, `z` and `w` of the quaternion.
+    pub rotation: [i16; 4],
+}
+
+impl From<Transform> for NetTransformQ {
+    fn from(o: Transform) -> Self {
+        let rotation = o.rotation.normalize();
+        let quantize = |v: f32| (v * i16::MAX as f32).round() as i16;
+        NetTransformQ {
+            translation: o.translation,
+            rotation: rotation.to_array().map(quantize),
+        }
+    }
+}
+
+impl From<NetTransformQ> for Transform {
+    fn from(o: NetTransformQ) -> Self {
+        let [x, y, z, w] = o.rotation.map(|v| v as f32 / i16::MAX as f32);
+        Transform {
+            translation: o.translation,
+            rotation: Vec4::new(x, y, z, w)
+                .try_normalize()
+                .map_or(Quat::IDENTITY, Quat::from_vec4),
+            ..default()
+        }
+    }
+}
+
+impl Finite for NetTransform {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite() && self.rotation.is_finite() && self.scale.is_finite()
+    }
+}
+
+impl Finite for NetTransformTR {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite() && self.rotation.is_finite()
+    }
+}
+
+impl Finite for NetTransformT {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite()
+    }
+}
+
+impl Finite for NetTransform2d {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite() && self.rotation.is_finite() && self.scale.is_finite()
+    }
+}
+
+impl Finite for NetTransform2dTR {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite() && self.rotation.is_finite()
+    }
+}
+
+impl Finite for NetTransform2dT {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite()
+    }
+}
+
+impl Finite for NetTransformQ {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite()
+    }
+}
@@ -0,0 +1,289 @@
+//! Server-side validation of component updates sent by clients.
+//!
+//! When a [`NetComp`](crate::sync::NetComp) lets clients write to a component with
+//! [`SNetDir::From`](crate::sync::SNetDir::From), the server applies whatever arrives. Validators
+//! let the server accept, clamp, or reject these updates before they are applied, so basic
+//! anti-cheat checks can live in the sync pipeline.
+//!
+//! ```ignore
+//! app.add_sync_validator::<Transform, NetTransform, _>(|_update, msg, _current| {
+//!     if msg.translation.y < 0.0 {
+//!         let mut clamped = msg.clone();
+//!         clamped.translation.y = 0.0;
+//!         Validation::Clamp(clamped, "below the floor")
+//!     } else {
+//!         Validation::Accept
+//!     }
+//! });
+//! ```
+//!
+//! Some common validators are provided, and can be combined by adding more than one:
+//!
+//! ```ignore
+//! app.add_sync_validator::<Transform, NetTransform, _>(reject_non_finite())
+//!     .add_sync_validator::<Transform, NetTransform, _>(max_speed(
+//!         10.0,
+//!         |msg: &NetTransform| msg.translation,
+//!         |current: &Transform| current.translation,
+//!     ));
+//! ```
+//!
+//! Every clamped or rejected update sends a [`SyncViolation`] event, which can be used for
+//! logging or kicking.
+//!
+//! Clients can sanitize the updates they receive from the server the same way, with
+//! [`add_recv_sanitizer`](crate::AppExt::add_recv_sanitizer). This keeps policies like ignoring
+//! absurd teleports or smoothing small corrections out of gameplay systems:
+//!
+//! ```ignore
+//! app.add_recv_sanitizer::<Transform, NetTransform, _>(|_update, msg, current| {
+//!     if msg.translation.distance(current.translation) < 0.05 {
+//!         let mut smoothed = msg.clone();
+//!         smoothed.translation = current.translation.lerp(msg.translation, 0.5);
+//!         Validation::Clamp(smoothed, "small correction")
+//!     } else {
+//!         Validation::Accept
+//!     }
+//! });
+//! ```
+//!
+//! Sanitized updates don't send a [`SyncViolation`], as the server is trusted.
+
+use crate::sync::NetId;
+use bevy::prelude::*;
+use carrier_pigeon::CId;
+use std::fmt::{Debug, Formatter};
+
+/// The result of validating a component update.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Validation<M> {
+    /// Apply the update as it is.
+    Accept,
+    /// Apply the given message instead of the one that was received, for the given reason.
+    Clamp(M, &'static str),
+    /// Drop the update, for the given reason.
+    Reject(&'static str),
+}
+
+/// Information about a component update that is being validated.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Update {
+    /// The client that sent the update, or 0 if it was sent by the server.
+    pub cid: CId,
+    /// The id of the [`NetEntity`](crate::sync::NetEntity) that the update is for.
+    pub id: NetId,
+    /// The time, in seconds, between this update and the last applied update, if both were sent
+    /// with a send time.
+    pub dt: Option<f32>,
+}
+
+/// An event that is sent when a client's component update is clamped or rejected by a validator.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SyncViolation {
+    /// The client that sent the update.
+    pub cid: CId,
+    /// The id of the [`NetEntity`](crate::sync::NetEntity) that the update was for.
+    pub id: NetId,
+    /// The type name of the component.
+    pub component: &'static str,
+    /// The reason given by the validator.
+    pub reason: &'static str,
+    /// Whether the update was rejected. If not, it was clamped.
+    pub rejected: bool,
+}
+
+/// A function that validates an update to component `T` sent by a client.
+///
+/// It is given information about the update, the received message, and the current value of the
+/// component.
+pub type Validator<T, M> = Box<dyn Fn(&Update, &M, &T) -> Validation<M> + Send + Sync>;
+
+/// The validators for updates to component `T` using message type `M`.
+///
+/// Validators are added with [`add_sync_validator`](crate::AppExt::add_sync_validator) and run in
+/// the order they were added. A clamped message is passed on to the next validator.
+#[derive(Resource)]
+pub struct SyncValidators<T, M> {
+    validators: Vec<Validator<T, M>>,
+}
+
+impl<T, M> Default for SyncValidators<T, M> {
+    fn default() -> Self {
+        SyncValidators { validators: vec![] }
+    }
+}
+
+impl<T, M> Debug for SyncValidators<T, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncValidators")
+            .field("validators", &self.validators.len())
+            .finish()
+    }
+}
+
+impl<T, M> SyncValidators<T, M> {
+    /// Adds a validator.
+    pub fn add(
+        &mut self,
+        validator: impl Fn(&Update, &M, &T) -> Validation<M> + Send + Sync + 'static,
+    ) {
+        self.validators.push(Box::new(validator));
+    }
+
+    /// Runs all validators on `msg` for a component whose current value is `current`.
+    pub fn validate(&self, update: &Update, msg: &M, current: &T) -> Validation<M> {
+        let mut clamped = None;
+        for validator in self.validators.iter() {
+            match validator(update, clamped.as_ref().map_or(msg, |(m, _)| m), current) {
+                Validation::Accept => {}
+                Validation::Clamp(m, reason) => clamped = Some((m, reason)),
+                Validation::Reject(reason) => return Validation::Reject(reason),
+            }
+        }
+        match clamped {
+            Some((m, reason)) => Validation::Clamp(m, reason),
+            None => Validation::Accept,
+        }
+    }
+}
+
+/// The sanitizers for updates to component `T` using message type `M` that the client receives
+/// from the server.
+///
+/// Sanitizers are added with [`add_recv_sanitizer`](crate::AppExt::add_recv_sanitizer), and
+/// work like [`SyncValidators`], so the same validators can be used.
+#[derive(Resource)]
+pub struct RecvSanitizers<T, M> {
+    sanitizers: SyncValidators<T, M>,
+}
+
+impl<T, M> Default for RecvSanitizers<T, M> {
+    fn default() -> Self {
+        RecvSanitizers {
+            sanitizers: SyncValidators::default(),
+        }
+    }
+}
+
+impl<T, M> Debug for RecvSanitizers<T, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecvSanitizers")
+            .field("sanitizers", &self.sanitizers.validators.len())
+            .finish()
+    }
+}
+
+impl<T, M> RecvSanitizers<T, M> {
+    /// Adds a sanitizer.
+    pub fn add(
+        &mut self,
+        sanitizer: impl Fn(&Update, &M, &T) -> Validation<M> + Send + Sync + 'static,
+    ) {
+        self.sanitizers.add(sanitizer);
+    }
+
+    /// Runs all sanitizers on `msg` for a component whose current value is `current`.
+    pub fn sanitize(&self, update: &Update, msg: &M, current: &T) -> Validation<M> {
+        self.sanitizers.validate(update, msg, current)
+    }
+}
+
+/// A value that can be checked for `NaN` and infinity.
+pub trait Finite {
+    /// Whether all parts of this value are finite.
+    fn is_finite(&self) -> bool;
+}
+
+impl Finite for f32 {
+    fn is_finite(&self) -> bool {
+        f32::is_finite(*self)
+    }
+}
+
+impl Finite for f64 {
+    fn is_finite(&self) -> bool {
+        f64::is_finite(*self)
+    }
+}
+
+impl Finite for Vec2 {
+    fn is_finite(&self) -> bool {
+        Vec2::is_finite(*self)
+    }
+}
+
+impl Finite for Vec3 {
+    fn is_finite(&self) -> bool {
+        Vec3::is_finite(*self)
+    }
+}
+
+impl Finite for Vec4 {
+    fn is_finite(&self) -> bool {
+        Vec4::is_finite(*self)
+    }
+}
+
+impl Finite for Quat {
+    fn is_finite(&self) -> bool {
+        Quat::is_finite(*self)
+    }
+}
+
+impl Finite for Transform {
+    fn is_finite(&self) -> bool {
+        self.translation.is_finite() && self.rotation.is_finite() && self.scale.is_finite()
+    }
+}
+
+/// A validator that rejects messages containing `NaN` or infinity.
+pub fn reject_non_finite<T, M: Finite>() -> impl Fn(&Update, &M, &T) -> Validation<M> {
+    |_, msg, _| {
+        if msg.is_finite() {
+            Validation::Accept
+        } else {
+            Validation::Reject("non-finite value")
+        }
+    }
+}
+
+/// A validator that clamps the field of the message returned by `field` to be between `min` and
+/// `max`.
+pub fn clamp_range<T, M: Clone>(
+    field: fn(&mut M) -> &mut f32,
+    min: f32,
+    max: f32,
+) -> impl Fn(&Update, &M, &T) -> Validation<M> {
+    move |_, msg, _| {
+        let mut clamped = msg.clone();
+        let value = field(&mut clamped);
+        if (min..=max).contains(value) {
+            return Validation::Accept;
+        }
+        *value = value.clamp(min, max);
+        Validation::Clamp(clamped, "value out of range")
+    }
+}
+
+/// A validator that rejects updates that move faster than `max_speed` units per second since the
+/// last applied update.
+///
+/// `msg_pos` gets the position from the message and `comp_pos` gets it from the component.
+/// Updates without a send time are accepted, as the speed can't be known.
+pub fn max_speed<T, M>(
+    max_speed: f32,
+    msg_pos: fn(&M) -> Vec3,
+    comp_pos: fn(&T) -> Vec3,
+) -> impl Fn(&Update, &M, &T) -> Validation<M> {
+    move |update, msg, current| {
+        let dt = match update.dt {
+            Some(dt) => dt,
+            None => return Validation::Accept,
+        };
+        if msg_pos(msg).distance(comp_pos(current)) > max_speed * dt {
+            Validation::Reject("moved too fast")
+        } else {
+            Validation::Accept
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Variable length encoding for ids and counters.
+//!
+//! `bincode` writes every `u64` in 8 bytes and every `u32` in 4, but the ids and counters that
+//! are sent with every update are usually small. The envelopes of the sync messages write them as
+//! varints (LEB128) instead: 7 bits per byte, so an id below 128 takes a single byte, and one
+//! below 16384 takes two.
+//!
+//! The same encoding can be used for the fields of your own messages with `serde`'s `with`
+//! attribute:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Kill {
+//!     #[serde(with = "bevy_pigeon::varint")]
+//!     killer: u64,
+//!     #[serde(with = "bevy_pigeon::varint")]
+//!     victim: u64,
+//! }
+//! ```
+//!
+//! Values with the high bits set, like the ids of provisional entities, take more space than
+//! they would fixed, up to 10 bytes for a `u64`, and 19 for a `u128`.
+
+use serde::de::{Error as _, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserializer, Serializer};
+use std::fmt::Formatter;
+
+/// The most bytes that a varint of a `u128` takes.
+const MAX_LEN: usize = 19;
+
+/// An unsigned integer that can be written as a varint.
+pub trait VarInt: Copy {
+    /// Widens `self` to a `u128`.
+    fn to_u128(self) -> u128;
+    /// Narrows `value` to `Self`, if it fits.
+    fn from_u128(value: u128) -> Option<Self>;
+}
+
+macro_rules! impl_var_int {
+    ($($t:ty),*) => {
+        $(
+            impl VarInt for $t {
+                fn to_u128(self) -> u128 {
+                    self as u128
+                }
+
+                fn from_u128(value: u128) -> Option<Self> {
+                    <$t>::try_from(value).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_var_int!(u16, u32, u64, u128, usize);
+
+/// Writes `value` as a varint.
+pub fn serialize<T: VarInt, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut value = value.to_u128();
+    let mut bytes = [0u8; MAX_LEN];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+    // A tuple has no length prefix; the last byte is the one without the high bit.
+    let mut tuple = serializer.serialize_tuple(len)?;
+    for byte in bytes[..len].iter() {
+        tuple.serialize_element(byte)?;
+    }
+    tuple.end()
+}
+
+/// Reads a varint that was written with [`serialize`].
+pub fn deserialize<'de, T: VarInt, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    let value = deserializer.deserialize_tuple(MAX_LEN, VarIntVisitor)?;
+    T::from_u128(value).ok_or_else(|| {
+        D::Error::custom(format!(
+            "varint {} is too large for {}",
+            value,
+            std::any::type_name::<T>()
+        ))
+    })
+}
+
+/// Reads the bytes of a varint from a tuple, until the byte without the high bit.
+struct VarIntVisitor;
+
+impl<'de> Visitor<'de> for VarIntVisitor {
+    type Value = u128;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "a varint")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<u128, A::Error> {
+        let mut value = 0u128;
+        for i in 0..MAX_LEN {
+            let byte: u8 = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+            let bits = (byte & 0x7f) as u128;
+            let shift = 7 * i as u32;
+            if shift == 126 && bits > 3 {
+                return Err(A::Error::custom("varint overflows a u128"));
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(A::Error::custom("varint is longer than 19 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+    struct Small(#[serde(with = "super")] u32);
+
+    #[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+    struct Large(#[serde(with = "super")] u64);
+
+    #[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+    struct Huge(#[serde(with = "super")] u128);
+
+    #[test]
+    fn values_round_trip_in_the_expected_length() {
+        let cases = [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (u32::MAX as u64, 5),
+            (u64::MAX, 10),
+        ];
+        for (value, len) in cases {
+            let bytes = bincode::serialize(&Large(value)).unwrap();
+            assert_eq!(bytes.len(), len, "length of {}", value);
+            assert_eq!(bincode::deserialize::<Large>(&bytes).unwrap(), Large(value));
+        }
+        let bytes = bincode::serialize(&Small(u32::MAX)).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Small>(&bytes).unwrap(),
+            Small(u32::MAX)
+        );
+        let bytes = bincode::serialize(&Huge(u128::MAX)).unwrap();
+        assert_eq!(bytes.len(), 19);
+        assert_eq!(
+            bincode::deserialize::<Huge>(&bytes).unwrap(),
+            Huge(u128::MAX)
+        );
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let bytes = bincode::serialize(&Large(u64::MAX)).unwrap();
+        for len in 0..bytes.len() {
+            assert!(bincode::deserialize::<Large>(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn overlong_input_is_an_error() {
+        // Every byte says that another one follows.
+        assert!(bincode::deserialize::<Huge>(&[0x80; 32]).is_err());
+        // The last byte of a u128 can only hold 2 bits.
+        let mut bytes = [0xff; 19];
+        bytes[18] = 0x04;
+        assert!(bincode::deserialize::<Huge>(&bytes).is_err());
+    }
+
+    #[test]
+    fn values_too_large_for_the_type_are_an_error() {
+        let bytes = bincode::serialize(&Large(u32::MAX as u64 + 1)).unwrap();
+        assert!(bincode::deserialize::<Small>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Protocol version negotiation.
+//!
+//! When a client with an old build connects to a newer server, its messages can deserialize into
+//! the new message layouts as garbage instead of failing. To prevent this, the connection message
+//! can carry a [`ProtocolVersion`] that the server checks before anything else.
+//!
+//! To use it, build the `MsgTable` with [`Versioned<C>`] and [`Handshake<R>`] as the connection
+//! and response message types, and register them with
+//! [`set_versioned_connection_msgs`](crate::AppExt::set_versioned_connection_msgs) instead of
+//! [`set_connection_msgs`](crate::AppExt::set_connection_msgs):
+//!
+//! ```ignore
+//! const GAME_VERSION: u32 = 3;
+//!
+//! let parts = table.build::<Versioned<Join>, Handshake<Welcome>, Leave>().unwrap();
+//! app.set_versioned_connection_msgs::<Join, Welcome>(GAME_VERSION);
+//!
+//! // On the client.
+//! let con_msg = Versioned::new(GAME_VERSION, Join::new("player"));
+//! app.insert_resource(Connecting::new::<Versioned<Join>, Handshake<Welcome>>(
+//!     addr, parts, Config::default(), con_msg,
+//! ));
+//! ```
+//!
+//! The server rejects clients with an incompatible version with a
+//! [`Rejection::VersionMismatch`], which the client gets as a [`ConnectionRejected`] event.
+//! If the client sent the hash of its [`MsgSchema`], the server also rejects it with a
+//! [`Rejection::SchemaMismatch`] if it doesn't match.
+//! Clients with a compatible version are passed on to the [`ConnectionHook<C, R>`] as usual, and
+//! the [`NetConnected<C>`] and [`ConnectionResponse<R>`] events have the inner messages.
+//!
+//! [`ConnectionHook<C, R>`]: crate::ConnectionHook
+//! [`NetConnected<C>`]: crate::NetConnected
+//! [`ConnectionResponse<R>`]: crate::ConnectionResponse
+
+use crate::connect::{ConnectSucceeded, ConnectionResponse};
+use crate::schema::{MsgSchema, SchemaEntry};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+
+/// The version of the protocol that a client or server speaks.
+#[derive(Resource, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct ProtocolVersion {
+    /// The major version of `bevy-pigeon`.
+    pub pigeon_major: u32,
+    /// The minor version of `bevy-pigeon`.
+    pub pigeon_minor: u32,
+    /// The version of the game, which should be changed whenever its messages change.
+    pub game: u32,
+}
+
+impl ProtocolVersion {
+    /// The protocol version of this build of `bevy-pigeon`, with the given game version.
+    pub fn current(game: u32) -> Self {
+        ProtocolVersion {
+            pigeon_major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
+            pigeon_minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
+            game,
+        }
+    }
+
+    /// Whether a peer with version `other` can talk to a peer with this version.
+    ///
+    /// The game versions need to be equal, and the `bevy-pigeon` versions need to be compatible
+    /// by semver: the same major version, and the same minor version before `1.0`.
+    pub fn is_compatible(&self, other: &ProtocolVersion) -> bool {
+        self.game == other.game
+            && self.pigeon_major == other.pigeon_major
+            && (self.pigeon_major != 0 || self.pigeon_minor == other.pigeon_minor)
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bevy-pigeon {}.{}, game version {}",
+            self.pigeon_major, self.pigeon_minor, self.game
+        )
+    }
+}
+
+/// A connection message `C` with the [`ProtocolVersion`] of the client.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct Versioned<C> {
+    /// The version of the client.
+    pub version: ProtocolVersion,
+    /// The hash of the client's [`MsgSchema`], if it should be verified.
+    pub schema: Option<u64>,
+    /// The connection message.
+    pub msg: C,
+}
+
+impl<C> Versioned<C> {
+    /// Wraps `msg` with the current protocol version and the given game version.
+    pub fn new(game: u32, msg: C) -> Self {
+        Versioned {
+            version: ProtocolVersion::current(game),
+            schema: None,
+            msg,
+        }
+    }
+
+    /// Sends the hash of `schema` along, so that the server verifies that it built the same
+    /// message table. See the [`schema`](crate::schema) module for more info.
+    pub fn with_schema(mut self, schema: &MsgSchema) -> Self {
+        self.schema = Some(schema.hash());
+        self
+    }
+}
+
+/// Why the server rejected a connection before asking the
+/// [`ConnectionHook`](crate::ConnectionHook).
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Rejection {
+    /// The client's protocol version is not compatible with the server's.
+    VersionMismatch {
+        /// The version of the server.
+        server: ProtocolVersion,
+        /// The version of the client.
+        client: ProtocolVersion,
+    },
+    /// The client's [`MsgSchema`] is different from the server's.
+    SchemaMismatch {
+        /// The schema of the server, so the client can tell how they differ.
+        server: Vec<SchemaEntry>,
+    },
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::VersionMismatch { server, client } => write!(
+                f,
+                "version mismatch: the server has {}, but the client has {}",
+                server, client
+            ),
+            Rejection::SchemaMismatch { .. } => {
+                write!(f, "the server built a different message table")
+            }
+        }
+    }
+}
+
+/// The response to a [`Versioned`] connection message.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Handshake<R> {
+    /// The versions are compatible, and this is the response of the
+    /// [`ConnectionHook`](crate::ConnectionHook), whether it accepted the connection or not.
+    Response(R),
+    /// The connection was rejected before the hook was asked.
+    Rejected(Rejection),
+}
+
+/// An event that is sent on the client when the server rejects its connection with a
+/// [`Rejection`].
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub struct ConnectionRejected {
+    /// Why the connection was rejected.
+    pub reason: Rejection,
+    /// How the client's [`MsgSchema`] differs from the server's, one line per difference, if
+    /// the reason is a [`Rejection::SchemaMismatch`].
+    pub schema_diff: Vec<String>,
+}
+
+/// Sends a [`ConnectionResponse`] or [`ConnectionRejected`] for every successful connection,
+/// depending on the [`Handshake`].
+pub fn recv_handshake<R: Clone + Any + Send + Sync>(
+    schema: Option<Res<MsgSchema>>,
+    mut er: EventReader<ConnectSucceeded>,
+    mut responses: EventWriter<ConnectionResponse<R>>,
+    mut rejections: EventWriter<ConnectionRejected>,
+) {
+    for event in er.iter() {
+        match event.response::<Handshake<R>>() {
+            Some(Handshake::Response(response)) => responses.send(ConnectionResponse {
+                response: response.clone(),
+            }),
+            Some(Handshake::Rejected(reason)) => {
+                error!("The server rejected the connection: {}", reason);
+                let schema_diff = match (reason, &schema) {
+                    (Rejection::SchemaMismatch { server }, Some(schema)) => schema.diff(server),
+                    _ => vec![],
+                };
+                for line in schema_diff.iter() {
+                    error!("{}", line);
+                }
+                rejections.send(ConnectionRejected {
+                    reason: reason.clone(),
+                    schema_diff,
+                });
+            }
+            None => warn!(
+                "The connection response is not of type {}",
+                std::any::type_name::<Handshake<R>>()
+            ),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Hiding entities from clients.
+//!
+//! Entities with a [`NetHidden`] component are not sent to the clients it matches. With the
+//! `visibility` feature, the [`NetVisibilityPlugin`] keeps [`NetHidden`] in sync with bevy's
+//! `Visibility`, so hiding an object on the server also stops broadcasting its state.
+
+use crate::spec::NetSpec;
+use bevy::prelude::*;
+use carrier_pigeon::net::CIdSpec;
+
+/// A component that stops the synced components of an entity from being sent to the clients
+/// matching the [`NetSpec`].
+///
+/// The default hides the entity from all clients.
+#[derive(Component, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct NetHidden(pub NetSpec);
+
+impl NetHidden {
+    /// Whether the entity is hidden from all clients.
+    pub fn from_all(&self) -> bool {
+        matches!(self.0.as_cid_spec(), Some(CIdSpec::All))
+    }
+}
+
+/// A plugin that hides entities whose `Visibility` is not visible from all clients, by adding
+/// and removing [`NetHidden`]. Requires the `visibility` feature.
+///
+/// This manages the [`NetHidden`] component of every [`NetEntity`](crate::sync::NetEntity) with
+/// a `Visibility`, so don't add [`NetHidden`] by hand to those entities.
+#[cfg(feature = "visibility")]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default, Hash)]
+pub struct NetVisibilityPlugin;
+
+#[cfg(feature = "visibility")]
+impl Plugin for NetVisibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(CoreStage::PostUpdate, hide_invisible.label(crate::NetLabel));
+    }
+}
+
+/// Adds or removes [`NetHidden`] when the `Visibility` of a networked entity changes.
+#[cfg(feature = "visibility")]
+fn hide_invisible(
+    mut commands: Commands,
+    q: Query<
+        (Entity, &Visibility, Option<&NetHidden>),
+        (With<crate::sync::NetEntity>, Changed<Visibility>),
+    >,
+) {
+    for (entity, visibility, hidden) in q.iter() {
+        match (visibility.is_visible, hidden.is_some()) {
+            (false, false) => {
+                commands.entity(entity).insert(NetHidden::default());
+            }
+            (true, true) => {
+                commands.entity(entity).remove::<NetHidden>();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Tests of component syncing, using the [`TestNet`] harness.
+
+use bevy::prelude::*;
+use bevy_pigeon::atomic::{GroupBuffer, SyncGroups};
+use bevy_pigeon::bundle::Synced;
+use bevy_pigeon::deferred::DeferredApply;
+use bevy_pigeon::sync::{NetComp, NetEntity, SyncConfig};
+use bevy_pigeon::testing::TestNet;
+use bevy_pigeon::{AppExt, NetSource, NetUpdated};
+use carrier_pigeon::Transport;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Component, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+struct Health(u32);
+
+#[derive(Component, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+struct Pos(i32);
+
+#[derive(Component, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+struct Vel(i32);
+
+/// Creates a [`TestNet`] with `clients` clients that sync [`Health`], and spawns the entity with
+/// [`NetEntity`] id 1 on every app, with `health` on the server.
+fn health_net(clients: usize, health: u32) -> TestNet {
+    let mut net = TestNet::new::<(), (), ()>(clients, |app, table| {
+        app.sync_comp::<Health, Health>(table, Transport::TCP);
+    })
+    .unwrap();
+    net.server.world.spawn((
+        NetEntity::new(1),
+        NetComp::<Health>::default(),
+        Health(health),
+    ));
+    for client in net.clients.iter_mut() {
+        client
+            .world
+            .spawn((NetEntity::new(1), NetComp::<Health>::default(), Health(0)));
+    }
+    net
+}
+
+/// The number of [`NetUpdated<T>`] events that were sent in the last update of `app`.
+fn updates<T: Component>(app: &App) -> usize {
+    app.world
+        .resource::<Events<NetUpdated<T>>>()
+        .iter_current_update_events()
+        .count()
+}
+
+#[test]
+fn server_changes_reach_every_client_in_one_step() {
+    let mut net = health_net(2, 10);
+    net.step();
+    assert_eq!(net.client_comp::<Health>(0, 1), Some(Health(10)));
+    assert_eq!(net.client_comp::<Health>(1, 1), Some(Health(10)));
+
+    let mut q = net.server.world.query::<&mut Health>();
+    q.single_mut(&mut net.server.world).0 = 3;
+    net.step();
+    assert_eq!(net.client_comp::<Health>(0, 1), Some(Health(3)));
+    assert_eq!(net.client_comp::<Health>(1, 1), Some(Health(3)));
+}
+
+#[test]
+fn assert_synced_steps_until_equal() {
+    let mut net = health_net(1, 5);
+    net.assert_synced::<Health>(1, 1);
+}
+
+#[test]
+fn udp_components_are_checked_by_stepping_until_equal() {
+    let mut net = TestNet::new::<(), (), ()>(1, |app, table| {
+        app.sync_comp::<Health, Health>(table, Transport::UDP);
+    })
+    .unwrap();
+    net.server.world.spawn((
+        NetEntity::new(1),
+        NetComp::<Health>::default().with_cd(false),
+        Health(8),
+    ));
+    net.clients[0]
+        .world
+        .spawn((NetEntity::new(1), NetComp::<Health>::default(), Health(0)));
+    // UDP messages aren't part of the lockstep, so they may take a few steps.
+    net.assert_synced::<Health>(1, 100);
+}
+
+#[test]
+fn client_group_is_applied_together_on_the_server() {
+    let mut net = TestNet::new::<(), (), ()>(1, |app, table| {
+        app.sync_bundle::<(Synced<Pos>, Synced<Vel>)>(table, SyncConfig::new(Transport::TCP));
+    })
+    .unwrap();
+    // Only the timeout would apply an incomplete group.
+    net.server.world.resource_mut::<SyncGroups>().max_wait = 60.0;
+    net.server.world.spawn((
+        NetEntity::new(1),
+        NetComp::<Pos>::client_to_server(),
+        NetComp::<Vel>::client_to_server(),
+        Pos(0),
+        Vel(0),
+    ));
+    // The client sends only `Pos` at first, so the server holds it.
+    let client = net.clients[0]
+        .world
+        .spawn((
+            NetEntity::new(1),
+            NetComp::<Pos>::client_to_server(),
+            Pos(4),
+        ))
+        .id();
+    net.step();
+    net.step();
+    assert_eq!(net.server_comp::<Pos>(1), Some(Pos(0)));
+    assert_eq!(
+        net.server.world.resource::<GroupBuffer<Pos, Pos>>().len(),
+        1
+    );
+    assert_eq!(updates::<Pos>(&net.server), 0);
+
+    net.clients[0]
+        .world
+        .entity_mut(client)
+        .insert((NetComp::<Vel>::client_to_server(), Vel(2)));
+    net.step();
+    net.step();
+    assert_eq!(net.server_comp::<Pos>(1), Some(Pos(4)));
+    assert_eq!(net.server_comp::<Vel>(1), Some(Vel(2)));
+    assert!(net
+        .server
+        .world
+        .resource::<GroupBuffer<Pos, Pos>>()
+        .is_empty());
+    assert_eq!(updates::<Pos>(&net.server), 1);
+}
+
+#[test]
+fn server_spreads_deferred_client_updates_over_frames() {
+    let mut net = TestNet::new::<(), (), ()>(1, |app, table| {
+        app.sync_comp::<Health, Health>(table, Transport::TCP);
+    })
+    .unwrap();
+    // At least one update is applied every frame, but no more.
+    net.server.defer_apply::<Health, Health>(Duration::ZERO);
+    for id in [1, 2] {
+        net.server.world.spawn((
+            NetEntity::new(id),
+            NetComp::<Health>::client_to_server(),
+            Health(0),
+        ));
+        net.clients[0].world.spawn((
+            NetEntity::new(id),
+            NetComp::<Health>::client_to_server(),
+            Health(7),
+        ));
+    }
+    // The clients send in the first step, and the server receives in the second.
+    net.step();
+    net.step();
+    let sources = |net: &mut TestNet| {
+        let mut q = net.server.world.query::<&NetSource<Health>>();
+        q.iter(&net.server.world).count()
+    };
+    let applied = |net: &mut TestNet| {
+        [1, 2]
+            .iter()
+            .filter(|id| net.server_comp::<Health>(**id) == Some(Health(7)))
+            .count()
+    };
+    assert_eq!(applied(&mut net), 1);
+    assert_eq!(
+        net.server
+            .world
+            .resource::<DeferredApply<Health, Health>>()
+            .len(),
+        1
+    );
+    assert_eq!(updates::<Health>(&net.server), 1);
+    assert_eq!(sources(&mut net), 1);
+
+    net.step();
+    assert_eq!(applied(&mut net), 2);
+    assert_eq!(updates::<Health>(&net.server), 1);
+    assert_eq!(sources(&mut net), 2);
+}